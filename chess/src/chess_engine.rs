@@ -0,0 +1,13 @@
+pub mod evaluation;
+pub mod transposition;
+pub mod move_picker;
+pub mod search;
+pub mod time_manager;
+pub mod mcts;
+pub mod book;
+pub mod syzygy;
+pub mod retrograde;
+pub mod tournament;
+pub mod tuning;
+pub mod nnue;
+pub mod datagen;
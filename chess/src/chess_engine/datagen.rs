@@ -0,0 +1,328 @@
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use game::rng::Rng;
+use crate::chess_engine::search::{SearchConfig, SearchLimits};
+use crate::chess_engine::tournament::{play_game, Adjudication, EngineConfig};
+use crate::chess_game::chess_piece::{ChessPiece, PieceName};
+use crate::chess_game::{ChessGameState, FenError, GameResult, Player};
+
+// Binary records are RECORD_SIZE bytes each, with numbers little endian:
+//   u64 occupancy, bit n set when square n (a1 = 0, b1 = 1, ..., h8 = 63) has a piece
+//   16 bytes of pieces, one nibble each in square order, low nibble first:
+//     pawn to king are 0 to 5 for white and 8 to 13 for black
+//   u8 flags: bit 0 set when black is to move, bits 1 to 4 the castling rights K, Q, k and q
+//   u8 en passant square, 255 for none
+//   u8 halfmove clock
+//   i16 search score in centipawns for the side to move
+//   u8 game result for white: 0 a loss, 1 a draw, 2 a win
+//   u16 ply of the position in its game
+// There is no header, so files can simply be concatenated.
+pub const RECORD_SIZE: usize = 32;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DatagenError {
+    // the data isn't a whole number of records
+    Length(usize),
+    Record(usize, FenError),
+}
+
+impl Display for DatagenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DatagenError::Length(length) => write!(f, "{} bytes is not a whole number of records", length),
+            DatagenError::Record(index, err) => write!(f, "record {}: {}", index, err),
+        }
+    }
+}
+
+// a position of a self-play game, labelled for training
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct DataRecord {
+    pub position: ChessGameState,
+    // for the side to move
+    pub score: i32,
+    pub result: GameResult,
+    pub ply: usize,
+}
+
+impl DataRecord {
+    pub fn to_bytes(&self) -> [u8; RECORD_SIZE] {
+        let mut bytes = [0; RECORD_SIZE];
+        let mut occupancy = 0u64;
+        let mut nibbles = 0;
+        for sq in self.position.board().iter() {
+            let Some(piece) = sq.get_piece() else {
+                continue;
+            };
+            occupancy |= 1 << usize::from(sq.get_id());
+            let code = usize::from(piece.get_name()) as u8 + if piece.get_owner() == Player::Black { 8 } else { 0 };
+            bytes[8 + nibbles / 2] |= code << (4 * (nibbles % 2));
+            nibbles += 1;
+        }
+        bytes[..8].copy_from_slice(&occupancy.to_le_bytes());
+        let castling = self.position.castling_rights().iter().enumerate()
+            .fold(0, |flags, (i, right)| flags | (u8::from(*right) << (i + 1)));
+        bytes[24] = u8::from(self.position.active_player() == Player::Black) | castling;
+        bytes[25] = self.position.ep_square().map_or(255, |sq| usize::from(sq) as u8);
+        bytes[26] = self.position.draw_clock().min(255) as u8;
+        bytes[27..29].copy_from_slice(&(self.score.clamp(i16::MIN as i32, i16::MAX as i32) as i16).to_le_bytes());
+        bytes[29] = match self.result {
+            GameResult::BlackWin => 0,
+            GameResult::Draw => 1,
+            GameResult::WhiteWin => 2,
+        };
+        bytes[30..32].copy_from_slice(&(self.ply.min(u16::MAX as usize) as u16).to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; RECORD_SIZE]) -> Result<Self, FenError> {
+        let occupancy = u64::from_le_bytes(bytes[..8].try_into().expect("eight bytes"));
+        let mut pieces = [None; 64];
+        let mut nibbles = 0;
+        for (sq, piece) in pieces.iter_mut().enumerate() {
+            if occupancy & (1 << sq) == 0 {
+                continue;
+            }
+            let code = (bytes[8 + nibbles / 2] >> (4 * (nibbles % 2))) & 0xF;
+            nibbles += 1;
+            let name = match code & 7 {
+                0 => PieceName::Pawn,
+                1 => PieceName::Knight,
+                2 => PieceName::Bishop,
+                3 => PieceName::Rook,
+                4 => PieceName::Queen,
+                5 => PieceName::King,
+                _ => return Err(FenError::Placement),
+            };
+            let owner = if code & 8 == 0 { Player::White } else { Player::Black };
+            *piece = Some(ChessPiece::new(owner, name, true));
+            if nibbles > 32 {
+                return Err(FenError::Placement);
+            }
+        }
+        // the position is rebuilt through its FEN, which takes care of the castling rights
+        let mut placement = Vec::new();
+        for rank in (0..8).rev() {
+            let mut row = String::new();
+            let mut empty = 0;
+            for file in 0..8 {
+                match pieces[rank * 8 + file] {
+                    Some(piece) => {
+                        if empty > 0 {
+                            row += &empty.to_string();
+                            empty = 0;
+                        }
+                        row += &piece.to_string();
+                    },
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                row += &empty.to_string();
+            }
+            placement.push(row);
+        }
+        let flags = bytes[24];
+        let castling: String = ["K", "Q", "k", "q"].iter().enumerate()
+            .filter(|(i, _)| flags & (1 << (i + 1)) != 0)
+            .map(|(_, symbol)| *symbol)
+            .collect();
+        let ep = match bytes[25] {
+            255 => String::from("-"),
+            sq if sq < 64 => format!("{}{}", (b'a' + sq % 8) as char, sq / 8 + 1),
+            _ => return Err(FenError::EnPassant),
+        };
+        let ply = u16::from_le_bytes([bytes[30], bytes[31]]) as usize;
+        let fen = format!("{} {} {} {} {} {}",
+            placement.join("/"),
+            if flags & 1 == 0 { "w" } else { "b" },
+            if castling.is_empty() { "-" } else { castling.as_str() },
+            ep,
+            bytes[26],
+            ply / 2 + 1);
+        let result = match bytes[29] {
+            0 => GameResult::BlackWin,
+            1 => GameResult::Draw,
+            2 => GameResult::WhiteWin,
+            _ => return Err(FenError::Placement),
+        };
+        Ok(Self {
+            position: ChessGameState::from_fen(&fen)?,
+            score: i16::from_le_bytes([bytes[27], bytes[28]]) as i32,
+            result,
+            ply,
+        })
+    }
+
+    // an EPD line with the score as ce, and the result as c9 like the tuner reads it
+    pub fn to_epd(&self) -> String {
+        let fen = self.position.get_fen();
+        let fields: Vec<&str> = fen.split_whitespace().take(4).collect();
+        format!("{} ce {}; c9 \"{}\"; ply {};", fields.join(" "), self.score, self.result.to_pgn(), self.ply)
+    }
+}
+
+pub fn write_binary(records: &[DataRecord]) -> Vec<u8> {
+    records.iter().flat_map(|record| record.to_bytes()).collect()
+}
+
+pub fn read_binary(bytes: &[u8]) -> Result<Vec<DataRecord>, DatagenError> {
+    if !bytes.len().is_multiple_of(RECORD_SIZE) {
+        return Err(DatagenError::Length(bytes.len()));
+    }
+    bytes.chunks_exact(RECORD_SIZE).enumerate()
+        .map(|(i, chunk)| DataRecord::from_bytes(chunk.try_into().expect("a whole record")).map_err(|err| DatagenError::Record(i, err)))
+        .collect()
+}
+
+pub fn write_epd(records: &[DataRecord]) -> String {
+    records.iter().map(|record| record.to_epd() + "\n").collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct DatagenConfig {
+    pub games: usize,
+    // each game has its own generator seeded from this, so the output doesn't depend on the threads
+    pub seed: u64,
+    // random moves played from the standard position before the engines take over
+    pub random_plies: usize,
+    pub config: SearchConfig,
+    pub limits: SearchLimits,
+    pub adjudication: Adjudication,
+    // positions before this ply aren't recorded
+    pub min_ply: usize,
+    pub skip_in_check: bool,
+    // skip positions where the best move is a capture or a promotion, whose score the evaluation can't see
+    pub skip_tactical: bool,
+    pub threads: usize,
+}
+
+impl Default for DatagenConfig {
+    fn default() -> Self {
+        Self {
+            games: 1,
+            seed: 0,
+            random_plies: 8,
+            config: SearchConfig { hash_size_mb: 4, ..SearchConfig::default() },
+            limits: SearchLimits { depth: Some(6), ..SearchLimits::default() },
+            adjudication: Adjudication::default(),
+            min_ply: 16,
+            skip_in_check: true,
+            skip_tactical: true,
+            threads: 1,
+        }
+    }
+}
+
+// a random legal opening that hasn't ended the game
+fn random_opening(rng: &mut Rng, plies: usize) -> ChessGameState {
+    'retry: loop {
+        let mut game = ChessGameState::new();
+        for _ in 0..plies {
+            let moves = game.get_legal_moves();
+            if moves.is_empty() {
+                continue 'retry;
+            }
+            game.make_move(*moves.iter().nth(rng.below(moves.len())).expect("a legal move"));
+        }
+        if game.result().is_none() {
+            return game;
+        }
+    }
+}
+
+fn generate_game(config: &DatagenConfig, index: usize) -> Vec<DataRecord> {
+    let mut rng = Rng::new(config.seed.wrapping_add(index as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    let start = random_opening(&mut rng, config.random_plies);
+    let engine = EngineConfig { name: String::new(), config: config.config, limits: config.limits };
+    let played = play_game([&engine, &engine], start, &config.adjudication);
+    let mut records = Vec::new();
+    let mut position = start;
+    for (i, (chess_move, score)) in played.moves.iter().zip(&played.scores).enumerate() {
+        let ply = config.random_plies + i;
+        let skipped = ply < config.min_ply
+            || (config.skip_in_check && position.in_check())
+            || (config.skip_tactical && chess_move.chess_move.is_tactical());
+        if !skipped {
+            records.push(DataRecord { position, score: *score, result: played.result, ply });
+        }
+        position.make_move(*chess_move);
+    }
+    records
+}
+
+// plays the self-play games on several threads, returning their positions in game order
+pub fn generate(config: &DatagenConfig) -> Vec<DataRecord> {
+    let next = AtomicUsize::new(0);
+    let games: Mutex<Vec<Vec<DataRecord>>> = Mutex::new(vec![Vec::new(); config.games]);
+    std::thread::scope(|scope| {
+        for _ in 0..config.threads.max(1) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                if index >= config.games {
+                    break;
+                }
+                let records = generate_game(config, index);
+                if let Ok(mut games) = games.lock() {
+                    games[index] = records;
+                }
+            });
+        }
+    });
+    games.into_inner().unwrap_or_default().into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess_engine::datagen::{generate, read_binary, write_binary, write_epd, DataRecord, DatagenConfig, DatagenError, RECORD_SIZE};
+    use crate::chess_engine::search::SearchLimits;
+    use crate::chess_engine::tournament::Adjudication;
+    use crate::chess_engine::tuning::parse_positions;
+    use crate::chess_game::{ChessGameState, GameResult};
+
+    #[test]
+    fn record_formats() {
+        let fens = [
+            "r3k2r/1P4P1/8/3pP3/8/8/1p4p1/R3K2R w KQkq d6 0 40",
+            "8/8/4k3/8/8/8/8/2KR4 b - - 17 60",
+        ];
+        for (fen, ply) in fens.iter().zip([78, 119]) {
+            let record = DataRecord { position: ChessGameState::from_fen(fen).unwrap(), score: -35, result: GameResult::BlackWin, ply };
+            let bytes = record.to_bytes();
+            assert_eq!(DataRecord::from_bytes(&bytes), Ok(record));
+        }
+        let record = DataRecord { position: ChessGameState::new(), score: 40_000, result: GameResult::Draw, ply: 0 };
+        assert_eq!(DataRecord::from_bytes(&record.to_bytes()).unwrap().score, i16::MAX as i32);
+        assert_eq!(record.to_epd(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ce 40000; c9 \"1/2-1/2\"; ply 0;");
+        assert_eq!(read_binary(&[0; RECORD_SIZE + 1]), Err(DatagenError::Length(RECORD_SIZE + 1)));
+    }
+
+    #[test]
+    fn self_play_positions() {
+        let config = DatagenConfig {
+            games: 4,
+            seed: 11,
+            limits: SearchLimits { depth: Some(2), ..SearchLimits::default() },
+            adjudication: Adjudication { max_plies: 60, ..Adjudication::default() },
+            min_ply: 12,
+            threads: 2,
+            ..DatagenConfig::default()
+        };
+        let records = generate(&config);
+        assert!(!records.is_empty());
+        assert!(records.iter().all(|r| r.ply >= 12 && !r.position.in_check()));
+        // the games start from different random openings
+        assert!(records.windows(2).any(|w| w[1].ply < w[0].ply));
+
+        // the same seed gives the same data, whatever the number of threads
+        let single = generate(&DatagenConfig { threads: 1, ..config.clone() });
+        assert_eq!(single, records);
+        assert_ne!(generate(&DatagenConfig { seed: 12, ..config }), records);
+
+        assert_eq!(read_binary(&write_binary(&records)).unwrap(), records);
+        let positions = parse_positions(&write_epd(&records)).unwrap();
+        assert_eq!(positions.len(), records.len());
+        assert_eq!(positions[0].0.get_fen().split(' ').take(4).collect::<Vec<_>>(), records[0].position.get_fen().split(' ').take(4).collect::<Vec<_>>());
+    }
+}
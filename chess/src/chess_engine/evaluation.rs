@@ -0,0 +1,270 @@
+use std::fmt::{Display, Formatter};
+use crate::chess_game::chess_piece::PieceName;
+use crate::chess_game::chess_square::SquareID;
+use crate::chess_game::{ChessGameState, Player};
+
+// piece-square tables are laid out as seen from white's side of the board: a8 first, h1 last
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+    50,  50,  50,  50,  50,  50,  50,  50,
+    10,  10,  20,  30,  30,  20,  10,  10,
+     5,   5,  10,  25,  25,  10,   5,   5,
+     0,   0,   0,  20,  20,   0,   0,   0,
+     5,  -5, -10,   0,   0, -10,  -5,   5,
+     5,  10,  10, -20, -20,  10,  10,   5,
+     0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+   -50, -40, -30, -30, -30, -30, -40, -50,
+   -40, -20,   0,   0,   0,   0, -20, -40,
+   -30,   0,  10,  15,  15,  10,   0, -30,
+   -30,   5,  15,  20,  20,  15,   5, -30,
+   -30,   0,  15,  20,  20,  15,   0, -30,
+   -30,   5,  10,  15,  15,  10,   5, -30,
+   -40, -20,   0,   5,   5,   0, -20, -40,
+   -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+   -20, -10, -10, -10, -10, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,  10,  10,   5,   0, -10,
+   -10,   5,   5,  10,  10,   5,   5, -10,
+   -10,   0,  10,  10,  10,  10,   0, -10,
+   -10,  10,  10,  10,  10,  10,  10, -10,
+   -10,   5,   0,   0,   0,   0,   5, -10,
+   -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+     5,  10,  10,  10,  10,  10,  10,   5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+     0,   0,   0,   5,   5,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,   5,   5,   5,   0, -10,
+    -5,   0,   5,   5,   5,   5,   0,  -5,
+     0,   0,   5,   5,   5,   5,   0,  -5,
+   -10,   5,   5,   5,   5,   5,   0, -10,
+   -10,   0,   5,   0,   0,   0,   0, -10,
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const KING_MIDDLE_TABLE: [i32; 64] = [
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -20, -30, -30, -40, -40, -30, -30, -20,
+   -10, -20, -20, -20, -20, -20, -20, -10,
+    20,  20,   0,   0,   0,   0,  20,  20,
+    20,  30,  10,   0,   0,  10,  30,  20,
+];
+
+#[rustfmt::skip]
+const KING_END_TABLE: [i32; 64] = [
+   -50, -40, -30, -20, -20, -30, -40, -50,
+   -30, -20, -10,   0,   0, -10, -20, -30,
+   -30, -10,  20,  30,  30,  20, -10, -30,
+   -30, -10,  30,  40,  40,  30, -10, -30,
+   -30, -10,  30,  40,  40,  30, -10, -30,
+   -30, -10,  20,  30,  30,  20, -10, -30,
+   -30, -30,   0,   0,   0,   0, -30, -30,
+   -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+// game phase weight of each piece; 24 means all minor and major pieces are on the board
+pub(crate) const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+pub(crate) const MAX_PHASE: i32 = 24;
+
+pub const fn piece_value(name: PieceName) -> i32 {
+    match name {
+        PieceName::Pawn => 100,
+        PieceName::Knight => 320,
+        PieceName::Bishop => 330,
+        PieceName::Rook => 500,
+        PieceName::Queen => 900,
+        PieceName::King => 20_000,
+    }
+}
+
+// the tunable weights of the evaluation
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct EvalParams {
+    // pawn to queen
+    pub piece_values: [i32; 5],
+    // pawn to queen, then the king in the middlegame and in the endgame
+    pub tables: [[i32; 64]; 7],
+}
+
+pub const KING_MIDDLE: usize = 5;
+pub const KING_END: usize = 6;
+
+pub const DEFAULT_PARAMS: EvalParams = EvalParams {
+    piece_values: [
+        piece_value(PieceName::Pawn),
+        piece_value(PieceName::Knight),
+        piece_value(PieceName::Bishop),
+        piece_value(PieceName::Rook),
+        piece_value(PieceName::Queen),
+    ],
+    tables: [PAWN_TABLE, KNIGHT_TABLE, BISHOP_TABLE, ROOK_TABLE, QUEEN_TABLE, KING_MIDDLE_TABLE, KING_END_TABLE],
+};
+
+// the names of the tables in config files
+const TABLE_NAMES: [&str; 7] = ["pawn", "knight", "bishop", "rook", "queen", "king_middle", "king_end"];
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParamsError {
+    // a line (counting from 1) with an unknown name or the wrong number of values
+    Line(usize),
+    Missing(&'static str),
+}
+
+impl Display for ParamsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParamsError::Line(line) => write!(f, "invalid line {}", line),
+            ParamsError::Missing(name) => write!(f, "missing {}", name),
+        }
+    }
+}
+
+impl EvalParams {
+    // one line per set of weights: its name, then the values, with the tables laid out like the constants
+    pub fn to_config(&self) -> String {
+        let join = |values: &[i32]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" ");
+        let mut text = format!("piece_values {}\n", join(&self.piece_values));
+        for (name, table) in TABLE_NAMES.iter().zip(&self.tables) {
+            text += &format!("{} {}\n", name, join(table));
+        }
+        text
+    }
+
+    // reads a config written by to_config; lines starting with # are comments
+    pub fn from_config(text: &str) -> Result<Self, ParamsError> {
+        let mut piece_values = None;
+        let mut tables = [None; 7];
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = ParamsError::Line(number + 1);
+            let mut words = line.split_whitespace();
+            let name = words.next().unwrap_or_default();
+            let values = words.map(|w| w.parse::<i32>()).collect::<Result<Vec<_>, _>>().map_err(|_| error.clone())?;
+            if name == "piece_values" {
+                piece_values = Some(values.try_into().map_err(|_| error)?);
+            } else {
+                let table = TABLE_NAMES.iter().position(|n| *n == name).ok_or(error.clone())?;
+                tables[table] = Some(values.try_into().map_err(|_| error)?);
+            }
+        }
+        let mut params = DEFAULT_PARAMS;
+        params.piece_values = piece_values.ok_or(ParamsError::Missing("piece_values"))?;
+        for (i, table) in tables.into_iter().enumerate() {
+            params.tables[i] = table.ok_or(ParamsError::Missing(TABLE_NAMES[i]))?;
+        }
+        Ok(params)
+    }
+}
+
+pub(crate) fn table_index(id: SquareID, player: Player) -> usize {
+    let index: usize = id.into();
+    match player {
+        // flip the rank, since the tables start at a8
+        Player::White => index ^ 56,
+        Player::Black => index,
+    }
+}
+
+// static evaluation in centipawns, from the point of view of the active player
+pub fn evaluate(game: &ChessGameState) -> i32 {
+    evaluate_with(game, &DEFAULT_PARAMS)
+}
+
+pub fn evaluate_with(game: &ChessGameState, params: &EvalParams) -> i32 {
+    let mut score = [0; 2];
+    let mut king_middle = [0; 2];
+    let mut king_end = [0; 2];
+    let mut phase = 0;
+    for sq in game.board().iter() {
+        if let Some(piece) = sq.get_piece() {
+            let player = piece.get_owner();
+            let side: usize = player.into();
+            let index = table_index(sq.get_id(), player);
+            let name = piece.get_name();
+            phase += PHASE_WEIGHTS[usize::from(name)];
+            if name == PieceName::King {
+                king_middle[side] = params.tables[KING_MIDDLE][index];
+                king_end[side] = params.tables[KING_END][index];
+            } else {
+                score[side] += params.piece_values[usize::from(name)] + params.tables[usize::from(name)][index];
+            }
+        }
+    }
+    // pieces in a crazyhouse pocket are material as much as those on the board
+    for player in [Player::White, Player::Black] {
+        for (name, count) in game.pocket(player).into_iter().enumerate() {
+            score[usize::from(player)] += params.piece_values[name] * i32::from(count);
+        }
+    }
+    let phase = phase.min(MAX_PHASE);
+    for side in 0..2 {
+        score[side] += (king_middle[side] * phase + king_end[side] * (MAX_PHASE - phase)) / MAX_PHASE;
+    }
+    match game.active_player() {
+        Player::White => score[0] - score[1],
+        Player::Black => score[1] - score[0],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess_engine::evaluation::{evaluate, evaluate_with, EvalParams, ParamsError, DEFAULT_PARAMS, KING_END};
+    use crate::chess_game::ChessGameState;
+
+    #[test]
+    fn symmetric_start() {
+        let game = ChessGameState::new();
+        assert_eq!(evaluate(&game), 0);
+    }
+
+    #[test]
+    fn mirrored_positions() {
+        let white = ChessGameState::from_fen("4k3/8/8/8/8/8/3Q4/4K3 w - - 0 1").unwrap();
+        let black = ChessGameState::from_fen("4k3/3q4/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+        assert!(evaluate(&white) > 800);
+        assert_eq!(evaluate(&white), evaluate(&black));
+    }
+
+    #[test]
+    fn params_config() {
+        let mut params = DEFAULT_PARAMS;
+        params.piece_values[1] = 350;
+        params.tables[KING_END][27] = -7;
+        let config = params.to_config();
+        assert_eq!(EvalParams::from_config(&format!("# tuned\n{}", config)), Ok(params.clone()));
+        assert_eq!(EvalParams::from_config(&config.replace("rook", "castle")), Err(ParamsError::Line(5)));
+        assert_eq!(EvalParams::from_config("piece_values 1 2 3 4 5"), Err(ParamsError::Missing("pawn")));
+
+        let game = ChessGameState::from_fen("4k3/8/8/8/8/8/3N4/4K3 w - - 0 1").unwrap();
+        assert_eq!(evaluate_with(&game, &params), evaluate(&game) + 30);
+    }
+}
@@ -0,0 +1,101 @@
+use game::mcts::RolloutPolicy;
+use game::rng::Rng;
+use game::{Game, Outcome};
+use crate::chess_game::chess_move::AnnotatedMove;
+use crate::chess_game::{ChessGameState, GameResult, Player};
+
+impl Game for ChessGameState {
+    type Move = AnnotatedMove;
+    type Player = Player;
+
+    fn current_player(&self) -> Player {
+        self.active_player()
+    }
+
+    fn legal_moves(&self) -> Vec<AnnotatedMove> {
+        self.get_legal_moves().iter().copied().collect()
+    }
+
+    fn play(&mut self, m: AnnotatedMove) {
+        self.make_move(m);
+    }
+
+    fn outcome(&self) -> Option<Outcome<Player>> {
+        self.result().map(|result| match result {
+            GameResult::WhiteWin => Outcome::Win(Player::White),
+            GameResult::BlackWin => Outcome::Win(Player::Black),
+            GameResult::Draw => Outcome::Draw,
+        })
+    }
+}
+
+// plays captures and promotions more often than quiet moves, which makes rollouts a little less aimless
+#[derive(Debug, Copy, Clone)]
+pub struct CaptureBiasedRollout {
+    // how much more likely a tactical move is to be played than a quiet one
+    pub tactical_weight: u32,
+}
+
+impl Default for CaptureBiasedRollout {
+    fn default() -> Self {
+        Self { tactical_weight: 4 }
+    }
+}
+
+impl RolloutPolicy<ChessGameState> for CaptureBiasedRollout {
+    fn choose(&mut self, _game: &ChessGameState, moves: &[AnnotatedMove], rng: &mut Rng) -> AnnotatedMove {
+        let weight = |m: &AnnotatedMove| if m.chess_move.is_tactical() { self.tactical_weight as usize } else { 1 };
+        let total: usize = moves.iter().map(weight).sum();
+        let mut pick = rng.below(total);
+        for m in moves {
+            if pick < weight(m) {
+                return *m;
+            }
+            pick -= weight(m);
+        }
+        unreachable!("the pick is below the total weight")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::mcts::{Budget, Mcts, MctsConfig, RandomRollout, RolloutPolicy};
+    use game::rng::Rng;
+    use game::{Game, Outcome};
+    use crate::chess_engine::mcts::CaptureBiasedRollout;
+    use crate::chess_game::chess_move::ChessMove;
+    use crate::chess_game::chess_square::SquareID;
+    use crate::chess_game::{ChessGameState, Player};
+
+    #[test]
+    fn terminal_values() {
+        let mate = ChessGameState::from_fen("r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4").unwrap();
+        assert_eq!(mate.outcome(), Some(Outcome::Win(Player::White)));
+        assert!(ChessGameState::new().outcome().is_none());
+        assert_eq!(ChessGameState::new().legal_moves().len(), 20);
+    }
+
+    #[test]
+    fn capture_bias() {
+        let game = ChessGameState::from_fen("r3k3/pp3ppp/8/1N6/8/8/PPP2PPP/4K3 w - - 0 1").unwrap();
+        let moves = game.legal_moves();
+        let captures = moves.iter().filter(|m| m.chess_move.is_tactical()).count();
+        assert_eq!(captures, 1);
+        let mut policy = CaptureBiasedRollout { tactical_weight: 1000 };
+        let mut rng = Rng::new(3);
+        let picked = (0..100).filter(|_| policy.choose(&game, &moves, &mut rng).chess_move.is_tactical()).count();
+        assert!(picked > 90);
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        let game = ChessGameState::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4").unwrap();
+        let config = MctsConfig { budget: Budget::Iterations(600), max_rollout_plies: 10, seed: 5, ..Default::default() };
+        let qxf7 = ChessMove::Capture(SquareID::parse("h5").unwrap(), SquareID::parse("f7").unwrap());
+
+        let mut mcts = Mcts::new(config, RandomRollout);
+        assert_eq!(mcts.search(&game).map(|m| m.chess_move), Some(qxf7));
+        let mut mcts = Mcts::new(config, CaptureBiasedRollout::default());
+        assert_eq!(mcts.search(&game).map(|m| m.chess_move), Some(qxf7));
+    }
+}
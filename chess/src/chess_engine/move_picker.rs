@@ -0,0 +1,481 @@
+use crate::chess_engine::evaluation::piece_value;
+use crate::chess_game::chess_move::ChessMove;
+use crate::chess_game::chess_piece::{ChessPiece, PieceName};
+use crate::chess_game::chess_square::{SquareID, SquareOffset};
+use crate::chess_game::{ChessGameState, MoveGen, Player};
+
+pub const MAX_PLY: usize = 128;
+
+// history scores are kept within +/- this bound
+const HISTORY_MAX: i32 = 16_384;
+
+// the piece that made the previous move and the square it moved to, used to look up countermoves
+pub type PrevMove = Option<(ChessPiece, SquareID)>;
+
+fn piece_index(piece: ChessPiece) -> usize {
+    let player: usize = piece.get_owner().into();
+    let name: usize = piece.get_name().into();
+    player * 6 + name
+}
+
+// move ordering statistics gathered while searching
+pub struct Heuristics {
+    killers: Vec<[Option<ChessMove>; 2]>,
+    // butterfly table indexed by player, origin and destination square
+    history: Vec<[[i32; 64]; 64]>,
+    // indexed by the previous move's piece and destination square
+    counter_moves: Vec<[Option<ChessMove>; 64]>,
+}
+
+impl Heuristics {
+    pub fn new() -> Self {
+        Self {
+            killers: vec![[None; 2]; MAX_PLY],
+            history: vec![[[0; 64]; 64]; 2],
+            counter_moves: vec![[None; 64]; 12],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    // called between searches: killers are position specific, and old history should fade
+    pub fn age(&mut self) {
+        self.killers.fill([None; 2]);
+        for side in self.history.iter_mut() {
+            for from in side.iter_mut() {
+                for score in from.iter_mut() {
+                    *score /= 2;
+                }
+            }
+        }
+    }
+
+    pub fn killers(&self, ply: usize) -> [Option<ChessMove>; 2] {
+        self.killers.get(ply).copied().unwrap_or([None; 2])
+    }
+
+    pub fn history(&self, game: &ChessGameState, chess_move: ChessMove) -> i32 {
+        let side: usize = game.active_player().into();
+        let (from, to) = game.move_squares(chess_move);
+        self.history[side][usize::from(from)][usize::from(to)]
+    }
+
+    pub fn counter_move(&self, prev: PrevMove) -> Option<ChessMove> {
+        prev.and_then(|(piece, to)| self.counter_moves[piece_index(piece)][usize::from(to)])
+    }
+
+    fn update_history(&mut self, game: &ChessGameState, chess_move: ChessMove, bonus: i32) {
+        let side: usize = game.active_player().into();
+        let (from, to) = game.move_squares(chess_move);
+        let entry = &mut self.history[side][usize::from(from)][usize::from(to)];
+        // gravity keeps the scores bounded and lets recent results dominate
+        *entry += bonus - *entry * bonus.abs() / HISTORY_MAX;
+    }
+
+    // rewards a quiet move that caused a beta cutoff and penalizes the quiet moves tried before it
+    pub fn update_cutoff(&mut self, game: &ChessGameState, best: ChessMove, tried: &[ChessMove], depth: i32, ply: usize, prev: PrevMove) {
+        if best.is_tactical() {
+            return;
+        }
+        if let Some(killers) = self.killers.get_mut(ply) && killers[0] != Some(best) {
+            killers[1] = killers[0];
+            killers[0] = Some(best);
+        }
+        if let Some((piece, to)) = prev {
+            self.counter_moves[piece_index(piece)][usize::from(to)] = Some(best);
+        }
+        let bonus = (depth * depth).min(400);
+        self.update_history(game, best, bonus);
+        for m in tried.iter().filter(|m| **m != best && !m.is_tactical()) {
+            self.update_history(game, *m, -bonus);
+        }
+    }
+}
+
+impl Default for Heuristics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum Stage {
+    HashMove,
+    GenTactical,
+    GoodTactical,
+    FirstKiller,
+    SecondKiller,
+    CounterMove,
+    GenQuiet,
+    Quiet,
+    BadTactical,
+    Unordered,
+    Done,
+}
+
+// hands out the moves of a position one at a time, best guesses first:
+// hash move, winning captures (MVV-LVA, filtered by SEE), killers, countermove,
+// quiet moves by history and finally losing captures.
+// Quiet moves are only generated once the earlier stages failed to produce a cutoff.
+pub struct MovePicker {
+    stage: Stage,
+    hash_move: Option<ChessMove>,
+    killers: [Option<ChessMove>; 2],
+    counter_move: Option<ChessMove>,
+    tactical_only: bool,
+    moves: Vec<(ChessMove, i32)>,
+    bad_tactical: Vec<(ChessMove, i32)>,
+    index: usize,
+}
+
+impl MovePicker {
+    pub fn new(hash_move: Option<ChessMove>, heuristics: &Heuristics, ply: usize, prev: PrevMove) -> Self {
+        Self {
+            stage: Stage::HashMove,
+            hash_move,
+            killers: heuristics.killers(ply),
+            counter_move: heuristics.counter_move(prev),
+            tactical_only: false,
+            moves: Vec::new(),
+            bad_tactical: Vec::new(),
+            index: 0,
+        }
+    }
+
+    // for quiescence search: only captures and promotions that don't lose material
+    pub fn new_tactical(hash_move: Option<ChessMove>) -> Self {
+        Self {
+            stage: Stage::HashMove,
+            hash_move: hash_move.filter(|m| m.is_tactical()),
+            killers: [None; 2],
+            counter_move: None,
+            tactical_only: true,
+            moves: Vec::new(),
+            bad_tactical: Vec::new(),
+            index: 0,
+        }
+    }
+
+    // plain generation order, without any move ordering
+    pub fn unordered(game: &ChessGameState, kind: MoveGen) -> Self {
+        Self {
+            stage: Stage::Unordered,
+            hash_move: None,
+            killers: [None; 2],
+            counter_move: None,
+            tactical_only: kind == MoveGen::Tactical,
+            moves: game.get_moves(kind).into_iter().map(|m| (m, 0)).collect(),
+            bad_tactical: Vec::new(),
+            index: 0,
+        }
+    }
+
+    pub fn next(&mut self, game: &ChessGameState, heuristics: &Heuristics) -> Option<ChessMove> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GenTactical;
+                    if let Some(m) = self.hash_move {
+                        if game.is_pseudo_legal(m) {
+                            return Some(m);
+                        }
+                        self.hash_move = None;
+                    }
+                },
+                Stage::GenTactical => {
+                    self.moves.clear();
+                    for m in game.get_moves(MoveGen::Tactical) {
+                        if Some(m) == self.hash_move {
+                            continue;
+                        }
+                        let score = mvv_lva(game, m);
+                        if see(game, m) >= 0 {
+                            self.moves.push((m, score));
+                        } else {
+                            self.bad_tactical.push((m, score));
+                        }
+                    }
+                    self.index = 0;
+                    self.stage = Stage::GoodTactical;
+                },
+                Stage::GoodTactical => {
+                    if let Some(m) = self.pick_best() {
+                        return Some(m);
+                    }
+                    self.stage = if self.tactical_only { Stage::Done } else { Stage::FirstKiller };
+                },
+                Stage::FirstKiller => {
+                    self.stage = Stage::SecondKiller;
+                    if let Some(m) = self.killers[0] {
+                        if self.is_new_quiet(game, m) {
+                            return Some(m);
+                        }
+                        self.killers[0] = None;
+                    }
+                },
+                Stage::SecondKiller => {
+                    self.stage = Stage::CounterMove;
+                    if let Some(m) = self.killers[1] {
+                        if Some(m) != self.killers[0] && self.is_new_quiet(game, m) {
+                            return Some(m);
+                        }
+                        self.killers[1] = None;
+                    }
+                },
+                Stage::CounterMove => {
+                    self.stage = Stage::GenQuiet;
+                    if let Some(m) = self.counter_move {
+                        if !self.killers.contains(&Some(m)) && self.is_new_quiet(game, m) {
+                            return Some(m);
+                        }
+                        self.counter_move = None;
+                    }
+                },
+                Stage::GenQuiet => {
+                    self.moves.clear();
+                    for m in game.get_moves(MoveGen::Quiet) {
+                        if Some(m) == self.hash_move || self.killers.contains(&Some(m)) || Some(m) == self.counter_move {
+                            continue;
+                        }
+                        self.moves.push((m, heuristics.history(game, m)));
+                    }
+                    self.index = 0;
+                    self.stage = Stage::Quiet;
+                },
+                Stage::Quiet => {
+                    if let Some(m) = self.pick_best() {
+                        return Some(m);
+                    }
+                    self.moves = std::mem::take(&mut self.bad_tactical);
+                    self.index = 0;
+                    self.stage = Stage::BadTactical;
+                },
+                Stage::BadTactical => {
+                    if let Some(m) = self.pick_best() {
+                        return Some(m);
+                    }
+                    self.stage = Stage::Done;
+                },
+                Stage::Unordered => {
+                    if let Some((m, _)) = self.moves.get(self.index) {
+                        self.index += 1;
+                        return Some(*m);
+                    }
+                    self.stage = Stage::Done;
+                },
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    fn is_new_quiet(&self, game: &ChessGameState, chess_move: ChessMove) -> bool {
+        !chess_move.is_tactical() && Some(chess_move) != self.hash_move && game.is_pseudo_legal(chess_move)
+    }
+
+    // selection sort step: moves are usually only partially searched, so a full sort is wasted work
+    fn pick_best(&mut self) -> Option<ChessMove> {
+        if self.index >= self.moves.len() {
+            return None;
+        }
+        let mut best = self.index;
+        for i in self.index + 1..self.moves.len() {
+            if self.moves[i].1 > self.moves[best].1 {
+                best = i;
+            }
+        }
+        self.moves.swap(self.index, best);
+        self.index += 1;
+        Some(self.moves[self.index - 1].0)
+    }
+}
+
+// most valuable victim, least valuable attacker
+fn mvv_lva(game: &ChessGameState, chess_move: ChessMove) -> i32 {
+    let (from, to) = game.move_squares(chess_move);
+    let attacker = game.board().square_by_id(from).get_piece().map_or(0, |p| piece_value(p.get_name()));
+    let victim = match chess_move {
+        ChessMove::EnPassant(_, _) => piece_value(PieceName::Pawn),
+        _ => game.board().square_by_id(to).get_piece().map_or(0, |p| piece_value(p.get_name())),
+    };
+    let promotion = match chess_move {
+        ChessMove::Promotion(_, name) | ChessMove::CapturePromotion(_, _, name) => piece_value(name),
+        _ => 0,
+    };
+    (victim + promotion) * 16 - attacker / 100
+}
+
+// static exchange evaluation: the material balance after both sides keep recapturing on the
+// destination square with their least valuable piece, each side being free to stop.
+pub fn see(game: &ChessGameState, chess_move: ChessMove) -> i32 {
+    let (from, to) = game.move_squares(chess_move);
+    let mut pieces: [Option<ChessPiece>; 64] = std::array::from_fn(|i| game.board().square_by_id(i.into()).get_piece());
+    let Some(mover) = pieces[usize::from(from)] else {
+        return 0;
+    };
+    let mut gain = [0; 32];
+    gain[0] = match chess_move {
+        ChessMove::EnPassant(_, _) => {
+            pieces[usize::from(SquareID(to.file(), from.rank()))] = None;
+            piece_value(PieceName::Pawn)
+        },
+        _ => pieces[usize::from(to)].map_or(0, |p| piece_value(p.get_name())),
+    };
+    let mut on_square = piece_value(mover.get_name());
+    if let ChessMove::Promotion(_, name) | ChessMove::CapturePromotion(_, _, name) = chess_move {
+        gain[0] += piece_value(name) - piece_value(PieceName::Pawn);
+        on_square = piece_value(name);
+    }
+    pieces[usize::from(from)] = None;
+
+    let mut side = mover.get_owner().opponent();
+    let mut depth = 0;
+    while depth + 1 < gain.len() {
+        let Some((id, name)) = least_valuable_attacker(&pieces, to, side) else {
+            break;
+        };
+        if name == PieceName::King {
+            // the king may only recapture if the square is no longer defended
+            let mut without_king = pieces;
+            without_king[usize::from(id)] = None;
+            if least_valuable_attacker(&without_king, to, side.opponent()).is_some() {
+                break;
+            }
+        }
+        depth += 1;
+        gain[depth] = on_square - gain[depth - 1];
+        on_square = piece_value(name);
+        pieces[usize::from(id)] = None;
+        side = side.opponent();
+    }
+    while depth > 0 {
+        gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+        depth -= 1;
+    }
+    gain[0]
+}
+
+fn least_valuable_attacker(pieces: &[Option<ChessPiece>; 64], target: SquareID, player: Player) -> Option<(SquareID, PieceName)> {
+    let owned = |id: SquareID, names: &[PieceName]| {
+        pieces[usize::from(id)].is_some_and(|p| p.get_owner() == player && names.contains(&p.get_name()))
+    };
+    let mut best: Option<(SquareID, PieceName)> = None;
+    let mut consider = |id: SquareID, name: PieceName| {
+        if best.is_none_or(|(_, b)| piece_value(name) < piece_value(b)) {
+            best = Some((id, name));
+        }
+    };
+
+    // pawns attack diagonally forward, so look diagonally backward from the target
+    let back = match player {
+        Player::White => -1,
+        Player::Black => 1,
+    };
+    for offset in [SquareOffset(-1, back), SquareOffset(1, back)] {
+        if let Some(id) = target.add_offset(offset) && owned(id, &[PieceName::Pawn]) {
+            consider(id, PieceName::Pawn);
+        }
+    }
+    for offset in PieceName::knight_offsets() {
+        if let Some(id) = target.add_offset(offset) && owned(id, &[PieceName::Knight]) {
+            consider(id, PieceName::Knight);
+        }
+    }
+    for offset in PieceName::king_offsets() {
+        if let Some(id) = target.add_offset(offset) && owned(id, &[PieceName::King]) {
+            consider(id, PieceName::King);
+        }
+    }
+    let directions = [
+        (SquareOffset(-1, -1), [PieceName::Bishop, PieceName::Queen]),
+        (SquareOffset(-1, 1), [PieceName::Bishop, PieceName::Queen]),
+        (SquareOffset(1, -1), [PieceName::Bishop, PieceName::Queen]),
+        (SquareOffset(1, 1), [PieceName::Bishop, PieceName::Queen]),
+        (SquareOffset(-1, 0), [PieceName::Rook, PieceName::Queen]),
+        (SquareOffset(1, 0), [PieceName::Rook, PieceName::Queen]),
+        (SquareOffset(0, -1), [PieceName::Rook, PieceName::Queen]),
+        (SquareOffset(0, 1), [PieceName::Rook, PieceName::Queen]),
+    ];
+    for (step, sliders) in directions {
+        let mut current = target;
+        while let Some(id) = current.add_offset(step) {
+            if let Some(piece) = pieces[usize::from(id)] {
+                if owned(id, &sliders) {
+                    consider(id, piece.get_name());
+                }
+                break;
+            }
+            current = id;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess_engine::move_picker::{see, Heuristics, MovePicker};
+    use crate::chess_game::chess_move::ChessMove;
+    use crate::chess_game::chess_square::SquareID;
+    use crate::chess_game::{ChessGameState, MoveGen};
+
+    fn sq(name: &str) -> SquareID {
+        SquareID::parse(name).unwrap()
+    }
+
+    #[test]
+    fn see_values() {
+        // undefended pawn
+        let game = ChessGameState::from_fen("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1").unwrap();
+        assert_eq!(see(&game, ChessMove::Capture(sq("e1"), sq("e5"))), 100);
+
+        // the queen takes a defended pawn and is recaptured
+        let game = ChessGameState::from_fen("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1").unwrap();
+        assert_eq!(see(&game, ChessMove::Capture(sq("d3"), sq("e5"))), -220);
+
+        // x-ray: the rook behind the rook joins the exchange
+        let game = ChessGameState::from_fen("4k3/4r3/8/4p3/8/8/4R3/4RK2 w - - 0 1").unwrap();
+        assert_eq!(see(&game, ChessMove::Capture(sq("e2"), sq("e5"))), 100);
+    }
+
+    #[test]
+    fn picker_yields_every_move_once() {
+        let game = ChessGameState::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let heuristics = Heuristics::new();
+        let hash_move = ChessMove::Move(sq("a2"), sq("a3"));
+        let mut picker = MovePicker::new(Some(hash_move), &heuristics, 0, None);
+        let mut picked = Vec::new();
+        while let Some(m) = picker.next(&game, &heuristics) {
+            assert!(!picked.contains(&m));
+            picked.push(m);
+        }
+        assert_eq!(picked.len(), game.get_moves(MoveGen::All).len());
+        assert_eq!(picked[0], hash_move);
+
+        // the bishop trade comes before winning a pawn, and losing the queen for a knight comes after all quiet moves
+        let bxa6 = picked.iter().position(|m| *m == ChessMove::Capture(sq("e2"), sq("a6"))).unwrap();
+        let gxh3 = picked.iter().position(|m| *m == ChessMove::Capture(sq("g2"), sq("h3"))).unwrap();
+        let qxf6 = picked.iter().position(|m| *m == ChessMove::Capture(sq("f3"), sq("f6"))).unwrap();
+        let last_quiet = picked.iter().rposition(|m| !m.is_tactical()).unwrap();
+        assert!(bxa6 < gxh3);
+        assert!(gxh3 < last_quiet);
+        assert!(last_quiet < qxf6);
+    }
+
+    #[test]
+    fn killers_and_history() {
+        let game = ChessGameState::new();
+        let mut heuristics = Heuristics::new();
+        let nf3 = ChessMove::Move(sq("g1"), sq("f3"));
+        let d4 = ChessMove::Move(sq("d2"), sq("d4"));
+        heuristics.update_cutoff(&game, nf3, &[d4, nf3], 4, 2, None);
+        assert_eq!(heuristics.killers(2), [Some(nf3), None]);
+        assert!(heuristics.history(&game, nf3) > 0);
+        assert!(heuristics.history(&game, d4) < 0);
+
+        let mut picker = MovePicker::new(None, &heuristics, 2, None);
+        assert_eq!(picker.next(&game, &heuristics), Some(nf3));
+        // the next best quiet move by history isn't the penalized d4
+        assert_ne!(picker.next(&game, &heuristics), Some(d4));
+    }
+}
@@ -0,0 +1,413 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use game::rng::Rng;
+use crate::chess_game::chess_piece::{ChessPiece, PieceName};
+use crate::chess_game::chess_square::SquareID;
+use crate::chess_game::{ChessGameState, Player};
+
+// Weights file format, all numbers little endian:
+//   the magic "NNUE", then the format version as a u32 (1)
+//   the feature transformer size H, the number of dense layers N and the output size of each layer, all u32;
+//   the first layer takes 2 * H inputs and the last one has a single output
+//   the feature transformer: FEATURES * H f32 weights, one row of H per feature, then H f32 biases
+//   each dense layer: inputs * outputs f32 weights, one row of inputs per output, then its f32 biases
+// The features are HalfKP: for each side's perspective, the king square together with the square and kind of
+// every other piece. The transformer's output for the side to move comes first, and all activations except the
+// last one are clipped to [0, 1]. The output is the evaluation in pawns, for the side to move.
+const MAGIC: &[u8; 4] = b"NNUE";
+const VERSION: u32 = 1;
+// king square, 10 kinds of pieces (pawn to queen, own or opposing), piece square
+pub const FEATURES: usize = 64 * 10 * 64;
+// quantization: transformer outputs are 0 to 127 for activations of 0 to 1, and dense weights have 64 steps per unit
+const ACTIVATION_SCALE: i32 = 127;
+const WEIGHT_SCALE: i32 = 64;
+// the width of the partial sums in the integer loops, so compilers can turn them into vector instructions
+const LANES: usize = 16;
+
+#[derive(Debug)]
+pub enum NnueError {
+    Io(std::io::Error),
+    Format(&'static str),
+}
+
+impl Display for NnueError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NnueError::Io(err) => write!(f, "{}", err),
+            NnueError::Format(problem) => write!(f, "invalid network file: {}", problem),
+        }
+    }
+}
+
+impl From<std::io::Error> for NnueError {
+    fn from(err: std::io::Error) -> Self {
+        NnueError::Io(err)
+    }
+}
+
+fn orient(perspective: Player, sq: usize) -> usize {
+    match perspective {
+        Player::White => sq,
+        // black sees the board upside down
+        Player::Black => sq ^ 56,
+    }
+}
+
+// the input index of a piece other than a king, from one side's perspective
+pub fn feature_index(perspective: Player, king: SquareID, piece: ChessPiece, sq: SquareID) -> usize {
+    let kind = usize::from(piece.get_name()) * 2 + usize::from(piece.get_owner() != perspective);
+    (orient(perspective, usize::from(king)) * 10 + kind) * 64 + orient(perspective, usize::from(sq))
+}
+
+fn active_features(game: &ChessGameState, perspective: Player) -> Vec<usize> {
+    let king = game.board().get_king_sq(perspective).get_id();
+    game.board().iter()
+        .filter_map(|sq| sq.get_piece().filter(|p| p.get_name() != PieceName::King).map(|p| feature_index(perspective, king, p, sq.get_id())))
+        .collect()
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], NnueError> {
+        let slice = self.bytes.get(self.pos..self.pos + count).ok_or(NnueError::Format("truncated"))?;
+        self.pos += count;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<usize, NnueError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    fn f32s(&mut self, count: usize) -> Result<Vec<f32>, NnueError> {
+        Ok(self.take(count * 4)?.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FloatLayer {
+    pub inputs: usize,
+    pub outputs: usize,
+    pub weights: Vec<f32>,
+    pub biases: Vec<f32>,
+}
+
+// the network as stored, evaluated in floating point; the reference for the quantized network
+#[derive(Debug, Clone, PartialEq)]
+pub struct FloatNetwork {
+    pub hidden: usize,
+    pub transformer_weights: Vec<f32>,
+    pub transformer_biases: Vec<f32>,
+    pub layers: Vec<FloatLayer>,
+}
+
+impl FloatNetwork {
+    // small random weights, e.g. as a starting point for training
+    pub fn random(hidden: usize, layer_sizes: &[usize], rng: &mut Rng) -> Self {
+        let mut uniform = |count: usize, range: f32| -> Vec<f32> {
+            (0..count).map(|_| (rng.next_f64() as f32 * 2.0 - 1.0) * range).collect()
+        };
+        let transformer_weights = uniform(FEATURES * hidden, 0.1);
+        let transformer_biases = uniform(hidden, 0.5).into_iter().map(|b| b.abs()).collect();
+        let mut layers = Vec::new();
+        let mut inputs = 2 * hidden;
+        for outputs in layer_sizes.iter().copied().chain(std::iter::once(1)) {
+            let range = 1.0 / (inputs as f32).sqrt();
+            layers.push(FloatLayer { inputs, outputs, weights: uniform(inputs * outputs, range), biases: uniform(outputs, 0.1) });
+            inputs = outputs;
+        }
+        Self { hidden, transformer_weights, transformer_biases, layers }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, NnueError> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(4)? != MAGIC {
+            return Err(NnueError::Format("not a network file"));
+        }
+        if reader.u32()? != VERSION as usize {
+            return Err(NnueError::Format("unsupported version"));
+        }
+        let hidden = reader.u32()?;
+        let layer_count = reader.u32()?;
+        let sizes = (0..layer_count).map(|_| reader.u32()).collect::<Result<Vec<_>, _>>()?;
+        if hidden == 0 || sizes.last() != Some(&1) || sizes.contains(&0) {
+            return Err(NnueError::Format("invalid layer sizes"));
+        }
+        let transformer_weights = reader.f32s(FEATURES * hidden)?;
+        let transformer_biases = reader.f32s(hidden)?;
+        let mut layers = Vec::new();
+        let mut inputs = 2 * hidden;
+        for outputs in sizes {
+            let weights = reader.f32s(inputs * outputs)?;
+            let biases = reader.f32s(outputs)?;
+            layers.push(FloatLayer { inputs, outputs, weights, biases });
+            inputs = outputs;
+        }
+        if reader.pos != bytes.len() {
+            return Err(NnueError::Format("trailing data"));
+        }
+        Ok(Self { hidden, transformer_weights, transformer_biases, layers })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        let sizes = [VERSION, self.hidden as u32, self.layers.len() as u32].into_iter()
+            .chain(self.layers.iter().map(|layer| layer.outputs as u32));
+        sizes.for_each(|size| bytes.extend(size.to_le_bytes()));
+        let values = self.transformer_weights.iter().chain(&self.transformer_biases)
+            .chain(self.layers.iter().flat_map(|layer| layer.weights.iter().chain(&layer.biases)));
+        values.for_each(|value| bytes.extend(value.to_le_bytes()));
+        bytes
+    }
+
+    pub fn load(path: &Path) -> Result<Self, NnueError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), NnueError> {
+        Ok(std::fs::write(path, self.to_bytes())?)
+    }
+
+    // the evaluation in centipawns for the side to move, without any quantization
+    pub fn evaluate(&self, game: &ChessGameState) -> f32 {
+        let player = game.active_player();
+        let mut input = Vec::with_capacity(2 * self.hidden);
+        for perspective in [player, player.opponent()] {
+            let mut values = self.transformer_biases.clone();
+            for feature in active_features(game, perspective) {
+                let weights = &self.transformer_weights[feature * self.hidden..(feature + 1) * self.hidden];
+                values.iter_mut().zip(weights).for_each(|(v, w)| *v += w);
+            }
+            input.extend(values.into_iter().map(|v| v.clamp(0.0, 1.0)));
+        }
+        for (i, layer) in self.layers.iter().enumerate() {
+            let outputs = layer.weights.chunks_exact(layer.inputs).zip(&layer.biases)
+                .map(|(row, bias)| bias + row.iter().zip(&input).map(|(w, x)| w * x).sum::<f32>());
+            if i + 1 == self.layers.len() {
+                return outputs.sum::<f32>() * 100.0;
+            }
+            input = outputs.map(|v| v.clamp(0.0, 1.0)).collect();
+        }
+        0.0
+    }
+}
+
+struct Layer {
+    inputs: usize,
+    weights: Vec<i8>,
+    biases: Vec<i32>,
+}
+
+// the transformer outputs of both perspectives, indexed by player
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Accumulator {
+    values: [Vec<i16>; 2],
+}
+
+fn add_weights(values: &mut [i16], weights: &[i16]) {
+    values.iter_mut().zip(weights).for_each(|(v, w)| *v = v.wrapping_add(*w));
+}
+
+fn sub_weights(values: &mut [i16], weights: &[i16]) {
+    values.iter_mut().zip(weights).for_each(|(v, w)| *v = v.wrapping_sub(*w));
+}
+
+fn dot(weights: &[i8], input: &[u8]) -> i32 {
+    let mut sums = [0i32; LANES];
+    let mut weight_chunks = weights.chunks_exact(LANES);
+    let mut input_chunks = input.chunks_exact(LANES);
+    for (w, x) in weight_chunks.by_ref().zip(input_chunks.by_ref()) {
+        for lane in 0..LANES {
+            sums[lane] += w[lane] as i32 * x[lane] as i32;
+        }
+    }
+    let rest: i32 = weight_chunks.remainder().iter().zip(input_chunks.remainder()).map(|(w, x)| *w as i32 * *x as i32).sum();
+    sums.iter().sum::<i32>() + rest
+}
+
+// the quantized network used by the search, with integer arithmetic throughout
+pub struct Network {
+    hidden: usize,
+    transformer_weights: Vec<i16>,
+    transformer_biases: Vec<i16>,
+    layers: Vec<Layer>,
+}
+
+impl Network {
+    pub fn quantize(network: &FloatNetwork) -> Self {
+        let transformer = |values: &[f32]| -> Vec<i16> {
+            values.iter().map(|v| (v * ACTIVATION_SCALE as f32).round() as i16).collect()
+        };
+        let layers = network.layers.iter().map(|layer| Layer {
+            inputs: layer.inputs,
+            weights: layer.weights.iter().map(|w| (w * WEIGHT_SCALE as f32).round().clamp(-127.0, 127.0) as i8).collect(),
+            biases: layer.biases.iter().map(|b| (b * (ACTIVATION_SCALE * WEIGHT_SCALE) as f32).round() as i32).collect(),
+        }).collect();
+        Self {
+            hidden: network.hidden,
+            transformer_weights: transformer(&network.transformer_weights),
+            transformer_biases: transformer(&network.transformer_biases),
+            layers,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, NnueError> {
+        Ok(Self::quantize(&FloatNetwork::load(path)?))
+    }
+
+    fn feature_weights(&self, feature: usize) -> &[i16] {
+        &self.transformer_weights[feature * self.hidden..(feature + 1) * self.hidden]
+    }
+
+    fn refresh_perspective(&self, game: &ChessGameState, perspective: Player) -> Vec<i16> {
+        let mut values = self.transformer_biases.clone();
+        for feature in active_features(game, perspective) {
+            add_weights(&mut values, self.feature_weights(feature));
+        }
+        values
+    }
+
+    // the accumulator of a position, from scratch
+    pub fn refresh(&self, game: &ChessGameState) -> Accumulator {
+        Accumulator { values: [Player::White, Player::Black].map(|player| self.refresh_perspective(game, player)) }
+    }
+
+    // the accumulator after a move (or a null move), from the one before it: only the pieces that changed squares
+    // are updated, unless the perspective's own king moved
+    pub fn update(&self, previous: &Accumulator, before: &ChessGameState, after: &ChessGameState, accumulator: &mut Accumulator) {
+        for perspective in [Player::White, Player::Black] {
+            let side = usize::from(perspective);
+            let king = after.board().get_king_sq(perspective).get_id();
+            if before.board().get_king_sq(perspective).get_id() != king {
+                accumulator.values[side] = self.refresh_perspective(after, perspective);
+                continue;
+            }
+            accumulator.values[side].clone_from(&previous.values[side]);
+            for (old, new) in before.board().iter().zip(after.board().iter()) {
+                if old.get_piece() == new.get_piece() {
+                    continue;
+                }
+                if let Some(piece) = old.get_piece() && piece.get_name() != PieceName::King {
+                    sub_weights(&mut accumulator.values[side], self.feature_weights(feature_index(perspective, king, piece, old.get_id())));
+                }
+                if let Some(piece) = new.get_piece() && piece.get_name() != PieceName::King {
+                    add_weights(&mut accumulator.values[side], self.feature_weights(feature_index(perspective, king, piece, new.get_id())));
+                }
+            }
+        }
+    }
+
+    // the evaluation in centipawns for the side to move
+    pub fn evaluate(&self, accumulator: &Accumulator, player: Player) -> i32 {
+        let mut input: Vec<u8> = [player, player.opponent()].iter()
+            .flat_map(|p| accumulator.values[usize::from(*p)].iter())
+            .map(|v| (*v as i32).clamp(0, ACTIVATION_SCALE) as u8)
+            .collect();
+        for (i, layer) in self.layers.iter().enumerate() {
+            let outputs = layer.weights.chunks_exact(layer.inputs).zip(&layer.biases).map(|(row, bias)| bias + dot(row, &input));
+            if i + 1 == self.layers.len() {
+                return outputs.sum::<i32>() * 100 / (ACTIVATION_SCALE * WEIGHT_SCALE);
+            }
+            input = outputs.map(|v| (v / WEIGHT_SCALE).clamp(0, ACTIVATION_SCALE) as u8).collect();
+        }
+        0
+    }
+
+    pub fn evaluate_game(&self, game: &ChessGameState) -> i32 {
+        self.evaluate(&self.refresh(game), game.active_player())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use game::rng::Rng;
+    use crate::chess_engine::nnue::{dot, FloatNetwork, Network, NnueError};
+    use crate::chess_game::ChessGameState;
+
+    pub(crate) fn small_network(seed: u64) -> FloatNetwork {
+        FloatNetwork::random(8, &[8], &mut Rng::new(seed))
+    }
+
+    #[test]
+    fn file_format() {
+        let network = small_network(1);
+        let bytes = network.to_bytes();
+        assert_eq!(&bytes[..4], b"NNUE");
+        assert_eq!(FloatNetwork::from_bytes(&bytes).unwrap(), network);
+        assert!(matches!(FloatNetwork::from_bytes(&bytes[..bytes.len() - 1]), Err(NnueError::Format("truncated"))));
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = 2;
+        assert!(matches!(FloatNetwork::from_bytes(&wrong_version), Err(NnueError::Format("unsupported version"))));
+
+        let path = std::env::temp_dir().join(format!("chess_nnue_{}.bin", std::process::id()));
+        network.save(&path).unwrap();
+        assert_eq!(Network::load(&path).unwrap().evaluate_game(&ChessGameState::new()), Network::quantize(&network).evaluate_game(&ChessGameState::new()));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn integer_dot_product() {
+        let weights: Vec<i8> = (0..37).map(|i| (i * 7 % 255 - 127) as i8).collect();
+        let input: Vec<u8> = (0..37).map(|i| (i * 13 % 128) as u8).collect();
+        let expected: i32 = weights.iter().zip(&input).map(|(w, x)| *w as i32 * *x as i32).sum();
+        assert_eq!(dot(&weights, &input), expected);
+    }
+
+    #[test]
+    fn incremental_updates_match_refresh() {
+        let network = Network::quantize(&small_network(2));
+        let mut rng = Rng::new(5);
+        // castling, en passant and promotions all come up in random games from these
+        let starts = [
+            ChessGameState::new(),
+            ChessGameState::from_fen("r3k2r/1P4P1/8/3pP3/8/8/1p4p1/R3K2R w KQkq d6 0 1").unwrap(),
+        ];
+        for start in starts {
+            for _ in 0..10 {
+                let mut game = start;
+                let mut accumulator = network.refresh(&game);
+                let mut next = accumulator.clone();
+                for _ in 0..150 {
+                    let moves = game.get_legal_moves();
+                    if moves.is_empty() || game.result().is_some() {
+                        break;
+                    }
+                    let mut child = game;
+                    child.make_move(*moves.iter().nth(rng.below(moves.len())).unwrap());
+                    network.update(&accumulator, &game, &child, &mut next);
+                    assert_eq!(next, network.refresh(&child));
+                    std::mem::swap(&mut accumulator, &mut next);
+                    game = child;
+                }
+                let null = game.make_null_move();
+                network.update(&accumulator, &game, &null, &mut next);
+                assert_eq!(next, network.refresh(&null));
+            }
+        }
+    }
+
+    #[test]
+    fn quantized_matches_float() {
+        let float = small_network(3);
+        let network = Network::quantize(&float);
+        let mut rng = Rng::new(9);
+        let mut game = ChessGameState::new();
+        let mut distinct = std::collections::HashSet::new();
+        for _ in 0..40 {
+            let moves = game.get_legal_moves();
+            if moves.is_empty() {
+                break;
+            }
+            game.make_move(*moves.iter().nth(rng.below(moves.len())).unwrap());
+            let quantized = network.evaluate_game(&game);
+            let reference = float.evaluate(&game);
+            assert!((quantized as f32 - reference).abs() <= 3.0 + reference.abs() * 0.05, "{} vs {}", quantized, reference);
+            distinct.insert(quantized);
+        }
+        assert!(distinct.len() > 5);
+    }
+}
@@ -0,0 +1,781 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use crate::chess_game::chess_move::{AnnotatedMove, ChessMove};
+use crate::chess_game::chess_piece::{ChessPiece, PieceName};
+use crate::chess_game::chess_square::{SquareID, SquareOffset};
+use crate::chess_game::{ChessGameState, MoveGen, Player};
+
+const MAGIC: &[u8; 4] = b"DTM1";
+const MAX_PIECES: usize = 4;
+// the white king squares left after removing symmetries: the a1-d1-d4 triangle, or the a to d files with pawns
+const TRIANGLE: [usize; 10] = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+const PAWN_KING_SLOTS: usize = 32;
+
+// plies of a position being generated: positive when the side to move mates in that many, otherwise minus the
+// plies until it is mated
+const UNKNOWN: i16 = i16::MIN;
+const INVALID: i16 = i16::MIN + 1;
+const DRAW: i16 = i16::MIN + 2;
+
+// distance to mate in moves, for the side to move
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Dtm {
+    Win(u32),
+    // Loss(0) is checkmate
+    Loss(u32),
+    Draw,
+}
+
+impl Dtm {
+    fn from_plies(plies: i16) -> Self {
+        match plies {
+            UNKNOWN | INVALID | DRAW => Dtm::Draw,
+            p if p > 0 => Dtm::Win((p as u32).div_ceil(2)),
+            p => Dtm::Loss((-p) as u32 / 2),
+        }
+    }
+
+    fn plies(self) -> Option<i16> {
+        match self {
+            Dtm::Win(moves) => Some(2 * moves as i16 - 1),
+            Dtm::Loss(moves) => Some(-2 * moves as i16),
+            Dtm::Draw => None,
+        }
+    }
+
+    // one byte on disk: 0 for draws, 1 to 127 for wins and 128 and up for losses
+    fn from_byte(byte: u8) -> Self {
+        match byte {
+            0 => Dtm::Draw,
+            1..=127 => Dtm::Win(byte as u32),
+            _ => Dtm::Loss(byte as u32 - 128),
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Dtm::Win(moves) => moves.min(127) as u8,
+            Dtm::Loss(moves) => 128 + moves.min(127) as u8,
+            Dtm::Draw => 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DtmError {
+    Io(std::io::Error),
+    // a table name that isn't two to four pieces with a king each, e.g. KQK or KBNK
+    Material(String),
+    Format,
+}
+
+impl Display for DtmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DtmError::Io(err) => write!(f, "{}", err),
+            DtmError::Material(name) => write!(f, "not a supported material: {}", name),
+            DtmError::Format => write!(f, "not a DTM table"),
+        }
+    }
+}
+
+impl From<std::io::Error> for DtmError {
+    fn from(err: std::io::Error) -> Self {
+        DtmError::Io(err)
+    }
+}
+
+fn letter(name: PieceName) -> char {
+    match name {
+        PieceName::Pawn => 'P',
+        PieceName::Knight => 'N',
+        PieceName::Bishop => 'B',
+        PieceName::Rook => 'R',
+        PieceName::Queen => 'Q',
+        PieceName::King => 'K',
+    }
+}
+
+fn value(name: PieceName) -> u32 {
+    match name {
+        PieceName::Pawn => 1,
+        PieceName::Knight | PieceName::Bishop => 3,
+        PieceName::Rook => 5,
+        PieceName::Queen => 9,
+        PieceName::King => 0,
+    }
+}
+
+// the pieces of a table: the kings, then white's other pieces, then black's, strongest first
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Material {
+    pieces: Vec<(Player, PieceName)>,
+}
+
+impl Material {
+    fn new(white: Vec<PieceName>, black: Vec<PieceName>) -> Self {
+        let mut pieces = vec![(Player::White, PieceName::King), (Player::Black, PieceName::King)];
+        for (player, mut names) in [(Player::White, white), (Player::Black, black)] {
+            names.sort_by_key(|name| std::cmp::Reverse(usize::from(*name)));
+            pieces.extend(names.into_iter().map(|name| (player, name)));
+        }
+        Self { pieces }
+    }
+
+    // a name such as KQK, where the pieces may come in any order and either side first
+    fn parse(name: &str) -> Result<Self, DtmError> {
+        let error = || DtmError::Material(String::from(name));
+        let black_king = name.get(1..).and_then(|rest| rest.find('K')).ok_or_else(error)? + 1;
+        let (white, black) = name.split_at(black_king);
+        let names = |side: &str| -> Result<Vec<PieceName>, DtmError> {
+            side.chars().skip(1).map(|c| match c {
+                'Q' => Ok(PieceName::Queen),
+                'R' => Ok(PieceName::Rook),
+                'B' => Ok(PieceName::Bishop),
+                'N' => Ok(PieceName::Knight),
+                'P' => Ok(PieceName::Pawn),
+                _ => Err(error()),
+            }).collect()
+        };
+        if !white.starts_with('K') || name.len() > MAX_PIECES {
+            return Err(error());
+        }
+        Ok(Self::normalize(Self::new(names(white)?, names(black)?)).0)
+    }
+
+    // the material of a position, and whether the colors have to be swapped to have the stronger side as white
+    fn of(game: &ChessGameState) -> (Self, bool) {
+        let side = |player: Player| -> Vec<PieceName> {
+            game.board().iter()
+                .filter_map(|sq| sq.get_piece())
+                .filter(|p| p.get_owner() == player && p.get_name() != PieceName::King)
+                .map(|p| p.get_name())
+                .collect()
+        };
+        Self::normalize(Self::new(side(Player::White), side(Player::Black)))
+    }
+
+    // puts the stronger side first; of equally strong sides, the one with the stronger pieces, e.g. KBKN
+    fn normalize(material: Self) -> (Self, bool) {
+        let side = |player: Player| -> Vec<PieceName> {
+            material.pieces[2..].iter().filter(|(owner, _)| *owner == player).map(|(_, name)| *name).collect()
+        };
+        let (white, black) = (side(Player::White), side(Player::Black));
+        let strength = |names: &Vec<PieceName>| {
+            (names.iter().map(|n| value(*n)).sum::<u32>(), names.len(), names.iter().map(|n| usize::from(*n)).collect::<Vec<_>>())
+        };
+        if strength(&black) > strength(&white) {
+            (Self::new(black, white), true)
+        } else {
+            (Self::new(white, black), false)
+        }
+    }
+
+    fn name(&self) -> String {
+        let side = |player: Player| -> String {
+            self.pieces.iter().filter(|(owner, _)| *owner == player).map(|(_, name)| letter(*name)).collect()
+        };
+        side(Player::White) + &side(Player::Black)
+    }
+
+    fn has_pawns(&self) -> bool {
+        self.pieces.iter().any(|(_, name)| *name == PieceName::Pawn)
+    }
+
+    // a king and at most a knight or bishop can't mate a bare king
+    fn is_insufficient(&self) -> bool {
+        let others: Vec<PieceName> = self.pieces[2..].iter().map(|(_, name)| *name).collect();
+        others.is_empty() || (others.len() == 1 && matches!(others[0], PieceName::Knight | PieceName::Bishop))
+    }
+
+    fn king_slots(&self) -> usize {
+        if self.has_pawns() { PAWN_KING_SLOTS } else { TRIANGLE.len() }
+    }
+
+    // positions per side to move
+    fn size(&self) -> usize {
+        self.king_slots() * 64usize.pow(self.pieces.len() as u32 - 1)
+    }
+
+    // the symmetries of the board that keep the position the same: mirroring the files, and without pawns
+    // also the ranks and the diagonal
+    fn symmetries(&self) -> usize {
+        if self.has_pawns() { 2 } else { 8 }
+    }
+
+    // the lowest index of the symmetric positions, or None if the squares overlap
+    fn index(&self, squares: &[usize]) -> Option<usize> {
+        let mut best = None;
+        for symmetry in 0..self.symmetries() {
+            let king = transform(squares[0], symmetry);
+            let slot = if self.has_pawns() {
+                (king % 8 < 4).then(|| king / 8 * 4 + king % 8)
+            } else {
+                TRIANGLE.iter().position(|sq| *sq == king)
+            };
+            let Some(slot) = slot else {
+                continue;
+            };
+            let mut transformed: Vec<usize> = squares.iter().map(|sq| transform(*sq, symmetry)).collect();
+            // identical pieces are interchangeable, so they are kept in square order
+            let mut start = 1;
+            while start < transformed.len() {
+                let end = (start..transformed.len()).find(|i| self.pieces[*i] != self.pieces[start]).unwrap_or(transformed.len());
+                transformed[start..end].sort();
+                start = end;
+            }
+            let index = transformed[1..].iter().fold(slot, |index, sq| index * 64 + sq);
+            if best.is_none_or(|best| index < best) {
+                best = Some(index);
+            }
+        }
+        best
+    }
+
+    fn squares(&self, index: usize) -> Vec<usize> {
+        let mut squares = vec![0; self.pieces.len()];
+        let mut rest = index;
+        for sq in squares[1..].iter_mut().rev() {
+            *sq = rest % 64;
+            rest /= 64;
+        }
+        squares[0] = if self.has_pawns() { rest / 4 * 8 + rest % 4 } else { TRIANGLE[rest] };
+        squares
+    }
+
+    // the squares of the pieces of a position, in the order of the material
+    fn squares_of(&self, game: &ChessGameState, flip: bool) -> Vec<usize> {
+        let mut squares = Vec::with_capacity(self.pieces.len());
+        for (i, (player, name)) in self.pieces.iter().enumerate() {
+            if i > 0 && self.pieces[i - 1] == (*player, *name) {
+                continue;
+            }
+            let owner = if flip { player.opponent() } else { *player };
+            for sq in game.board().iter() {
+                if let Some(p) = sq.get_piece() && p.get_owner() == owner && p.get_name() == *name {
+                    let id = usize::from(sq.get_id());
+                    squares.push(if flip { id ^ 56 } else { id });
+                }
+            }
+        }
+        squares
+    }
+
+    // the position at an index, if it is the lowest of its symmetries and legal
+    fn position(&self, index: usize, side: Player) -> Option<ChessGameState> {
+        let squares = self.squares(index);
+        if self.index(&squares) != Some(index) {
+            return None;
+        }
+        self.build(&squares, side)
+    }
+
+    fn build(&self, squares: &[usize], side: Player) -> Option<ChessGameState> {
+        let mut pieces = [None; 64];
+        for (sq, (player, name)) in squares.iter().zip(&self.pieces) {
+            let rank = sq / 8;
+            if pieces[*sq].is_some() || (*name == PieceName::Pawn && (rank == 0 || rank == 7)) {
+                return None;
+            }
+            pieces[*sq] = Some(ChessPiece::new(*player, *name, true));
+        }
+        let game = ChessGameState::from_pieces(pieces, side).ok()?;
+        // the side that just moved can't be in check
+        let waiting = side.opponent();
+        game.board().get_king_sq(waiting).not_seen_by(side).then_some(game)
+    }
+}
+
+// symmetry bit 0 mirrors the files, bit 1 the ranks, and bit 2 swaps files and ranks
+fn transform(sq: usize, symmetry: usize) -> usize {
+    let mut sq = sq;
+    if symmetry & 1 != 0 {
+        sq ^= 7;
+    }
+    if symmetry & 2 != 0 {
+        sq ^= 56;
+    }
+    if symmetry & 4 != 0 {
+        sq = (sq % 8) * 8 + sq / 8;
+    }
+    sq
+}
+
+fn side_index(side: Player) -> usize {
+    usize::from(side)
+}
+
+// the legal moves of a position, with the positions they lead to
+fn successors(game: &ChessGameState) -> Vec<(ChessMove, ChessGameState)> {
+    game.get_moves(MoveGen::All).into_iter()
+        .filter_map(|m| game.make_pseudo_legal(m).map(|child| (m, child)))
+        .collect()
+}
+
+// the squares a piece could have come from without capturing, on an otherwise unchanged board
+fn unmove_origins(game: &ChessGameState, from: usize, player: Player, name: PieceName) -> Vec<usize> {
+    let empty = |sq: usize| game.board().square_by_id(sq.into()).get_piece().is_none();
+    let step = |sq: usize, offset: SquareOffset| SquareID::from(sq).add_offset(offset).map(usize::from);
+    let mut origins = Vec::new();
+    match name {
+        PieceName::Pawn => {
+            let (back, start_rank, double_rank) = match player {
+                Player::White => (SquareOffset(0, -1), 1, 3),
+                Player::Black => (SquareOffset(0, 1), 6, 4),
+            };
+            if let Some(one) = step(from, back) && empty(one) && one / 8 != 0 && one / 8 != 7 {
+                origins.push(one);
+                if from / 8 == double_rank && let Some(two) = step(one, back) && empty(two) && two / 8 == start_rank {
+                    origins.push(two);
+                }
+            }
+        },
+        PieceName::Knight | PieceName::King => {
+            let offsets = if name == PieceName::Knight { PieceName::knight_offsets() } else { PieceName::king_offsets() };
+            origins.extend(offsets.into_iter().filter_map(|offset| step(from, offset)).filter(|sq| empty(*sq)));
+        },
+        _ => {
+            let mut directions = Vec::new();
+            if name != PieceName::Bishop {
+                directions.extend([SquareOffset(1, 0), SquareOffset(-1, 0), SquareOffset(0, 1), SquareOffset(0, -1)]);
+            }
+            if name != PieceName::Rook {
+                directions.extend([SquareOffset(1, 1), SquareOffset(1, -1), SquareOffset(-1, 1), SquareOffset(-1, -1)]);
+            }
+            for direction in directions {
+                let mut sq = from;
+                while let Some(next) = step(sq, direction) && empty(next) {
+                    origins.push(next);
+                    sq = next;
+                }
+            }
+        },
+    }
+    origins
+}
+
+// distance to mate for every position of one material, for both sides to move
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DtmTable {
+    material: Material,
+    // indexed by side to move, then position
+    values: [Vec<u8>; 2],
+}
+
+impl DtmTable {
+    pub fn name(&self) -> String {
+        self.material.name()
+    }
+
+    // the longest win in moves, with each side to move
+    pub fn longest_win(&self) -> [u32; 2] {
+        self.values.each_ref().map(|values| values.iter()
+            .filter_map(|b| match Dtm::from_byte(*b) {
+                Dtm::Win(moves) => Some(moves),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0))
+    }
+
+    fn get(&self, squares: &[usize], side: Player) -> Dtm {
+        self.material.index(squares).map_or(Dtm::Draw, |index| Dtm::from_byte(self.values[side_index(side)][index]))
+    }
+
+    // the header, then each side's values as runs: the value and the run length as a LEB128 number
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let name = self.name();
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(name.len() as u8);
+        bytes.extend(name.as_bytes());
+        for values in &self.values {
+            let mut i = 0;
+            while i < values.len() {
+                let run = values[i..].iter().take_while(|v| **v == values[i]).count();
+                bytes.push(values[i]);
+                let mut length = run;
+                loop {
+                    let byte = (length & 0x7F) as u8;
+                    length >>= 7;
+                    if length == 0 {
+                        bytes.push(byte);
+                        break;
+                    }
+                    bytes.push(byte | 0x80);
+                }
+                i += run;
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DtmError> {
+        if bytes.get(..4) != Some(MAGIC.as_slice()) {
+            return Err(DtmError::Format);
+        }
+        let name_len = *bytes.get(4).ok_or(DtmError::Format)? as usize;
+        let name = bytes.get(5..5 + name_len).and_then(|b| std::str::from_utf8(b).ok()).ok_or(DtmError::Format)?;
+        let material = Material::parse(name)?;
+        let size = material.size();
+        let mut pos = 5 + name_len;
+        let mut values = [Vec::with_capacity(size), Vec::with_capacity(size)];
+        for side in values.iter_mut() {
+            while side.len() < size {
+                let value = *bytes.get(pos).ok_or(DtmError::Format)?;
+                pos += 1;
+                let mut run = 0;
+                let mut shift = 0;
+                loop {
+                    let byte = *bytes.get(pos).ok_or(DtmError::Format)?;
+                    pos += 1;
+                    run |= ((byte & 0x7F) as usize) << shift;
+                    shift += 7;
+                    if byte & 0x80 == 0 || shift > 28 {
+                        break;
+                    }
+                }
+                if side.len() + run > size {
+                    return Err(DtmError::Format);
+                }
+                side.resize(side.len() + run, value);
+            }
+        }
+        Ok(Self { material, values })
+    }
+}
+
+// a set of generated tables, which later tables use for captures and promotions
+#[derive(Debug, Default)]
+pub struct DtmTables {
+    tables: HashMap<String, DtmTable>,
+}
+
+impl DtmTables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn table(&self, name: &str) -> Option<&DtmTable> {
+        self.tables.get(name)
+    }
+
+    // generates a table such as KQK or KBNK, along with the tables it converts into
+    pub fn generate(&mut self, name: &str) -> Result<&DtmTable, DtmError> {
+        let material = Material::parse(name)?;
+        self.generate_material(&material);
+        Ok(&self.tables[&material.name()])
+    }
+
+    fn generate_material(&mut self, material: &Material) {
+        let name = material.name();
+        if self.tables.contains_key(&name) || material.is_insufficient() {
+            return;
+        }
+        // captures and promotions lead to other tables
+        for i in 2..material.pieces.len() {
+            let (player, piece) = material.pieces[i];
+            let mut captured = material.clone();
+            captured.pieces.remove(i);
+            let (captured, _) = Material::normalize(captured);
+            self.generate_material(&captured);
+            if piece == PieceName::Pawn {
+                for promoted in [PieceName::Queen, PieceName::Rook, PieceName::Bishop, PieceName::Knight] {
+                    let mut promotion = material.clone();
+                    promotion.pieces[i] = (player, promoted);
+                    let (promotion, _) = Material::normalize(promotion);
+                    self.generate_material(&promotion);
+                }
+            }
+        }
+        let table = Generator::new(material.clone(), self).run();
+        self.tables.insert(name, table);
+    }
+
+    // the distance to mate of a position, if its material has a table (or can't mate at all)
+    pub fn probe(&self, game: &ChessGameState) -> Option<Dtm> {
+        if game.castling_rights().contains(&true) || !game.variant().uses_standard_rules() {
+            return None;
+        }
+        let (material, flip) = Material::of(game);
+        if material.is_insufficient() {
+            return Some(Dtm::Draw);
+        }
+        let table = self.tables.get(&material.name())?;
+        let side = if flip { game.active_player().opponent() } else { game.active_player() };
+        Some(table.get(&material.squares_of(game, flip), side))
+    }
+
+    // a move that keeps the result of the position: the quickest mate, the slowest loss or a draw
+    pub fn best_move(&self, game: &ChessGameState) -> Option<(AnnotatedMove, Dtm)> {
+        let dtm = self.probe(game)?;
+        let score = |child: Dtm| match child {
+            Dtm::Loss(moves) => 1000 - moves as i32,
+            Dtm::Draw => 0,
+            Dtm::Win(moves) => moves as i32 - 1000,
+        };
+        let best = game.get_legal_moves().iter()
+            .filter_map(|m| {
+                let mut child = *game;
+                child.make_move(*m);
+                Some((*m, score(self.probe(&child)?)))
+            })
+            .max_by_key(|(_, score)| *score)?;
+        Some((best.0, dtm))
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), DtmError> {
+        for (name, table) in &self.tables {
+            std::fs::write(dir.join(format!("{}.dtm", name)), table.to_bytes())?;
+        }
+        Ok(())
+    }
+
+    // reads every .dtm file in a directory
+    pub fn load(dir: &Path) -> Result<Self, DtmError> {
+        let mut tables = Self::new();
+        for file in std::fs::read_dir(dir)? {
+            let path = file?.path();
+            if path.extension().is_some_and(|e| e == "dtm") {
+                let table = DtmTable::from_bytes(&std::fs::read(path)?)?;
+                tables.tables.insert(table.name(), table);
+            }
+        }
+        Ok(tables)
+    }
+}
+
+// retrograde analysis: starting from the mates, each ply finds the positions that can reach a lost position
+// (which are won), and the positions where every move reaches a won one (which are lost)
+struct Generator<'a> {
+    material: Material,
+    tables: &'a DtmTables,
+    plies: [Vec<i16>; 2],
+    // positions waiting to be resolved, by ply
+    queue: Vec<Vec<(Player, usize, i16)>>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(material: Material, tables: &'a DtmTables) -> Self {
+        let size = material.size();
+        Self { material, tables, plies: [vec![UNKNOWN; size], vec![UNKNOWN; size]], queue: Vec::new() }
+    }
+
+    fn schedule(&mut self, side: Player, index: usize, plies: i16) {
+        let ply = plies.unsigned_abs() as usize;
+        if self.queue.len() <= ply {
+            self.queue.resize_with(ply + 1, Vec::new);
+        }
+        self.queue[ply].push((side, index, plies));
+    }
+
+    // the plies of a position reached by a move, from the point of view of its side to move
+    fn child_plies(&self, child: &ChessGameState) -> i16 {
+        let (material, flip) = Material::of(child);
+        if material == self.material && !flip {
+            let index = self.material.index(&self.material.squares_of(child, false)).expect("a legal position");
+            return self.plies[side_index(child.active_player())][index];
+        }
+        self.tables.probe(child).and_then(Dtm::plies).unwrap_or(DRAW)
+    }
+
+    fn is_conversion(&self, child: &ChessGameState) -> bool {
+        let (material, flip) = Material::of(child);
+        material != self.material || flip
+    }
+
+    fn run(mut self) -> DtmTable {
+        for side in [Player::White, Player::Black] {
+            for index in 0..self.material.size() {
+                let Some(game) = self.material.position(index, side) else {
+                    self.plies[side_index(side)][index] = INVALID;
+                    continue;
+                };
+                self.initialize(&game, side, index);
+            }
+        }
+
+        let mut ply = 0;
+        while ply < self.queue.len() {
+            let entries = std::mem::take(&mut self.queue[ply]);
+            for (side, index, plies) in entries {
+                if self.plies[side_index(side)][index] != UNKNOWN {
+                    continue;
+                }
+                self.plies[side_index(side)][index] = plies;
+                let game = self.material.position(index, side).expect("only legal positions are queued");
+                self.retract(&game, plies);
+            }
+            ply += 1;
+        }
+
+        let values = self.plies.map(|plies| plies.into_iter().map(|p| Dtm::from_plies(p).to_byte()).collect());
+        DtmTable { material: self.material, values }
+    }
+
+    // mates, stalemates, and the results of captures and promotions
+    fn initialize(&mut self, game: &ChessGameState, side: Player, index: usize) {
+        let moves = successors(game);
+        if moves.is_empty() {
+            if game.in_check() {
+                self.schedule(side, index, 0);
+            } else {
+                self.plies[side_index(side)][index] = DRAW;
+            }
+            return;
+        }
+        let mut quickest_win = None;
+        let mut slowest_loss = Some(0);
+        for (_, child) in &moves {
+            if !self.is_conversion(child) {
+                slowest_loss = None;
+                continue;
+            }
+            match self.child_plies(child) {
+                p if p != DRAW && p <= 0 => quickest_win = Some(quickest_win.map_or(1 - p, |w: i16| w.min(1 - p))),
+                p if p != DRAW => slowest_loss = slowest_loss.map(|l: i16| l.max(p + 1)),
+                _ => slowest_loss = None,
+            }
+        }
+        if let Some(win) = quickest_win {
+            self.schedule(side, index, win);
+        } else if let Some(loss) = slowest_loss {
+            self.schedule(side, index, -loss);
+        } else if moves.iter().all(|(_, child)| self.is_conversion(child)) {
+            self.plies[side_index(side)][index] = DRAW;
+        }
+    }
+
+    // finds the positions that lead here with the other side to move
+    fn retract(&mut self, game: &ChessGameState, plies: i16) {
+        let side = game.active_player();
+        let mover = side.opponent();
+        let squares = self.material.squares_of(game, false);
+        for (i, (player, name)) in self.material.pieces.clone().into_iter().enumerate() {
+            if player != mover {
+                continue;
+            }
+            for origin in unmove_origins(game, squares[i], player, name) {
+                let mut previous = squares.clone();
+                previous[i] = origin;
+                let Some(index) = self.material.index(&previous) else {
+                    continue;
+                };
+                if self.plies[side_index(mover)][index] != UNKNOWN {
+                    continue;
+                }
+                let Some(predecessor) = self.material.build(&previous, mover) else {
+                    continue;
+                };
+                if plies <= 0 {
+                    // a move to a lost position wins
+                    self.schedule(mover, index, 1 - plies);
+                } else if let Some(loss) = self.all_moves_lose(&predecessor) {
+                    self.schedule(mover, index, -loss);
+                }
+            }
+        }
+    }
+
+    // the plies to being mated, if every move leads to a won position for the opponent
+    fn all_moves_lose(&self, game: &ChessGameState) -> Option<i16> {
+        let mut slowest = 0;
+        for (_, child) in successors(game) {
+            let plies = self.child_plies(&child);
+            if plies == UNKNOWN || plies == DRAW || plies <= 0 {
+                return None;
+            }
+            slowest = slowest.max(plies + 1);
+        }
+        Some(slowest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess_engine::retrograde::{Dtm, DtmTable, DtmTables, Material};
+    use crate::chess_game::ChessGameState;
+
+    fn game(fen: &str) -> ChessGameState {
+        ChessGameState::from_fen(fen).unwrap()
+    }
+
+    #[test]
+    fn indexing() {
+        let material = Material::parse("KBNK").unwrap();
+        assert_eq!(material.name(), "KBNK");
+        assert_eq!(material.size(), 10 * 64 * 64 * 64);
+        assert!(Material::parse("KQKRN").is_err());
+        assert!(Material::parse("QK").is_err());
+        // stored under one name, with the stronger side first
+        assert_eq!(Material::parse("KKQ").unwrap().name(), "KQK");
+        assert_eq!(Material::parse("KNRK").unwrap().name(), "KRNK");
+        assert_eq!(Material::parse("KNKB").unwrap(), Material::parse("KBKN").unwrap());
+        let (material, flip) = Material::of(&game("8/8/8/8/8/2k1b3/8/1N5K w - - 0 1"));
+        assert_eq!(material.name(), "KBKN");
+        assert!(flip);
+        assert_eq!(Material::parse("KPK").unwrap().size(), 32 * 64 * 64);
+
+        // mirrored positions share an index
+        let kqk = Material::parse("KQK").unwrap();
+        let a = kqk.index(&[1, 40, 35]).unwrap();
+        let b = kqk.index(&[6, 47, 36]).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(kqk.squares(a).len(), 3);
+        assert_eq!(kqk.index(&kqk.squares(a)), Some(a));
+
+        let (material, flip) = Material::of(&game("8/8/8/8/8/2K5/8/1q5k w - - 0 1"));
+        assert_eq!(material.name(), "KQK");
+        assert!(flip);
+    }
+
+    #[test]
+    fn known_maximum_distances() {
+        let mut tables = DtmTables::new();
+        assert_eq!(tables.generate("KQK").unwrap().longest_win()[0], 10);
+        assert_eq!(tables.generate("KRK").unwrap().longest_win()[0], 16);
+
+        assert_eq!(tables.probe(&game("k7/8/1K6/8/8/8/8/7R w - - 0 1")), Some(Dtm::Win(1)));
+        assert_eq!(tables.probe(&game("R6k/8/6K1/8/8/8/8/8 b - - 0 1")), Some(Dtm::Loss(0)));
+        // a stalemate, and a rook that is lost
+        assert_eq!(tables.probe(&game("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1")), Some(Dtm::Draw));
+        assert!(matches!(tables.probe(&game("8/8/8/8/8/2k5/1r6/7K b - - 0 1")), Some(Dtm::Win(_))));
+        assert_eq!(tables.probe(&game("8/8/8/8/8/2k5/1R6/7K b - - 0 1")), Some(Dtm::Draw));
+
+        let mate = game("k7/8/1K6/8/8/8/8/7R w - - 0 1");
+        let (best, dtm) = tables.best_move(&mate).unwrap();
+        assert_eq!(mate.move_to_uci(best.chess_move), "h1h8");
+        assert_eq!(dtm, Dtm::Win(1));
+    }
+
+    #[test]
+    #[ignore = "slow: KBNK takes minutes to generate"]
+    fn bishop_and_knight_mate() {
+        let mut tables = DtmTables::new();
+        assert_eq!(tables.generate("KBNK").unwrap().longest_win()[0], 33);
+        assert!(matches!(tables.probe(&game("8/8/8/3k4/8/8/8/KBN5 w - - 0 1")), Some(Dtm::Win(_))));
+    }
+
+    #[test]
+    fn pawn_endings() {
+        let mut tables = DtmTables::new();
+        tables.generate("KPK").unwrap();
+        assert!(tables.table("KQK").is_some());
+        // the opposition decides
+        assert_eq!(tables.probe(&game("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1")), Some(Dtm::Draw));
+        assert!(matches!(tables.probe(&game("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1")), Some(Dtm::Loss(_))));
+        // the pawn runs
+        assert!(matches!(tables.probe(&game("8/8/8/P7/8/8/8/K6k w - - 0 1")), Some(Dtm::Win(_))));
+
+        let dir = std::env::temp_dir().join(format!("chess_dtm_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        tables.save(&dir).unwrap();
+        let loaded = DtmTables::load(&dir).unwrap();
+        assert_eq!(loaded.table("KPK"), tables.table("KPK"));
+        let bytes = tables.table("KPK").unwrap().to_bytes();
+        assert!(bytes.len() < 2 * 32 * 64 * 64);
+        assert_eq!(DtmTable::from_bytes(&bytes).as_ref().ok(), tables.table("KPK"));
+        assert!(DtmTable::from_bytes(&bytes[..bytes.len() / 2]).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
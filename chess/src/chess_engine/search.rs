@@ -0,0 +1,1071 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::chess_engine::evaluation::{evaluate_with, EvalParams, DEFAULT_PARAMS};
+use crate::chess_engine::nnue::{Accumulator, Network};
+use crate::chess_engine::syzygy::Tablebase;
+use crate::chess_engine::move_picker::{Heuristics, MovePicker, PrevMove, MAX_PLY};
+use crate::chess_engine::time_manager::{GameTime, TimeManager};
+use crate::chess_game::game_clock::{Clock, SystemClock};
+use crate::chess_engine::transposition::{Bound, TTEntry, TranspositionTable};
+use crate::chess_game::chess_move::{AnnotatedMove, ChessMove};
+use crate::chess_game::chess_piece::PieceName;
+use crate::chess_game::{ChessGameState, GameResult, MoveGen, Player};
+
+pub const INFINITY: i32 = 32_000;
+// a mate in n plies scores MATE_SCORE - n
+pub const MATE_SCORE: i32 = 30_000;
+const MATE_BOUND: i32 = MATE_SCORE - MAX_PLY as i32;
+
+const ASPIRATION_WINDOW: i32 = 25;
+// indexed by depth
+const FUTILITY_MARGINS: [i32; 3] = [0, 150, 300];
+const REVERSE_FUTILITY_MARGIN: i32 = 120;
+const REVERSE_FUTILITY_DEPTH: i32 = 3;
+const NULL_MOVE_DEPTH: i32 = 3;
+const LMR_DEPTH: i32 = 3;
+// the number of moves searched at full depth before reductions start
+const LMR_MOVES: usize = 3;
+// depth staggering for the helper threads
+const SKIP_SIZE: [i32; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [i32; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+// every technique can be switched off, so its contribution can be measured in self-play
+#[derive(Debug, Copy, Clone)]
+pub struct SearchConfig {
+    pub move_ordering: bool,
+    // let the opponent move twice; if we are still above beta the node is very likely a cutoff
+    pub null_move_pruning: bool,
+    // search late quiet moves to a reduced depth first
+    pub late_move_reductions: bool,
+    // skip quiet moves near the leaves that can't raise the static evaluation to alpha
+    pub futility_pruning: bool,
+    // cut nodes near the leaves whose static evaluation is far above beta
+    pub reverse_futility_pruning: bool,
+    pub check_extensions: bool,
+    // search with a narrow window around the previous iteration's score
+    pub aspiration_windows: bool,
+    pub hash_size_mb: usize,
+    pub threads: usize,
+    // time kept in reserve for communicating with the GUI
+    pub move_overhead: Duration,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            move_ordering: true,
+            null_move_pruning: true,
+            late_move_reductions: true,
+            futility_pruning: true,
+            reverse_futility_pruning: true,
+            check_extensions: true,
+            aspiration_windows: true,
+            hash_size_mb: 16,
+            threads: 1,
+            move_overhead: Duration::from_millis(50),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SearchLimits {
+    pub depth: Option<i32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+    // the time manager decides how much of the clock to use
+    pub game_time: Option<GameTime>,
+    // keep searching until stopped, e.g. for analysis
+    pub infinite: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub best_move: Option<ChessMove>,
+    // centipawns from the point of view of the active player
+    pub score: i32,
+    pub depth: i32,
+    pub nodes: u64,
+    pub time: Duration,
+    pub pv: Vec<ChessMove>,
+    // the rank of this line in a multi-pv search, starting at 1
+    pub multipv: usize,
+}
+
+// called with the result of every completed iteration
+pub type InfoCallback = Box<dyn FnMut(&SearchResult) + Send>;
+
+// lets other threads stop a running search, or tell it that pondering is over
+#[derive(Debug, Default)]
+pub struct SearchControl {
+    stop: AtomicBool,
+    pondering: AtomicBool,
+}
+
+impl SearchControl {
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    pub fn set_pondering(&self, pondering: bool) {
+        self.pondering.store(pondering, Ordering::Relaxed);
+    }
+
+    // while pondering the search ignores its time limit
+    pub fn is_pondering(&self) -> bool {
+        self.pondering.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.stop.store(false, Ordering::Relaxed);
+        self.pondering.store(false, Ordering::Relaxed);
+    }
+}
+
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() >= MATE_BOUND
+}
+
+// mate scores are stored relative to the node instead of the root, so they stay valid at other plies
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+// the score of a finished game for the player to move, which like a mate prefers the quickest win
+fn result_score(game: &ChessGameState, result: GameResult, ply: usize) -> i32 {
+    match (result, game.active_player()) {
+        (GameResult::Draw, _) => 0,
+        (GameResult::WhiteWin, Player::White) | (GameResult::BlackWin, Player::Black) => MATE_SCORE - ply as i32,
+        _ => -MATE_SCORE + ply as i32,
+    }
+}
+
+// null move pruning is only safe when the side to move has pieces besides king and pawns
+fn has_non_pawn_material(game: &ChessGameState) -> bool {
+    game.board().iter().any(|sq| sq.get_piece().is_some_and(|p| {
+        p.get_owner() == game.active_player() && p.get_name() != PieceName::Pawn && p.get_name() != PieceName::King
+    }))
+}
+
+// late moves are reduced more the deeper the search and the later they come in the move order
+fn lmr_reduction(depth: i32, move_index: usize) -> i32 {
+    (0.75 + (depth as f64).ln() * (move_index as f64).ln() / 2.25) as i32
+}
+
+// iterative deepening alpha-beta (principal variation) search with quiescence, on one or more threads
+pub struct Searcher {
+    config: SearchConfig,
+    tt: TranspositionTable,
+    // one set per thread
+    heuristics: Vec<Heuristics>,
+    control: Arc<SearchControl>,
+    info_callback: Option<InfoCallback>,
+    time: TimeManager,
+    // hashes of the positions played before the root, for repetition detection
+    game_history: Vec<u64>,
+    tablebase: Option<Arc<Tablebase>>,
+    // evaluates positions in place of the handcrafted evaluation
+    network: Option<Arc<Network>>,
+    // weights for the handcrafted evaluation in place of the defaults, e.g. from the tuner
+    params: Option<Arc<EvalParams>>,
+}
+
+impl Searcher {
+    pub fn new(config: SearchConfig) -> Self {
+        Self {
+            config,
+            tt: TranspositionTable::new(config.hash_size_mb),
+            heuristics: (0..config.threads.max(1)).map(|_| Heuristics::new()).collect(),
+            control: Arc::new(SearchControl::default()),
+            info_callback: None,
+            time: TimeManager::new(Box::new(SystemClock::new())),
+            game_history: Vec::new(),
+            tablebase: None,
+            network: None,
+            params: None,
+        }
+    }
+
+    pub fn config(&self) -> &SearchConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: SearchConfig) {
+        if config.hash_size_mb != self.config.hash_size_mb {
+            self.tt = TranspositionTable::new(config.hash_size_mb);
+        }
+        self.heuristics.resize_with(config.threads.max(1), Heuristics::new);
+        self.config = config;
+    }
+
+    pub fn clear_hash(&mut self) {
+        self.tt.clear();
+    }
+
+    pub fn control(&self) -> Arc<SearchControl> {
+        self.control.clone()
+    }
+
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.time.set_clock(clock);
+    }
+
+    pub fn set_info_callback(&mut self, callback: Option<InfoCallback>) {
+        self.info_callback = callback;
+    }
+
+    pub fn new_game(&mut self) {
+        self.tt.clear();
+        self.heuristics.iter_mut().for_each(Heuristics::clear);
+        self.game_history.clear();
+    }
+
+    pub fn set_game_history(&mut self, hashes: Vec<u64>) {
+        self.game_history = hashes;
+    }
+
+    pub fn set_tablebase(&mut self, tablebase: Option<Arc<Tablebase>>) {
+        self.tablebase = tablebase;
+    }
+
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.network = network;
+    }
+
+    pub fn set_params(&mut self, params: Option<Arc<EvalParams>>) {
+        self.params = params;
+    }
+
+    pub fn search(&mut self, game: &ChessGameState, limits: SearchLimits) -> SearchResult {
+        self.search_lines(game, limits, 1).remove(0)
+    }
+
+    // the best `count` distinct root moves, each with its own score and principal variation
+    pub fn search_multipv(&mut self, game: &ChessGameState, limits: SearchLimits, count: usize) -> Vec<(AnnotatedMove, i32, Vec<ChessMove>)> {
+        self.search_lines(game, limits, count).into_iter()
+            .filter_map(|line| {
+                let best_move = game.annotate_move(line.best_move?)?;
+                Some((best_move, line.score, line.pv))
+            })
+            .collect()
+    }
+
+    // ranked results for the best `count` root moves; there is always at least one, even without legal moves
+    pub fn search_lines(&mut self, game: &ChessGameState, limits: SearchLimits, count: usize) -> Vec<SearchResult> {
+        // in the tablebases, only the moves that keep the best outcome are searched
+        let tablebase_moves: Option<Vec<ChessMove>> = self.tablebase.as_ref()
+            .and_then(|tb| tb.best_moves(game))
+            .map(|moves| moves.iter().map(|m| m.chess_move).collect());
+        let root_moves: Vec<ChessMove> = match &tablebase_moves {
+            Some(moves) => moves.clone(),
+            None => game.get_legal_moves().iter().map(|m| m.chess_move).collect(),
+        };
+        self.time.start(limits.movetime, limits.game_time, self.config.move_overhead);
+        if root_moves.len() == 1 {
+            self.time.set_forced();
+        }
+        let count = count.clamp(1, root_moves.len().max(1));
+        let game = *game;
+        let done = AtomicBool::new(false);
+        let node_counts: Vec<AtomicU64> = self.heuristics.iter().map(|_| AtomicU64::new(0)).collect();
+        let shared = Shared {
+            config: self.config,
+            limits,
+            tt: &self.tt,
+            control: &self.control,
+            done: &done,
+            game_history: &self.game_history,
+            node_counts: &node_counts,
+            root_moves: tablebase_moves.as_deref(),
+            network: self.network.as_deref(),
+            params: self.params.as_deref().unwrap_or(&DEFAULT_PARAMS),
+        };
+        let (main_heuristics, helper_heuristics) = self.heuristics.split_first_mut().expect("at least one thread");
+
+        // lazy SMP: the helpers search the same position, sharing what they find through the transposition table
+        let (mut lines, helper_results) = std::thread::scope(|scope| {
+            let helpers: Vec<_> = helper_heuristics.iter_mut().enumerate().map(|(i, heuristics)| {
+                let mut helper = Worker::new(i + 1, shared, heuristics, None);
+                scope.spawn(move || helper.iterate(&game, 1, None).remove(0))
+            }).collect();
+            let mut main = Worker::new(0, shared, main_heuristics, Some(&mut self.time));
+            let lines = main.iterate(&game, count, self.info_callback.as_mut());
+            done.store(true, Ordering::Relaxed);
+            let helper_results: Vec<SearchResult> = helpers.into_iter().filter_map(|h| h.join().ok()).collect();
+            (lines, helper_results)
+        });
+
+        // with a single line, vote for the deepest completed iteration, then the best score; the main thread wins ties
+        if count == 1 {
+            let main_result = lines.remove(0);
+            lines.push(helper_results.into_iter()
+                .filter(|r| r.best_move.is_some())
+                .fold(main_result, |best, r| if (r.depth, r.score) > (best.depth, best.score) { r } else { best }));
+        }
+        let nodes = node_counts.iter().map(|n| n.load(Ordering::Relaxed)).sum();
+        let time = self.time.elapsed();
+        for line in lines.iter_mut() {
+            line.nodes = nodes;
+            line.time = time;
+        }
+        lines
+    }
+}
+
+// what the threads of one search share
+#[derive(Copy, Clone)]
+struct Shared<'a> {
+    config: SearchConfig,
+    limits: SearchLimits,
+    tt: &'a TranspositionTable,
+    control: &'a SearchControl,
+    // set by the main thread when it has finished, to stop the helpers
+    done: &'a AtomicBool,
+    game_history: &'a [u64],
+    // the nodes searched by each thread
+    node_counts: &'a [AtomicU64],
+    // the root moves to search, when not all of them
+    root_moves: Option<&'a [ChessMove]>,
+    network: Option<&'a Network>,
+    params: &'a EvalParams,
+}
+
+// the state of one search thread
+struct Worker<'a> {
+    id: usize,
+    config: SearchConfig,
+    limits: SearchLimits,
+    tt: &'a TranspositionTable,
+    heuristics: &'a mut Heuristics,
+    control: &'a SearchControl,
+    done: &'a AtomicBool,
+    game_history: &'a [u64],
+    node_counts: &'a [AtomicU64],
+    root_moves: Option<&'a [ChessMove]>,
+    network: Option<&'a Network>,
+    params: &'a EvalParams,
+    // the network's accumulators of the positions on the current path, indexed by ply
+    accumulators: Vec<Accumulator>,
+    // only the main thread keeps time
+    time: Option<&'a mut TimeManager>,
+    pondering: bool,
+    nodes: u64,
+    stopped: bool,
+    // root moves left out to find the next best line
+    excluded: Vec<ChessMove>,
+    path: Vec<u64>,
+    pv: Vec<Vec<ChessMove>>,
+}
+
+impl<'a> Worker<'a> {
+    fn new(id: usize, shared: Shared<'a>, heuristics: &'a mut Heuristics, time: Option<&'a mut TimeManager>) -> Self {
+        Self {
+            id,
+            config: shared.config,
+            limits: shared.limits,
+            tt: shared.tt,
+            heuristics,
+            control: shared.control,
+            done: shared.done,
+            game_history: shared.game_history,
+            node_counts: shared.node_counts,
+            root_moves: shared.root_moves,
+            network: shared.network,
+            params: shared.params,
+            accumulators: Vec::new(),
+            time,
+            pondering: shared.control.is_pondering(),
+            nodes: 0,
+            stopped: false,
+            excluded: Vec::new(),
+            path: Vec::new(),
+            pv: vec![Vec::new(); MAX_PLY + 1],
+        }
+    }
+
+    // helpers skip some depths, so that the threads don't all search the same depth at the same time
+    fn skip_depth(&self, depth: i32) -> bool {
+        if self.id == 0 {
+            return false;
+        }
+        let i = (self.id - 1) % SKIP_SIZE.len();
+        (depth + SKIP_PHASE[i]) / SKIP_SIZE[i] % 2 != 0
+    }
+
+    fn total_nodes(&self) -> u64 {
+        self.node_counts.iter().enumerate()
+            .map(|(i, n)| if i == self.id { self.nodes } else { n.load(Ordering::Relaxed) })
+            .sum()
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.time.as_ref().map_or(Duration::ZERO, |time| time.elapsed())
+    }
+
+    // iterative deepening, searching the best `count` root moves at each depth by excluding the lines already found
+    fn iterate(&mut self, game: &ChessGameState, count: usize, mut info_callback: Option<&mut InfoCallback>) -> Vec<SearchResult> {
+        self.heuristics.age();
+        // the network was trained on standard chess, and can't judge positions where a king may be missing
+        if !game.variant().uses_standard_rules() {
+            self.network = None;
+        }
+        if let Some(network) = self.network {
+            self.accumulators = vec![network.refresh(game); MAX_PLY + 2];
+        }
+        let empty = SearchResult { best_move: None, score: 0, depth: 0, nodes: 0, time: Duration::ZERO, pv: Vec::new(), multipv: 1 };
+        let mut lines = vec![empty];
+
+        let max_depth = self.limits.depth.unwrap_or(MAX_PLY as i32 - 1).clamp(1, MAX_PLY as i32 - 1);
+        for depth in 1..=max_depth {
+            if depth > 1 && self.skip_depth(depth) {
+                continue;
+            }
+            self.excluded.clear();
+            let mut new_lines = Vec::new();
+            while new_lines.len() < count {
+                let previous = lines.get(new_lines.len()).map_or(0, |line| line.score);
+                let score = self.aspiration_search(game, depth, previous);
+                // a partial iteration can't be trusted, unless it is all we have
+                if self.stopped && (lines[0].best_move.is_some() || !new_lines.is_empty()) {
+                    break;
+                }
+                new_lines.push(SearchResult {
+                    best_move: self.pv[0].first().copied(),
+                    score,
+                    depth,
+                    nodes: self.total_nodes(),
+                    time: self.elapsed(),
+                    pv: self.pv[0].clone(),
+                    multipv: new_lines.len() + 1,
+                });
+                // without legal moves there is only the score
+                if self.stopped || self.pv[0].is_empty() {
+                    break;
+                }
+                self.excluded.push(self.pv[0][0]);
+            }
+            if self.stopped && lines[0].best_move.is_some() {
+                break;
+            }
+            // a later line can come out ahead of an earlier one, since it was searched with a better move ordering
+            new_lines.sort_by_key(|line| -line.score);
+            for (i, line) in new_lines.iter_mut().enumerate() {
+                line.multipv = i + 1;
+            }
+            lines = new_lines;
+            if let Some(callback) = info_callback.as_mut() {
+                lines.iter().for_each(callback);
+            }
+            if self.stopped || lines.iter().all(|line| is_mate_score(line.score) && MATE_SCORE - line.score.abs() <= depth) {
+                break;
+            }
+            // the clock doesn't run while pondering
+            if let Some(time) = self.time.as_mut() {
+                let out_of_time = time.iteration_done(lines[0].best_move, lines[0].score);
+                if out_of_time && !self.limits.infinite && !self.pondering {
+                    break;
+                }
+            }
+        }
+        self.node_counts[self.id].store(self.nodes, Ordering::Relaxed);
+        lines
+    }
+
+    fn aspiration_search(&mut self, game: &ChessGameState, depth: i32, previous: i32) -> i32 {
+        let mut delta = ASPIRATION_WINDOW;
+        let (mut alpha, mut beta) = if self.config.aspiration_windows && depth >= 4 && !is_mate_score(previous) {
+            (previous - delta, previous + delta)
+        } else {
+            (-INFINITY, INFINITY)
+        };
+        loop {
+            self.path.clear();
+            let score = self.negamax(game, depth, alpha, beta, 0, None, true);
+            if self.stopped {
+                return score;
+            }
+            // widen the window on the failing side until the score falls inside it
+            if score <= alpha {
+                alpha = (score - delta).max(-INFINITY);
+            } else if score >= beta {
+                beta = (score + delta).min(INFINITY);
+            } else {
+                return score;
+            }
+            delta *= 2;
+            if delta > 1000 {
+                alpha = -INFINITY;
+                beta = INFINITY;
+            }
+        }
+    }
+
+    fn check_limits(&mut self) {
+        // with several threads the node limit applies to each of them
+        if self.control.is_stopped() || self.done.load(Ordering::Relaxed) || self.limits.nodes.is_some_and(|n| self.nodes >= n) {
+            self.stopped = true;
+        }
+        // reading the clock is comparatively slow
+        if self.nodes.is_multiple_of(1024) {
+            self.node_counts[self.id].store(self.nodes, Ordering::Relaxed);
+            let Some(time) = self.time.as_mut() else {
+                return;
+            };
+            if self.pondering {
+                if self.control.is_pondering() {
+                    return;
+                }
+                // the opponent played the expected move: the clock starts now
+                self.pondering = false;
+                time.restart();
+            }
+            if time.hard_limit_reached() {
+                self.stopped = true;
+            }
+        }
+    }
+
+    fn evaluate(&self, game: &ChessGameState, ply: usize) -> i32 {
+        match self.network {
+            Some(network) => network.evaluate(&self.accumulators[ply], game.active_player()),
+            None => evaluate_with(game, self.params),
+        }
+    }
+
+    // updates the accumulator of a child position from its parent's
+    fn enter(&mut self, game: &ChessGameState, child: &ChessGameState, ply: usize) {
+        if let Some(network) = self.network {
+            let (parents, children) = self.accumulators.split_at_mut(ply + 1);
+            network.update(&parents[ply], game, child, &mut children[0]);
+        }
+    }
+
+    fn is_repetition(&self, game: &ChessGameState, hash: u64) -> bool {
+        // only positions since the last irreversible move can repeat
+        let reversible = game.draw_clock();
+        self.path.iter().rev().chain(self.game_history.iter().rev())
+            .take(reversible)
+            .skip(1)
+            .step_by(2)
+            .any(|h| *h == hash)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn negamax(&mut self, game: &ChessGameState, mut depth: i32, mut alpha: i32, beta: i32, ply: usize, prev: PrevMove, allow_null: bool) -> i32 {
+        self.pv[ply].clear();
+        let in_check = game.in_check();
+        if in_check && self.config.check_extensions {
+            depth += 1;
+        }
+        if depth <= 0 || ply >= MAX_PLY {
+            return self.quiesce(game, alpha, beta, ply);
+        }
+        self.nodes += 1;
+        self.check_limits();
+        if self.stopped {
+            return 0;
+        }
+
+        let hash = game.hash();
+        if ply > 0 && let Some(result) = game.result() {
+            return result_score(game, result, ply);
+        }
+        if ply > 0 && self.is_repetition(game, hash) {
+            return 0;
+        }
+
+        let pv_node = beta - alpha > 1;
+        let tt_entry = self.tt.probe(hash);
+        if let Some(entry) = tt_entry && !pv_node && entry.depth >= depth {
+            let score = score_from_tt(entry.score, ply);
+            let cutoff = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if cutoff {
+                return score;
+            }
+        }
+
+        let static_eval = if in_check { -INFINITY } else { self.evaluate(game, ply) };
+        if !pv_node && !in_check && !is_mate_score(beta) {
+            if self.config.reverse_futility_pruning && depth <= REVERSE_FUTILITY_DEPTH
+                && static_eval - REVERSE_FUTILITY_MARGIN * depth >= beta {
+                return static_eval;
+            }
+
+            // zugzwang is common with only king and pawns, where passing would be an illusory advantage
+            if self.config.null_move_pruning && allow_null && depth >= NULL_MOVE_DEPTH && static_eval >= beta
+                && has_non_pawn_material(game) {
+                let reduction = 2 + depth / 4;
+                let child = game.make_null_move();
+                self.enter(game, &child, ply);
+                self.path.push(hash);
+                let score = -self.negamax(&child, depth - 1 - reduction, -beta, -beta + 1, ply + 1, None, false);
+                self.path.pop();
+                if self.stopped {
+                    return 0;
+                }
+                if score >= beta {
+                    // unproven mates from a null move search aren't trustworthy
+                    return if is_mate_score(score) { beta } else { score };
+                }
+            }
+        }
+
+        let mut picker = if self.config.move_ordering {
+            MovePicker::new(tt_entry.and_then(|e| e.best_move), self.heuristics, ply, prev)
+        } else {
+            MovePicker::unordered(game, MoveGen::All)
+        };
+        let futile = self.config.futility_pruning && !pv_node && !in_check
+            && depth < FUTILITY_MARGINS.len() as i32
+            && static_eval + FUTILITY_MARGINS[depth as usize] <= alpha;
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        let mut tried = Vec::new();
+        self.path.push(hash);
+        while let Some(m) = picker.next(game, self.heuristics) {
+            if ply == 0 && (self.excluded.contains(&m) || self.root_moves.is_some_and(|moves| !moves.contains(&m))) {
+                continue;
+            }
+            let Some(child) = game.make_pseudo_legal(m) else {
+                continue;
+            };
+            self.enter(game, &child, ply);
+            let gives_check = child.in_check();
+            let quiet = !m.is_tactical() && !gives_check;
+            if futile && quiet && !tried.is_empty() {
+                continue;
+            }
+            let (_, to) = game.move_squares(m);
+            let child_prev = child.board().square_by_id(to).get_piece().map(|p| (p, to));
+            let score = if tried.is_empty() {
+                -self.negamax(&child, depth - 1, -beta, -alpha, ply + 1, child_prev, true)
+            } else {
+                let reduction = if self.config.late_move_reductions && depth >= LMR_DEPTH && tried.len() >= LMR_MOVES
+                    && quiet && !in_check {
+                    lmr_reduction(depth, tried.len()).min(depth - 2)
+                } else {
+                    0
+                };
+                // scout with a null window, and only re-search when the move might be better
+                let mut score = -self.negamax(&child, depth - 1 - reduction, -alpha - 1, -alpha, ply + 1, child_prev, true);
+                if reduction > 0 && score > alpha {
+                    score = -self.negamax(&child, depth - 1, -alpha - 1, -alpha, ply + 1, child_prev, true);
+                }
+                if score > alpha && score < beta {
+                    -self.negamax(&child, depth - 1, -beta, -alpha, ply + 1, child_prev, true)
+                } else {
+                    score
+                }
+            };
+            tried.push(m);
+            if self.stopped {
+                self.path.pop();
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = Some(m);
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(ply, m);
+                    if score >= beta {
+                        if self.config.move_ordering {
+                            self.heuristics.update_cutoff(game, m, &tried, depth, ply, prev);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+        self.path.pop();
+
+        // futility pruning never skips the first legal move, so no moves means checkmate or stalemate
+        if tried.is_empty() {
+            return result_score(game, game.variant().no_moves_result(game), ply);
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        // the result of a root search with excluded moves isn't the true value of the position
+        if ply > 0 || (self.excluded.is_empty() && self.root_moves.is_none()) {
+            self.tt.store(TTEntry { hash, depth, score: score_to_tt(best_score, ply), bound, best_move });
+        }
+        best_score
+    }
+
+    // only searches captures and promotions, so the static evaluation is taken in quiet positions
+    fn quiesce(&mut self, game: &ChessGameState, mut alpha: i32, beta: i32, ply: usize) -> i32 {
+        self.pv[ply].clear();
+        self.nodes += 1;
+        self.check_limits();
+        if self.stopped {
+            return 0;
+        }
+        if let Some(result) = game.result() {
+            return result_score(game, result, ply);
+        }
+        // the PV and accumulator stacks end here, even for a line of checks
+        if ply >= MAX_PLY {
+            return self.evaluate(game, ply);
+        }
+
+        let in_check = game.in_check();
+        let mut best_score = -INFINITY;
+        if !in_check {
+            best_score = self.evaluate(game, ply);
+            if best_score >= beta {
+                return best_score;
+            }
+            alpha = alpha.max(best_score);
+        }
+
+        // when in check every evasion has to be considered.
+        // Captures are always ordered: without MVV-LVA and SEE pruning the quiescence search explodes.
+        let mut picker = match (self.config.move_ordering, in_check) {
+            (true, true) => MovePicker::new(None, self.heuristics, ply, None),
+            (false, true) => MovePicker::unordered(game, MoveGen::All),
+            (_, false) => MovePicker::new_tactical(None),
+        };
+        let mut legal_moves = 0;
+        while let Some(m) = picker.next(game, self.heuristics) {
+            let Some(child) = game.make_pseudo_legal(m) else {
+                continue;
+            };
+            legal_moves += 1;
+            self.enter(game, &child, ply);
+            let score = -self.quiesce(&child, -beta, -alpha, ply + 1);
+            if self.stopped {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(ply, m);
+                    if score >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+        if in_check && legal_moves == 0 {
+            return result_score(game, game.variant().no_moves_result(game), ply);
+        }
+        best_score
+    }
+
+    fn update_pv(&mut self, ply: usize, chess_move: ChessMove) {
+        let (head, tail) = self.pv.split_at_mut(ply + 1);
+        let line = &mut head[ply];
+        line.clear();
+        line.push(chess_move);
+        line.extend_from_slice(&tail[0]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use crate::chess_engine::evaluation::{EvalParams, DEFAULT_PARAMS};
+    use crate::chess_engine::nnue::tests::small_network;
+    use crate::chess_engine::nnue::Network;
+    use crate::chess_engine::search::{has_non_pawn_material, is_mate_score, SearchConfig, SearchLimits, Searcher, MATE_SCORE};
+    use crate::chess_engine::syzygy::tests::{single_value_tables, temp_dir};
+    use crate::chess_engine::syzygy::Tablebase;
+    use crate::chess_engine::time_manager::{allocate, GameTime};
+    use crate::chess_game::game_clock::MockClock;
+    use crate::chess_game::chess_move::ChessMove;
+    use crate::chess_game::chess_square::SquareID;
+    use crate::chess_game::ChessGameState;
+
+    fn sq(name: &str) -> SquareID {
+        SquareID::parse(name).unwrap()
+    }
+
+    fn depth(depth: i32) -> SearchLimits {
+        SearchLimits { depth: Some(depth), ..Default::default() }
+    }
+
+    // full width alpha-beta, without any of the selective techniques
+    fn plain() -> SearchConfig {
+        SearchConfig {
+            null_move_pruning: false,
+            late_move_reductions: false,
+            futility_pruning: false,
+            reverse_futility_pruning: false,
+            check_extensions: false,
+            aspiration_windows: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        let game = ChessGameState::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut searcher = Searcher::new(SearchConfig::default());
+        let result = searcher.search(&game, depth(3));
+        assert_eq!(result.best_move, Some(ChessMove::Move(sq("a1"), sq("a8"))));
+        assert_eq!(result.score, MATE_SCORE - 1);
+    }
+
+    #[test]
+    fn wins_hanging_queen() {
+        let game = ChessGameState::from_fen("rnb1kbnr/pppp1ppp/8/4p1q1/3P4/8/PPP1PPPP/RNBQKBNR w KQkq - 0 3").unwrap();
+        let mut searcher = Searcher::new(SearchConfig::default());
+        let result = searcher.search(&game, depth(2));
+        assert_eq!(result.best_move, Some(ChessMove::Capture(sq("c1"), sq("g5"))));
+        assert_eq!(result.pv[0], ChessMove::Capture(sq("c1"), sq("g5")));
+    }
+
+    // node count benchmark: move ordering has to cut the tree down without changing the result
+    #[test]
+    fn move_ordering_reduces_nodes() {
+        let positions = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+            "2r3k1/pp3ppp/2n5/3p4/3P4/2P2N2/P4PPP/2R3K1 b - - 0 1",
+        ];
+        let mut ordered_total = 0;
+        let mut unordered_total = 0;
+        for fen in positions {
+            let game = ChessGameState::from_fen(fen).unwrap();
+            let mut ordered = Searcher::new(plain());
+            let mut unordered = Searcher::new(SearchConfig { move_ordering: false, ..plain() });
+            let ordered_result = ordered.search(&game, depth(3));
+            let unordered_result = unordered.search(&game, depth(3));
+            assert_eq!(ordered_result.score, unordered_result.score);
+            ordered_total += ordered_result.nodes;
+            unordered_total += unordered_result.nodes;
+        }
+        assert!(ordered_total * 2 < unordered_total);
+    }
+
+    #[test]
+    fn zugzwang_guard() {
+        let pawns = ChessGameState::from_fen("8/5k2/3p4/3P4/2P5/8/5K2/8 w - - 0 1").unwrap();
+        assert!(!has_non_pawn_material(&pawns));
+        let knight = ChessGameState::from_fen("8/5k2/3p4/3P4/2P5/8/5K2/6N1 w - - 0 1").unwrap();
+        assert!(has_non_pawn_material(&knight));
+        // black has a knight, but it's white's move
+        let black_knight = ChessGameState::from_fen("6n1/5k2/3p4/3P4/2P5/8/5K2/8 w - - 0 1").unwrap();
+        assert!(!has_non_pawn_material(&black_knight));
+    }
+
+    #[test]
+    fn each_technique_keeps_tactics() {
+        let toggles: [fn(&mut SearchConfig); 6] = [
+            |c| c.null_move_pruning = false,
+            |c| c.late_move_reductions = false,
+            |c| c.futility_pruning = false,
+            |c| c.reverse_futility_pruning = false,
+            |c| c.check_extensions = false,
+            |c| c.aspiration_windows = false,
+        ];
+        let mate = ChessGameState::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4").unwrap();
+        // the knight fork on c7 wins the rook
+        let fork = ChessGameState::from_fen("r3k3/pp3ppp/8/1N6/8/8/PPP2PPP/4K3 w - - 0 1").unwrap();
+        for toggle in toggles {
+            let mut config = SearchConfig::default();
+            toggle(&mut config);
+            let mut searcher = Searcher::new(config);
+            let result = searcher.search(&mate, depth(4));
+            assert_eq!(result.best_move, Some(ChessMove::Capture(sq("h5"), sq("f7"))));
+            assert_eq!(result.score, MATE_SCORE - 1);
+
+            searcher.new_game();
+            let result = searcher.search(&fork, depth(5));
+            assert_eq!(result.best_move, Some(ChessMove::Move(sq("b5"), sq("c7"))));
+            assert!(result.score > 300);
+        }
+    }
+
+    #[test]
+    fn selectivity_reduces_nodes() {
+        let positions = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+            "2r3k1/pp3ppp/2n5/3p4/3P4/2P2N2/P4PPP/2R3K1 b - - 0 1",
+        ];
+        let mut selective_total = 0;
+        let mut plain_total = 0;
+        for fen in positions {
+            let game = ChessGameState::from_fen(fen).unwrap();
+            let selective = Searcher::new(SearchConfig::default()).search(&game, depth(5));
+            let plain = Searcher::new(plain()).search(&game, depth(5));
+            selective_total += selective.nodes;
+            plain_total += plain.nodes;
+        }
+        assert!(selective_total * 2 < plain_total);
+    }
+
+    #[test]
+    fn time_management() {
+        let game_time = GameTime { remaining: Duration::from_secs(60), increment: Duration::ZERO, moves_to_go: None };
+        let limits = SearchLimits { game_time: Some(game_time), ..Default::default() };
+
+        // the only legal move is Kh7
+        let forced = ChessGameState::from_fen("7k/8/8/8/8/8/8/K5R1 b - - 0 1").unwrap();
+        assert_eq!(forced.get_legal_moves().len(), 1);
+        let result = Searcher::new(SearchConfig::default()).search(&forced, limits);
+        assert_eq!(result.depth, 1);
+        assert_eq!(result.best_move, Some(ChessMove::Move(sq("h8"), sq("h7"))));
+
+        // every reading of the mock clock takes a millisecond, so the search has to be cut off
+        let game_time = GameTime { remaining: Duration::from_millis(500), ..game_time };
+        let limits = SearchLimits { game_time: Some(game_time), ..Default::default() };
+        let mut searcher = Searcher::new(SearchConfig::default());
+        searcher.set_clock(Box::new(MockClock::with_step(Duration::from_millis(1))));
+        let result = searcher.search(&ChessGameState::new(), limits);
+        assert!(result.best_move.is_some());
+        let (_, hard) = allocate(game_time, SearchConfig::default().move_overhead);
+        assert!(result.time <= hard + Duration::from_millis(2));
+    }
+
+    #[test]
+    fn single_thread_is_deterministic() {
+        let game = ChessGameState::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let first = Searcher::new(SearchConfig::default()).search(&game, depth(5));
+        let second = Searcher::new(SearchConfig::default()).search(&game, depth(5));
+        assert_eq!(first.nodes, second.nodes);
+        assert_eq!(first.score, second.score);
+        assert_eq!(first.pv, second.pv);
+    }
+
+    #[test]
+    fn lazy_smp() {
+        let config = SearchConfig { threads: 4, ..Default::default() };
+        let mut searcher = Searcher::new(config);
+        let mate = ChessGameState::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4").unwrap();
+        let result = searcher.search(&mate, depth(4));
+        assert_eq!(result.best_move, Some(ChessMove::Capture(sq("h5"), sq("f7"))));
+        assert_eq!(result.score, MATE_SCORE - 1);
+
+        let fork = ChessGameState::from_fen("r3k3/pp3ppp/8/1N6/8/8/PPP2PPP/4K3 w - - 0 1").unwrap();
+        searcher.new_game();
+        let result = searcher.search(&fork, depth(6));
+        assert_eq!(result.best_move, Some(ChessMove::Move(sq("b5"), sq("c7"))));
+        assert!(result.depth >= 6);
+
+        // the helpers stop with the main thread
+        searcher.set_config(SearchConfig { threads: 3, ..config });
+        let nodes = SearchLimits { nodes: Some(20_000), ..Default::default() };
+        let result = searcher.search(&ChessGameState::new(), nodes);
+        assert!(result.best_move.is_some());
+        assert!(result.nodes < 3 * 20_000 + 3 * 1024);
+    }
+
+    #[test]
+    fn multipv() {
+        // Qxf7# is the only mate, and taking the queen on h5 isn't possible
+        let game = ChessGameState::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4").unwrap();
+        let mut searcher = Searcher::new(SearchConfig::default());
+        let lines = searcher.search_multipv(&game, depth(4), 3);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].0.chess_move, ChessMove::Capture(sq("h5"), sq("f7")));
+        assert_eq!(lines[0].1, MATE_SCORE - 1);
+        assert!(lines.windows(2).all(|w| w[0].1 >= w[1].1));
+        assert!(!is_mate_score(lines[1].1));
+        for (m, _, pv) in &lines {
+            assert_eq!(pv[0], m.chess_move);
+        }
+        assert_ne!(lines[1].0, lines[2].0);
+
+        // the number of lines is limited by the legal moves
+        let forced = ChessGameState::from_fen("7k/8/8/8/8/8/8/K5R1 b - - 0 1").unwrap();
+        assert_eq!(searcher.search_multipv(&forced, depth(3), 4).len(), 1);
+
+        let lines = searcher.search_lines(&ChessGameState::new(), depth(3), 20);
+        assert_eq!(lines.len(), 20);
+        assert_eq!(lines.iter().map(|l| l.multipv).collect::<Vec<_>>(), (1..=20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn tablebase_root() {
+        let dir = temp_dir("search");
+        single_value_tables(&dir);
+        let tablebase = Arc::new(Tablebase::open(dir.to_str().unwrap()).unwrap());
+        let game = ChessGameState::from_fen("8/8/8/8/8/2k5/8/1Q5K w - - 0 1").unwrap();
+        let best: Vec<ChessMove> = tablebase.best_moves(&game).unwrap().iter().map(|m| m.chess_move).collect();
+
+        let mut searcher = Searcher::new(SearchConfig::default());
+        searcher.set_tablebase(Some(tablebase));
+        let lines = searcher.search_lines(&game, depth(1), 100);
+        assert_eq!(lines.len(), best.len());
+        assert!(lines.iter().all(|line| best.contains(&line.best_move.unwrap())));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn network_evaluation() {
+        let network = Arc::new(Network::quantize(&small_network(4)));
+        let mut searcher = Searcher::new(plain());
+        searcher.set_network(Some(network.clone()));
+
+        // no captures are possible after the first move, so the score is the network's evaluation of a child
+        let game = ChessGameState::new();
+        let result = searcher.search(&game, depth(1));
+        let expected = game.get_legal_moves().iter()
+            .map(|m| {
+                let mut child = game;
+                child.make_move(*m);
+                -network.evaluate_game(&child)
+            })
+            .max()
+            .unwrap();
+        assert_eq!(result.score, expected);
+
+        let mate = ChessGameState::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut searcher = Searcher::new(SearchConfig::default());
+        searcher.set_network(Some(network));
+        let result = searcher.search(&mate, depth(4));
+        assert_eq!(result.best_move, Some(ChessMove::Move(sq("a1"), sq("a8"))));
+        assert_eq!(result.score, MATE_SCORE - 1);
+    }
+
+    #[test]
+    fn eval_params() {
+        // a knight up, with the knight worth three times as much in the loaded weights
+        let game = ChessGameState::from_fen("4k3/8/8/8/8/8/8/3NK3 w - - 0 1").unwrap();
+        let mut params = DEFAULT_PARAMS;
+        params.piece_values[1] *= 3;
+        let params = EvalParams::from_config(&params.to_config()).unwrap();
+        let mut searcher = Searcher::new(plain());
+        let default = searcher.search(&game, depth(2)).score;
+        searcher.set_params(Some(Arc::new(params)));
+        let tuned = searcher.search(&game, depth(2)).score;
+        assert_eq!(tuned - default, 2 * DEFAULT_PARAMS.piece_values[1]);
+        searcher.set_params(None);
+        assert_eq!(searcher.search(&game, depth(2)).score, default);
+    }
+}
@@ -0,0 +1,1427 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use crate::chess_game::chess_move::{AnnotatedMove, Annotation};
+use crate::chess_game::chess_piece::PieceName;
+use crate::chess_game::{ChessGameState, GameResult, Player};
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+const MAX_PIECES: usize = 7;
+// root moves are ranked around this, well beyond any distance to zeroing
+const MAX_DTZ: i32 = 1 << 18;
+// the number of ways to place three unique pieces, and two kings, after removing symmetries
+const UNIQUE_PIECES_SIZE: u64 = 31332;
+const KINGS_SIZE: u64 = 462;
+
+// the flags of a table
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+// the outcome of a position with perfect play, where cursed wins and blessed losses are drawn by the fifty-move rule
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Self {
+        match value {
+            ..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+
+    fn value(self) -> i32 {
+        self as i32 - 2
+    }
+
+    // the same outcome from the opponent's point of view
+    pub fn flip(self) -> Self {
+        Self::from_value(-self.value())
+    }
+}
+
+#[derive(Debug)]
+pub enum TablebaseError {
+    Io(std::io::Error),
+}
+
+impl Display for TablebaseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TablebaseError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<std::io::Error> for TablebaseError {
+    fn from(err: std::io::Error) -> Self {
+        TablebaseError::Io(err)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+// why a table lookup gave no value
+enum Miss {
+    // no table, or a broken one
+    Missing,
+    // the DTZ table only has the positions with the other side to move
+    ChangeStm,
+}
+
+// the squares a1 (0) to h8 (63) mapped to indices of the table encoding
+struct Indexing {
+    binomial: [[u64; 64]; MAX_PIECES],
+    // squares below the a1-h8 diagonal to 0..27
+    map_b1h1h7: [u64; 64],
+    // the a1-d1-d4 triangle to 0..9, with the diagonal last
+    map_a1d1d4: [u64; 64],
+    // both kings, the first in the a1-d1-d4 triangle, to 0..461
+    map_kk: [[u64; 64]; 10],
+    // the pawn squares a2-h7 to 0..47; the leading pawn has the highest value
+    map_pawns: [u64; 64],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+// positive above the a1-h8 diagonal, negative below it
+fn off_diagonal(sq: usize) -> i32 {
+    (sq / 8) as i32 - (sq % 8) as i32
+}
+
+fn king_distance(a: usize, b: usize) -> usize {
+    (a / 8).abs_diff(b / 8).max((a % 8).abs_diff(b % 8))
+}
+
+impl Indexing {
+    fn new() -> Self {
+        let mut ix = Indexing {
+            binomial: [[0; 64]; MAX_PIECES],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            map_pawns: [0; 64],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        for (code, sq) in (0..64).filter(|sq| off_diagonal(*sq) < 0).enumerate() {
+            ix.map_b1h1h7[sq] = code as u64;
+        }
+
+        let mut code = 0;
+        let mut diagonal = Vec::new();
+        for sq in (0..28).filter(|sq| sq % 8 <= 3) {
+            if off_diagonal(sq) < 0 {
+                ix.map_a1d1d4[sq] = code;
+                code += 1;
+            } else if off_diagonal(sq) == 0 {
+                diagonal.push(sq);
+            }
+        }
+        for sq in diagonal {
+            ix.map_a1d1d4[sq] = code;
+            code += 1;
+        }
+
+        // kings both on the diagonal come last; with the first on it, the second can't be above it
+        let mut code = 0;
+        let mut both_on_diagonal = Vec::new();
+        for idx in 0..10 {
+            // b1 (1) is the only square of the triangle mapped to 0
+            for first in (0..28).filter(|sq| ix.map_a1d1d4[*sq] == idx as u64 && (idx != 0 || *sq == 1)) {
+                for second in 0..64 {
+                    if king_distance(first, second) <= 1 || (off_diagonal(first) == 0 && off_diagonal(second) > 0) {
+                        continue;
+                    }
+                    if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                        both_on_diagonal.push((idx, second));
+                    } else {
+                        ix.map_kk[idx][second] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, second) in both_on_diagonal {
+            ix.map_kk[idx][second] = code;
+            code += 1;
+        }
+
+        ix.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..MAX_PIECES.min(n + 1) {
+                ix.binomial[k][n] = if k > 0 { ix.binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { ix.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        let mut available = 47;
+        for lead_count in 1..6 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..7 {
+                    let sq = rank * 8 + file;
+                    if lead_count == 1 {
+                        ix.map_pawns[sq] = available;
+                        ix.map_pawns[sq ^ 7] = available - 1;
+                        available = available.saturating_sub(2);
+                    }
+                    ix.lead_pawn_idx[lead_count][sq] = idx;
+                    idx += ix.binomial[lead_count - 1][ix.map_pawns[sq] as usize];
+                }
+                ix.lead_pawns_size[lead_count][file] = idx;
+            }
+        }
+        ix
+    }
+}
+
+// the material of a table, e.g. KRPvKR
+#[derive(Debug, Clone)]
+struct Material {
+    // the table's name, and the name with the colors swapped
+    key: String,
+    key2: String,
+    piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    // the pawns of the leading color, then the other
+    pawn_count: [usize; 2],
+}
+
+impl Material {
+    fn parse(name: &str) -> Option<Self> {
+        let (white, black) = name.split_once('v')?;
+        let valid = |side: &str| side.starts_with('K') && side.chars().all(|c| "KQRBNP".contains(c));
+        if !valid(white) || !valid(black) || white.len() + black.len() > MAX_PIECES {
+            return None;
+        }
+        let count = |side: &str, c: char| side.chars().filter(|p| *p == c).count();
+        let has_unique_pieces = "QRBNP".chars().any(|c| count(white, c) == 1 || count(black, c) == 1);
+        let (white_pawns, black_pawns) = (count(white, 'P'), count(black, 'P'));
+        // the side with fewer pawns leads, for better compression
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        Some(Self {
+            key: format!("{}v{}", white, black),
+            key2: format!("{}v{}", black, white),
+            piece_count: white.len() + black.len(),
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces,
+            pawn_count: if white_leads { [white_pawns, black_pawns] } else { [black_pawns, white_pawns] },
+        })
+    }
+}
+
+// piece codes as stored in the tables: 1 (pawn) to 6 (king) for white, plus 8 for black
+fn piece_code(player: Player, name: PieceName) -> u8 {
+    let code = usize::from(name) as u8 + 1;
+    match player {
+        Player::White => code,
+        Player::Black => code + 8,
+    }
+}
+
+fn piece_letter(name: PieceName) -> char {
+    match name {
+        PieceName::Pawn => 'P',
+        PieceName::Knight => 'N',
+        PieceName::Bishop => 'B',
+        PieceName::Rook => 'R',
+        PieceName::Queen => 'Q',
+        PieceName::King => 'K',
+    }
+}
+
+// the squares and codes of all the pieces on the board
+fn piece_list(game: &ChessGameState) -> Vec<(usize, u8)> {
+    game.board().iter()
+        .filter_map(|sq| sq.get_piece().map(|p| (usize::from(sq.get_id()), piece_code(p.get_owner(), p.get_name()))))
+        .collect()
+}
+
+// the name of the table a position belongs to, with white's pieces first
+fn material_key(game: &ChessGameState) -> String {
+    let side = |player: Player| -> String {
+        let mut letters: Vec<char> = game.board().iter()
+            .filter_map(|sq| sq.get_piece())
+            .filter(|p| p.get_owner() == player)
+            .map(|p| piece_letter(p.get_name()))
+            .collect();
+        letters.sort_by_key(|c| "KQRBNP".find(*c));
+        letters.into_iter().collect()
+    };
+    format!("{}v{}", side(Player::White), side(Player::Black))
+}
+
+fn read_u8(bytes: &[u8], pos: usize) -> Option<u8> {
+    bytes.get(pos).copied()
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+// the compressed data is read big endian, as a stream of bits
+fn read_u32_be(bytes: &[u8], pos: usize) -> u32 {
+    bytes.get(pos..pos + 4).map_or(0, |b| u32::from_be_bytes(b.try_into().expect("four bytes")))
+}
+
+// one of the (up to 8) subtables of a table: by side to move, and the file of the leading pawn
+#[derive(Debug, Clone, Default)]
+struct PairsData {
+    flags: u8,
+    pieces: [u8; MAX_PIECES],
+    // the lengths of the groups the pieces are encoded in, zero terminated
+    group_len: [usize; MAX_PIECES + 1],
+    group_idx: [u64; MAX_PIECES + 1],
+    block_size: usize,
+    span: u64,
+    block_count: usize,
+    // the value of a table with a single value
+    min_sym_len: u8,
+    lowest_sym: usize,
+    // the smallest code of each symbol length, left aligned
+    base64: Vec<u64>,
+    // the number of values a symbol expands to, minus one
+    symlen: Vec<u8>,
+    btree: usize,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    block_length: usize,
+    block_length_size: usize,
+    data: usize,
+    // DTZ only: where the value maps of the four outcomes start
+    map_idx: [usize; 4],
+}
+
+impl PairsData {
+    // each symbol pairs two others, stored as 12 bit numbers
+    fn left(&self, bytes: &[u8], sym: usize) -> Option<usize> {
+        let lr = bytes.get(self.btree + 3 * sym..self.btree + 3 * sym + 3)?;
+        Some(((lr[1] as usize & 0xF) << 8) | lr[0] as usize)
+    }
+
+    fn right(&self, bytes: &[u8], sym: usize) -> Option<usize> {
+        let lr = bytes.get(self.btree + 3 * sym..self.btree + 3 * sym + 3)?;
+        Some(((lr[2] as usize) << 4) | (lr[1] as usize >> 4))
+    }
+
+    fn set_symlen(&mut self, bytes: &[u8], sym: usize, visited: &mut [bool]) -> Option<u8> {
+        visited[sym] = true;
+        let right = self.right(bytes, sym)?;
+        if right == 0xFFF {
+            return Some(0);
+        }
+        let left = self.left(bytes, sym)?;
+        for child in [left, right] {
+            if !*visited.get(child)? {
+                self.symlen[child] = self.set_symlen(bytes, child, visited)?;
+            }
+        }
+        Some(self.symlen[left].wrapping_add(self.symlen[right]).wrapping_add(1))
+    }
+
+    // reads the sizes and the Huffman code of the subtable, returning where the next one starts
+    fn set_sizes(&mut self, bytes: &[u8], mut pos: usize) -> Option<usize> {
+        self.flags = read_u8(bytes, pos)?;
+        pos += 1;
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            self.min_sym_len = read_u8(bytes, pos)?;
+            return Some(pos + 1);
+        }
+
+        let table_size = self.group_idx[self.group_len.iter().position(|len| *len == 0)?];
+        self.block_size = 1 << read_u8(bytes, pos)?;
+        self.span = 1 << read_u8(bytes, pos + 1)?;
+        self.sparse_index_size = table_size.div_ceil(self.span) as usize;
+        let padding = read_u8(bytes, pos + 2)? as usize;
+        self.block_count = read_u32(bytes, pos + 3)? as usize;
+        // padded so that the sparse index never points out of range
+        self.block_length_size = self.block_count + padding;
+        let max_sym_len = read_u8(bytes, pos + 7)?;
+        self.min_sym_len = read_u8(bytes, pos + 8)?;
+        pos += 9;
+        self.lowest_sym = pos;
+        let lengths = max_sym_len.checked_sub(self.min_sym_len)? as usize + 1;
+
+        // canonical Huffman codes: longer symbols have lower values
+        let lowest = |i: usize| read_u16(bytes, pos + 2 * i).map(u64::from);
+        self.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            self.base64[i] = self.base64[i + 1].wrapping_add(lowest(i)?).wrapping_sub(lowest(i + 1)?) / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            *base = base.checked_shl(64u32.saturating_sub(i as u32 + self.min_sym_len as u32)).unwrap_or(0);
+        }
+        pos += lengths * 2;
+
+        let symbols = read_u16(bytes, pos)? as usize;
+        pos += 2;
+        self.btree = pos;
+        self.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for sym in 0..symbols {
+            if !visited[sym] {
+                self.symlen[sym] = self.set_symlen(bytes, sym, &mut visited)?;
+            }
+        }
+        Some(pos + symbols * 3 + (symbols & 1))
+    }
+
+    fn block_len(&self, bytes: &[u8], block: usize) -> Option<i64> {
+        read_u16(bytes, self.block_length + 2 * block).map(i64::from)
+    }
+
+    // the value stored at an index
+    fn decompress(&self, bytes: &[u8], idx: u64) -> Option<i32> {
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            return Some(self.min_sym_len as i32);
+        }
+
+        // the sparse index knows the block and offset of the middle of every span
+        let k = (idx / self.span) as usize;
+        let entry = self.sparse_index + 6 * k;
+        let mut block = read_u32(bytes, entry)? as usize;
+        let mut offset = read_u16(bytes, entry + 4)? as i64;
+        offset += (idx % self.span) as i64 - (self.span / 2) as i64;
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += self.block_len(bytes, block)? + 1;
+        }
+        while offset > self.block_len(bytes, block)? {
+            offset -= self.block_len(bytes, block)? + 1;
+            block += 1;
+        }
+
+        // find the symbol containing the value
+        let mut ptr = self.data + block * self.block_size;
+        let mut buf = u64::from_be_bytes(bytes.get(ptr..ptr + 8)?.try_into().ok()?);
+        ptr += 8;
+        let mut buf_size = 64;
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while buf < *self.base64.get(len)? {
+                len += 1;
+            }
+            let shift = 64 - len as u32 - self.min_sym_len as u32;
+            sym = (buf - self.base64[len]).checked_shr(shift).unwrap_or(0) as usize;
+            sym += read_u16(bytes, self.lowest_sym + 2 * len)? as usize;
+            let values = *self.symlen.get(sym)? as i64 + 1;
+            if offset < values {
+                break;
+            }
+            offset -= values;
+            let len = len as u32 + self.min_sym_len as u32;
+            buf = buf.checked_shl(len).unwrap_or(0);
+            buf_size -= len;
+            if buf_size <= 32 {
+                buf_size += 32;
+                buf |= (read_u32_be(bytes, ptr) as u64) << (64 - buf_size);
+                ptr += 4;
+            }
+        }
+
+        // then expand the pairs it is made of
+        while *self.symlen.get(sym)? != 0 {
+            let left = self.left(bytes, sym)?;
+            let values = *self.symlen.get(left)? as i64 + 1;
+            if offset < values {
+                sym = left;
+            } else {
+                offset -= values;
+                sym = self.right(bytes, sym)?;
+            }
+        }
+        self.left(bytes, sym).map(|value| value as i32)
+    }
+}
+
+// a WDL or DTZ table loaded into memory
+struct Table {
+    bytes: Vec<u8>,
+    kind: TableKind,
+    // by side to move (DTZ tables have one side), then by the file of the leading pawn (a to d)
+    pairs: Vec<Vec<PairsData>>,
+}
+
+impl Table {
+    fn load(path: &PathBuf, kind: TableKind, material: &Material, ix: &Indexing) -> Option<Self> {
+        let bytes = std::fs::read(path).ok()?;
+        let magic = match kind {
+            TableKind::Wdl => WDL_MAGIC,
+            TableKind::Dtz => DTZ_MAGIC,
+        };
+        if bytes.len() % 64 != 16 || bytes[..4] != magic {
+            return None;
+        }
+        let mut table = Table { bytes: Vec::new(), kind, pairs: Vec::new() };
+        table.parse(&bytes, material, ix)?;
+        table.bytes = bytes;
+        Some(table)
+    }
+
+    fn parse(&mut self, bytes: &[u8], material: &Material, ix: &Indexing) -> Option<()> {
+        let flags = read_u8(bytes, 4)?;
+        if (flags & 2 != 0) != material.has_pawns {
+            return None;
+        }
+        let mut pos = 5;
+        let sides = if self.kind == TableKind::Wdl && material.key != material.key2 { 2 } else { 1 };
+        let files = if material.has_pawns { 4 } else { 1 };
+        // both sides have pawns
+        let pp = material.has_pawns && material.pawn_count[1] > 0;
+        self.pairs = vec![vec![PairsData::default(); files]; sides];
+
+        for file in 0..files {
+            let first = read_u8(bytes, pos)?;
+            let second = if pp { read_u8(bytes, pos + 1)? } else { 0xFF };
+            let order = [[first & 0xF, second & 0xF], [first >> 4, second >> 4]];
+            pos += 1 + pp as usize;
+            for k in 0..material.piece_count {
+                let byte = read_u8(bytes, pos)?;
+                for (side, pairs) in self.pairs.iter_mut().enumerate() {
+                    pairs[file].pieces[k] = if side == 1 { byte >> 4 } else { byte & 0xF };
+                }
+                pos += 1;
+            }
+            for (side, pairs) in self.pairs.iter_mut().enumerate() {
+                set_groups(&mut pairs[file], material, order[side], file, ix);
+            }
+        }
+        pos += pos & 1;
+
+        for file in 0..files {
+            for pairs in self.pairs.iter_mut() {
+                pos = pairs[file].set_sizes(bytes, pos)?;
+            }
+        }
+
+        if self.kind == TableKind::Dtz {
+            for file in 0..files {
+                let d = &mut self.pairs[0][file];
+                if d.flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+                if d.flags & FLAG_WIDE != 0 {
+                    pos += pos & 1;
+                    for i in 0..4 {
+                        d.map_idx[i] = pos + 2;
+                        pos += 2 * read_u16(bytes, pos)? as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        d.map_idx[i] = pos + 1;
+                        pos += read_u8(bytes, pos)? as usize + 1;
+                    }
+                }
+            }
+            pos += pos & 1;
+        }
+
+        for file in 0..files {
+            for pairs in self.pairs.iter_mut() {
+                pairs[file].sparse_index = pos;
+                pos += pairs[file].sparse_index_size * 6;
+            }
+        }
+        for file in 0..files {
+            for pairs in self.pairs.iter_mut() {
+                pairs[file].block_length = pos;
+                pos += pairs[file].block_length_size * 2;
+            }
+        }
+        for file in 0..files {
+            for pairs in self.pairs.iter_mut() {
+                pos = pos.next_multiple_of(64);
+                pairs[file].data = pos;
+                pos += pairs[file].block_count * pairs[file].block_size;
+            }
+        }
+        (pos <= bytes.len()).then_some(())
+    }
+
+    // the raw value of a position: the WDL value plus 2, or the DTZ in plies
+    fn probe(&self, material: &Material, game: &ChessGameState, key: &str, wdl: Wdl, ix: &Indexing) -> Result<i32, Miss> {
+        let (stm, file, idx) = self.index(material, game, key, ix).ok_or(Miss::Missing)?;
+        let d = &self.pairs[stm % self.pairs.len()][file];
+        if self.kind == TableKind::Dtz && (d.flags & FLAG_STM) as usize != stm
+            && (material.key != material.key2 || material.has_pawns) {
+            return Err(Miss::ChangeStm);
+        }
+        let value = d.decompress(&self.bytes, idx).ok_or(Miss::Missing)?;
+        match self.kind {
+            TableKind::Wdl => Ok(value),
+            TableKind::Dtz => self.map_dtz(d, value, wdl).ok_or(Miss::Missing),
+        }
+    }
+
+    // the side to move and the file of the leading pawn, which pick the subtable, and the index of a position in it
+    fn index(&self, material: &Material, game: &ChessGameState, key: &str, ix: &Indexing) -> Option<(usize, usize, u64)> {
+        // tables are built with white as the stronger side, and symmetric ones with white to move
+        let black_to_move = game.active_player() == Player::Black;
+        let flip = (material.key == material.key2 && black_to_move) || key != material.key;
+        let (flip_color, flip_squares) = if flip { (8, 56) } else { (0, 0) };
+        let stm = (flip != black_to_move) as usize;
+
+        let pieces = piece_list(game);
+        let mut squares = Vec::with_capacity(pieces.len());
+        let mut codes = Vec::with_capacity(pieces.len());
+        let mut lead_count = 0;
+        let mut file = 0;
+        let lead_pawn = self.pairs[0][0].pieces[0];
+        if material.has_pawns {
+            for (sq, code) in pieces.iter().filter(|(_, code)| code ^ flip_color == lead_pawn) {
+                squares.push(sq ^ flip_squares);
+                codes.push(code ^ flip_color);
+            }
+            lead_count = squares.len();
+            let lead = (0..lead_count).max_by_key(|i| ix.map_pawns[squares[*i]])?;
+            squares.swap(0, lead);
+            file = (squares[0] % 8).min(7 - squares[0] % 8);
+        }
+
+        let d = &self.pairs[stm % self.pairs.len()][file];
+        for (sq, code) in pieces.iter().filter(|(_, code)| !material.has_pawns || code ^ flip_color != lead_pawn) {
+            squares.push(sq ^ flip_squares);
+            codes.push(code ^ flip_color);
+        }
+        let size = squares.len();
+
+        // put the pieces in the order the table was encoded in
+        for i in lead_count..size.saturating_sub(1) {
+            if let Some(j) = (i + 1..size).find(|j| d.pieces[i] == codes[*j]) {
+                codes.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        // the leading piece goes on files a to d
+        if squares[0] % 8 > 3 {
+            squares.iter_mut().for_each(|sq| *sq ^= 7);
+        }
+
+        let mut idx = if material.has_pawns {
+            let mut idx = ix.lead_pawn_idx[lead_count][squares[0]];
+            squares[1..lead_count].sort_by_key(|sq| ix.map_pawns[*sq]);
+            for (i, sq) in squares.iter().enumerate().take(lead_count).skip(1) {
+                idx += ix.binomial[i][ix.map_pawns[*sq] as usize];
+            }
+            idx
+        } else {
+            // without pawns, the leading piece also goes on ranks 1 to 4, and below the diagonal
+            if squares[0] / 8 > 3 {
+                squares.iter_mut().for_each(|sq| *sq ^= 56);
+            }
+            if let Some(i) = (0..d.group_len[0]).find(|i| off_diagonal(squares[*i]) != 0)
+                && off_diagonal(squares[i]) > 0 {
+                squares[i..].iter_mut().for_each(|sq| *sq = ((*sq >> 3) | (*sq << 3)) & 63);
+            }
+            if material.has_unique_pieces {
+                encode_unique_pieces(&squares, ix)
+            } else {
+                ix.map_kk[ix.map_a1d1d4[squares[0]] as usize][squares[1]]
+            }
+        };
+
+        // then the remaining groups, each ignoring the squares taken by the previous ones
+        idx *= d.group_idx[0];
+        let mut start = d.group_len[0];
+        let mut remaining_pawns = material.has_pawns && material.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let len = d.group_len[next];
+            squares[start..start + len].sort();
+            let mut n = 0;
+            for i in 0..len {
+                let sq = squares[start + i];
+                let adjust = squares[..start].iter().filter(|other| sq > **other).count();
+                n += ix.binomial[i + 1][sq - adjust - if remaining_pawns { 8 } else { 0 }];
+            }
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            start += len;
+            next += 1;
+        }
+        Some((stm, file, idx))
+    }
+
+    fn map_dtz(&self, d: &PairsData, value: i32, wdl: Wdl) -> Option<i32> {
+        let mut value = value;
+        if d.flags & FLAG_MAPPED != 0 {
+            let map = d.map_idx[match wdl {
+                Wdl::Loss => 1,
+                Wdl::BlessedLoss => 3,
+                Wdl::CursedWin => 2,
+                _ => 0,
+            }];
+            value = if d.flags & FLAG_WIDE != 0 {
+                read_u16(&self.bytes, map + 2 * value as usize)? as i32
+            } else {
+                read_u8(&self.bytes, map + value as usize)? as i32
+            };
+        }
+        // stored in moves unless the table says plies
+        let in_moves = match wdl {
+            Wdl::Win => d.flags & FLAG_WIN_PLIES == 0,
+            Wdl::Loss => d.flags & FLAG_LOSS_PLIES == 0,
+            _ => true,
+        };
+        Some(if in_moves { value * 2 } else { value } + 1)
+    }
+}
+
+fn encode_unique_pieces(squares: &[usize], ix: &Indexing) -> u64 {
+    let (s0, s1, s2) = (squares[0], squares[1], squares[2]);
+    let rank = |sq: usize| (sq / 8) as u64;
+    let adjust1 = (s1 > s0) as u64;
+    let adjust2 = (s2 > s0) as u64 + (s2 > s1) as u64;
+    if off_diagonal(s0) != 0 {
+        (ix.map_a1d1d4[s0] * 63 + s1 as u64 - adjust1) * 62 + s2 as u64 - adjust2
+    } else if off_diagonal(s1) != 0 {
+        (6 * 63 + rank(s0) * 28 + ix.map_b1h1h7[s1]) * 62 + s2 as u64 - adjust2
+    } else if off_diagonal(s2) != 0 {
+        6 * 63 * 62 + 4 * 28 * 62 + rank(s0) * 7 * 28 + (rank(s1) - adjust1) * 28 + ix.map_b1h1h7[s2]
+    } else {
+        6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + rank(s0) * 7 * 6 + (rank(s1) - adjust1) * 6 + rank(s2) - adjust2
+    }
+}
+
+// splits the pieces into the groups they are encoded in, and finds the factor of each group in the index
+fn set_groups(d: &mut PairsData, material: &Material, order: [u8; 2], file: usize, ix: &Indexing) {
+    let mut n = 0;
+    let mut first_len: i32 = if material.has_pawns { 0 } else if material.has_unique_pieces { 3 } else { 2 };
+    d.group_len[0] = 1;
+    for i in 1..material.piece_count {
+        first_len -= 1;
+        if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+            d.group_len[n] += 1;
+        } else {
+            n += 1;
+            d.group_len[n] = 1;
+        }
+    }
+    n += 1;
+    d.group_len[n] = 0;
+
+    let pp = material.has_pawns && material.pawn_count[1] > 0;
+    let mut next = if pp { 2 } else { 1 };
+    let mut free_squares = 64 - d.group_len[0] - if pp { d.group_len[1] } else { 0 };
+    let mut idx = 1;
+    let mut k = 0;
+    while (next < n || k == order[0] || k == order[1]) && k < 16 {
+        if k == order[0] {
+            d.group_idx[0] = idx;
+            idx *= if material.has_pawns {
+                ix.lead_pawns_size[d.group_len[0]][file]
+            } else if material.has_unique_pieces {
+                UNIQUE_PIECES_SIZE
+            } else {
+                KINGS_SIZE
+            };
+        } else if k == order[1] {
+            d.group_idx[1] = idx;
+            idx *= ix.binomial[d.group_len[1]][48 - d.group_len[0]];
+        } else {
+            d.group_idx[next] = idx;
+            idx *= ix.binomial[d.group_len[next]][free_squares];
+            free_squares -= d.group_len[next];
+            next += 1;
+        }
+        k += 1;
+    }
+    d.group_idx[n] = idx;
+}
+
+// the tables of one material combination, loaded when first probed
+struct Entry {
+    material: Material,
+    wdl_path: Option<PathBuf>,
+    dtz_path: Option<PathBuf>,
+    wdl: OnceLock<Option<Table>>,
+    dtz: OnceLock<Option<Table>>,
+}
+
+// a root move with its distance to zeroing, and its rank under the fifty-move rule
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct RootMove {
+    pub chess_move: AnnotatedMove,
+    pub dtz: i32,
+    pub rank: i32,
+}
+
+// Syzygy endgame tablebases read from local directories
+pub struct Tablebase {
+    entries: Vec<Entry>,
+    // table names (both color orders) to entries
+    by_key: HashMap<String, usize>,
+    max_pieces: usize,
+    indexing: Indexing,
+}
+
+impl Tablebase {
+    // finds the .rtbw and .rtbz files in a list of directories, separated as in the PATH variable
+    pub fn open(paths: &str) -> Result<Self, TablebaseError> {
+        let mut tablebase = Tablebase { entries: Vec::new(), by_key: HashMap::new(), max_pieces: 0, indexing: Indexing::new() };
+        for dir in std::env::split_paths(paths).filter(|dir| !dir.as_os_str().is_empty()) {
+            for file in std::fs::read_dir(dir)? {
+                let path = file?.path();
+                let kind = match path.extension().and_then(|e| e.to_str()) {
+                    Some("rtbw") => TableKind::Wdl,
+                    Some("rtbz") => TableKind::Dtz,
+                    _ => continue,
+                };
+                let Some(material) = path.file_stem().and_then(|s| s.to_str()).and_then(Material::parse) else {
+                    continue;
+                };
+                let index = match tablebase.by_key.get(&material.key) {
+                    Some(index) => *index,
+                    None => {
+                        tablebase.max_pieces = tablebase.max_pieces.max(material.piece_count);
+                        tablebase.by_key.insert(material.key.clone(), tablebase.entries.len());
+                        tablebase.by_key.insert(material.key2.clone(), tablebase.entries.len());
+                        tablebase.entries.push(Entry {
+                            material,
+                            wdl_path: None,
+                            dtz_path: None,
+                            wdl: OnceLock::new(),
+                            dtz: OnceLock::new(),
+                        });
+                        tablebase.entries.len() - 1
+                    },
+                };
+                let entry = &mut tablebase.entries[index];
+                match kind {
+                    TableKind::Wdl => entry.wdl_path = Some(path),
+                    TableKind::Dtz => entry.dtz_path = Some(path),
+                }
+            }
+        }
+        Ok(tablebase)
+    }
+
+    // the most pieces of any table found, kings included
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    pub fn table_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    // tables don't have castling rights, and only go up to so many pieces of standard chess
+    pub fn can_probe(&self, game: &ChessGameState) -> bool {
+        let pieces = game.board().iter().filter(|sq| sq.get_piece().is_some()).count();
+        pieces <= self.max_pieces && !game.castling_rights().contains(&true) && game.variant().uses_standard_rules()
+    }
+
+    // the outcome for the side to move, ignoring the fifty-move rule counter of the position
+    pub fn probe_wdl(&self, game: &ChessGameState) -> Option<Wdl> {
+        if !self.can_probe(game) {
+            return None;
+        }
+        self.search(game, false).map(|(wdl, _)| wdl)
+    }
+
+    // the plies to the next capture or pawn move with perfect play, negative when losing and 0 when drawn.
+    // beyond 100 (or below -100) the result is a cursed win (or blessed loss)
+    pub fn probe_dtz(&self, game: &ChessGameState) -> Option<i32> {
+        if !self.can_probe(game) {
+            return None;
+        }
+        self.dtz(game)
+    }
+
+    // every legal move with its DTZ from the root, ranked so that wins within the fifty-move rule come first,
+    // the quickest of them best, and losses are dragged out as long as possible
+    pub fn root_moves(&self, game: &ChessGameState) -> Option<Vec<RootMove>> {
+        if !self.can_probe(game) {
+            return None;
+        }
+        let clock = game.draw_clock() as i32;
+        let mut moves = Vec::new();
+        for m in game.get_legal_moves().iter() {
+            let mut child = *game;
+            child.make_move(*m);
+            let mut dtz = if child.draw_clock() == 0 {
+                dtz_before_zeroing(self.search(&child, false)?.0.flip())
+            } else if child.result() == Some(GameResult::Draw) {
+                0
+            } else {
+                let dtz = -self.dtz(&child)?;
+                dtz + dtz.signum()
+            };
+            if m.annotation == Annotation::CheckMate && dtz == 2 {
+                dtz = 1;
+            }
+            let rank = if dtz > 0 && dtz + clock <= 99 {
+                MAX_DTZ - dtz
+            } else if dtz > 0 {
+                // a cursed win still beats a draw, in case the opponent goes wrong
+                MAX_DTZ / 2 - dtz
+            } else if dtz < 0 && clock - dtz <= 100 {
+                -MAX_DTZ - dtz
+            } else if dtz < 0 {
+                // the fifty-move rule may come to the rescue
+                -MAX_DTZ / 2 - dtz
+            } else {
+                0
+            };
+            moves.push(RootMove { chess_move: *m, dtz, rank });
+        }
+        moves.sort_by_key(|m| -m.rank);
+        Some(moves)
+    }
+
+    // the legal moves that keep the best outcome; None when the position isn't in the tables
+    pub fn best_moves(&self, game: &ChessGameState) -> Option<Vec<AnnotatedMove>> {
+        let moves = self.root_moves(game)?;
+        let best = moves.first()?.rank;
+        Some(moves.into_iter().take_while(|m| m.rank == best).map(|m| m.chess_move).collect())
+    }
+
+    fn table(&self, game: &ChessGameState, kind: TableKind, wdl: Wdl) -> Result<i32, Miss> {
+        let key = material_key(game);
+        let entry = &self.entries[*self.by_key.get(&key).ok_or(Miss::Missing)?];
+        let (path, table) = match kind {
+            TableKind::Wdl => (&entry.wdl_path, &entry.wdl),
+            TableKind::Dtz => (&entry.dtz_path, &entry.dtz),
+        };
+        let path = path.as_ref().ok_or(Miss::Missing)?;
+        let table = table.get_or_init(|| Table::load(path, kind, &entry.material, &self.indexing));
+        table.as_ref().ok_or(Miss::Missing)?.probe(&entry.material, game, &key, wdl, &self.indexing)
+    }
+
+    fn probe_wdl_table(&self, game: &ChessGameState) -> Option<Wdl> {
+        // two bare kings have no table
+        if game.board().iter().filter(|sq| sq.get_piece().is_some()).count() == 2 {
+            return Some(Wdl::Draw);
+        }
+        self.table(game, TableKind::Wdl, Wdl::Draw).ok().map(|value| Wdl::from_value(value - 2))
+    }
+
+    // the tables don't know about en passant, and a capture may be better than what they store,
+    // so the captures (and with `zeroing`, the pawn moves) are searched first.
+    // also returns whether the best move is one of them
+    fn search(&self, game: &ChessGameState, zeroing: bool) -> Option<(Wdl, bool)> {
+        let moves = game.get_legal_moves();
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for m in moves.iter().filter(|m| m.chess_move.is_capture() || (zeroing && is_pawn_move(game, m))) {
+            searched += 1;
+            let mut child = *game;
+            child.make_move(*m);
+            let value = self.search(&child, false)?.0.flip();
+            if value > best {
+                best = value;
+                if value == Wdl::Win {
+                    return Some((value, true));
+                }
+            }
+        }
+
+        // when every legal move was searched, the table isn't needed (and may be wrong, e.g. with en passant)
+        let no_more_moves = searched > 0 && searched == moves.len();
+        let value = if no_more_moves { best } else { self.probe_wdl_table(game)? };
+        if best >= value {
+            Some((best, best > Wdl::Draw || no_more_moves))
+        } else {
+            Some((value, false))
+        }
+    }
+
+    fn dtz(&self, game: &ChessGameState) -> Option<i32> {
+        let (wdl, zeroing_best) = self.search(game, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if zeroing_best {
+            return Some(dtz_before_zeroing(wdl));
+        }
+        match self.table(game, TableKind::Dtz, wdl) {
+            Ok(dtz) => {
+                let fifty_moves = if matches!(wdl, Wdl::BlessedLoss | Wdl::CursedWin) { 100 } else { 0 };
+                return Some((dtz + fifty_moves) * wdl.value().signum());
+            },
+            Err(Miss::Missing) => return None,
+            Err(Miss::ChangeStm) => {},
+        }
+
+        // the table has the other side to move, so look one ply ahead for the best DTZ
+        let mut best: Option<i32> = None;
+        for m in game.get_legal_moves().iter() {
+            let zeroing = m.chess_move.is_capture() || is_pawn_move(game, m);
+            let mut child = *game;
+            child.make_move(*m);
+            // for a zeroing move, the DTZ before it is made
+            let mut dtz = if zeroing {
+                -dtz_before_zeroing(self.search(&child, false)?.0)
+            } else {
+                -self.dtz(&child)?
+            };
+            if dtz == 1 && m.annotation == Annotation::CheckMate {
+                best = Some(1);
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz.signum() == wdl.value().signum() && best.is_none_or(|best| dtz < best) {
+                best = Some(dtz);
+            }
+        }
+        // without legal moves, the side to move is mated
+        Some(best.unwrap_or(-1))
+    }
+}
+
+fn is_pawn_move(game: &ChessGameState, m: &AnnotatedMove) -> bool {
+    let (from, _) = game.move_squares(m.chess_move);
+    game.board().square_by_id(from).get_piece().is_some_and(|p| p.get_name() == PieceName::Pawn)
+}
+
+// the DTZ of a position whose best move zeroes the fifty-move counter
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::Draw => 0,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::cmp::Reverse;
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use crate::chess_engine::retrograde::{Dtm, DtmTables};
+    use crate::chess_engine::syzygy::{piece_code, piece_letter, set_groups, Indexing, Material, PairsData, Table, TableKind,
+                                      Tablebase, Wdl, DTZ_MAGIC, FLAG_MAPPED, FLAG_SINGLE_VALUE, FLAG_WIN_PLIES, WDL_MAGIC};
+    use crate::chess_game::chess_piece::{ChessPiece, PieceName};
+    use crate::chess_game::{ChessGameState, Player};
+
+    // generated subtables are stored in blocks of 32 bytes, with a sparse index entry every 1024 values
+    const BLOCK_SIZE_LOG: u8 = 5;
+    const SPAN_LOG: u8 = 10;
+    // keeps the offsets in the sparse index within 16 bits
+    const MAX_SYMBOL_VALUES: usize = 64;
+
+    fn game(fen: &str) -> ChessGameState {
+        ChessGameState::from_fen(fen).unwrap()
+    }
+
+    // a KQvK table where every position has the same value for each side to move
+    pub(crate) fn single_value_tables(dir: &Path) {
+        // split (both sides to move), no pawns; the pieces are encoded as K, Q and k
+        let header = |magic: [u8; 4]| [magic.as_slice(), &[1, 0x00, 0x66, 0x55, 0xEE, 0]].concat();
+        let mut wdl = header(WDL_MAGIC);
+        // white to move wins, black to move loses
+        wdl.extend([FLAG_SINGLE_VALUE, 4, FLAG_SINGLE_VALUE, 0]);
+        wdl.resize(80, 0);
+        std::fs::write(dir.join("KQvK.rtbw"), wdl).unwrap();
+
+        let mut dtz = header(DTZ_MAGIC);
+        // with white to move, in plies
+        dtz.extend([FLAG_SINGLE_VALUE | FLAG_WIN_PLIES, 5]);
+        dtz.resize(80, 0);
+        std::fs::write(dir.join("KQvK.rtbz"), dtz).unwrap();
+    }
+
+    pub(crate) fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chess_syzygy_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // a subtable as it is laid out in the file
+    #[derive(Default)]
+    struct Subtable {
+        sizes: Vec<u8>,
+        sparse_index: Vec<u8>,
+        block_lengths: Vec<u8>,
+        data: Vec<u8>,
+    }
+
+    // compresses values the way the Syzygy generator does: frequent pairs of symbols become new symbols, and
+    // the symbols get a canonical Huffman code
+    fn compress(values: &[u8], flags: u8) -> Subtable {
+        let mut leaves = values.to_vec();
+        leaves.sort();
+        leaves.dedup();
+        if leaves.len() == 1 {
+            return Subtable { sizes: vec![flags | FLAG_SINGLE_VALUE, leaves[0]], ..Subtable::default() };
+        }
+
+        // a symbol is a value or a pair of symbols, with the number of values it expands to
+        let mut symbols: Vec<(usize, Option<usize>, usize)> = leaves.iter().map(|v| (*v as usize, None, 1)).collect();
+        let mut stream: Vec<usize> = values.iter().map(|v| leaves.binary_search(v).unwrap()).collect();
+        for _ in 0..16 {
+            let mut pairs = HashMap::new();
+            for w in stream.windows(2).filter(|w| symbols[w[0]].2 + symbols[w[1]].2 <= MAX_SYMBOL_VALUES) {
+                *pairs.entry((w[0], w[1])).or_insert(0) += 1;
+            }
+            let Some((&(left, right), &count)) = pairs.iter().max_by_key(|(pair, count)| (**count, Reverse(**pair))) else {
+                break;
+            };
+            if count < 16 {
+                break;
+            }
+            symbols.push((left, Some(right), symbols[left].2 + symbols[right].2));
+            let pair = symbols.len() - 1;
+            let mut paired = Vec::with_capacity(stream.len());
+            let mut i = 0;
+            while i < stream.len() {
+                if stream[i] == left && stream.get(i + 1) == Some(&right) {
+                    paired.push(pair);
+                    i += 2;
+                } else {
+                    paired.push(stream[i]);
+                    i += 1;
+                }
+            }
+            stream = paired;
+        }
+
+        // every symbol gets a code, even the ones only used in pairs
+        let mut frequency = vec![1; symbols.len()];
+        stream.iter().for_each(|s| frequency[*s] += 1);
+        let mut lengths = vec![0u32; symbols.len()];
+        let mut nodes: Vec<(usize, Vec<usize>)> = frequency.iter().enumerate().map(|(s, f)| (*f, vec![s])).collect();
+        while nodes.len() > 1 {
+            nodes.sort_by_key(|(weight, _)| Reverse(*weight));
+            let (first, mut merged) = nodes.pop().unwrap();
+            let (second, other) = nodes.pop().unwrap();
+            merged.extend(other);
+            merged.iter().for_each(|s| lengths[*s] += 1);
+            nodes.push((first + second, merged));
+        }
+        let (min_len, max_len) = (*lengths.iter().min().unwrap(), *lengths.iter().max().unwrap());
+        assert!(max_len <= 32);
+
+        // the longest codes get the lowest symbols, and the lowest codes of their length
+        let mut order: Vec<usize> = (0..symbols.len()).collect();
+        order.sort_by_key(|s| (Reverse(lengths[*s]), *s));
+        let mut number = vec![0; symbols.len()];
+        order.iter().enumerate().for_each(|(n, s)| number[*s] = n);
+        let lowest = |len: u32| lengths.iter().filter(|l| **l > len).count() as u64;
+        let count = |len: u32| lengths.iter().filter(|l| **l == len).count() as u64;
+        let mut base = vec![0u64; max_len as usize + 1];
+        for len in (min_len..max_len).rev() {
+            base[len as usize] = (base[len as usize + 1] + count(len + 1)) / 2;
+        }
+
+        let mut sizes = vec![flags, BLOCK_SIZE_LOG, SPAN_LOG, 0, 0, 0, 0, 0, max_len as u8, min_len as u8];
+        for len in min_len..=max_len {
+            sizes.extend((lowest(len) as u16).to_le_bytes());
+        }
+        sizes.extend((symbols.len() as u16).to_le_bytes());
+        for s in &order {
+            let (left, right) = match symbols[*s] {
+                (value, None, _) => (value, 0xFFF),
+                (left, Some(right), _) => (number[left], number[right]),
+            };
+            sizes.extend([left as u8, ((left >> 8) & 0xF | (right & 0xF) << 4) as u8, (right >> 4) as u8]);
+        }
+        if symbols.len() % 2 == 1 {
+            sizes.push(0);
+        }
+
+        // whole symbols go into each block, with the number of values they expand to
+        let block_bits = 8 << BLOCK_SIZE_LOG;
+        let mut blocks: Vec<(Vec<bool>, usize)> = vec![(Vec::new(), 0)];
+        for s in &stream {
+            let len = lengths[*s];
+            let code = base[len as usize] + number[*s] as u64 - lowest(len);
+            if blocks.last().unwrap().0.len() + len as usize > block_bits {
+                blocks.push((Vec::new(), 0));
+            }
+            let block = blocks.last_mut().unwrap();
+            block.0.extend((0..len).rev().map(|bit| (code >> bit) & 1 != 0));
+            block.1 += symbols[*s].2;
+        }
+        sizes[4..8].copy_from_slice(&(blocks.len() as u32).to_le_bytes());
+
+        let mut subtable = Subtable { sizes, ..Subtable::default() };
+        let mut starts = Vec::new();
+        let mut start = 0;
+        for (bits, count) in &blocks {
+            let mut block = vec![0u8; 1 << BLOCK_SIZE_LOG];
+            for (i, _) in bits.iter().enumerate().filter(|(_, bit)| **bit) {
+                block[i / 8] |= 0x80 >> (i % 8);
+            }
+            subtable.data.extend(block);
+            subtable.block_lengths.extend((*count as u16 - 1).to_le_bytes());
+            starts.push(start);
+            start += count;
+        }
+
+        // the block and offset of the middle of each span, past the last block for a partial span
+        let span = 1 << SPAN_LOG;
+        for k in 0..values.len().div_ceil(span) {
+            let middle = k * span + span / 2;
+            let block = starts.iter().rposition(|start| *start <= middle.min(values.len() - 1)).unwrap();
+            subtable.sparse_index.extend((block as u32).to_le_bytes());
+            subtable.sparse_index.extend(u16::try_from(middle - starts[block]).unwrap().to_le_bytes());
+        }
+        subtable
+    }
+
+    // the pieces of a table of a king and a piece against a king, in the order they are encoded in
+    fn table_pieces(name: &str) -> [(Player, PieceName); 3] {
+        let letter = name.chars().nth(1).unwrap();
+        let piece = [PieceName::Pawn, PieceName::Knight, PieceName::Bishop, PieceName::Rook, PieceName::Queen]
+            .into_iter()
+            .find(|p| piece_letter(*p) == letter)
+            .unwrap();
+        let king = (Player::White, PieceName::King);
+        if piece == PieceName::Pawn {
+            [(Player::White, piece), king, (Player::Black, PieceName::King)]
+        } else {
+            [king, (Player::White, piece), (Player::Black, PieceName::King)]
+        }
+    }
+
+    // the legal positions of a table with the first piece on files a to d, and below the diagonal without pawns
+    fn for_each_position(name: &str, mut f: impl FnMut(&ChessGameState)) {
+        let pieces = table_pieces(name);
+        let pawns = pieces[0].1 == PieceName::Pawn;
+        for first in (0..64).filter(|sq| sq % 8 < 4 && if pawns { (8..56).contains(sq) } else { sq / 8 <= sq % 8 }) {
+            for second in (0..64).filter(|sq| *sq != first) {
+                for third in (0..64).filter(|sq| *sq != first && *sq != second) {
+                    let mut board = [None; 64];
+                    for (sq, (player, name)) in [first, second, third].into_iter().zip(pieces) {
+                        board[sq] = Some(ChessPiece::new(player, name, true));
+                    }
+                    for side in [Player::White, Player::Black] {
+                        let Ok(game) = ChessGameState::from_pieces(board, side) else {
+                            continue;
+                        };
+                        if game.board().get_king_sq(side.opponent()).not_seen_by(side) {
+                            f(&game);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // writes a WDL or DTZ table of a king and a piece against a king, from the distances to mate.
+    // without pawns the DTZ is the distance to mate, stored in moves or, with FLAG_WIN_PLIES, in plies
+    fn write_table(dir: &Path, dtm: &DtmTables, name: &str, kind: TableKind, flags: u8) {
+        let ix = Indexing::new();
+        let material = Material::parse(name).unwrap();
+        let pieces = table_pieces(name).map(|(player, name)| piece_code(player, name));
+        let files = if material.has_pawns { 4 } else { 1 };
+        let sides = if kind == TableKind::Wdl { 2 } else { 1 };
+        let mut table = Table { bytes: Vec::new(), kind, pairs: vec![vec![PairsData::default(); files]; sides] };
+        for pairs in table.pairs.iter_mut() {
+            for (file, d) in pairs.iter_mut().enumerate() {
+                d.pieces[..3].copy_from_slice(&pieces);
+                set_groups(d, &material, [0, 0xF], file, &ix);
+            }
+        }
+
+        // positions that don't occur are stored as their neighbours, for better compression
+        let mut values: Vec<Vec<Vec<Option<u8>>>> = table.pairs.iter()
+            .map(|pairs| pairs.iter().map(|d| vec![None; d.group_idx[d.group_len.iter().position(|len| *len == 0).unwrap()] as usize]).collect())
+            .collect();
+        for_each_position(name, |game| {
+            let value = match (kind, dtm.probe(game).unwrap()) {
+                (TableKind::Wdl, Dtm::Win(_)) => 4,
+                (TableKind::Wdl, Dtm::Loss(_)) => 0,
+                (TableKind::Wdl, Dtm::Draw) => 2,
+                (TableKind::Dtz, Dtm::Win(moves)) if flags & FLAG_WIN_PLIES != 0 => 2 * moves as u8 - 2,
+                (TableKind::Dtz, Dtm::Win(moves)) => moves as u8 - 1,
+                // only white wins are stored
+                (TableKind::Dtz, _) => return,
+            };
+            let (stm, file, idx) = table.index(&material, game, name, &ix).unwrap();
+            let entry = &mut values[stm % sides][file][idx as usize];
+            assert!(entry.is_none_or(|stored| stored == value), "{} has two values at {}", name, idx);
+            *entry = Some(value);
+        });
+        // a mapped table stores the index of the value in a map
+        let mut map: Vec<u8> = values.iter().flatten().flatten().flatten().copied().collect();
+        map.sort_by_key(|value| Reverse(*value));
+        map.dedup();
+
+        let mut subtables = Vec::new();
+        for file in 0..files {
+            for side_values in &values {
+                let mut last = if kind == TableKind::Wdl { 2 } else { 0 };
+                let mut filled = Vec::with_capacity(side_values[file].len());
+                for value in &side_values[file] {
+                    if let Some(value) = value {
+                        last = if flags & FLAG_MAPPED != 0 { map.iter().position(|v| v == value).unwrap() as u8 } else { *value };
+                    }
+                    filled.push(last);
+                }
+                subtables.push(compress(&filled, flags));
+            }
+        }
+
+        let mut bytes = match kind {
+            TableKind::Wdl => WDL_MAGIC,
+            TableKind::Dtz => DTZ_MAGIC,
+        }.to_vec();
+        bytes.push((sides == 2) as u8 | (material.has_pawns as u8) << 1);
+        for _ in 0..files {
+            // the pieces are encoded as one group, the same for both sides to move
+            bytes.push(0);
+            bytes.extend(pieces.map(|code| code | code << 4));
+        }
+        bytes.resize(bytes.len().next_multiple_of(2), 0);
+        subtables.iter().for_each(|s| bytes.extend(&s.sizes));
+        if kind == TableKind::Dtz {
+            if flags & FLAG_MAPPED != 0 {
+                for _ in 0..files {
+                    // wins, then the empty maps of losses, cursed wins and blessed losses
+                    bytes.push(map.len() as u8);
+                    bytes.extend(&map);
+                    bytes.extend([0, 0, 0]);
+                }
+            }
+            bytes.resize(bytes.len().next_multiple_of(2), 0);
+        }
+        subtables.iter().for_each(|s| bytes.extend(&s.sparse_index));
+        subtables.iter().for_each(|s| bytes.extend(&s.block_lengths));
+        for s in &subtables {
+            bytes.resize(bytes.len().next_multiple_of(64), 0);
+            bytes.extend(&s.data);
+        }
+        bytes.resize(bytes.len().next_multiple_of(64) + 16, 0);
+
+        let entries: usize = values.iter().flatten().map(|v| v.len()).sum();
+        assert!(bytes.len() < entries, "{} isn't compressed", name);
+        let extension = if kind == TableKind::Wdl { "rtbw" } else { "rtbz" };
+        std::fs::write(dir.join(format!("{}.{}", name, extension)), bytes).unwrap();
+    }
+
+    #[test]
+    fn indexing() {
+        let ix = Indexing::new();
+        assert_eq!(ix.map_kk.iter().flatten().max(), Some(&461));
+        assert_eq!(ix.binomial[2][5], 10);
+        assert_eq!(ix.binomial[5][63], 7_028_847);
+        assert_eq!(ix.map_pawns.iter().max(), Some(&47));
+        assert_eq!(ix.lead_pawns_size[1], [6; 4]);
+
+        let material = Material::parse("KRPvKP").unwrap();
+        assert_eq!(material.key2, "KPvKRP");
+        assert!(material.has_pawns && material.has_unique_pieces);
+        assert_eq!(material.pawn_count, [1, 1]);
+        assert!(Material::parse("KRvKX").is_none());
+        assert!(!Material::parse("KNNvK").unwrap().has_unique_pieces);
+    }
+
+    #[test]
+    fn synthetic_tables() {
+        let dir = temp_dir("synthetic");
+        single_value_tables(&dir);
+        let tb = Tablebase::open(dir.to_str().unwrap()).unwrap();
+        assert_eq!(tb.max_pieces(), 3);
+        assert_eq!(tb.table_count(), 1);
+
+        assert_eq!(tb.probe_wdl(&game("8/8/8/8/8/2k5/8/1Q5K w - - 0 1")), Some(Wdl::Win));
+        assert_eq!(tb.probe_wdl(&game("8/8/8/8/8/2k5/8/1Q5K b - - 0 1")), Some(Wdl::Loss));
+        // the colors are swapped for a black queen
+        assert_eq!(tb.probe_wdl(&game("8/8/8/8/8/2K5/8/1q5k b - - 0 1")), Some(Wdl::Win));
+        // the hanging queen is captured
+        assert_eq!(tb.probe_wdl(&game("8/8/8/8/8/2k5/1Q6/7K b - - 0 1")), Some(Wdl::Draw));
+        assert_eq!(tb.probe_dtz(&game("8/8/8/8/8/2k5/1Q6/7K b - - 0 1")), Some(0));
+        // no table for these
+        assert_eq!(tb.probe_wdl(&game("8/8/8/8/8/2k5/8/1R5K w - - 0 1")), None);
+        assert_eq!(tb.probe_wdl(&game("r3k3/8/8/8/8/8/8/4K3 w q - 0 1")), None);
+
+        assert_eq!(tb.probe_dtz(&game("8/8/8/8/8/2k5/8/1Q5K w - - 0 1")), Some(6));
+        // stored for white only, so black looks one move ahead
+        assert_eq!(tb.probe_dtz(&game("8/8/8/8/8/2k5/8/1Q5K b - - 0 1")), Some(-7));
+
+        // any move but one that hangs the queen
+        let best = tb.best_moves(&game("8/8/8/8/8/2k5/8/1Q5K w - - 0 1")).unwrap();
+        let game = game("8/8/8/8/8/2k5/8/1Q5K w - - 0 1");
+        let names: Vec<String> = best.iter().map(|m| game.move_to_uci(m.chess_move)).collect();
+        assert!(names.contains(&String::from("b1b8")));
+        for hanging in ["b1b2", "b1b3", "b1b4", "b1c2", "b1d3"] {
+            assert!(!names.contains(&String::from(hanging)));
+        }
+        assert_eq!(names.len(), game.get_legal_moves().len() - 5);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn generated_tables() {
+        let mut dtm = DtmTables::new();
+        dtm.generate("KPK").unwrap();
+        let dir = temp_dir("generated");
+        write_table(&dir, &dtm, "KQvK", TableKind::Wdl, 0);
+        write_table(&dir, &dtm, "KQvK", TableKind::Dtz, 0);
+        write_table(&dir, &dtm, "KRvK", TableKind::Wdl, 0);
+        write_table(&dir, &dtm, "KRvK", TableKind::Dtz, FLAG_MAPPED | FLAG_WIN_PLIES);
+        write_table(&dir, &dtm, "KPvK", TableKind::Wdl, 0);
+        // the minor pieces are drawn, and KPvK promotes to them
+        write_table(&dir, &dtm, "KBvK", TableKind::Wdl, 0);
+        write_table(&dir, &dtm, "KNvK", TableKind::Wdl, 0);
+        let tb = Tablebase::open(dir.to_str().unwrap()).unwrap();
+        assert_eq!(tb.table_count(), 5);
+
+        assert_eq!(tb.probe_wdl(&game("8/8/8/8/8/2k5/8/1Q5K w - - 0 1")), Some(Wdl::Win));
+        assert_eq!(tb.probe_wdl(&game("8/8/8/8/8/8/8/KBk5 w - - 0 1")), Some(Wdl::Draw));
+        // KRvK: mate in one
+        let mate = game("k7/8/1K6/8/8/8/8/7R w - - 0 1");
+        assert_eq!(tb.probe_dtz(&mate), Some(1));
+        let best = tb.best_moves(&mate).unwrap();
+        assert_eq!(best.len(), 1);
+        assert_eq!(mate.move_to_uci(best[0].chess_move), "h1h8");
+        // KPvK: the pawn can't be stopped
+        let pawn = game("8/4P3/8/8/8/k7/8/K7 w - - 0 1");
+        assert_eq!(tb.probe_wdl(&pawn), Some(Wdl::Win));
+        assert_eq!(tb.probe_dtz(&pawn), Some(1));
+
+        // a sample of the positions against the distances to mate, which without pawns are the DTZ
+        for name in ["KQvK", "KRvK", "KPvK"] {
+            let mut n = 0;
+            for_each_position(name, |game| {
+                n += 1;
+                if n % 97 != 0 {
+                    return;
+                }
+                let (wdl, dtz) = match dtm.probe(game).unwrap() {
+                    Dtm::Win(moves) => (Wdl::Win, 2 * moves as i32 - 1),
+                    Dtm::Loss(0) => (Wdl::Loss, -1),
+                    Dtm::Loss(moves) => (Wdl::Loss, -2 * moves as i32),
+                    Dtm::Draw => (Wdl::Draw, 0),
+                };
+                assert_eq!(tb.probe_wdl(game), Some(wdl), "{}", game.get_fen());
+                if name != "KPvK" {
+                    assert_eq!(tb.probe_dtz(game), Some(dtz), "{}", game.get_fen());
+                }
+            });
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
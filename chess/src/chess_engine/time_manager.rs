@@ -0,0 +1,249 @@
+use std::time::Duration;
+use crate::chess_game::chess_move::ChessMove;
+use crate::chess_game::game_clock::Clock;
+
+// the number of moves to plan for when the time control doesn't say
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+// never plan to use more than this share of the remaining time on one move
+const MAX_USAGE: f64 = 0.8;
+// how far past the soft limit a single move may run
+const HARD_LIMIT_FACTOR: u32 = 4;
+// score drops (in centipawns) between iterations that earn more time
+const SMALL_DROP: i32 = 20;
+const LARGE_DROP: i32 = 50;
+
+// the state of the engine's clock in a game
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct GameTime {
+    pub remaining: Duration,
+    pub increment: Duration,
+    // the moves left until the next time control, if any
+    pub moves_to_go: Option<u32>,
+}
+
+// the (soft, hard) time limits for one move: no new iteration starts after the soft limit,
+// and the search is stopped at the hard limit
+pub fn allocate(game_time: GameTime, overhead: Duration) -> (Duration, Duration) {
+    // the overhead covers the time lost communicating with the GUI
+    let available = game_time.remaining.saturating_sub(overhead);
+    let moves_to_go = game_time.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+    let base = available / moves_to_go + game_time.increment * 3 / 4;
+    let hard = (base * HARD_LIMIT_FACTOR).min(available.mul_f64(MAX_USAGE));
+    (base.min(hard), hard)
+}
+
+// decides how long to think, and whether to start another iteration
+pub struct TimeManager {
+    clock: Box<dyn Clock>,
+    start: Duration,
+    soft: Option<Duration>,
+    hard: Option<Duration>,
+    // grows when the best move keeps changing and decays while it is stable
+    instability: f64,
+    // more time when the score is dropping
+    score_factor: f64,
+    forced: bool,
+    last_best: Option<ChessMove>,
+    last_score: Option<i32>,
+}
+
+impl TimeManager {
+    pub fn new(clock: Box<dyn Clock>) -> Self {
+        let start = clock.now();
+        Self {
+            clock,
+            start,
+            soft: None,
+            hard: None,
+            instability: 0.0,
+            score_factor: 1.0,
+            forced: false,
+            last_best: None,
+            last_score: None,
+        }
+    }
+
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+        self.restart();
+    }
+
+    // starts timing a new search; without a movetime or a game clock the search isn't timed
+    pub fn start(&mut self, movetime: Option<Duration>, game_time: Option<GameTime>, overhead: Duration) {
+        self.restart();
+        (self.soft, self.hard) = match (movetime, game_time) {
+            (Some(movetime), _) => {
+                let limit = movetime.saturating_sub(overhead);
+                (Some(limit), Some(limit))
+            },
+            (None, Some(game_time)) => {
+                let (soft, hard) = allocate(game_time, overhead);
+                (Some(soft), Some(hard))
+            },
+            (None, None) => (None, None),
+        };
+        self.instability = 0.0;
+        self.score_factor = 1.0;
+        self.forced = false;
+        self.last_best = None;
+        self.last_score = None;
+    }
+
+    // restarts the clock, e.g. when the opponent plays the move we were pondering on
+    pub fn restart(&mut self) {
+        self.start = self.clock.now();
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.clock.now().saturating_sub(self.start)
+    }
+
+    pub fn soft_limit(&self) -> Option<Duration> {
+        self.soft
+    }
+
+    pub fn hard_limit(&self) -> Option<Duration> {
+        self.hard
+    }
+
+    pub fn hard_limit_reached(&self) -> bool {
+        self.hard.is_some_and(|hard| self.elapsed() >= hard)
+    }
+
+    // there is only one legal move, so there is nothing to think about
+    pub fn set_forced(&mut self) {
+        self.forced = true;
+    }
+
+    // the soft limit scaled by how unsettled the search looks
+    pub fn adjusted_soft_limit(&self) -> Option<Duration> {
+        let (soft, hard) = self.soft.zip(self.hard)?;
+        if self.forced {
+            return Some(Duration::ZERO);
+        }
+        let scale = (0.8 + self.instability) * self.score_factor;
+        Some(soft.mul_f64(scale).min(hard))
+    }
+
+    // records a completed iteration; returns true if no further iteration should be started
+    pub fn iteration_done(&mut self, best_move: Option<ChessMove>, score: i32) -> bool {
+        if self.last_best.is_some() && best_move != self.last_best {
+            self.instability = (self.instability + 0.6).min(1.2);
+        } else {
+            self.instability /= 2.0;
+        }
+        let drop = self.last_score.map_or(0, |last| last - score);
+        self.score_factor = if drop >= LARGE_DROP {
+            1.5
+        } else if drop >= SMALL_DROP {
+            1.2
+        } else {
+            1.0
+        };
+        self.last_best = best_move;
+        self.last_score = Some(score);
+        self.adjusted_soft_limit().is_some_and(|soft| self.elapsed() >= soft)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::chess_engine::time_manager::{allocate, GameTime, TimeManager};
+    use crate::chess_game::game_clock::MockClock;
+    use crate::chess_game::chess_move::ChessMove;
+    use crate::chess_game::chess_square::SquareID;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn quiet(from: &str, to: &str) -> Option<ChessMove> {
+        Some(ChessMove::Move(SquareID::parse(from).unwrap(), SquareID::parse(to).unwrap()))
+    }
+
+    #[test]
+    fn allocation() {
+        let (soft, hard) = allocate(GameTime { remaining: ms(60_050), increment: ms(0), moves_to_go: None }, ms(50));
+        assert_eq!(soft, ms(2000));
+        assert_eq!(hard, ms(8000));
+
+        let (soft, hard) = allocate(GameTime { remaining: ms(10_050), increment: ms(1000), moves_to_go: Some(1) }, ms(50));
+        assert_eq!(soft, hard);
+        assert_eq!(hard, ms(8000));
+    }
+
+    #[test]
+    fn never_past_the_overhead() {
+        let overhead = ms(30);
+        for remaining in [0, 10, 31, 100, 1000, 5000, 60_000, 600_000] {
+            for increment in [0, 100, 2000, 30_000] {
+                for moves_to_go in [None, Some(1), Some(2), Some(40)] {
+                    let game_time = GameTime { remaining: ms(remaining), increment: ms(increment), moves_to_go };
+                    let (soft, hard) = allocate(game_time, overhead);
+                    assert!(soft <= hard);
+                    assert!(hard <= ms(remaining).saturating_sub(overhead), "{:?}", game_time);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn movetime() {
+        let clock = MockClock::new();
+        let mut tm = TimeManager::new(Box::new(clock.clone()));
+        tm.start(Some(ms(1000)), None, ms(50));
+        assert_eq!(tm.hard_limit(), Some(ms(950)));
+        clock.advance(ms(900));
+        assert!(!tm.hard_limit_reached());
+        clock.advance(ms(50));
+        assert!(tm.hard_limit_reached());
+
+        tm.start(None, None, ms(50));
+        clock.advance(ms(100_000));
+        assert!(!tm.hard_limit_reached());
+        assert!(!tm.iteration_done(None, 0));
+    }
+
+    #[test]
+    fn extensions() {
+        // a soft limit of one second
+        let game_time = GameTime { remaining: ms(30_000), increment: ms(0), moves_to_go: Some(30) };
+        let clock = MockClock::new();
+        let mut tm = TimeManager::new(Box::new(clock.clone()));
+
+        tm.start(None, Some(game_time), ms(0));
+        assert_eq!(tm.soft_limit(), Some(ms(1000)));
+        clock.advance(ms(500));
+        assert!(!tm.iteration_done(quiet("e2", "e4"), 20));
+        clock.advance(ms(400));
+        // a stable best move stops early
+        assert!(tm.iteration_done(quiet("e2", "e4"), 20));
+
+        tm.start(None, Some(game_time), ms(0));
+        clock.advance(ms(500));
+        assert!(!tm.iteration_done(quiet("e2", "e4"), 20));
+        clock.advance(ms(400));
+        // the best move changed
+        assert!(!tm.iteration_done(quiet("d2", "d4"), 20));
+
+        tm.start(None, Some(game_time), ms(0));
+        clock.advance(ms(500));
+        assert!(!tm.iteration_done(quiet("e2", "e4"), 20));
+        clock.advance(ms(400));
+        // the score dropped
+        assert!(!tm.iteration_done(quiet("e2", "e4"), -40));
+        clock.advance(ms(2000));
+        // but only so far
+        assert!(tm.iteration_done(quiet("d2", "d4"), -200));
+    }
+
+    #[test]
+    fn forced_move() {
+        let game_time = GameTime { remaining: ms(30_000), increment: ms(0), moves_to_go: None };
+        let mut tm = TimeManager::new(Box::new(MockClock::new()));
+        tm.start(None, Some(game_time), ms(0));
+        tm.set_forced();
+        assert!(tm.iteration_done(quiet("e2", "e4"), 0));
+    }
+}
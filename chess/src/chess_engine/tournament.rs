@@ -0,0 +1,432 @@
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use crate::chess_engine::search::{SearchConfig, SearchLimits, Searcher};
+use crate::chess_game::chess_move::AnnotatedMove;
+use crate::chess_game::pgn::{parse_pgn, PgnError, PgnGame};
+use crate::chess_game::variant::Variant;
+use crate::chess_game::{ChessGameState, GameResult, Player};
+
+// one of the two players of a match
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    pub name: String,
+    pub config: SearchConfig,
+    pub limits: SearchLimits,
+}
+
+// ends games early once the engines' scores make the outcome clear
+#[derive(Debug, Copy, Clone)]
+pub struct Adjudication {
+    // an engine resigns after scoring at most -resign_score on resign_moves of its moves in a row
+    pub resign_score: i32,
+    pub resign_moves: usize,
+    // a draw once both engines score within draw_score for draw_moves moves each, from draw_move_number on
+    pub draw_score: i32,
+    pub draw_moves: usize,
+    pub draw_move_number: usize,
+    // games still going after this many plies are drawn
+    pub max_plies: usize,
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Self {
+            resign_score: 1000,
+            resign_moves: 3,
+            draw_score: 10,
+            draw_moves: 8,
+            draw_move_number: 40,
+            max_plies: 400,
+        }
+    }
+}
+
+// a sequential probability ratio test of H0: elo = elo0 against H1: elo = elo1
+#[derive(Debug, Copy, Clone)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    // the chances of accepting H1 when H0 holds, and H0 when H1 holds
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Default for Sprt {
+    fn default() -> Self {
+        Self { elo0: 0.0, elo1: 5.0, alpha: 0.05, beta: 0.05 }
+    }
+}
+
+impl Sprt {
+    // H0 is accepted below the lower bound, H1 above the upper one
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+}
+
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn elo_of(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+// wins, draws and losses of the first engine
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub struct Score {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl Score {
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    // the fraction of the points, 0.5 without games
+    pub fn points(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.5;
+        }
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    // the variance of a single game's result
+    fn variance(&self) -> f64 {
+        let points = self.points();
+        let games = self.games() as f64;
+        (self.wins as f64 * (1.0 - points).powi(2)
+            + self.draws as f64 * (0.5 - points).powi(2)
+            + self.losses as f64 * points.powi(2)) / games
+    }
+
+    // the elo difference and the margin of its 95% confidence interval, if neither side scored everything
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let points = self.points();
+        if self.games() == 0 || points <= 0.0 || points >= 1.0 {
+            return None;
+        }
+        let deviation = (self.variance() / self.games() as f64).sqrt();
+        let low = (points - 1.96 * deviation).max(f64::EPSILON);
+        let high = (points + 1.96 * deviation).min(1.0 - f64::EPSILON);
+        Some((elo_of(points), (elo_of(high) - elo_of(low)) / 2.0))
+    }
+
+    // the log-likelihood ratio of the results, using the normal approximation of the generalized SPRT
+    pub fn llr(&self, sprt: &Sprt) -> f64 {
+        let variance = if self.games() == 0 { 0.0 } else { self.variance() };
+        if variance == 0.0 {
+            return 0.0;
+        }
+        let (s0, s1) = (expected_score(sprt.elo0), expected_score(sprt.elo1));
+        (s1 - s0) * (2.0 * self.points() - s0 - s1) * self.games() as f64 / (2.0 * variance)
+    }
+
+    fn add(&mut self, result: GameResult, first_player: Player) {
+        match (result, first_player) {
+            (GameResult::Draw, _) => self.draws += 1,
+            (GameResult::WhiteWin, Player::White) | (GameResult::BlackWin, Player::Black) => self.wins += 1,
+            _ => self.losses += 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchConfig {
+    // each opening is played twice, with the colors reversed; without any the standard position is used
+    pub openings: Vec<ChessGameState>,
+    // pairs of games to play, cycling through the openings
+    pub rounds: usize,
+    // games played at the same time
+    pub threads: usize,
+    pub adjudication: Adjudication,
+    // stop as soon as the test accepts either hypothesis
+    pub sprt: Option<Sprt>,
+    pub event: String,
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        Self {
+            openings: Vec::new(),
+            rounds: 1,
+            threads: 1,
+            adjudication: Adjudication::default(),
+            sprt: None,
+            event: String::from("Self-play match"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchResult {
+    pub names: [String; 2],
+    pub score: Score,
+    // in the order they were started
+    pub games: Vec<PgnGame>,
+    pub sprt: Option<Sprt>,
+    // Some(true) if the SPRT accepted H1, Some(false) if it accepted H0
+    pub accepted: Option<bool>,
+}
+
+impl MatchResult {
+    pub fn to_pgn(&self) -> String {
+        self.games.iter().map(PgnGame::to_pgn).collect::<Vec<_>>().join("\n")
+    }
+}
+
+impl Display for MatchResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let score = &self.score;
+        writeln!(f, "Score of {} vs {}: {} - {} - {} [{:.3}] {}",
+            self.names[0], self.names[1], score.wins, score.losses, score.draws, score.points(), score.games())?;
+        match score.elo() {
+            Some((elo, margin)) => writeln!(f, "Elo difference: {:.1} +/- {:.1}", elo, margin)?,
+            None => writeln!(f, "Elo difference: unknown")?,
+        }
+        if let Some(sprt) = &self.sprt {
+            let (lower, upper) = sprt.bounds();
+            write!(f, "SPRT: llr {:.2} ({:.2}, {:.2}) [{:.1}, {:.1}]", score.llr(sprt), lower, upper, sprt.elo0, sprt.elo1)?;
+            match self.accepted {
+                Some(true) => writeln!(f, ", H1 accepted")?,
+                Some(false) => writeln!(f, ", H0 accepted")?,
+                None => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// the last position of each game of a PGN collection, or one FEN or EPD position per line
+pub fn parse_openings(text: &str) -> Result<Vec<ChessGameState>, PgnError> {
+    if text.trim_start().starts_with('[') || text.contains("1.") {
+        return Ok(parse_pgn(text)?.iter().map(PgnGame::final_position).collect());
+    }
+    let mut openings = Vec::new();
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        // EPD operations follow the first four fields
+        let fen = line.split_whitespace().take(4).collect::<Vec<_>>().join(" ");
+        openings.push(ChessGameState::from_fen(&fen)?);
+    }
+    Ok(openings)
+}
+
+pub(crate) struct PlayedGame {
+    pub moves: Vec<AnnotatedMove>,
+    // the search score of each move, for the side that played it
+    pub scores: Vec<i32>,
+    pub result: GameResult,
+}
+
+// plays one game between fresh searchers, indexed by the color they play
+pub(crate) fn play_game(engines: [&EngineConfig; 2], start: ChessGameState, adjudication: &Adjudication) -> PlayedGame {
+    let mut searchers = engines.map(|engine| Searcher::new(engine.config));
+    let mut game = start;
+    let mut history = Vec::new();
+    let mut moves = Vec::new();
+    let mut scores = Vec::new();
+    let mut resign_counts = [0; 2];
+    let mut draw_count = 0;
+    let result = loop {
+        if let Some(result) = game.result() {
+            break result;
+        }
+        if history.iter().filter(|hash| **hash == game.hash()).count() >= 2 || moves.len() >= adjudication.max_plies {
+            break GameResult::Draw;
+        }
+        let player = game.active_player();
+        let side = usize::from(player);
+        searchers[side].set_game_history(history.clone());
+        let result = searchers[side].search(&game, engines[side].limits);
+        let Some(best_move) = result.best_move.and_then(|m| game.annotate_move(m)) else {
+            break GameResult::win_for(player.opponent());
+        };
+
+        if result.score <= -adjudication.resign_score {
+            resign_counts[side] += 1;
+            if resign_counts[side] >= adjudication.resign_moves {
+                break GameResult::win_for(player.opponent());
+            }
+        } else {
+            resign_counts[side] = 0;
+        }
+        if game.turn() >= adjudication.draw_move_number && result.score.abs() <= adjudication.draw_score {
+            draw_count += 1;
+        } else {
+            draw_count = 0;
+        }
+
+        history.push(game.hash());
+        game.make_move(best_move);
+        moves.push(best_move);
+        scores.push(result.score);
+        if draw_count >= 2 * adjudication.draw_moves {
+            break GameResult::Draw;
+        }
+    };
+    PlayedGame { moves, scores, result }
+}
+
+// plays the two engines against each other, both colors on each opening
+pub fn run_match(first: &EngineConfig, second: &EngineConfig, config: &MatchConfig) -> MatchResult {
+    let openings = if config.openings.is_empty() { vec![ChessGameState::new()] } else { config.openings.clone() };
+    let total = 2 * config.rounds;
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let mut score = Score::default();
+    let mut accepted = None;
+    let mut games: Vec<Option<PgnGame>> = vec![None; total];
+
+    std::thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..config.threads.max(1) {
+            let sender = sender.clone();
+            let (next, stop, openings) = (&next, &stop, &openings);
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= total {
+                        break;
+                    }
+                    // the first engine plays white in the first game of each pair
+                    let engines = if index % 2 == 0 { [first, second] } else { [second, first] };
+                    let start = openings[index / 2 % openings.len()];
+                    let played = play_game(engines, start, &config.adjudication);
+                    if sender.send((index, start, played.moves, played.result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        for (index, start, moves, result) in receiver {
+            let first_player = if index % 2 == 0 { Player::White } else { Player::Black };
+            score.add(result, first_player);
+            let (white, black) = if index % 2 == 0 { (first, second) } else { (second, first) };
+            let mut tags = vec![
+                (String::from("Event"), config.event.clone()),
+                (String::from("Site"), String::from("?")),
+                (String::from("Round"), (index + 1).to_string()),
+                (String::from("White"), white.name.clone()),
+                (String::from("Black"), black.name.clone()),
+                (String::from("Result"), String::from(result.to_pgn())),
+            ];
+            if start.variant() != Variant::Standard {
+                tags.push((String::from("Variant"), String::from(start.variant().name())));
+            }
+            if start.get_fen() != ChessGameState::new().get_fen() {
+                tags.push((String::from("SetUp"), String::from("1")));
+                tags.push((String::from("FEN"), start.get_fen()));
+            }
+            tags.push((String::from("PlyCount"), moves.len().to_string()));
+            games[index] = Some(PgnGame { tags, start, moves, result: String::from(result.to_pgn()) });
+
+            if let Some(sprt) = &config.sprt && accepted.is_none() {
+                let llr = score.llr(sprt);
+                let (lower, upper) = sprt.bounds();
+                if llr <= lower || llr >= upper {
+                    accepted = Some(llr >= upper);
+                    stop.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    });
+
+    MatchResult {
+        names: [first.name.clone(), second.name.clone()],
+        score,
+        games: games.into_iter().flatten().collect(),
+        sprt: config.sprt,
+        accepted,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess_engine::search::{SearchConfig, SearchLimits};
+    use crate::chess_engine::tournament::{parse_openings, play_game, run_match, Adjudication, EngineConfig, MatchConfig, Score, Sprt};
+    use crate::chess_game::{ChessGameState, GameResult};
+
+    fn engine(name: &str, depth: i32) -> EngineConfig {
+        let config = SearchConfig { hash_size_mb: 1, ..SearchConfig::default() };
+        EngineConfig { name: String::from(name), config, limits: SearchLimits { depth: Some(depth), ..SearchLimits::default() } }
+    }
+
+    #[test]
+    fn statistics() {
+        let score = Score { wins: 100, draws: 100, losses: 50 };
+        assert_eq!(score.points(), 0.6);
+        let (elo, margin) = score.elo().unwrap();
+        assert!((elo - 70.44).abs() < 0.01);
+        assert!(margin > 30.0 && margin < 45.0);
+        let sprt = Sprt { elo0: 0.0, elo1: 10.0, alpha: 0.05, beta: 0.05 };
+        assert!((score.llr(&sprt) - 2.384).abs() < 0.01);
+        let (lower, upper) = sprt.bounds();
+        assert!((lower + 2.944).abs() < 0.001 && (upper - 2.944).abs() < 0.001);
+
+        assert_eq!(Score { wins: 3, draws: 0, losses: 0 }.elo(), None);
+        assert_eq!(Score::default().llr(&sprt), 0.0);
+        assert!(Score { wins: 10, draws: 20, losses: 60 }.llr(&sprt) < lower);
+    }
+
+    #[test]
+    fn openings() {
+        let epd = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - bm e5; id \"e4\";\n\n4k3/8/8/8/8/8/8/4K3 w - -\n";
+        let openings = parse_openings(epd).unwrap();
+        assert_eq!(openings.len(), 2);
+        assert!(parse_openings("not a position").is_err());
+        let pgn = parse_openings("1. e4 e5 2. Nf3 *\n\n1. d4 *").unwrap();
+        assert_eq!(pgn[0].get_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+        assert_eq!(pgn.len(), 2);
+    }
+
+    #[test]
+    fn adjudication() {
+        let strong = engine("strong", 2);
+        let adjudication = Adjudication { resign_moves: 1, ..Adjudication::default() };
+        // black resigns at once
+        let start = ChessGameState::from_fen("k7/8/8/8/8/8/QQ6/K7 b - - 0 1").unwrap();
+        let played = play_game([&strong, &strong], start, &adjudication);
+        assert_eq!((played.moves.len(), played.result), (0, GameResult::WhiteWin));
+
+        // bare kings are drawn by the rules, and long games by adjudication
+        let kings = ChessGameState::from_fen("k7/8/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        assert_eq!(play_game([&strong, &strong], kings, &adjudication).result, GameResult::Draw);
+        let short = Adjudication { max_plies: 6, ..Adjudication::default() };
+        let played = play_game([&strong, &strong], ChessGameState::new(), &short);
+        assert_eq!((played.moves.len(), played.scores.len(), played.result), (6, 6, GameResult::Draw));
+    }
+
+    #[test]
+    fn match_games() {
+        let openings = parse_openings("1. e4 e5 *\n1. d4 d5 *").unwrap();
+        let adjudication = Adjudication { max_plies: 40, ..Adjudication::default() };
+        let config = MatchConfig { openings, rounds: 3, threads: 2, adjudication, ..MatchConfig::default() };
+        let result = run_match(&engine("deep", 3), &engine("shallow", 1), &config);
+        assert_eq!(result.score.games(), 6);
+        assert_eq!(result.games.len(), 6);
+        assert!(result.score.wins > result.score.losses);
+        // colors alternate on the same opening, and the third round reuses the first opening
+        assert_eq!(result.games[0].tag("White"), Some("deep"));
+        assert_eq!(result.games[1].tag("White"), Some("shallow"));
+        assert_eq!(result.games[0].start, result.games[1].start);
+        assert_eq!(result.games[0].start, result.games[4].start);
+        assert_ne!(result.games[0].start, result.games[2].start);
+        assert_eq!(result.games[2].tag("Round"), Some("3"));
+        assert!(result.to_string().starts_with("Score of deep vs shallow"));
+
+        let pgn = result.to_pgn();
+        assert_eq!(crate::chess_game::pgn::parse_pgn(&pgn).unwrap().len(), 6);
+
+        // the deeper search is clearly not weaker, which is decided early
+        let sprt = Sprt { elo0: -200.0, elo1: 0.0, alpha: 0.2, beta: 0.2 };
+        let config = MatchConfig { rounds: 50, sprt: Some(sprt), ..config };
+        let result = run_match(&engine("deep", 3), &engine("shallow", 1), &config);
+        assert_eq!(result.accepted, Some(true));
+        assert!(result.score.games() < 100);
+    }
+}
@@ -0,0 +1,215 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::chess_game::chess_move::ChessMove;
+use crate::chess_game::chess_piece::PieceName;
+use crate::chess_game::chess_square::SquareID;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Bound {
+    Exact,
+    // the score is at least this value (fail-high)
+    Lower,
+    // the score is at most this value (fail-low)
+    Upper,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct TTEntry {
+    pub hash: u64,
+    pub depth: i32,
+    pub score: i32,
+    pub bound: Bound,
+    pub best_move: Option<ChessMove>,
+}
+
+// the 19 bit move encoding: kind, from, to and promotion or dropped piece; 0 is no move
+fn encode_move(chess_move: Option<ChessMove>) -> u64 {
+    let (kind, from, to, piece): (u64, usize, usize, usize) = match chess_move {
+        None => return 0,
+        Some(ChessMove::Move(from, to)) => (1, from.into(), to.into(), 0),
+        Some(ChessMove::Capture(from, to)) => (2, from.into(), to.into(), 0),
+        Some(ChessMove::EnPassant(from, to)) => (3, from.into(), to.into(), 0),
+        Some(ChessMove::ShortCastle) => (4, 0, 0, 0),
+        Some(ChessMove::LongCastle) => (5, 0, 0, 0),
+        Some(ChessMove::Promotion(to, name)) => (6, 0, to.into(), name.into()),
+        Some(ChessMove::CapturePromotion(from, to, name)) => (7, from.into(), to.into(), name.into()),
+        Some(ChessMove::Drop(name, to)) => (8, 0, to.into(), name.into()),
+    };
+    kind | (from as u64) << 4 | (to as u64) << 10 | (piece as u64) << 16
+}
+
+fn decode_move(bits: u64) -> Option<ChessMove> {
+    let from = SquareID::from((bits >> 4 & 63) as usize);
+    let to = SquareID::from((bits >> 10 & 63) as usize);
+    let piece = PieceName::from((bits >> 16 & 7) as usize);
+    match bits & 15 {
+        1 => Some(ChessMove::Move(from, to)),
+        2 => Some(ChessMove::Capture(from, to)),
+        3 => Some(ChessMove::EnPassant(from, to)),
+        4 => Some(ChessMove::ShortCastle),
+        5 => Some(ChessMove::LongCastle),
+        6 => Some(ChessMove::Promotion(to, piece)),
+        7 => Some(ChessMove::CapturePromotion(from, to, piece)),
+        8 => Some(ChessMove::Drop(piece, to)),
+        _ => None,
+    }
+}
+
+// everything but the hash in one word: score (16 bits), depth (8), bound (2) and move (19);
+// an empty slot has no bound
+fn pack(entry: &TTEntry) -> u64 {
+    let score = entry.score.clamp(i16::MIN as i32, i16::MAX as i32) as i16 as u16 as u64;
+    let depth = entry.depth.clamp(i8::MIN as i32, i8::MAX as i32) as i8 as u8 as u64;
+    let bound = match entry.bound {
+        Bound::Exact => 1,
+        Bound::Lower => 2,
+        Bound::Upper => 3,
+    };
+    score | depth << 16 | bound << 24 | encode_move(entry.best_move) << 32
+}
+
+fn unpack(hash: u64, data: u64) -> Option<TTEntry> {
+    let bound = match data >> 24 & 3 {
+        1 => Bound::Exact,
+        2 => Bound::Lower,
+        3 => Bound::Upper,
+        _ => return None,
+    };
+    Some(TTEntry {
+        hash,
+        depth: (data >> 16) as u8 as i8 as i32,
+        score: data as u16 as i16 as i32,
+        bound,
+        best_move: decode_move(data >> 32),
+    })
+}
+
+// the key is stored xor-ed with the data, so an entry torn by two threads writing at once
+// fails the hash check instead of returning mixed up data
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+impl Slot {
+    fn load(&self) -> Option<TTEntry> {
+        let key = self.key.load(Ordering::Relaxed);
+        let data = self.data.load(Ordering::Relaxed);
+        unpack(key ^ data, data)
+    }
+}
+
+// a lock-free table that can be shared between search threads
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let count = (size_mb * 1024 * 1024 / size_of::<Slot>()).max(1);
+        Self { slots: (0..count).map(|_| Slot::default()).collect() }
+    }
+
+    fn slot(&self, hash: u64) -> &Slot {
+        &self.slots[(hash % self.slots.len() as u64) as usize]
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<TTEntry> {
+        self.slot(hash).load().filter(|e| e.hash == hash)
+    }
+
+    pub fn store(&self, entry: TTEntry) {
+        let slot = self.slot(entry.hash);
+        let old = slot.load();
+        // keep deeper results for the same position, but always replace other positions
+        let replace = match old {
+            Some(old) => old.hash != entry.hash || entry.depth >= old.depth || entry.bound == Bound::Exact,
+            None => true,
+        };
+        if replace {
+            // don't lose the best move when storing a result that has none
+            let best_move = entry.best_move.or(old.filter(|old| old.hash == entry.hash).and_then(|old| old.best_move));
+            let data = pack(&TTEntry { best_move, ..entry });
+            slot.key.store(entry.hash ^ data, Ordering::Relaxed);
+            slot.data.store(data, Ordering::Relaxed);
+        }
+    }
+
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess_engine::transposition::{decode_move, encode_move, Bound, TTEntry, TranspositionTable};
+    use crate::chess_game::chess_move::ChessMove;
+    use crate::chess_game::chess_piece::PieceName;
+    use crate::chess_game::chess_square::{File, Rank, SquareID};
+
+    #[test]
+    fn store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        let e4 = ChessMove::Move(SquareID(File::E, Rank::Two), SquareID(File::E, Rank::Four));
+        tt.store(TTEntry { hash: 42, depth: 3, score: 25, bound: Bound::Exact, best_move: Some(e4) });
+        assert_eq!(tt.probe(42).map(|e| e.best_move), Some(Some(e4)));
+        assert!(tt.probe(43).is_none());
+
+        // a shallower bound doesn't replace the deeper exact result
+        tt.store(TTEntry { hash: 42, depth: 1, score: -10, bound: Bound::Upper, best_move: None });
+        assert_eq!(tt.probe(42).unwrap().score, 25);
+
+        let mate = TTEntry { hash: 42, depth: 9, score: -29_990, bound: Bound::Lower, best_move: None };
+        tt.store(mate);
+        assert_eq!(tt.probe(42), Some(TTEntry { best_move: Some(e4), ..mate }));
+
+        tt.clear();
+        assert!(tt.probe(42).is_none());
+        assert!(tt.probe(0).is_none());
+    }
+
+    #[test]
+    fn move_encoding() {
+        let a7 = SquareID(File::A, Rank::Seven);
+        let b8 = SquareID(File::B, Rank::Eight);
+        let moves = [
+            ChessMove::Move(a7, b8),
+            ChessMove::Capture(b8, a7),
+            ChessMove::EnPassant(a7, b8),
+            ChessMove::ShortCastle,
+            ChessMove::LongCastle,
+            ChessMove::Promotion(b8, PieceName::Knight),
+            ChessMove::CapturePromotion(a7, b8, PieceName::Queen),
+            ChessMove::Drop(PieceName::Pawn, a7),
+        ];
+        for m in moves {
+            assert_eq!(decode_move(encode_move(Some(m))), Some(m));
+        }
+        assert_eq!(decode_move(encode_move(None)), None);
+    }
+
+    #[test]
+    fn concurrent_writes() {
+        // a small table, so the threads keep overwriting each other's slots
+        let tt = TranspositionTable::new(0);
+        let score_of = |hash: u64| (hash % 1000) as i32;
+        std::thread::scope(|scope| {
+            for t in 0..4u64 {
+                let tt = &tt;
+                scope.spawn(move || {
+                    for i in 0..20_000u64 {
+                        let hash = (i * 4 + t).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+                        tt.store(TTEntry { hash, depth: 1, score: score_of(hash), bound: Bound::Exact, best_move: None });
+                        let probe = hash.wrapping_sub(0x9E37_79B9_7F4A_7C15);
+                        if let Some(entry) = tt.probe(probe) {
+                            assert_eq!(entry.score, score_of(probe));
+                        }
+                    }
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,335 @@
+use std::fmt::{Display, Formatter};
+use crate::chess_engine::evaluation::{evaluate_with, table_index, EvalParams, KING_END, KING_MIDDLE, MAX_PHASE, PHASE_WEIGHTS};
+use crate::chess_engine::search::INFINITY;
+use crate::chess_game::chess_piece::PieceName;
+use crate::chess_game::{ChessGameState, FenError, MoveGen, Player};
+
+// captures are followed this deep when looking for a quiet position
+const QUIESCENCE_DEPTH: usize = 16;
+// piece values, then the tables
+const PARAM_COUNT: usize = 5 + 7 * 64;
+// the pawn value stays at 100, so the weights keep their centipawn scale
+const FIRST_TUNED: usize = 1;
+// Adam optimizer settings; the learning rate is in centipawns
+const LEARNING_RATE: f64 = 1.0;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TuningError {
+    // the line (counting from 1) has no valid position
+    Position(usize, FenError),
+    // or no game result
+    Result(usize),
+}
+
+impl Display for TuningError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TuningError::Position(line, err) => write!(f, "line {}: {}", line, err),
+            TuningError::Result(line) => write!(f, "line {}: missing game result", line),
+        }
+    }
+}
+
+// a result from white's point of view: 1-0, 0-1, 1/2-1/2, or a number from 0 to 1
+fn parse_result(word: &str) -> Option<f64> {
+    let word = word.trim_matches(|c| "\"[];".contains(c));
+    match word {
+        "1-0" => Some(1.0),
+        "0-1" => Some(0.0),
+        "1/2-1/2" | "1/2" => Some(0.5),
+        _ => word.parse::<f64>().ok().filter(|r| (0.0..=1.0).contains(r)),
+    }
+}
+
+// labelled positions, one per line: EPD with a c9 opcode holding the result, or a FEN followed by the result
+pub fn parse_positions(text: &str) -> Result<Vec<(ChessGameState, f64)>, TuningError> {
+    let mut positions = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let result = match line.split_once(" c9 ") {
+            Some((_, operand)) => operand.split(';').next().and_then(parse_result),
+            None => line.split_whitespace().last().and_then(parse_result),
+        };
+        let result = result.ok_or(TuningError::Result(number + 1))?;
+        let fen = line.split_whitespace().take(4).collect::<Vec<_>>().join(" ");
+        let game = ChessGameState::from_fen(&fen).map_err(|err| TuningError::Position(number + 1, err))?;
+        positions.push((game, result));
+    }
+    Ok(positions)
+}
+
+// a captures-only search, returning the position at the end of its principal variation
+fn quiesce(game: &ChessGameState, mut alpha: i32, beta: i32, params: &EvalParams, depth: usize) -> (i32, ChessGameState) {
+    let stand_pat = evaluate_with(game, params);
+    let mut best = (stand_pat, *game);
+    if stand_pat >= beta || depth >= QUIESCENCE_DEPTH {
+        return best;
+    }
+    alpha = alpha.max(stand_pat);
+    for chess_move in game.get_moves(MoveGen::Tactical) {
+        let Some(child) = game.make_pseudo_legal(chess_move) else {
+            continue;
+        };
+        let (score, leaf) = quiesce(&child, -beta, -alpha, params, depth + 1);
+        if -score > best.0 {
+            best = (-score, leaf);
+        }
+        if -score > alpha {
+            alpha = -score;
+            if alpha >= beta {
+                break;
+            }
+        }
+    }
+    best
+}
+
+pub fn quiet_position(game: &ChessGameState, params: &EvalParams) -> ChessGameState {
+    quiesce(game, -INFINITY, INFINITY, params, 0).1
+}
+
+fn to_vector(params: &EvalParams) -> Vec<f64> {
+    params.piece_values.iter().chain(params.tables.iter().flatten()).map(|v| *v as f64).collect()
+}
+
+fn from_vector(vector: &[f64]) -> EvalParams {
+    let rounded: Vec<i32> = vector.iter().map(|v| v.round() as i32).collect();
+    let mut params = EvalParams { piece_values: [0; 5], tables: [[0; 64]; 7] };
+    params.piece_values.copy_from_slice(&rounded[..5]);
+    for (table, values) in params.tables.iter_mut().zip(rounded[5..].chunks(64)) {
+        table.copy_from_slice(values);
+    }
+    params
+}
+
+fn table_param(table: usize, index: usize) -> usize {
+    5 + table * 64 + index
+}
+
+// the evaluation from white's point of view is linear in the weights; these are the coefficients
+fn features(game: &ChessGameState) -> Vec<(usize, f64)> {
+    let mut features = Vec::new();
+    let mut kings = Vec::new();
+    let mut phase = 0;
+    for sq in game.board().iter() {
+        let Some(piece) = sq.get_piece() else {
+            continue;
+        };
+        let player = piece.get_owner();
+        let sign = if player == Player::White { 1.0 } else { -1.0 };
+        let index = table_index(sq.get_id(), player);
+        let name = piece.get_name();
+        phase += PHASE_WEIGHTS[usize::from(name)];
+        if name == PieceName::King {
+            kings.push((sign, index));
+        } else {
+            features.push((usize::from(name), sign));
+            features.push((table_param(usize::from(name), index), sign));
+        }
+    }
+    let middle = phase.min(MAX_PHASE) as f64 / MAX_PHASE as f64;
+    for (sign, index) in kings {
+        features.push((table_param(KING_MIDDLE, index), sign * middle));
+        features.push((table_param(KING_END, index), sign * (1.0 - middle)));
+    }
+    // identical pieces share their value
+    features.sort_by_key(|(param, _)| *param);
+    features.dedup_by(|(param, weight), (kept, total)| {
+        if param == kept {
+            *total += *weight;
+        }
+        param == kept
+    });
+    features
+}
+
+// the expected score for a white advantage in centipawns
+fn sigmoid(score: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score / 400.0))
+}
+
+struct TuningPosition {
+    features: Vec<(usize, f64)>,
+    // from white's point of view
+    result: f64,
+}
+
+// texel tuning: fits the evaluation to game results, by minimizing the squared error of the sigmoid of the
+// evaluation of quiet positions
+pub struct Tuner {
+    positions: Vec<TuningPosition>,
+    params: Vec<f64>,
+}
+
+impl Tuner {
+    pub fn new(positions: &[(ChessGameState, f64)], params: &EvalParams) -> Self {
+        let positions = positions.iter()
+            .map(|(game, result)| TuningPosition { features: features(&quiet_position(game, params)), result: *result })
+            .collect();
+        Self { positions, params: to_vector(params) }
+    }
+
+    pub fn params(&self) -> EvalParams {
+        from_vector(&self.params)
+    }
+
+    fn evaluate(&self, position: &TuningPosition) -> f64 {
+        position.features.iter().map(|(param, weight)| self.params[*param] * weight).sum()
+    }
+
+    pub fn error(&self, k: f64) -> f64 {
+        let total: f64 = self.positions.iter()
+            .map(|position| (position.result - sigmoid(self.evaluate(position), k)).powi(2))
+            .sum();
+        total / self.positions.len().max(1) as f64
+    }
+
+    // the scaling constant that best fits the current weights, by golden section search
+    pub fn optimal_k(&self) -> f64 {
+        let ratio = (5f64.sqrt() - 1.0) / 2.0;
+        let (mut low, mut high) = (0.0, 10.0);
+        while high - low > 1e-4 {
+            let a = high - ratio * (high - low);
+            let b = low + ratio * (high - low);
+            if self.error(a) < self.error(b) {
+                high = b;
+            } else {
+                low = a;
+            }
+        }
+        (low + high) / 2.0
+    }
+
+    fn gradient(&self, k: f64) -> Vec<f64> {
+        let mut gradient = vec![0.0; PARAM_COUNT];
+        let scale = k * 10f64.ln() / 400.0;
+        for position in &self.positions {
+            let expected = sigmoid(self.evaluate(position), k);
+            let slope = -2.0 * (position.result - expected) * expected * (1.0 - expected) * scale;
+            for (param, weight) in &position.features {
+                gradient[*param] += slope * weight;
+            }
+        }
+        let count = self.positions.len().max(1) as f64;
+        gradient.iter().map(|g| g / count).collect()
+    }
+
+    // gradient descent with Adam for a number of passes over the positions; returns the final error
+    pub fn tune(&mut self, k: f64, epochs: usize) -> f64 {
+        let mut moments = vec![0.0; PARAM_COUNT];
+        let mut velocities = vec![0.0; PARAM_COUNT];
+        for epoch in 1..=epochs {
+            let gradient = self.gradient(k);
+            for param in FIRST_TUNED..PARAM_COUNT {
+                moments[param] = BETA1 * moments[param] + (1.0 - BETA1) * gradient[param];
+                velocities[param] = BETA2 * velocities[param] + (1.0 - BETA2) * gradient[param].powi(2);
+                let moment = moments[param] / (1.0 - BETA1.powi(epoch as i32));
+                let velocity = velocities[param] / (1.0 - BETA2.powi(epoch as i32));
+                self.params[param] -= LEARNING_RATE * moment / (velocity.sqrt() + 1e-8);
+            }
+        }
+        self.error(k)
+    }
+}
+
+// the weights as Rust constants, to replace the defaults in the evaluation
+pub fn rust_constants(params: &EvalParams) -> String {
+    let names = ["pawn", "knight", "bishop", "rook", "queen", "king in the middlegame", "king in the endgame"];
+    let values = params.piece_values.map(|v| v.to_string()).join(", ");
+    let mut text = format!("#[rustfmt::skip]\npub const TUNED_PARAMS: EvalParams = EvalParams {{\n    piece_values: [{}],\n    tables: [\n", values);
+    for (name, table) in names.iter().zip(&params.tables) {
+        text += &format!("        // {}\n        [\n", name);
+        for row in table.chunks(8) {
+            let row: Vec<String> = row.iter().map(|v| format!("{:>4}", v)).collect();
+            text += &format!("            {},\n", row.join(","));
+        }
+        text += "        ],\n";
+    }
+    text + "    ],\n};\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use game::rng::Rng;
+    use crate::chess_engine::evaluation::{evaluate_with, DEFAULT_PARAMS};
+    use crate::chess_engine::tuning::{features, parse_positions, quiet_position, rust_constants, sigmoid, to_vector, Tuner, TuningError};
+    use crate::chess_game::{ChessGameState, FenError};
+
+    #[test]
+    fn read_positions() {
+        let text = "\
+rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - c9 \"1/2-1/2\"; id \"e4\";
+# a comment
+4k3/8/8/8/8/8/3Q4/4K3 w - - 0 1 [1.0]
+4k3/8/8/8/8/8/3q4/4K3 w - - 0-1
+
+4k3/8/8/8/8/8/8/4K3 w - - 0.5
+";
+        let positions = parse_positions(text).unwrap();
+        let results: Vec<f64> = positions.iter().map(|(_, r)| *r).collect();
+        assert_eq!(results, vec![0.5, 1.0, 0.0, 0.5]);
+        assert_eq!(parse_positions("4k3/8/8/8/8/8/8/4K3 w - - 2-0").unwrap_err(), TuningError::Result(1));
+        assert_eq!(parse_positions("4k3/8/8/8/8/8/8/4K4 w - - 1-0").unwrap_err(), TuningError::Position(1, FenError::Placement));
+    }
+
+    #[test]
+    fn quiet_positions() {
+        // the queen takes the hanging rook, which can't be recaptured
+        let game = ChessGameState::from_fen("4k3/8/8/3r4/8/8/3Q4/4K3 w - - 0 1").unwrap();
+        assert_eq!(quiet_position(&game, &DEFAULT_PARAMS).get_fen(), "4k3/8/8/3Q4/8/8/8/4K3 b - - 0 1");
+        let quiet = ChessGameState::new();
+        assert_eq!(quiet_position(&quiet, &DEFAULT_PARAMS), quiet);
+
+        // the features reproduce the evaluation
+        let game = ChessGameState::from_fen("r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/3P1N2/PPP2PPP/RNBQK2R b KQkq - 0 5").unwrap();
+        let params = to_vector(&DEFAULT_PARAMS);
+        let linear: f64 = features(&game).iter().map(|(param, weight)| params[*param] * weight).sum();
+        assert!((linear + evaluate_with(&game, &DEFAULT_PARAMS) as f64).abs() < 1.0);
+    }
+
+    #[test]
+    fn tuning_recovers_weights() {
+        // positions from random games, labelled with the expected score of a stronger knight
+        let mut rng = Rng::new(3);
+        let mut positions = Vec::new();
+        while positions.len() < 400 {
+            let mut game = ChessGameState::new();
+            for _ in 0..(20 + rng.below(60)) {
+                let moves = game.get_legal_moves();
+                if moves.is_empty() {
+                    break;
+                }
+                game.make_move(*moves.iter().nth(rng.below(moves.len())).unwrap());
+            }
+            positions.push((game, 0.5));
+        }
+        let mut truth = DEFAULT_PARAMS;
+        truth.piece_values[1] = 420;
+        let mut tuner = Tuner::new(&positions, &DEFAULT_PARAMS);
+        let truth_vector = to_vector(&truth);
+        for position in tuner.positions.iter_mut() {
+            let score = position.features.iter().map(|(param, weight)| truth_vector[*param] * weight).sum();
+            position.result = sigmoid(score, 1.0);
+        }
+
+        let before = tuner.error(1.0);
+        let after = tuner.tune(1.0, 300);
+        assert!(after < before / 5.0);
+        let tuned = tuner.params();
+        assert_eq!(tuned.piece_values[0], 100);
+        let knight = ChessGameState::from_fen("4k3/8/8/8/8/8/3N4/4K3 w - - 0 1").unwrap();
+        assert!(evaluate_with(&knight, &tuned) > evaluate_with(&knight, &DEFAULT_PARAMS) + 50);
+
+        tuner.params = truth_vector;
+        assert!((tuner.optimal_k() - 1.0).abs() < 0.01);
+
+        let constants = rust_constants(&tuned);
+        assert!(constants.starts_with("#[rustfmt::skip]\npub const TUNED_PARAMS: EvalParams = EvalParams {\n"));
+        assert_eq!(constants.lines().count(), 4 + 7 * 11 + 2);
+    }
+}
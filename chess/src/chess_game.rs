@@ -1,12 +1,25 @@
+use std::fmt::{Display, Formatter};
 use crate::chess_game::chess_board::ChessBoard;
 use crate::chess_game::chess_move::{AnnotatedMove, Annotation, ChessMove, MoveList};
 use crate::chess_game::chess_piece::{ChessPiece, PieceName};
 use crate::chess_game::chess_square::{ChessSquare, File, Rank, SquareID, SquareOffset};
+use crate::chess_game::variant::Variant;
+use crate::chess_game::zobrist::KEYS;
 
 pub mod chess_square;
 pub mod chess_piece;
 pub mod chess_move;
 pub mod chess_board;
+pub mod zobrist;
+pub mod notation;
+pub mod pgn;
+pub mod variant;
+pub mod setup;
+pub mod game_clock;
+pub mod session;
+
+// the fifty-move rule is counted in half-moves since the last capture or pawn move
+const FIFTY_MOVE_PLIES: usize = 100;
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum Player {
@@ -23,6 +36,15 @@ impl Player {
     }
 }
 
+impl From<Player> for usize {
+    fn from(player: Player) -> Self {
+        match player {
+            Player::White => 0,
+            Player::Black => 1,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum GameResult {
     WhiteWin,
@@ -30,6 +52,69 @@ pub enum GameResult {
     Draw,
 }
 
+impl GameResult {
+    pub fn win_for(player: Player) -> GameResult {
+        match player {
+            Player::White => GameResult::WhiteWin,
+            Player::Black => GameResult::BlackWin,
+        }
+    }
+
+    // the result as written in PGN
+    pub fn to_pgn(self) -> &'static str {
+        match self {
+            GameResult::WhiteWin => "1-0",
+            GameResult::BlackWin => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        }
+    }
+}
+
+// which pseudo-legal moves to generate
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum MoveGen {
+    All,
+    // captures and promotions
+    Tactical,
+    Quiet,
+}
+
+impl MoveGen {
+    fn accepts(&self, chess_move: &ChessMove) -> bool {
+        match self {
+            MoveGen::All => true,
+            MoveGen::Tactical => chess_move.is_tactical(),
+            MoveGen::Quiet => !chess_move.is_tactical(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum FenError {
+    FieldCount,
+    Placement,
+    ActivePlayer,
+    Castling,
+    EnPassant,
+    Clock,
+    Kings,
+    Checks,
+}
+
+impl Display for FenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FenError::FieldCount => write!(f, "expected 4 to 6 space separated fields"),
+            FenError::Placement => write!(f, "invalid piece placement"),
+            FenError::ActivePlayer => write!(f, "active player must be 'w' or 'b'"),
+            FenError::Castling => write!(f, "invalid castling availability"),
+            FenError::EnPassant => write!(f, "invalid en passant square"),
+            FenError::Clock => write!(f, "invalid halfmove clock or fullmove number"),
+            FenError::Kings => write!(f, "each player must have exactly one king"),
+            FenError::Checks => write!(f, "invalid check counts"),
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub struct ChessGameState {
@@ -39,6 +124,11 @@ pub struct ChessGameState {
     ep_square: Option<SquareID>,
     draw_clock: usize,
     turn_num: usize,
+    variant: Variant,
+    // crazyhouse pockets: the number of each piece but the king a player can drop, by player and piece
+    pockets: [[u8; 5]; 2],
+    // three-check: the number of checks each player has given
+    checks: [u8; 2],
 }
 
 impl ChessGameState {
@@ -50,13 +140,241 @@ impl ChessGameState {
             ep_square: None,
             draw_clock: 0,
             turn_num: 1,
+            variant: Variant::Standard,
+            pockets: [[0; 5]; 2],
+            checks: [0; 2],
+        }
+    }
+
+    // the starting position of a variant
+    pub fn with_variant(variant: Variant) -> Self {
+        Self::from_variant_fen(variant, variant.start_fen()).expect("variants start from a valid position")
+    }
+
+    pub fn from_fen(fen: &str) -> Result<Self, FenError> {
+        Self::from_variant_fen(Variant::Standard, fen)
+    }
+
+    pub fn from_variant_fen(variant: Variant, fen: &str) -> Result<Self, FenError> {
+        let mut fields: Vec<&str> = fen.split_whitespace().collect();
+        // three-check counts follow the move number, e.g. +1+0 once white has given a check
+        let mut checks = [0; 2];
+        if variant.counts_checks() && let Some(counts) = fields.pop_if(|f| f.starts_with('+')) {
+            let counts: Vec<Option<u8>> = counts[1..].split('+').map(|n| n.parse().ok().filter(|n| *n <= 3)).collect();
+            match counts[..] {
+                [Some(white), Some(black)] => checks = [white, black],
+                _ => return Err(FenError::Checks),
+            }
+        }
+        if fields.len() < 4 || fields.len() > 6 {
+            return Err(FenError::FieldCount);
+        }
+
+        let active_player = match fields[1] {
+            "w" => Player::White,
+            "b" => Player::Black,
+            _ => return Err(FenError::ActivePlayer),
+        };
+
+        // crazyhouse pockets follow the placement in brackets: ...RNBQKBNR[Qp]
+        let (placement, pocket_fen) = match fields[0].split_once('[') {
+            Some((placement, pocket)) if variant.has_pockets() => (placement, pocket.strip_suffix(']').ok_or(FenError::Placement)?),
+            Some(_) => return Err(FenError::Placement),
+            None => (fields[0], ""),
+        };
+        let mut pockets = [[0; 5]; 2];
+        for c in pocket_fen.chars().filter(|c| *c != '-') {
+            let piece = ChessPiece::from_symbol(c, true).filter(|p| p.get_name() != PieceName::King).ok_or(FenError::Placement)?;
+            pockets[usize::from(piece.get_owner())][usize::from(piece.get_name())] += 1;
+        }
+
+        let mut pieces: [Option<ChessPiece>; 64] = [None; 64];
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::Placement);
+        }
+        for (i, rank_fen) in ranks.iter().enumerate() {
+            let rank: Rank = (7 - i).into();
+            let mut file = 0;
+            for c in rank_fen.chars() {
+                // a tilde marks the piece before it as promoted
+                if c == '~' && file > 0 && let Some(piece) = pieces[usize::from(SquareID((file - 1).into(), rank))].as_mut() {
+                    piece.set_promoted(true);
+                } else if let Some(empty) = c.to_digit(10) {
+                    if empty == 0 {
+                        return Err(FenError::Placement);
+                    }
+                    file += empty as usize;
+                } else {
+                    if file >= 8 {
+                        return Err(FenError::Placement);
+                    }
+                    let id = SquareID(file.into(), rank);
+                    pieces[usize::from(id)] = Some(ChessPiece::from_symbol(c, false).ok_or(FenError::Placement)?);
+                    file += 1;
+                }
+            }
+            if file != 8 {
+                return Err(FenError::Placement);
+            }
+        }
+
+        let rook_files = Self::parse_castling(fields[2], &pieces)?;
+        let pieces = std::array::from_fn(|i| pieces[i].map(|p| Self::fen_piece_moved(p, i.into(), rook_files)));
+
+        let ep_square = match fields[3] {
+            "-" => None,
+            sq => {
+                let id = SquareID::parse(sq).ok_or(FenError::EnPassant)?;
+                if id.rank() != Rank::Three && id.rank() != Rank::Six {
+                    return Err(FenError::EnPassant);
+                }
+                Some(id)
+            },
+        };
+
+        let draw_clock = match fields.get(4) {
+            Some(clock) => clock.parse().map_err(|_| FenError::Clock)?,
+            None => 0,
+        };
+        let turn_num = match fields.get(5) {
+            Some(turn) => turn.parse().map_err(|_| FenError::Clock)?,
+            None => 1,
+        };
+        Self::from_parts(variant, pieces, pockets, checks, active_player, ep_square, draw_clock, turn_num)
+    }
+
+    // the Chess960 starting position with the given number (0 to 959) in Scharnagl's numbering, 518 being the
+    // standard one
+    pub fn from_chess960(index: usize) -> Option<Self> {
+        // the remaining pieces go on the n-th empty square, counting from the a-file
+        fn place(rank: &mut [Option<char>; 8], n: usize, piece: char) {
+            let file = (0..8).filter(|f| rank[*f].is_none()).nth(n).unwrap();
+            rank[file] = Some(piece);
         }
+        if index >= 960 {
+            return None;
+        }
+        let mut rank = [None; 8];
+        rank[index % 4 * 2 + 1] = Some('b');
+        rank[index / 4 % 4 * 2] = Some('b');
+        place(&mut rank, index / 16 % 6, 'q');
+        let knights = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+        let (first, second) = knights[index / 96];
+        place(&mut rank, second, 'n');
+        place(&mut rank, first, 'n');
+        for piece in ['r', 'k', 'r'] {
+            place(&mut rank, 0, piece);
+        }
+        let black: String = rank.iter().flatten().collect();
+        let fen = format!("{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1", black, black.to_uppercase());
+        Self::from_variant_fen(Variant::Chess960, &fen).ok()
+    }
+
+    // a position without castling rights or en passant square, e.g. from an endgame table
+    pub fn from_pieces(pieces: [Option<ChessPiece>; 64], active_player: Player) -> Result<Self, FenError> {
+        let pieces = std::array::from_fn(|i| pieces[i].map(|p| Self::fen_piece_moved(p, i.into(), [None; 4])));
+        Self::from_parts(Variant::Standard, pieces, [[0; 5]; 2], [0; 2], active_player, None, 0, 1)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn from_parts(variant: Variant, pieces: [Option<ChessPiece>; 64], pockets: [[u8; 5]; 2], checks: [u8; 2], active_player: Player,
+                  ep_square: Option<SquareID>, draw_clock: usize, turn_num: usize) -> Result<Self, FenError> {
+        for player in [Player::White, Player::Black] {
+            let kings = pieces.iter()
+                .filter(|p| p.is_some_and(|p| p.get_name() == PieceName::King && p.get_owner() == player))
+                .count();
+            if variant.king_count(player).is_some_and(|count| count != kings) {
+                return Err(FenError::Kings);
+            }
+        }
+
+        let mut game = Self {
+            board: ChessBoard::from_pieces(pieces),
+            active_player,
+            result: None,
+            ep_square,
+            draw_clock,
+            turn_num,
+            variant,
+            pockets,
+            checks,
+        };
+        game.result = variant.result(&game);
+        if game.result.is_none() && !game.has_legal_moves() {
+            game.result = Some(variant.no_moves_result(&game));
+        }
+        Ok(game)
+    }
+
+    // the files of the castling rooks in FEN order (K, Q, k, q), from X-FEN, where KQkq stand for the outermost
+    // rook on that side of the king, or Shredder-FEN, which names the rook files (HAha)
+    fn parse_castling(field: &str, pieces: &[Option<ChessPiece>; 64]) -> Result<[Option<File>; 4], FenError> {
+        let mut rook_files = [None; 4];
+        if field == "-" {
+            return Ok(rook_files);
+        }
+        for c in field.chars() {
+            let player = if c.is_ascii_uppercase() { Player::White } else { Player::Black };
+            let home_rank = match player {
+                Player::White => Rank::One,
+                Player::Black => Rank::Eight,
+            };
+            let has_piece = |f: usize, name: PieceName| {
+                pieces[usize::from(SquareID(f.into(), home_rank))].is_some_and(|p| p.get_owner() == player && p.get_name() == name)
+            };
+            let king_file = (0..8).find(|f| has_piece(*f, PieceName::King));
+            let (short, rook_file) = match c.to_ascii_lowercase() {
+                'k' => (true, king_file.and_then(|k| (k + 1..8).rev().find(|f| has_piece(*f, PieceName::Rook)))),
+                'q' => (false, king_file.and_then(|k| (0..k).find(|f| has_piece(*f, PieceName::Rook)))),
+                file @ 'a'..='h' => {
+                    let file = file as usize - 'a' as usize;
+                    (king_file.is_some_and(|k| file > k), has_piece(file, PieceName::Rook).then_some(file))
+                },
+                _ => return Err(FenError::Castling),
+            };
+            // rights without a king or rook to castle with are ignored
+            if king_file.is_some() {
+                rook_files[usize::from(player) * 2 + usize::from(!short)] = rook_file.map(File::from);
+            }
+        }
+        Ok(rook_files)
+    }
+
+    // FEN doesn't record which pieces have moved, so infer it from the starting squares and castling rights
+    fn fen_piece_moved(mut piece: ChessPiece, id: SquareID, rook_files: [Option<File>; 4]) -> ChessPiece {
+        let (home_rank, pawn_rank, rights) = match piece.get_owner() {
+            Player::White => (Rank::One, Rank::Two, &rook_files[..2]),
+            Player::Black => (Rank::Eight, Rank::Seven, &rook_files[2..]),
+        };
+        let moved = match piece.get_name() {
+            // horde pawns on the first rank can advance two squares as well
+            PieceName::Pawn => id.rank() != pawn_rank && id.rank() != home_rank,
+            PieceName::King => id.rank() != home_rank || rights.iter().all(Option::is_none),
+            PieceName::Rook => id.rank() != home_rank || !rights.contains(&Some(id.file())),
+            _ => false,
+        };
+        piece.set_moved(moved);
+        piece
     }
 
     pub fn board(&self) -> &ChessBoard {
         &self.board
     }
 
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    // the pieces the player can drop, indexed by PieceName from pawn to queen
+    pub fn pocket(&self, player: Player) -> [u8; 5] {
+        self.pockets[usize::from(player)]
+    }
+
+    pub fn checks_given(&self, player: Player) -> u8 {
+        self.checks[usize::from(player)]
+    }
+
     pub fn active_player(&self) -> Player {
         self.active_player
     }
@@ -69,7 +387,80 @@ impl ChessGameState {
         self.turn_num
     }
 
+    pub fn ep_square(&self) -> Option<SquareID> {
+        self.ep_square
+    }
+
+    pub fn draw_clock(&self) -> usize {
+        self.draw_clock
+    }
+
+    pub fn in_check(&self) -> bool {
+        self.variant.in_check(self, self.active_player)
+    }
+
+    // whether the player could mate by some series of legal moves: anything besides the king will do, except that
+    // a lone knight or bishop needs the opponent to have something besides a king to block with
+    pub fn has_mating_material(&self, player: Player) -> bool {
+        let pieces = |owner: Player| self.board.iter()
+            .filter_map(|sq| sq.get_piece())
+            .filter(move |p| p.get_owner() == owner && p.get_name() != PieceName::King)
+            .map(|p| p.get_name())
+            .collect::<Vec<_>>();
+        match pieces(player)[..] {
+            [] => false,
+            [PieceName::Knight | PieceName::Bishop] => !pieces(player.opponent()).is_empty(),
+            _ => true,
+        }
+    }
+
+    // ends the game because the player ran out of time: a loss, or a draw if the opponent couldn't mate anyway
+    pub fn flag(&mut self, player: Player) {
+        if self.result.is_some() {
+            return;
+        }
+        let opponent = player.opponent();
+        self.result = Some(match self.variant.has_royal_king() && !self.has_mating_material(opponent) {
+            true => GameResult::Draw,
+            false => GameResult::win_for(opponent),
+        });
+    }
+
+    pub fn hash(&self) -> u64 {
+        let mut hash = KEYS.side(self.active_player);
+        for sq in self.board.iter() {
+            if let Some(piece) = sq.get_piece() {
+                hash ^= KEYS.piece(piece, sq.get_id());
+            }
+        }
+        for (i, right) in self.castling_rights().iter().enumerate() {
+            if *right {
+                hash ^= KEYS.castling(i);
+            }
+        }
+        if let Some(ep_sq) = self.ep_square {
+            hash ^= KEYS.ep(ep_sq);
+        }
+        for player in [Player::White, Player::Black] {
+            for (name, count) in self.pocket(player).into_iter().enumerate() {
+                hash ^= KEYS.pocket(player, name.into(), count);
+            }
+            hash ^= KEYS.checks(player, self.checks_given(player));
+        }
+        hash
+    }
+
+    // castling rights are written in X-FEN, which is the same as standard FEN outside Chess960
     pub fn get_fen(&self) -> String {
+        self.fen(false)
+    }
+
+    // castling rights are written as the files of the rooks, e.g. HAha
+    pub fn get_shredder_fen(&self) -> String {
+        self.fen(true)
+    }
+
+    fn fen(&self, shredder: bool) -> String {
         let mut fen = String::new();
         for r in (0..8).rev() {
             fen += self.get_rank_fen(r.into()).as_str();
@@ -77,11 +468,21 @@ impl ChessGameState {
                 fen += "/";
             }
         }
+        if self.variant.has_pockets() {
+            fen += "[";
+            for player in [Player::White, Player::Black] {
+                for name in (0..5).rev() {
+                    let piece = ChessPiece::new(player, name.into(), true);
+                    fen += &piece.symbol().repeat(self.pockets[usize::from(player)][name].into());
+                }
+            }
+            fen += "]";
+        }
         match self.active_player {
             Player::White => fen += " w ",
             Player::Black => fen += " b ",
         };
-        fen += self.get_castling_fen().as_str();
+        fen += self.get_castling_fen(shredder).as_str();
         fen += " ";
         match self.ep_square {
             None => fen += "-",
@@ -91,6 +492,9 @@ impl ChessGameState {
         fen += self.draw_clock.to_string().as_str();
         fen += " ";
         fen += self.turn_num.to_string().as_str();
+        if self.variant.counts_checks() {
+            fen += &format!(" +{}+{}", self.checks[0], self.checks[1]);
+        }
         fen
     }
 
@@ -105,6 +509,9 @@ impl ChessGameState {
                     empty_sq = 0;
                 }
                 rank_fen += piece.to_string().as_str();
+                if piece.is_promoted() && self.variant.has_pockets() {
+                    rank_fen += "~";
+                }
             } else {
                 empty_sq += 1;
             }
@@ -115,32 +522,64 @@ impl ChessGameState {
         rank_fen
     }
 
-    fn get_castling_fen(&self) -> String {
+    fn get_castling_fen(&self, shredder: bool) -> String {
         let mut castling_fen = String::new();
-        let w_king = self.castling_valid(SquareID(File::E, Rank::One), PieceName::King);
-        let wk_rook = self.castling_valid(SquareID(File::H, Rank::One), PieceName::Rook);
-        if w_king && wk_rook {
-            castling_fen += "K";
-        }
-        let wq_rook = self.castling_valid(SquareID(File::A, Rank::One), PieceName::Rook);
-        if w_king && wq_rook {
-            castling_fen += "Q";
-        }
-
-        let b_king = self.castling_valid(SquareID(File::E, Rank::Eight), PieceName::King);
-        let bk_rook = self.castling_valid(SquareID(File::H, Rank::Eight), PieceName::Rook);
-        if b_king && bk_rook {
-            castling_fen += "k";
+        for (i, rook) in self.castling_rooks().into_iter().enumerate() {
+            let Some(rook) = rook else {
+                continue;
+            };
+            let player = if i < 2 { Player::White } else { Player::Black };
+            let short = i % 2 == 0;
+            let rook_file: usize = rook.file().into();
+            // X-FEN only names the file of a rook with another one further out on the same side
+            let outermost = !self.board.iter().any(|sq| {
+                let file: usize = sq.get_id().file().into();
+                sq.get_id().rank() == rook.rank() && (file > rook_file) == short && file != rook_file
+                    && sq.get_piece().is_some_and(|p| p.get_owner() == player && p.get_name() == PieceName::Rook)
+            });
+            castling_fen += &match (outermost && !shredder, player) {
+                (true, _) => String::from(["K", "Q", "k", "q"][i]),
+                (false, Player::White) => rook.to_str()[..1].to_uppercase(),
+                (false, Player::Black) => rook.to_str()[..1].to_string(),
+            };
         }
-        let bq_rook = self.castling_valid(SquareID(File::A, Rank::Eight), PieceName::Rook);
-        if b_king && bq_rook {
-            castling_fen += "q";
+        if castling_fen.is_empty() {
+            castling_fen += "-";
         }
         castling_fen
     }
 
-    fn castling_valid(&self, id: SquareID, name: PieceName) -> bool {
-        self.board.square_by_id(id).get_piece().is_some_and(|p| p.get_name() == name && p.not_moved())
+    // castling availability in FEN order: K, Q, k, q
+    pub fn castling_rights(&self) -> [bool; 4] {
+        self.castling_rooks().map(|rook| rook.is_some())
+    }
+
+    // the rooks the castling rights refer to, in FEN order
+    fn castling_rooks(&self) -> [Option<SquareID>; 4] {
+        [(Player::White, true), (Player::White, false), (Player::Black, true), (Player::Black, false)]
+            .map(|(player, short)| self.board.castling_rook(player, short))
+    }
+
+    // the origin and destination squares of a move, castling being described by the king's move
+    pub fn move_squares(&self, chess_move: ChessMove) -> (SquareID, SquareID) {
+        match chess_move {
+            ChessMove::Move(id, target) => (id, target),
+            ChessMove::Capture(id, target) => (id, target),
+            ChessMove::EnPassant(id, target) => (id, target),
+            ChessMove::ShortCastle | ChessMove::LongCastle => {
+                let king = self.board.get_king_sq(self.active_player).get_id();
+                (king, SquareID(chess_move.castling_files().unwrap().0, king.rank()))
+            },
+            ChessMove::Promotion(target, _) => {
+                let back_offset = match self.active_player {
+                    Player::White => SquareOffset(0, -1),
+                    Player::Black => SquareOffset(0, 1),
+                };
+                (target.add_offset(back_offset).unwrap(), target)
+            },
+            ChessMove::CapturePromotion(id, target, _) => (id, target),
+            ChessMove::Drop(_, target) => (target, target),
+        }
     }
 
     pub fn make_move(&mut self, annotated_move: AnnotatedMove) {
@@ -149,9 +588,9 @@ impl ChessGameState {
             ChessMove::Move(id, target) => {
                 if self.board.square_by_id(id).get_piece().is_some_and(|p| p.get_name() == PieceName::Pawn) {
                     self.draw_clock = 0;
-                    // handle ep square
+                    // handle ep square, which horde pawns advancing from the first rank don't leave
                     let offset = id.calc_offset(target);
-                    if offset.file() == 0 && offset.rank().abs() == 2 {
+                    if offset.file() == 0 && offset.rank().abs() == 2 && matches!(id.rank(), Rank::Two | Rank::Seven) {
                         let ep_offset = SquareOffset(0, offset.rank() / 2);
                         let ep_sq = id.add_offset(ep_offset).unwrap();
                         self.ep_square = Some(ep_sq);
@@ -166,100 +605,176 @@ impl ChessGameState {
             ChessMove::LongCastle => self.draw_clock += 1,
             ChessMove::Promotion(_, _) => self.draw_clock = 0,
             ChessMove::CapturePromotion(_, _, _) => self.draw_clock = 0,
+            ChessMove::Drop(_, _) => self.draw_clock += 1,
         }
-
-        match annotated_move.annotation {
-            Annotation::CheckMate => {
-                match self.active_player {
-                    Player::White => self.result = Some(GameResult::WhiteWin),
-                    Player::Black => self.result = Some(GameResult::BlackWin),
-                };
-            },
-            Annotation::Draw => self.result = Some(GameResult::Draw),
-            _ => {},
+        if self.variant.has_pockets() {
+            self.update_pockets(annotated_move.chess_move);
         }
+
         if self.active_player == Player::Black {
             self.turn_num += 1;
         }
 
-        if self.result == None && self.draw_clock >= 50 {
+        self.board.make_move(annotated_move.chess_move, self.active_player);
+        if annotated_move.chess_move.is_capture() {
+            let (_, target) = self.move_squares(annotated_move.chess_move);
+            self.variant.capture(&mut self.board, target);
+        }
+        self.active_player = self.active_player.opponent();
+        // the same condition as a check annotation, but unannotated moves made by the search count as well
+        if self.variant.counts_checks() && self.in_check() {
+            self.checks[usize::from(self.active_player.opponent())] += 1;
+        }
+
+        if self.result.is_none() {
+            self.result = self.variant.result(self);
+        }
+        // checkmate and stalemate annotations mean the opponent has no legal moves left
+        if self.result.is_none() && matches!(annotated_move.annotation, Annotation::CheckMate | Annotation::Draw) {
+            self.result = Some(self.variant.no_moves_result(self));
+        }
+        if self.result.is_none() && self.draw_clock >= FIFTY_MOVE_PLIES {
             self.result = Some(GameResult::Draw);
         }
+    }
 
-        self.board.make_move(annotated_move.chess_move, self.active_player);
-        self.active_player = self.active_player.opponent();
+    // captured pieces go to the capturer's pocket, promoted ones as pawns, and dropped ones leave it
+    fn update_pockets(&mut self, chess_move: ChessMove) {
+        let pocket = &mut self.pockets[usize::from(self.active_player)];
+        let captured = match chess_move {
+            ChessMove::Capture(_, target) | ChessMove::CapturePromotion(_, target, _) => self.board.square_by_id(target).get_piece(),
+            ChessMove::EnPassant(_, _) => Some(ChessPiece::new(self.active_player.opponent(), PieceName::Pawn, true)),
+            ChessMove::Drop(name, _) => {
+                pocket[usize::from(name)] -= 1;
+                None
+            },
+            _ => None,
+        };
+        if let Some(piece) = captured {
+            let name = if piece.is_promoted() { PieceName::Pawn } else { piece.get_name() };
+            pocket[usize::from(name)] += 1;
+        }
+    }
+
+    // whether the active player can drop the piece there: it has to be in the pocket, the square empty,
+    // and pawns can't go on the first or last rank
+    pub fn can_drop(&self, name: PieceName, target: SquareID) -> bool {
+        self.variant.has_pockets()
+            && self.pockets[usize::from(self.active_player)][usize::from(name)] > 0
+            && self.board.square_by_id(target).get_piece().is_none()
+            && !(name == PieceName::Pawn && (target.rank() == Rank::One || target.rank() == Rank::Eight))
+    }
+
+    // passes the turn without moving, as used by null move pruning
+    pub fn make_null_move(&self) -> ChessGameState {
+        let mut my_copy = *self;
+        my_copy.ep_square = None;
+        my_copy.draw_clock += 1;
+        if my_copy.active_player == Player::Black {
+            my_copy.turn_num += 1;
+        }
+        my_copy.active_player = my_copy.active_player.opponent();
+        my_copy
     }
 
     pub fn get_legal_moves(&self) -> MoveList {
         let mut move_list = MoveList::new();
-        let opponent = self.active_player.opponent();
         let all_moves = self.get_all_moves();
         for m in all_moves {
-            let my_copy = {
-                let mut my_copy = self.clone();
-                my_copy.make_move(AnnotatedMove::new(m, Annotation::None));
-                my_copy
-            };
-            let king_sq = my_copy.board.get_king_sq(self.active_player);
-            if king_sq.not_seen_by(opponent) {
-                // move is legal
-                let is_check = my_copy.board.get_king_sq(opponent).is_seen_by(self.active_player);
-                let has_legal_move = my_copy.has_legal_moves();
-                let annotation = match (is_check, has_legal_move) {
-                    (true, true) => Annotation::Check,
-                    (true, false) => Annotation::CheckMate,
-                    (false, true) => Annotation::None,
-                    (false, false) => Annotation::Draw,
-                };
-                move_list.add_move(AnnotatedMove::new(m, annotation));
+            if let Some(annotated_move) = self.annotate_move(m) {
+                move_list.add_move(annotated_move);
             }
         }
         move_list
     }
 
+    // annotates a pseudo-legal move with check, mate or stalemate, returning None if it is illegal
+    pub fn annotate_move(&self, chess_move: ChessMove) -> Option<AnnotatedMove> {
+        let my_copy = self.make_pseudo_legal(chess_move)?;
+        let is_check = my_copy.in_check();
+        // a game the variant's rules decide is over whatever moves the opponent has left
+        let has_legal_move = my_copy.variant.result(&my_copy).is_some() || my_copy.has_legal_moves();
+        let annotation = match (is_check, has_legal_move) {
+            (true, true) => Annotation::Check,
+            (true, false) => Annotation::CheckMate,
+            (false, true) => Annotation::None,
+            (false, false) => Annotation::Draw,
+        };
+        Some(AnnotatedMove::new(chess_move, annotation))
+    }
+
     fn has_legal_moves(&self) -> bool {
-        let opponent = self.active_player.opponent();
-        let all_moves = self.get_all_moves();
-        for m in all_moves {
-            let my_copy = {
-                let mut my_copy = self.clone();
-                my_copy.make_move(AnnotatedMove::new(m, Annotation::None));
-                my_copy
-            };
-            let king_sq = my_copy.board.get_king_sq(self.active_player);
-            if king_sq.not_seen_by(opponent) {
-                return true;
-            }
+        self.get_all_moves().into_iter().any(|m| self.make_pseudo_legal(m).is_some())
+    }
+
+    // pseudo-legal moves: they may still leave the active player's king in check
+    pub fn get_moves(&self, kind: MoveGen) -> Vec<ChessMove> {
+        let mut moves = self.get_all_moves();
+        if kind != MoveGen::All {
+            moves.retain(|m| kind.accepts(m));
         }
-        false
+        moves
+    }
+
+    // checks whether a move (e.g. from a hash table) can be played in this position, ignoring checks
+    pub fn is_pseudo_legal(&self, chess_move: ChessMove) -> bool {
+        if let ChessMove::Drop(name, target) = chess_move {
+            return name != PieceName::King && self.can_drop(name, target);
+        }
+        // a quiet move is only allowed when there is nothing to capture
+        if self.variant.forces_captures() {
+            return self.get_all_moves().contains(&chess_move);
+        }
+        let (id, _) = self.move_squares(chess_move);
+        let square = self.board.square_by_id(id);
+        match square.get_piece() {
+            Some(piece) if piece.get_owner() == self.active_player => {
+                let mut moves = Vec::new();
+                self.add_piece_moves(square, piece, &mut moves);
+                moves.contains(&chess_move)
+            },
+            _ => false,
+        }
+    }
+
+    // plays a pseudo-legal move without annotating it, returning None if it leaves the king in check
+    pub fn make_pseudo_legal(&self, chess_move: ChessMove) -> Option<ChessGameState> {
+        let mut my_copy = *self;
+        my_copy.make_move(AnnotatedMove::new(chess_move, Annotation::None));
+        my_copy.variant.legal_after_move(&my_copy).then_some(my_copy)
     }
 
     fn get_all_moves(&self) -> Vec<ChessMove> {
         let mut moves = Vec::new();
 
         for square in self.board.iter() {
-            if square.get_piece().is_some_and(|p| p.get_owner() == self.active_player) {
-                let piece = square.get_piece().unwrap();
-                match piece.get_name() {
-                    PieceName::Pawn => self.add_pawn_moves(square, piece, &mut moves),
-                    PieceName::Knight => self.add_knight_moves(square, &mut moves),
-                    PieceName::Bishop => self.add_bishop_moves(square, &mut moves),
-                    PieceName::Rook => self.add_rook_moves(square, &mut moves),
-                    PieceName::Queen => self.add_queen_moves(square, &mut moves),
-                    PieceName::King => self.add_king_moves(square, piece, &mut moves),
-                }
+            if let Some(piece) = square.get_piece().filter(|p| p.get_owner() == self.active_player) {
+                self.add_piece_moves(square, piece, &mut moves);
             }
         }
+        self.variant.add_moves(self, &mut moves);
+        self.variant.restrict_moves(&mut moves);
         moves
     }
 
+    fn add_piece_moves(&self, square: &ChessSquare, piece: ChessPiece, moves: &mut Vec<ChessMove>) {
+        match piece.get_name() {
+            PieceName::Pawn => self.add_pawn_moves(square, piece, moves),
+            PieceName::Knight => self.add_knight_moves(square, moves),
+            PieceName::Bishop => self.add_bishop_moves(square, moves),
+            PieceName::Rook => self.add_rook_moves(square, moves),
+            PieceName::Queen => self.add_queen_moves(square, moves),
+            PieceName::King => self.add_king_moves(square, piece, moves),
+        }
+    }
+
     fn add_pawn_moves(&self, sq: &ChessSquare, piece: ChessPiece, moves: &mut Vec<ChessMove>) {
         let id = sq.get_id();
         let promotion_rank = match self.active_player {
             Player::White => Rank::Eight,
             Player::Black => Rank::One,
         };
-        let promote_to = [PieceName::Knight, PieceName::Bishop, PieceName::Rook, PieceName::Queen];
+        let promote_to = self.variant.promotions();
         //push
         let push_offset = match self.active_player {
             Player::White => SquareOffset(0, 1),
@@ -363,14 +878,13 @@ impl ChessGameState {
 
     fn add_king_moves(&self, sq: &ChessSquare, piece: ChessPiece, moves: &mut Vec<ChessMove>) {
         let id = sq.get_id();
-        let opponent = self.active_player.opponent();
         // standard moves
         let offsets = PieceName::king_offsets();
         for offset in offsets.into_iter() {
             let offset_sq = id.add_offset(offset);
             if let Some(target) = offset_sq {
                 let target_sq = self.board.square_by_id(target);
-                if target_sq.not_seen_by(opponent) {
+                if self.variant.king_safe_on(self, self.active_player, target) {
                     if target_sq.get_piece().is_none() {
                         moves.push(ChessMove::Move(id, target));
                     } else if target_sq.get_piece().unwrap().get_owner() != self.active_player {
@@ -380,47 +894,60 @@ impl ChessGameState {
             }
         }
         // castling
-        if piece.not_moved() && sq.not_seen_by(opponent) {
-            let rank = id.rank();
-            //short-castle
-            let rook = self.board.square_by_id(SquareID(File::H, rank));
-            if rook.get_piece().is_some_and(|p| p.get_name() == PieceName::Rook && p.not_moved()) {
-                let b_sq = self.board.square_by_id(SquareID(File::F, rank));
-                if b_sq.get_piece().is_none() && b_sq.not_seen_by(opponent) {
-                    let n_sq = self.board.square_by_id(SquareID(File::G, rank));
-                    if n_sq.get_piece().is_none() && n_sq.not_seen_by(opponent) {
-                        moves.push(ChessMove::ShortCastle);
-                    }
-                }
-            }
-            //long-castle
-            let rook = self.board.square_by_id(SquareID(File::A, rank));
-            if rook.get_piece().is_some_and(|p| p.get_name() == PieceName::Rook && p.not_moved()) {
-                let q_sq = self.board.square_by_id(SquareID(File::D, rank));
-                if q_sq.get_piece().is_none() && q_sq.not_seen_by(opponent) {
-                    let b_sq = self.board.square_by_id(SquareID(File::C, rank));
-                    if b_sq.get_piece().is_none() && b_sq.not_seen_by(opponent) {
-                        let n_sq = self.board.square_by_id(SquareID(File::B, rank));
-                        if n_sq.get_piece().is_none() {
-                            moves.push(ChessMove::LongCastle);
-                        }
-                    }
+        if self.variant.has_royal_king() && piece.not_moved() && !self.in_check() {
+            for castle in [ChessMove::ShortCastle, ChessMove::LongCastle] {
+                let rook = self.board.castling_rook(self.active_player, castle == ChessMove::ShortCastle);
+                if rook.is_some_and(|rook| self.castling_allowed(id, rook, castle)) {
+                    moves.push(castle);
                 }
             }
         }
     }
+
+    // the Chess960 rules, which include the standard ones: every square the king or rook crosses or lands on is
+    // empty apart from the two of them, and none the king crosses or lands on is attacked
+    fn castling_allowed(&self, king: SquareID, rook: SquareID, castle: ChessMove) -> bool {
+        let (king_file, rook_file) = castle.castling_files().unwrap();
+        let path = |from: SquareID, to: File| {
+            let (from, to): (usize, usize) = (from.file().into(), to.into());
+            (from.min(to)..=from.max(to)).map(move |f| SquareID(f.into(), king.rank()))
+        };
+        let empty = path(king, king_file).chain(path(rook, rook_file))
+            .all(|id| id == king || id == rook || self.board.square_by_id(id).get_piece().is_none());
+        empty && path(king, king_file).all(|id| self.variant.king_safe_on(self, self.active_player, id))
+    }
+}
+
+impl Default for ChessGameState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use crate::chess_game::chess_move::{AnnotatedMove, Annotation, ChessMove};
     use crate::chess_game::chess_square::{File, Rank, SquareID};
-    use crate::chess_game::{ChessGameState, GameResult, Player};
+    use crate::chess_game::variant::Variant;
+    use crate::chess_game::{ChessGameState, FenError, GameResult, MoveGen, Player};
 
     fn show() -> bool {
         true
     }
 
+    pub(crate) fn perft(game: &ChessGameState, depth: usize) -> usize {
+        // no moves are counted once a variant's rules end the game
+        if game.variant().result(game).is_some() {
+            return 0;
+        }
+        let children = game.get_moves(MoveGen::All).into_iter().filter_map(|m| game.make_pseudo_legal(m));
+        if depth == 1 {
+            children.count()
+        } else {
+            children.map(|child| perft(&child, depth - 1)).sum()
+        }
+    }
+
     #[test]
     fn test_fen() {
         let mut game = ChessGameState::new();
@@ -441,6 +968,33 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_from_fen() {
+        let start = ChessGameState::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        assert_eq!(start, ChessGameState::new());
+        assert_eq!(start.hash(), ChessGameState::new().hash());
+
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 3 20",
+        ];
+        for fen in fens {
+            assert_eq!(ChessGameState::from_fen(fen).unwrap().get_fen(), fen);
+        }
+        let kiwipete = ChessGameState::from_fen(fens[0]).unwrap();
+        assert_eq!(kiwipete.get_legal_moves().len(), 48);
+
+        assert_eq!(ChessGameState::from_fen("8/8/8/8/8/8/8/8 w - - 0 1"), Err(FenError::Kings));
+        assert_eq!(ChessGameState::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1"), Err(FenError::Placement));
+        assert_eq!(ChessGameState::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1"), Err(FenError::ActivePlayer));
+
+        // checkmated on setup
+        let mated = ChessGameState::from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3").unwrap();
+        assert_eq!(mated.result(), Some(GameResult::BlackWin));
+    }
+
     #[test]
     fn initial_moves() {
         let game = ChessGameState::new();
@@ -561,4 +1115,114 @@ mod tests {
             println!("{}", game.board);
         }
     }
+
+    #[test]
+    fn chess960_positions() {
+        let standard = ChessGameState::from_chess960(518).unwrap();
+        assert_eq!(standard.variant(), Variant::Chess960);
+        assert_eq!(standard, ChessGameState::with_variant(Variant::Chess960));
+        assert_eq!(standard.get_fen(), ChessGameState::new().get_fen());
+        let first = ChessGameState::from_chess960(0).unwrap();
+        assert_eq!(first.get_fen(), "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1");
+        assert_eq!(first.get_shredder_fen(), "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1");
+        assert_eq!(ChessGameState::from_chess960(959).unwrap().get_shredder_fen(),
+                   "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w CAca - 0 1");
+        assert!(ChessGameState::from_chess960(960).is_none());
+
+        let mut fens: Vec<String> = (0..960).map(|i| ChessGameState::from_chess960(i).unwrap().get_fen()).collect();
+        fens.sort();
+        fens.dedup();
+        assert_eq!(fens.len(), 960);
+    }
+
+    #[test]
+    fn chess960_fen() {
+        // Shredder-FEN and X-FEN name the same rights
+        let shredder = ChessGameState::from_fen("2r1kr2/8/8/8/8/8/8/1R2K1R1 w GBfc - 0 1").unwrap();
+        let x_fen = ChessGameState::from_fen("2r1kr2/8/8/8/8/8/8/1R2K1R1 w KQkq - 0 1").unwrap();
+        assert_eq!(shredder, x_fen);
+        assert_eq!(shredder.hash(), x_fen.hash());
+        assert_eq!(shredder.get_fen(), "2r1kr2/8/8/8/8/8/8/1R2K1R1 w KQkq - 0 1");
+        assert_eq!(shredder.get_shredder_fen(), "2r1kr2/8/8/8/8/8/8/1R2K1R1 w GBfc - 0 1");
+
+        // X-FEN names an inner rook by its file
+        let inner = ChessGameState::from_fen("4k3/8/8/8/8/8/8/RK2R2R w Eq - 0 1").unwrap();
+        assert_eq!(inner.castling_rights(), [true, false, false, false]);
+        assert_eq!(inner.get_fen(), "4k3/8/8/8/8/8/8/RK2R2R w E - 0 1");
+        assert_eq!(inner.get_shredder_fen(), "4k3/8/8/8/8/8/8/RK2R2R w E - 0 1");
+        assert_eq!(ChessGameState::from_fen("4k3/8/8/8/8/8/8/RK5R w X - 0 1"), Err(FenError::Castling));
+    }
+
+    #[test]
+    fn chess960_castling() {
+        // the king stays on g1 while the rook jumps over it to f1
+        let mut game = ChessGameState::from_fen("1r4kr/8/8/8/8/8/8/1R4KR w KQkq - 0 1").unwrap();
+        let moves = game.get_legal_moves();
+        assert!(moves.has_move(AnnotatedMove::new(ChessMove::ShortCastle, Annotation::None)));
+        assert!(moves.has_move(AnnotatedMove::new(ChessMove::LongCastle, Annotation::None)));
+        game.make_move(AnnotatedMove::new(ChessMove::ShortCastle, Annotation::None));
+        assert_eq!(game.get_fen(), "1r4kr/8/8/8/8/8/8/1R3RK1 b kq - 1 1");
+        game.make_move(AnnotatedMove::new(ChessMove::LongCastle, Annotation::None));
+        assert_eq!(game.get_fen(), "2kr3r/8/8/8/8/8/8/1R3RK1 w - - 2 2");
+        assert_eq!(game.board().square_by_id(SquareID(File::B, Rank::Eight)).get_piece(), None);
+
+        // the squares the rook crosses must be empty too, though not safe
+        let blocked = ChessGameState::from_fen("4k3/8/8/8/8/8/8/RNK5 w Q - 0 1").unwrap();
+        assert!(!blocked.get_legal_moves().iter().any(|m| m.chess_move == ChessMove::LongCastle));
+        let attacked = ChessGameState::from_fen("1r2k3/8/8/8/8/8/8/R1K5 w Q - 0 1").unwrap();
+        assert!(attacked.get_legal_moves().iter().any(|m| m.chess_move == ChessMove::LongCastle));
+        // the rook shields the king's destination from the a-file rook until it moves away
+        let shielded = ChessGameState::from_fen("4k3/8/8/8/8/8/8/rR1K4 w Q - 0 1").unwrap();
+        assert!(!shielded.get_legal_moves().iter().any(|m| m.chess_move == ChessMove::LongCastle));
+    }
+
+    #[test]
+    fn game_results() {
+        assert_eq!(GameResult::win_for(Player::Black), GameResult::BlackWin);
+        assert_eq!(GameResult::win_for(Player::White).to_pgn(), "1-0");
+        assert_eq!(GameResult::Draw.to_pgn(), "1/2-1/2");
+    }
+
+    #[test]
+    fn fifty_move_rule() {
+        let play = |game: &mut ChessGameState, san: &str| {
+            let m = game.parse_san(san).unwrap();
+            game.make_move(m);
+        };
+        // fifty moves by each side, not fifty half-moves
+        let mut game = ChessGameState::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 49 30").unwrap();
+        play(&mut game, "Ra2");
+        assert_eq!((game.draw_clock(), game.result()), (50, None));
+
+        let mut game = ChessGameState::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 98 80").unwrap();
+        play(&mut game, "Ra2");
+        assert_eq!((game.draw_clock(), game.result()), (99, None));
+        play(&mut game, "Kd7");
+        assert_eq!((game.draw_clock(), game.result()), (100, Some(GameResult::Draw)));
+
+        // a mate on the last move still counts
+        let mut game = ChessGameState::from_fen("k7/8/1K6/8/8/8/8/7R w - - 99 80").unwrap();
+        play(&mut game, "Rh8#");
+        assert_eq!(game.result(), Some(GameResult::WhiteWin));
+    }
+
+    #[test]
+    fn chess960_perft() {
+        let positions = [
+            ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", [21, 528, 12189]),
+            ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", [21, 807, 18002]),
+            ("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", [20, 479, 10471]),
+            ("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", [22, 593, 13440]),
+            ("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9", [28, 1120, 31058]),
+        ];
+        for (fen, counts) in positions {
+            let game = ChessGameState::from_fen(fen).unwrap();
+            assert_eq!(game.get_shredder_fen(), fen);
+            for (depth, count) in counts.into_iter().enumerate() {
+                assert_eq!(perft(&game, depth + 1), count, "{} at depth {}", fen, depth + 1);
+            }
+        }
+        let kiwipete = ChessGameState::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(perft(&kiwipete, 3), 97862);
+    }
 }
@@ -2,7 +2,7 @@ use std::fmt::{Display, Formatter};
 use std::slice::Iter;
 use crate::chess_game::chess_move::ChessMove;
 use crate::chess_game::chess_piece::{ChessPiece, PieceName};
-use crate::chess_game::chess_square::{ChessSquare, File, Rank, SquareID, SquareOffset};
+use crate::chess_game::chess_square::{ChessSquare, Rank, SquareColor, SquareID, SquareOffset};
 use crate::chess_game::Player;
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -11,10 +11,30 @@ pub struct ChessBoard {
 }
 
 impl ChessBoard {
+    // the standard starting position; other ones are built with Setup
     pub fn new() -> ChessBoard {
-        Self {
-            board: std::array::from_fn(|i| ChessSquare::initial(i)),
-        }
+        let back_rank = [PieceName::Rook, PieceName::Knight, PieceName::Bishop, PieceName::Queen, PieceName::King,
+            PieceName::Bishop, PieceName::Knight, PieceName::Rook];
+        Self::from_pieces(std::array::from_fn(|i| {
+            let id: SquareID = i.into();
+            let file: usize = id.file().into();
+            match id.rank() {
+                Rank::One => Some(ChessPiece::new(Player::White, back_rank[file], false)),
+                Rank::Two => Some(ChessPiece::new(Player::White, PieceName::Pawn, false)),
+                Rank::Seven => Some(ChessPiece::new(Player::Black, PieceName::Pawn, false)),
+                Rank::Eight => Some(ChessPiece::new(Player::Black, back_rank[file], false)),
+                _ => None,
+            }
+        }))
+    }
+
+    // builds a board from an arbitrary placement, starting from a1 (0) up to h8 (63)
+    pub fn from_pieces(pieces: [Option<ChessPiece>; 64]) -> ChessBoard {
+        let mut board = Self {
+            board: std::array::from_fn(|i| ChessSquare::new(i.into(), pieces[i], [0, 0])),
+        };
+        board.calc_seen();
+        board
     }
 
     pub fn square_by_id(&self, id: SquareID) -> &ChessSquare {
@@ -28,13 +48,32 @@ impl ChessBoard {
     }
 
     pub fn get_king_sq(&self, player: Player) -> &ChessSquare {
+        self.find_king(player).expect("No king square found")
+    }
+
+    // the player's king, which only variants like atomic can remove from the board
+    pub fn find_king(&self, player: Player) -> Option<&ChessSquare> {
         //TODO: cache this value
-        for i in 0..64 {
-            if self.board[i].get_piece().is_some_and(|p| p.get_name() == PieceName::King && p.get_owner() == player) {
-                return &self.board[i];
-            }
+        self.board.iter().find(|sq| sq.get_piece().is_some_and(|p| p.get_name() == PieceName::King && p.get_owner() == player))
+    }
+
+    // the unmoved rook on the player's home rank the king can castle with, towards the h-file (short) or
+    // the a-file (long); the king has to be unmoved as well
+    pub fn castling_rook(&self, player: Player, short: bool) -> Option<SquareID> {
+        let home_rank = match player {
+            Player::White => Rank::One,
+            Player::Black => Rank::Eight,
+        };
+        let king_sq = self.find_king(player)?;
+        let king_id = king_sq.get_id();
+        if king_id.rank() != home_rank || !king_sq.get_piece().is_some_and(|p| p.not_moved()) {
+            return None;
         }
-        panic!("No king square found");
+        let king_file: usize = king_id.file().into();
+        (0..8).filter(|f| *f != king_file && (*f > king_file) == short)
+            .map(|f| SquareID(f.into(), home_rank))
+            .find(|id| self.square_by_id(*id).get_piece()
+                .is_some_and(|p| p.get_owner() == player && p.get_name() == PieceName::Rook && p.not_moved()))
     }
 
     pub fn iter(&'_ self) -> Iter<'_, ChessSquare> {
@@ -48,7 +87,8 @@ impl ChessBoard {
                 let mut piece = sq.get_piece().unwrap();
                 sq.clear_piece();
                 let target_sq = self.square_by_id_mut(target_id);
-                piece.set_moved(true);
+                // only horde pawns can reach their starting rank, from the first one, and may still advance two squares
+                piece.set_moved(piece.get_name() != PieceName::Pawn || target_id.rank() != pawn_rank(player));
                 target_sq.set_piece(piece);
             },
             ChessMove::EnPassant(id, target_id) => {
@@ -61,51 +101,19 @@ impl ChessBoard {
                 let ep_sq = self.square_by_id_mut(ep_id);
                 ep_sq.clear_piece();
             },
-            ChessMove::ShortCastle => {
-                let rank = match player {
-                    Player::White => Rank::One,
-                    Player::Black => Rank::Eight,
-                };
-                let king_id = SquareID(File::E, rank);
-                let king_sq = self.square_by_id_mut(king_id);
-                let mut king = king_sq.get_piece().unwrap();
-                king.set_moved(true);
-                king_sq.clear_piece();
-                let new_king_id = SquareID(File::G, rank);
-                let new_king_sq = self.square_by_id_mut(new_king_id);
-                new_king_sq.set_piece(king);
-
-                let rook_id = SquareID(File::H, rank);
-                let rook_sq = self.square_by_id_mut(rook_id);
-                let mut rook = rook_sq.get_piece().unwrap();
-                rook.set_moved(true);
-                rook_sq.clear_piece();
-                let new_rook_id = SquareID(File::F, rank);
-                let new_rook_sq = self.square_by_id_mut(new_rook_id);
-                new_rook_sq.set_piece(rook);
-            },
-            ChessMove::LongCastle => {
-                let rank = match player {
-                    Player::White => Rank::One,
-                    Player::Black => Rank::Eight,
-                };
-                let king_id = SquareID(File::E, rank);
-                let king_sq = self.square_by_id_mut(king_id);
-                let mut king = king_sq.get_piece().unwrap();
+            ChessMove::ShortCastle | ChessMove::LongCastle => {
+                let (king_file, rook_file) = chess_move.castling_files().unwrap();
+                let king_id = self.get_king_sq(player).get_id();
+                let rook_id = self.castling_rook(player, chess_move == ChessMove::ShortCastle).unwrap();
+                let mut king = self.square_by_id(king_id).get_piece().unwrap();
+                let mut rook = self.square_by_id(rook_id).get_piece().unwrap();
                 king.set_moved(true);
-                king_sq.clear_piece();
-                let new_king_id = SquareID(File::C, rank);
-                let new_king_sq = self.square_by_id_mut(new_king_id);
-                new_king_sq.set_piece(king);
-
-                let rook_id = SquareID(File::A, rank);
-                let rook_sq = self.square_by_id_mut(rook_id);
-                let mut rook = rook_sq.get_piece().unwrap();
                 rook.set_moved(true);
-                rook_sq.clear_piece();
-                let new_rook_id = SquareID(File::D, rank);
-                let new_rook_sq = self.square_by_id_mut(new_rook_id);
-                new_rook_sq.set_piece(rook);
+                // in Chess960 either piece may land on the other's square, so both leave first
+                self.square_by_id_mut(king_id).clear_piece();
+                self.square_by_id_mut(rook_id).clear_piece();
+                self.square_by_id_mut(SquareID(king_file, king_id.rank())).set_piece(king);
+                self.square_by_id_mut(SquareID(rook_file, king_id.rank())).set_piece(rook);
             },
             ChessMove::Promotion(target_id, piece_name) => {
                 let id = match player {
@@ -115,13 +123,30 @@ impl ChessBoard {
                 let sq = self.square_by_id_mut(id);
                 sq.clear_piece();
                 let target_sq = self.square_by_id_mut(target_id);
-                target_sq.set_piece(ChessPiece::new(player, piece_name, true));
+                target_sq.set_piece(ChessPiece::promoted(player, piece_name));
             },
             ChessMove::CapturePromotion(id, target_id, piece_name) => {
                 let sq = self.square_by_id_mut(id);
                 sq.clear_piece();
                 let target_sq = self.square_by_id_mut(target_id);
-                target_sq.set_piece(ChessPiece::new(player, piece_name, true));
+                target_sq.set_piece(ChessPiece::promoted(player, piece_name));
+            },
+            ChessMove::Drop(piece_name, target_id) => {
+                // pawns dropped on their starting rank may still advance two squares
+                let moved = piece_name != PieceName::Pawn || target_id.rank() != pawn_rank(player);
+                self.square_by_id_mut(target_id).set_piece(ChessPiece::new(player, piece_name, moved));
+            },
+        }
+        self.calc_seen();
+    }
+
+    // an atomic capture: the piece on the square and every piece but a pawn next to it are removed
+    pub fn explode(&mut self, center: SquareID) {
+        self.square_by_id_mut(center).clear_piece();
+        for id in PieceName::king_offsets().into_iter().filter_map(|offset| center.add_offset(offset)) {
+            let sq = self.square_by_id_mut(id);
+            if sq.get_piece().is_some_and(|p| p.get_name() != PieceName::Pawn) {
+                sq.clear_piece();
             }
         }
         self.calc_seen();
@@ -222,23 +247,79 @@ impl ChessBoard {
     }
 }
 
-impl Display for ChessBoard {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for rank in (0..=7).rev() {
-            for file in 0..=7 {
-                let id = SquareID(file.into(), rank.into());
-                let index: usize = id.into();
-                let square = &self.board[index];
-                square.fmt(f)?;
+impl Default for ChessBoard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// why a square is drawn in another colour
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Highlight {
+    LastMove,
+    Check,
+}
+
+// ANSI terminal colours: 256-colour backgrounds for the squares, and the pieces in white or black
+const LIGHT_SQUARE: &str = "\x1b[48;5;180m";
+const DARK_SQUARE: &str = "\x1b[48;5;137m";
+const LAST_MOVE_SQUARE: &str = "\x1b[48;5;143m";
+const CHECK_SQUARE: &str = "\x1b[48;5;167m";
+const WHITE_PIECE: &str = "\x1b[97m";
+const BLACK_PIECE: &str = "\x1b[30m";
+const RESET: &str = "\x1b[0m";
+
+impl ChessBoard {
+    // the board for a terminal: Unicode pieces on coloured squares with the ranks and files around them, and
+    // White at the bottom unless flipped
+    pub fn render(&self, highlights: &[(SquareID, Highlight)], flipped: bool) -> String {
+        let ranks: Vec<usize> = if flipped { (0..8).collect() } else { (0..8).rev().collect() };
+        let files: Vec<usize> = if flipped { (0..8).rev().collect() } else { (0..8).collect() };
+        let mut text = String::new();
+        for rank in &ranks {
+            text += &format!("{} ", rank + 1);
+            for file in &files {
+                let square = self.square_by_id(SquareID((*file).into(), (*rank).into()));
+                let background = match highlights.iter().find(|(id, _)| *id == square.get_id()) {
+                    Some((_, Highlight::Check)) => CHECK_SQUARE,
+                    Some((_, Highlight::LastMove)) => LAST_MOVE_SQUARE,
+                    None if square.get_color() == SquareColor::Light => LIGHT_SQUARE,
+                    None => DARK_SQUARE,
+                };
+                let piece = match square.get_piece() {
+                    Some(piece) if piece.get_owner() == Player::White => format!("{}{}", WHITE_PIECE, piece.unicode()),
+                    Some(piece) => format!("{}{}", BLACK_PIECE, piece.unicode()),
+                    None => String::from(" "),
+                };
+                text += &format!("{} {} ", background, piece);
             }
-            write!(f, "\n")?;
+            text += RESET;
+            text.push('\n');
+        }
+        text += "  ";
+        for file in &files {
+            text += &format!(" {} ", &SquareID((*file).into(), Rank::One).to_str()[..1]);
         }
-        Ok(())
+        text.push('\n');
+        text
+    }
+}
+
+fn pawn_rank(player: Player) -> Rank {
+    match player {
+        Player::White => Rank::Two,
+        Player::Black => Rank::Seven,
+    }
+}
+
+impl Display for ChessBoard {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.render(&[], false))
     }
 }
 #[cfg(test)]
 mod tests {
-    use crate::chess_game::chess_board::ChessBoard;
+    use crate::chess_game::chess_board::{ChessBoard, Highlight};
     use crate::chess_game::chess_move::ChessMove;
     use crate::chess_game::chess_piece::PieceName;
     use crate::chess_game::chess_square::{File, Rank, SquareColor, SquareID};
@@ -288,6 +369,31 @@ mod tests {
         assert_eq!(h8_piece.get_name(), PieceName::Rook);
     }
 
+    #[test]
+    fn test_render() {
+        let board = ChessBoard::new();
+        let e4 = SquareID(File::E, Rank::Four);
+        let text = board.render(&[(e4, Highlight::LastMove)], false);
+        // strip the colours
+        let mut plain = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().take_while(|c| *c != 'm').for_each(drop);
+            } else {
+                plain.push(c);
+            }
+        }
+        let lines: Vec<&str> = plain.lines().collect();
+        assert_eq!(lines[0], "8  ♜  ♞  ♝  ♛  ♚  ♝  ♞  ♜ ");
+        assert_eq!(lines[7], "1  ♖  ♘  ♗  ♕  ♔  ♗  ♘  ♖ ");
+        assert_eq!(lines[8], "   a  b  c  d  e  f  g  h ");
+        assert_eq!(text.matches("\x1b[48;5;143m").count(), 1);
+        let flipped = board.render(&[], true);
+        assert!(flipped.starts_with("1 "));
+        assert!(flipped.ends_with(" h  g  f  e  d  c  b  a \n"));
+    }
+
     #[test]
     fn test_initial_seen() {
         let start = ChessBoard::new();
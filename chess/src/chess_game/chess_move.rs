@@ -1,5 +1,6 @@
+use std::slice::Iter;
 use crate::chess_game::chess_piece::PieceName;
-use crate::chess_game::chess_square::SquareID;
+use crate::chess_game::chess_square::{File, SquareID};
 
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -11,6 +12,32 @@ pub enum ChessMove {
     LongCastle,
     Promotion(SquareID, PieceName),
     CapturePromotion(SquareID, SquareID, PieceName),
+    // a piece from the pocket put on an empty square, in crazyhouse
+    Drop(PieceName, SquareID),
+}
+
+impl ChessMove {
+    pub fn is_capture(&self) -> bool {
+        matches!(self, ChessMove::Capture(_, _) | ChessMove::EnPassant(_, _) | ChessMove::CapturePromotion(_, _, _))
+    }
+
+    pub fn is_promotion(&self) -> bool {
+        matches!(self, ChessMove::Promotion(_, _) | ChessMove::CapturePromotion(_, _, _))
+    }
+
+    // captures and promotions, i.e. the moves that change the material balance
+    pub fn is_tactical(&self) -> bool {
+        self.is_capture() || self.is_promotion()
+    }
+
+    // the files the king and rook end up on after castling, wherever they started
+    pub fn castling_files(&self) -> Option<(File, File)> {
+        match self {
+            ChessMove::ShortCastle => Some((File::G, File::F)),
+            ChessMove::LongCastle => Some((File::C, File::D)),
+            _ => None,
+        }
+    }
 }
 
 
@@ -54,4 +81,18 @@ impl MoveList {
     pub fn len(&self) -> usize {
         self.moves.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    pub fn iter(&'_ self) -> Iter<'_, AnnotatedMove> {
+        self.moves.iter()
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
 }
@@ -3,17 +3,47 @@ use crate::chess_game::chess_square::SquareOffset;
 use crate::chess_game::Player;
 
 
-// doesn't auto-derive PartialEq or Eq because we don't care about _moved
+// doesn't auto-derive PartialEq or Eq because we don't care about _moved or _promoted
 #[derive(Debug, Copy, Clone)]
 pub struct ChessPiece {
     owner: Player,
     name: PieceName,
     _moved: bool,
+    // a promoted pawn, which goes back to being a pawn when captured in crazyhouse
+    _promoted: bool,
 }
 
 impl ChessPiece {
     pub fn new(owner: Player, name: PieceName, moved: bool) -> ChessPiece {
-        Self { owner, name, _moved: moved }
+        Self { owner, name, _moved: moved, _promoted: false }
+    }
+
+    pub fn promoted(owner: Player, name: PieceName) -> ChessPiece {
+        Self { owner, name, _moved: true, _promoted: true }
+    }
+
+    // parses a FEN piece letter: uppercase for white, lowercase for black
+    pub fn from_symbol(symbol: char, moved: bool) -> Option<ChessPiece> {
+        let owner = if symbol.is_ascii_uppercase() { Player::White } else { Player::Black };
+        let name = match symbol.to_ascii_lowercase() {
+            'p' => PieceName::Pawn,
+            'n' => PieceName::Knight,
+            'b' => PieceName::Bishop,
+            'r' => PieceName::Rook,
+            'q' => PieceName::Queen,
+            'k' => PieceName::King,
+            _ => return None,
+        };
+        Some(Self::new(owner, name, moved))
+    }
+
+    // the chess symbol of the piece: outlined for white, filled for black
+    pub fn unicode(&self) -> char {
+        let symbols = match self.owner {
+            Player::White => ['♙', '♘', '♗', '♖', '♕', '♔'],
+            Player::Black => ['♟', '♞', '♝', '♜', '♛', '♚'],
+        };
+        symbols[usize::from(self.name)]
     }
 
     pub fn get_owner(&self) -> Player {
@@ -36,7 +66,15 @@ impl ChessPiece {
         self._moved = moved;
     }
 
-    pub fn to_string(&self) -> String {
+    pub fn is_promoted(&self) -> bool {
+        self._promoted
+    }
+
+    pub fn set_promoted(&mut self, promoted: bool) {
+        self._promoted = promoted;
+    }
+
+    pub fn symbol(&self) -> &'static str {
         match self.owner {
             Player::White => match self.name {
                 PieceName::Pawn => "P",
@@ -54,13 +92,13 @@ impl ChessPiece {
                 PieceName::Queen => "q",
                 PieceName::King => "k",
             }
-        }.to_string()
+        }
     }
 }
 
 impl PartialEq for ChessPiece {
     fn eq(&self, other: &ChessPiece) -> bool {
-        // do not compare _moved
+        // do not compare _moved or _promoted
         self.owner == other.owner && self.name == other.name
     }
 }
@@ -69,7 +107,7 @@ impl Eq for ChessPiece {}
 
 impl Display for ChessPiece {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.to_string())
+        write!(f, "{}", self.symbol())
     }
 }
 
@@ -83,6 +121,33 @@ pub enum PieceName {
     King,
 }
 
+impl From<PieceName> for usize {
+    fn from(name: PieceName) -> Self {
+        match name {
+            PieceName::Pawn => 0,
+            PieceName::Knight => 1,
+            PieceName::Bishop => 2,
+            PieceName::Rook => 3,
+            PieceName::Queen => 4,
+            PieceName::King => 5,
+        }
+    }
+}
+
+impl From<usize> for PieceName {
+    fn from(value: usize) -> Self {
+        match value % 6 {
+            0 => PieceName::Pawn,
+            1 => PieceName::Knight,
+            2 => PieceName::Bishop,
+            3 => PieceName::Rook,
+            4 => PieceName::Queen,
+            5 => PieceName::King,
+            _ => unreachable!(),
+        }
+    }
+}
+
 impl PieceName {
     pub fn knight_offsets() -> [SquareOffset; 8] {
         [SquareOffset(-2,-1), SquareOffset(-2,1), SquareOffset(-1,-2), SquareOffset(-1,2), SquareOffset(1,-2), SquareOffset(1, 2), SquareOffset(2,-1), SquareOffset(2, 1)]
@@ -1,6 +1,6 @@
 use std::fmt::{Display, Formatter};
 use std::ops::{Add, Sub};
-use crate::chess_game::chess_piece::{ChessPiece, PieceName};
+use crate::chess_game::chess_piece::ChessPiece;
 use crate::chess_game::Player;
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -12,60 +12,6 @@ pub struct ChessSquare {
 }
 
 impl ChessSquare {
-    // returns the state of the i-th square at the start of a chess game.
-    // starts from a1 (0), b1 (1), ..., up to h8 (63)
-    pub fn initial(i: usize) -> Self {
-        let id: SquareID = i.into();
-        let color: SquareColor = id.into();
-        let piece = match id {
-            SquareID(file, Rank::One) => match file {
-                File::A | File::H => Some(ChessPiece::new(Player::White, PieceName::Rook, false)),
-                File::B | File::G => Some(ChessPiece::new(Player::White, PieceName::Knight, false)),
-                File::C | File::F => Some(ChessPiece::new(Player::White, PieceName::Bishop, false)),
-                File::D => Some(ChessPiece::new(Player::White, PieceName::Queen, false)),
-                File::E => Some(ChessPiece::new(Player::White, PieceName::King, false)),
-            },
-            SquareID(_, Rank::Two) => Some(ChessPiece::new(Player::White, PieceName::Pawn, false)),
-            SquareID(_, Rank::Seven) => Some(ChessPiece::new(Player::Black, PieceName::Pawn, false)),
-            SquareID(file, Rank::Eight) => match file {
-                File::A | File::H => Some(ChessPiece::new(Player::Black, PieceName::Rook, false)),
-                File::B | File::G => Some(ChessPiece::new(Player::Black, PieceName::Knight, false)),
-                File::C | File::F => Some(ChessPiece::new(Player::Black, PieceName::Bishop, false)),
-                File::D => Some(ChessPiece::new(Player::Black, PieceName::Queen, false)),
-                File::E => Some(ChessPiece::new(Player::Black, PieceName::King, false)),
-            },
-            _ => None,
-        };
-        let seen_by = match id {
-            SquareID(file, Rank::One) => match file {
-                File::A | File::H => [0, 0],
-                _ => [1, 0],
-            },
-            SquareID(file, Rank::Two) => match file {
-                File::D | File::E => [4, 0],
-                _ => [1, 0],
-            },
-            SquareID(file, Rank::Three) => match file {
-                File::C | File::F => [3, 0],
-                _ => [2, 0]
-            },
-            SquareID(file, Rank::Eight) => match file {
-                File::A | File::H => [0, 0],
-                _ => [0, 1],
-            },
-            SquareID(file, Rank::Seven) => match file {
-                File::D | File::E => [0, 4],
-                _ => [0, 1],
-            },
-            SquareID(file, Rank::Six) => match file {
-                File::C | File::F => [0, 3],
-                _ => [0, 2]
-            },
-            _ => [0, 0]
-        };
-        Self { id, color, piece, seen_by }
-    }
-
     pub fn new(id: SquareID, piece: Option<ChessPiece>, seen: [u8; 2]) -> Self {
         let color = id.into();
         Self { id, color, piece , seen_by: seen }
@@ -127,8 +73,8 @@ impl ChessSquare {
 
 impl Display for ChessSquare {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        if self.piece.is_some() {
-            self.piece.unwrap().fmt(f)?;
+        if let Some(piece) = self.piece {
+            piece.fmt(f)?;
         } else {
             match self.color {
                 SquareColor::Light => write!(f, " ")?,
@@ -170,6 +116,21 @@ impl SquareID {
         file + rank
     }
 
+    // parses algebraic square names such as "e4"
+    pub fn parse(s: &str) -> Option<SquareID> {
+        let bytes = s.as_bytes();
+        if bytes.len() != 2 {
+            return None;
+        }
+        let file = bytes[0].wrapping_sub(b'a') as usize;
+        let rank = bytes[1].wrapping_sub(b'1') as usize;
+        if file < 8 && rank < 8 {
+            Some(SquareID(file.into(), rank.into()))
+        } else {
+            None
+        }
+    }
+
     pub fn add_offset(&self, offset: SquareOffset) -> Option<SquareID> {
         let fu: usize = self.0.into();
         let ru: usize = self.1.into();
@@ -177,7 +138,7 @@ impl SquareID {
         let ri: isize = ru as isize;
         let new_f = fi + offset.0;
         let new_r = ri + offset.1;
-        if new_f >= 0 && new_f < 8 && new_r >= 0 && new_r < 8 {
+        if (0..8).contains(&new_f) && (0..8).contains(&new_r) {
             Some(SquareID((new_f as usize).into(), (new_r as usize).into()))
         } else {
             None
@@ -358,4 +319,13 @@ mod tests {
         let id = SquareID(File::C, Rank::Four);
         assert_eq!(id.to_str(), "c4");
     }
+
+    #[test]
+    fn id_parse() {
+        assert_eq!(SquareID::parse("e1"), Some(SquareID(File::E, Rank::One)));
+        assert_eq!(SquareID::parse("h8"), Some(SquareID(File::H, Rank::Eight)));
+        assert_eq!(SquareID::parse("i1"), None);
+        assert_eq!(SquareID::parse("a9"), None);
+        assert_eq!(SquareID::parse("a10"), None);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,439 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::chess_engine::time_manager::GameTime;
+use crate::chess_game::chess_move::AnnotatedMove;
+use crate::chess_game::{ChessGameState, GameResult, Player};
+
+// a source of time, so clocks and the time manager can be tested without waiting
+pub trait Clock: Send {
+    // the time since some fixed point
+    fn now(&self) -> Duration;
+}
+
+pub struct SystemClock {
+    epoch: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self { epoch: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.epoch.elapsed()
+    }
+}
+
+// a clock that only moves when told to, or by a fixed step every time it is read
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    now: Arc<Mutex<Duration>>,
+    step: Duration,
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_step(step: Duration) -> Self {
+        Self { now: Arc::default(), step }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        let mut now = self.now.lock().unwrap();
+        let time = *now;
+        *now += self.step;
+        time
+    }
+}
+
+// what a player gets for each move besides the time of the stage
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Bonus {
+    #[default]
+    None,
+    // Fischer: added after every move
+    Increment(Duration),
+    // Bronstein: the time used for a move is given back, up to the delay
+    Bronstein(Duration),
+    // simple (US) delay: the clock only starts counting down once the delay has passed
+    Delay(Duration),
+}
+
+// one period of a time control: the time for a number of moves, or for the rest of the game
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Stage {
+    pub moves: Option<u32>,
+    pub time: Duration,
+    pub bonus: Bonus,
+}
+
+// the stages in the order they are played; a last stage for a number of moves repeats
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeControl {
+    stages: Vec<Stage>,
+}
+
+impl TimeControl {
+    pub fn new(stages: Vec<Stage>) -> Option<Self> {
+        (!stages.is_empty()).then_some(Self { stages })
+    }
+
+    pub fn sudden_death(time: Duration) -> Self {
+        Self::with_bonus(time, Bonus::None)
+    }
+
+    // a single stage for the whole game
+    pub fn with_bonus(time: Duration, bonus: Bonus) -> Self {
+        Self { stages: vec![Stage { moves: None, time, bonus }] }
+    }
+
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    fn stage(&self, index: usize) -> &Stage {
+        &self.stages[index.min(self.stages.len() - 1)]
+    }
+
+    // the PGN TimeControl tag: stages separated by colons, each of them "moves/seconds" or "seconds", with an
+    // optional "+increment", e.g. 40/5400+30:1800+30
+    pub fn parse(text: &str) -> Option<Self> {
+        let seconds = |s: &str| s.parse::<f64>().ok().filter(|s| s.is_finite() && *s >= 0.0).map(Duration::from_secs_f64);
+        let stages = text.split(':').map(|stage| {
+            let (moves, rest) = match stage.split_once('/') {
+                Some((moves, rest)) => (Some(moves.parse().ok().filter(|moves| *moves > 0)?), rest),
+                None => (None, stage),
+            };
+            let (time, bonus) = match rest.split_once('+') {
+                Some((time, increment)) => (seconds(time)?, Bonus::Increment(seconds(increment)?)),
+                None => (seconds(rest)?, Bonus::None),
+            };
+            Some(Stage { moves, time, bonus })
+        }).collect::<Option<Vec<_>>>()?;
+        Self::new(stages)
+    }
+
+    // the PGN TimeControl tag, which has no way of writing delays, so they are left out
+    pub fn to_pgn(&self) -> String {
+        let stages: Vec<String> = self.stages.iter().map(|stage| {
+            let mut text = match stage.moves {
+                Some(moves) => format!("{}/{}", moves, stage.time.as_secs_f64()),
+                None => stage.time.as_secs_f64().to_string(),
+            };
+            if let Bonus::Increment(increment) = stage.bonus {
+                text += &format!("+{}", increment.as_secs_f64());
+            }
+            text
+        }).collect();
+        stages.join(":")
+    }
+}
+
+// a chess clock for both players, which runs for the active player from when their turn starts
+pub struct GameClock {
+    control: TimeControl,
+    clock: Box<dyn Clock>,
+    remaining: [Duration; 2],
+    // the stage of the time control each player is in, and the moves they made in it
+    stage: [usize; 2],
+    stage_moves: [u32; 2],
+    active: Player,
+    // when the active player's turn started, while the clock runs
+    started: Option<Duration>,
+}
+
+impl GameClock {
+    pub fn new(control: TimeControl, clock: Box<dyn Clock>) -> Self {
+        let time = control.stage(0).time;
+        Self {
+            control,
+            clock,
+            remaining: [time; 2],
+            stage: [0; 2],
+            stage_moves: [0; 2],
+            active: Player::White,
+            started: None,
+        }
+    }
+
+    pub fn control(&self) -> &TimeControl {
+        &self.control
+    }
+
+    pub fn active_player(&self) -> Player {
+        self.active
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.started.is_some()
+    }
+
+    // starts the player's turn
+    pub fn start(&mut self, player: Player) {
+        self.stop();
+        self.active = player;
+        self.started = Some(self.clock.now());
+    }
+
+    // pauses the clock, charging the active player for the time used so far
+    pub fn stop(&mut self) {
+        if let Some(started) = self.started.take() {
+            let charge = self.charge(self.clock.now().saturating_sub(started));
+            let side = usize::from(self.active);
+            self.remaining[side] = self.remaining[side].saturating_sub(charge);
+        }
+    }
+
+    // the time a move costs that took this long: a simple delay is free
+    fn charge(&self, elapsed: Duration) -> Duration {
+        match self.control.stage(self.stage[usize::from(self.active)]).bonus {
+            Bonus::Delay(delay) => elapsed.saturating_sub(delay),
+            _ => elapsed,
+        }
+    }
+
+    pub fn remaining(&self, player: Player) -> Duration {
+        let remaining = self.remaining[usize::from(player)];
+        match self.started {
+            Some(started) if player == self.active => remaining.saturating_sub(self.charge(self.clock.now().saturating_sub(started))),
+            _ => remaining,
+        }
+    }
+
+    // the player whose time ran out, if any
+    pub fn flagged(&self) -> Option<Player> {
+        [Player::White, Player::Black].into_iter().find(|player| self.remaining(*player).is_zero())
+    }
+
+    // the moves the player has left until the next stage, or None when the stage lasts for the rest of the game
+    pub fn moves_to_go(&self, player: Player) -> Option<u32> {
+        let side = usize::from(player);
+        self.control.stage(self.stage[side]).moves.map(|moves| moves - self.stage_moves[side])
+    }
+
+    // the player's clock as the time manager sees it, counting delays like increments
+    pub fn game_time(&self, player: Player) -> GameTime {
+        let increment = match self.control.stage(self.stage[usize::from(player)]).bonus {
+            Bonus::None => Duration::ZERO,
+            Bonus::Increment(time) | Bonus::Bronstein(time) | Bonus::Delay(time) => time,
+        };
+        GameTime { remaining: self.remaining(player), increment, moves_to_go: self.moves_to_go(player) }
+    }
+
+    // the active player finishes a move, and the opponent's turn starts. The move gets its bonus and may complete a
+    // stage, adding the time of the next one, unless the flag fell first, which stops the clock.
+    pub fn press(&mut self) {
+        let now = self.clock.now();
+        let side = usize::from(self.active);
+        if let Some(started) = self.started.take() {
+            let elapsed = now.saturating_sub(started);
+            self.remaining[side] = self.remaining[side].saturating_sub(self.charge(elapsed));
+            if self.remaining[side].is_zero() {
+                return;
+            }
+            self.remaining[side] += match self.control.stage(self.stage[side]).bonus {
+                Bonus::Increment(increment) => increment,
+                Bonus::Bronstein(delay) => elapsed.min(delay),
+                Bonus::None | Bonus::Delay(_) => Duration::ZERO,
+            };
+        }
+        self.stage_moves[side] += 1;
+        if self.control.stage(self.stage[side]).moves == Some(self.stage_moves[side]) {
+            self.stage[side] += 1;
+            self.stage_moves[side] = 0;
+            self.remaining[side] += self.control.stage(self.stage[side]).time;
+        }
+        self.active = self.active.opponent();
+        self.started = Some(now);
+    }
+
+    // plays a move on the clock, which starts with the first move if it isn't running yet. A move made after the
+    // flag fell ends the game instead, and the clock stops once the game is over.
+    pub fn make_move(&mut self, game: &mut ChessGameState, annotated_move: AnnotatedMove) {
+        if game.result().is_some() {
+            return;
+        }
+        if !self.is_running() {
+            self.start(game.active_player());
+        }
+        self.press();
+        match self.flagged() {
+            Some(player) => game.flag(player),
+            None => {
+                game.make_move(annotated_move);
+                if game.result().is_some() {
+                    self.stop();
+                }
+            },
+        }
+    }
+
+    // ends the game if a player's time ran out while thinking
+    pub fn check_flag(&mut self, game: &mut ChessGameState) -> Option<GameResult> {
+        if game.result().is_none() && let Some(player) = self.flagged() {
+            self.stop();
+            game.flag(player);
+        }
+        game.result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::chess_engine::time_manager::GameTime;
+    use crate::chess_game::game_clock::MockClock;
+    use crate::chess_game::game_clock::{Bonus, GameClock, Stage, TimeControl};
+    use crate::chess_game::{ChessGameState, GameResult, Player};
+
+    fn secs(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    fn clock(control: TimeControl) -> (GameClock, MockClock) {
+        let time = MockClock::new();
+        (GameClock::new(control, Box::new(time.clone())), time)
+    }
+
+    #[test]
+    fn pgn_time_controls() {
+        let classical = TimeControl::parse("40/5400+30:1800+30").unwrap();
+        assert_eq!(classical.stages(), [
+            Stage { moves: Some(40), time: secs(5400), bonus: Bonus::Increment(secs(30)) },
+            Stage { moves: None, time: secs(1800), bonus: Bonus::Increment(secs(30)) },
+        ]);
+        assert_eq!(classical.to_pgn(), "40/5400+30:1800+30");
+        assert_eq!(TimeControl::parse("300"), Some(TimeControl::sudden_death(secs(300))));
+        assert_eq!(TimeControl::parse("0.5+0.1").unwrap().to_pgn(), "0.5+0.1");
+        for invalid in ["", "40/", "0/60", "60+", "-5", "?"] {
+            assert_eq!(TimeControl::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn increments_and_delays() {
+        // sudden death only runs down the active player's time
+        let (mut game_clock, time) = clock(TimeControl::sudden_death(secs(60)));
+        assert_eq!(game_clock.remaining(Player::White), secs(60));
+        game_clock.start(Player::White);
+        time.advance(secs(10));
+        assert_eq!(game_clock.remaining(Player::White), secs(50));
+        game_clock.press();
+        time.advance(secs(5));
+        assert_eq!((game_clock.remaining(Player::White), game_clock.remaining(Player::Black)), (secs(50), secs(55)));
+        game_clock.stop();
+        time.advance(secs(100));
+        assert_eq!(game_clock.remaining(Player::Black), secs(55));
+
+        // Fischer increments are added after the move, even when it took less time
+        let (mut game_clock, time) = clock(TimeControl::with_bonus(secs(60), Bonus::Increment(secs(2))));
+        game_clock.start(Player::White);
+        time.advance(secs(1));
+        game_clock.press();
+        assert_eq!(game_clock.remaining(Player::White), secs(61));
+
+        // Bronstein gives back at most the time used, and a simple delay is never charged
+        let (mut game_clock, time) = clock(TimeControl::with_bonus(secs(60), Bonus::Bronstein(secs(5))));
+        game_clock.start(Player::White);
+        time.advance(secs(3));
+        game_clock.press();
+        time.advance(secs(8));
+        game_clock.press();
+        assert_eq!((game_clock.remaining(Player::White), game_clock.remaining(Player::Black)), (secs(60), secs(57)));
+        let (mut game_clock, time) = clock(TimeControl::with_bonus(secs(60), Bonus::Delay(secs(5))));
+        game_clock.start(Player::White);
+        time.advance(secs(3));
+        assert_eq!(game_clock.remaining(Player::White), secs(60));
+        game_clock.press();
+        time.advance(secs(8));
+        game_clock.press();
+        assert_eq!((game_clock.remaining(Player::White), game_clock.remaining(Player::Black)), (secs(60), secs(57)));
+        assert_eq!(game_clock.game_time(Player::White), GameTime { remaining: secs(60), increment: secs(5), moves_to_go: None });
+    }
+
+    #[test]
+    fn stages() {
+        // two moves in a minute, then another minute for the rest of the game
+        let control = TimeControl::new(vec![
+            Stage { moves: Some(2), time: secs(60), bonus: Bonus::None },
+            Stage { moves: None, time: secs(60), bonus: Bonus::Increment(secs(1)) },
+        ]).unwrap();
+        let (mut game_clock, time) = clock(control);
+        game_clock.start(Player::White);
+        assert_eq!(game_clock.moves_to_go(Player::White), Some(2));
+        for _ in 0..2 {
+            time.advance(secs(10));
+            game_clock.press();
+            game_clock.press();
+        }
+        assert_eq!(game_clock.remaining(Player::White), secs(100));
+        assert_eq!(game_clock.moves_to_go(Player::White), None);
+        time.advance(secs(10));
+        game_clock.press();
+        assert_eq!(game_clock.remaining(Player::White), secs(91));
+
+        // a last stage for a number of moves repeats
+        let (mut game_clock, time) = clock(TimeControl::parse("1/60").unwrap());
+        game_clock.start(Player::White);
+        for _ in 0..3 {
+            time.advance(secs(30));
+            game_clock.press();
+            game_clock.press();
+        }
+        assert_eq!(game_clock.remaining(Player::White), secs(150));
+        assert_eq!(game_clock.moves_to_go(Player::White), Some(1));
+    }
+
+    #[test]
+    fn flag_fall() {
+        let (mut game_clock, time) = clock(TimeControl::sudden_death(secs(60)));
+        let mut game = ChessGameState::new();
+        // the clock starts with the first move
+        let m = game.parse_san("e4").unwrap();
+        game_clock.make_move(&mut game, m);
+        assert_eq!(game_clock.active_player(), Player::Black);
+        time.advance(secs(59));
+        assert_eq!(game_clock.check_flag(&mut game), None);
+        let m = game.parse_san("e5").unwrap();
+        game_clock.make_move(&mut game, m);
+        time.advance(secs(60));
+        assert_eq!(game_clock.check_flag(&mut game), Some(GameResult::BlackWin));
+        assert!(!game_clock.is_running());
+
+        // a move after the flag fell isn't played
+        let (mut game_clock, time) = clock(TimeControl::sudden_death(secs(60)));
+        let mut game = ChessGameState::new();
+        game_clock.start(Player::White);
+        time.advance(secs(61));
+        let m = game.parse_san("e4").unwrap();
+        game_clock.make_move(&mut game, m);
+        assert_eq!((game.result(), game.turn(), game.active_player()), (Some(GameResult::BlackWin), 1, Player::White));
+
+        // the opponent can't mate with a lone knight against a bare king
+        let (mut game_clock, time) = clock(TimeControl::sudden_death(secs(1)));
+        let mut game = ChessGameState::from_fen("k7/8/8/8/8/8/1N6/K6Q b - - 0 1").unwrap();
+        game_clock.start(Player::Black);
+        time.advance(secs(1));
+        assert_eq!(game_clock.check_flag(&mut game), Some(GameResult::WhiteWin));
+        let mut game = ChessGameState::from_fen("k7/8/8/8/8/8/1N6/K7 b - - 0 1").unwrap();
+        assert_eq!(game_clock.check_flag(&mut game), Some(GameResult::Draw));
+        let mut game = ChessGameState::from_fen("k7/p7/8/8/8/8/1N6/K7 b - - 0 1").unwrap();
+        assert_eq!(game_clock.check_flag(&mut game), Some(GameResult::WhiteWin));
+    }
+}
@@ -0,0 +1,269 @@
+use crate::chess_game::chess_move::{AnnotatedMove, Annotation, ChessMove};
+use crate::chess_game::chess_piece::PieceName;
+use crate::chess_game::chess_square::SquareID;
+use crate::chess_game::{ChessGameState, MoveGen};
+
+fn piece_letter(name: PieceName) -> &'static str {
+    match name {
+        PieceName::Pawn => "",
+        PieceName::Knight => "N",
+        PieceName::Bishop => "B",
+        PieceName::Rook => "R",
+        PieceName::Queen => "Q",
+        PieceName::King => "K",
+    }
+}
+
+fn letter_piece(letter: char) -> Option<PieceName> {
+    match letter {
+        'P' => Some(PieceName::Pawn),
+        'N' => Some(PieceName::Knight),
+        'B' => Some(PieceName::Bishop),
+        'R' => Some(PieceName::Rook),
+        'Q' => Some(PieceName::Queen),
+        'K' => Some(PieceName::King),
+        _ => None,
+    }
+}
+
+// drops are written with the piece letter and the square, pawns included: N@f3, P@e4
+fn drop_text(name: PieceName, target: SquareID) -> String {
+    let letter = if name == PieceName::Pawn { "P" } else { piece_letter(name) };
+    format!("{}@{}", letter, target.to_str())
+}
+
+// SAN without check marks, annotations like ! or ? and promotion equals signs; pawn drops may leave out the P
+fn normalize_san(san: &str) -> String {
+    let san = san.trim_end_matches(['+', '#', '!', '?']).replace('=', "").replace('0', "O");
+    if san.starts_with('@') { format!("P{}", san) } else { san }
+}
+
+impl ChessGameState {
+    // long algebraic notation as used by UCI: e2e4, e1g1 for castling, e7e8q for promotions, N@f3 for drops
+    pub fn move_to_uci(&self, chess_move: ChessMove) -> String {
+        if let ChessMove::Drop(name, target) = chess_move {
+            return drop_text(name, target);
+        }
+        let (from, to) = self.move_squares(chess_move);
+        let mut uci = from.to_str() + &to.to_str();
+        if let ChessMove::Promotion(_, name) | ChessMove::CapturePromotion(_, _, name) = chess_move {
+            uci += match name {
+                PieceName::Knight => "n",
+                PieceName::Bishop => "b",
+                PieceName::Rook => "r",
+                PieceName::King => "k",
+                _ => "q",
+            };
+        }
+        uci
+    }
+
+    // finds the legal move matching a UCI move string, annotated for check and mate
+    pub fn parse_uci_move(&self, uci: &str) -> Option<AnnotatedMove> {
+        let chess_move = self.get_moves(MoveGen::All).into_iter().find(|m| self.move_to_uci(*m) == uci)?;
+        self.annotate_move(chess_move)
+    }
+
+    // the UCI_Chess960 notation, where castling is the king taking its own rook, e.g. b1a1
+    pub fn move_to_uci_960(&self, chess_move: ChessMove) -> String {
+        match chess_move {
+            ChessMove::ShortCastle | ChessMove::LongCastle => {
+                let short = chess_move == ChessMove::ShortCastle;
+                let king = self.board.get_king_sq(self.active_player).get_id();
+                let rook = self.board.castling_rook(self.active_player, short).unwrap_or(king);
+                king.to_str() + &rook.to_str()
+            },
+            _ => self.move_to_uci(chess_move),
+        }
+    }
+
+    pub fn parse_uci_move_960(&self, uci: &str) -> Option<AnnotatedMove> {
+        let chess_move = self.get_moves(MoveGen::All).into_iter().find(|m| self.move_to_uci_960(*m) == uci)?;
+        self.annotate_move(chess_move)
+    }
+
+    // standard algebraic notation: Nbd7, exd5, e8=Q+, O-O-O#
+    pub fn move_to_san(&self, annotated_move: AnnotatedMove) -> String {
+        let chess_move = annotated_move.chess_move;
+        let (from, to) = self.move_squares(chess_move);
+        let name = self.board.square_by_id(from).get_piece().map_or(PieceName::Pawn, |p| p.get_name());
+        let mut san = match chess_move {
+            ChessMove::ShortCastle => String::from("O-O"),
+            ChessMove::LongCastle => String::from("O-O-O"),
+            ChessMove::Drop(name, target) => drop_text(name, target),
+            _ if name == PieceName::Pawn => {
+                let mut san = String::new();
+                if chess_move.is_capture() {
+                    san += &from.to_str()[..1];
+                    san += "x";
+                }
+                san += &to.to_str();
+                if let ChessMove::Promotion(_, promoted) | ChessMove::CapturePromotion(_, _, promoted) = chess_move {
+                    san += "=";
+                    san += piece_letter(promoted);
+                }
+                san
+            },
+            _ => {
+                // other pieces of the same kind that can reach the same square
+                let rivals: Vec<_> = self.get_legal_moves().iter()
+                    .filter(|m| !matches!(m.chess_move, ChessMove::ShortCastle | ChessMove::LongCastle | ChessMove::Drop(_, _)))
+                    .map(|m| self.move_squares(m.chess_move))
+                    .filter(|(other, target)| *target == to && *other != from
+                        && self.board.square_by_id(*other).get_piece().is_some_and(|p| p.get_name() == name))
+                    .map(|(other, _)| other)
+                    .collect();
+                let mut san = String::from(piece_letter(name));
+                if !rivals.is_empty() {
+                    if rivals.iter().all(|other| other.file() != from.file()) {
+                        san += &from.to_str()[..1];
+                    } else if rivals.iter().all(|other| other.rank() != from.rank()) {
+                        san += &from.to_str()[1..];
+                    } else {
+                        san += &from.to_str();
+                    }
+                }
+                if chess_move.is_capture() {
+                    san += "x";
+                }
+                san + &to.to_str()
+            },
+        };
+        match annotated_move.annotation {
+            Annotation::Check => san += "+",
+            Annotation::CheckMate => san += "#",
+            _ => {},
+        }
+        san
+    }
+
+    // finds the legal move for a SAN string; check marks and annotations are optional
+    pub fn parse_san(&self, san: &str) -> Option<AnnotatedMove> {
+        let san = normalize_san(san.trim());
+        let moves = self.get_legal_moves();
+        let castle = match san.as_str() {
+            "O-O" => Some(ChessMove::ShortCastle),
+            "O-O-O" => Some(ChessMove::LongCastle),
+            _ => None,
+        };
+        if let Some(castle) = castle {
+            return moves.iter().copied().find(|m| m.chess_move == castle);
+        }
+        if let Some((letter, target)) = san.split_once('@') {
+            let drop = ChessMove::Drop(letter_piece(letter.chars().next()?)?, SquareID::parse(target)?);
+            return moves.iter().copied().find(|m| m.chess_move == drop);
+        }
+
+        // the piece, the origin's file and/or rank when ambiguous, x, the target and the promotion
+        let (rest, promotion) = match san.char_indices().last()? {
+            (i, letter) if letter.is_ascii_uppercase() => (&san[..i], Some(letter_piece(letter)?)),
+            _ => (san.as_str(), None),
+        };
+        let (rest, target) = rest.split_at_checked(rest.len().checked_sub(2)?)?;
+        let target = SquareID::parse(target)?;
+        let (rest, capture) = rest.strip_suffix('x').map_or((rest, false), |rest| (rest, true));
+        let (name, origin) = match rest.chars().next() {
+            Some(letter) if letter.is_ascii_uppercase() => (letter_piece(letter)?, &rest[1..]),
+            _ => (PieceName::Pawn, rest),
+        };
+        let mut found = moves.iter().copied().filter(|m| {
+            if matches!(m.chess_move, ChessMove::ShortCastle | ChessMove::LongCastle | ChessMove::Drop(_, _)) {
+                return false;
+            }
+            let (from, to) = self.move_squares(m.chess_move);
+            let promoted = match m.chess_move {
+                ChessMove::Promotion(_, promoted) | ChessMove::CapturePromotion(_, _, promoted) => Some(promoted),
+                _ => None,
+            };
+            to == target && promoted == promotion && m.chess_move.is_capture() == capture
+                && self.board.square_by_id(from).get_piece().is_some_and(|p| p.get_name() == name)
+                && origin.chars().all(|c| from.to_str().contains(c))
+        });
+        // an ambiguous move matches more than one
+        let m = found.next()?;
+        found.next().is_none().then_some(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess_game::chess_move::{AnnotatedMove, Annotation, ChessMove};
+    use crate::chess_game::chess_piece::PieceName;
+    use crate::chess_game::chess_square::SquareID;
+    use crate::chess_game::variant::Variant;
+    use crate::chess_game::ChessGameState;
+
+    fn sq(name: &str) -> SquareID {
+        SquareID::parse(name).unwrap()
+    }
+
+    #[test]
+    fn uci_moves() {
+        let game = ChessGameState::from_fen("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(game.move_to_uci(ChessMove::ShortCastle), "e1g1");
+        assert_eq!(game.move_to_uci(ChessMove::LongCastle), "e1c1");
+        assert_eq!(game.move_to_uci(ChessMove::CapturePromotion(sq("b7"), sq("a8"), PieceName::Queen)), "b7a8q");
+        assert_eq!(game.move_to_uci(ChessMove::Promotion(sq("b8"), PieceName::Knight)), "b7b8n");
+
+        assert_eq!(game.parse_uci_move("e1c1").map(|m| m.chess_move), Some(ChessMove::LongCastle));
+        let promotion = game.parse_uci_move("b7a8q").unwrap();
+        assert_eq!(promotion.chess_move, ChessMove::CapturePromotion(sq("b7"), sq("a8"), PieceName::Queen));
+        assert_eq!(promotion.annotation, Annotation::Check);
+        assert!(game.parse_uci_move("e1e3").is_none());
+
+        let black = ChessGameState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1").unwrap();
+        assert_eq!(black.parse_uci_move("e8g8").map(|m| m.chess_move), Some(ChessMove::ShortCastle));
+        assert_eq!(black.move_to_uci_960(ChessMove::ShortCastle), "e8h8");
+        assert_eq!(black.parse_uci_move_960("e8a8").map(|m| m.chess_move), Some(ChessMove::LongCastle));
+        assert!(black.parse_uci_move_960("e8c8").is_none());
+
+        // b1c1 is an ordinary king move when castling is written as taking the rook
+        let chess960 = ChessGameState::from_fen("rk5r/8/8/8/8/8/8/RK5R w KQkq - 0 1").unwrap();
+        assert_eq!(chess960.move_to_uci_960(ChessMove::LongCastle), "b1a1");
+        assert_eq!(chess960.parse_uci_move_960("b1c1").map(|m| m.chess_move), Some(ChessMove::Move(sq("b1"), sq("c1"))));
+        assert_eq!(chess960.parse_uci_move_960("b1h1").map(|m| m.chess_move), Some(ChessMove::ShortCastle));
+    }
+
+    #[test]
+    fn san_moves() {
+        let game = ChessGameState::from_fen("r3k2r/1P6/8/3pP3/8/1N3N2/8/R3K2R w KQkq d6 0 1").unwrap();
+        let san = |m: ChessMove| game.move_to_san(game.annotate_move(m).unwrap());
+        assert_eq!(san(ChessMove::ShortCastle), "O-O");
+        assert_eq!(san(ChessMove::LongCastle), "O-O-O");
+        assert_eq!(san(ChessMove::EnPassant(sq("e5"), sq("d6"))), "exd6");
+        assert_eq!(san(ChessMove::Move(sq("e5"), sq("e6"))), "e6");
+        assert_eq!(san(ChessMove::CapturePromotion(sq("b7"), sq("a8"), PieceName::Queen)), "bxa8=Q+");
+        assert_eq!(san(ChessMove::Promotion(sq("b8"), PieceName::Knight)), "b8=N");
+        // both knights can go to d4
+        assert_eq!(san(ChessMove::Move(sq("b3"), sq("d4"))), "Nbd4");
+        assert_eq!(san(ChessMove::Move(sq("f3"), sq("h4"))), "Nh4");
+        assert_eq!(san(ChessMove::Move(sq("a1"), sq("a7"))), "Ra7");
+
+        assert_eq!(game.parse_san("Nfd4").map(|m| m.chess_move), Some(ChessMove::Move(sq("f3"), sq("d4"))));
+        assert_eq!(game.parse_san("0-0-0").map(|m| m.chess_move), Some(ChessMove::LongCastle));
+        assert_eq!(game.parse_san("bxa8Q").map(|m| m.annotation), Some(Annotation::Check));
+        assert_eq!(game.parse_san("e6!?").map(|m| m.chess_move), Some(ChessMove::Move(sq("e5"), sq("e6"))));
+        assert!(game.parse_san("Nd4").is_none());
+        assert!(game.parse_san("Qd1").is_none());
+        assert!(game.parse_san("Nxd4").is_none());
+        assert!(game.parse_san("e8=Q").is_none());
+        assert_eq!(game.parse_san("b8N").map(|m| m.chess_move), Some(ChessMove::Promotion(sq("b8"), PieceName::Knight)));
+        assert_eq!(game.parse_san("exd6").map(|m| m.chess_move), Some(ChessMove::EnPassant(sq("e5"), sq("d6"))));
+        // every legal move reads back from its SAN
+        for m in game.get_legal_moves().iter() {
+            assert_eq!(game.parse_san(&game.move_to_san(*m)), Some(*m));
+        }
+
+        let crazyhouse = ChessGameState::from_variant_fen(Variant::Crazyhouse, "4k3/8/8/8/8/8/8/4K3[NP] w - - 0 1").unwrap();
+        let knight = crazyhouse.parse_uci_move("N@f6").unwrap();
+        assert_eq!(knight.chess_move, ChessMove::Drop(PieceName::Knight, sq("f6")));
+        assert_eq!(crazyhouse.move_to_san(knight), "N@f6+");
+        assert_eq!(crazyhouse.move_to_uci(ChessMove::Drop(PieceName::Pawn, sq("e4"))), "P@e4");
+        assert_eq!(crazyhouse.parse_san("P@e4"), crazyhouse.parse_san("@e4"));
+
+        let mate = ChessGameState::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4").unwrap();
+        let qxf7 = AnnotatedMove::new(ChessMove::Capture(sq("h5"), sq("f7")), Annotation::CheckMate);
+        assert_eq!(mate.move_to_san(qxf7), "Qxf7#");
+        assert_eq!(mate.parse_san("Qxf7#"), Some(qxf7));
+    }
+}
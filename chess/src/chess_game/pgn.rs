@@ -0,0 +1,260 @@
+use std::fmt::{Display, Formatter};
+use crate::chess_game::chess_move::AnnotatedMove;
+use crate::chess_game::variant::Variant;
+use crate::chess_game::{ChessGameState, FenError, Player};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PgnError {
+    // a tag pair that isn't of the form [Name "value"]
+    Tag(String),
+    Fen(FenError),
+    // a Variant tag naming rules this crate doesn't know
+    Variant(String),
+    // the move text of a game (counting from 1) has a move that isn't legal
+    IllegalMove { game: usize, ply: usize, san: String },
+}
+
+impl Display for PgnError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PgnError::Tag(tag) => write!(f, "invalid tag pair: {}", tag),
+            PgnError::Fen(err) => write!(f, "invalid FEN tag: {}", err),
+            PgnError::Variant(name) => write!(f, "unsupported variant: {}", name),
+            PgnError::IllegalMove { game, ply, san } => write!(f, "illegal move {} at ply {} of game {}", san, ply, game),
+        }
+    }
+}
+
+impl From<FenError> for PgnError {
+    fn from(err: FenError) -> Self {
+        PgnError::Fen(err)
+    }
+}
+
+// a game from a PGN file: its tags, starting position and main line
+#[derive(Debug, Clone)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub start: ChessGameState,
+    pub moves: Vec<AnnotatedMove>,
+    // the game termination marker: 1-0, 0-1, 1/2-1/2 or *
+    pub result: String,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn final_position(&self) -> ChessGameState {
+        let mut game = self.start;
+        for m in &self.moves {
+            game.make_move(*m);
+        }
+        game
+    }
+
+    // export format: the tags, then the move text in SAN wrapped below 80 columns
+    pub fn to_pgn(&self) -> String {
+        let mut text = String::new();
+        for (name, value) in &self.tags {
+            text += &format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\""));
+        }
+        text.push('\n');
+        let mut words = Vec::new();
+        let mut game = self.start;
+        for (ply, m) in self.moves.iter().enumerate() {
+            if game.active_player() == Player::White {
+                words.push(format!("{}.", game.turn()));
+            } else if ply == 0 {
+                words.push(format!("{}...", game.turn()));
+            }
+            words.push(game.move_to_san(*m));
+            game.make_move(*m);
+        }
+        words.push(self.result.clone());
+        let mut line = String::new();
+        for word in words {
+            if !line.is_empty() && line.len() + 1 + word.len() >= 80 {
+                text += &line;
+                text.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line += &word;
+        }
+        text += &line;
+        text.push('\n');
+        text
+    }
+}
+
+enum Token {
+    Tag(String),
+    Move(String),
+    Result(String),
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut variation_depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '[' if variation_depth == 0 => {
+                let tag: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                tokens.push(Token::Tag(tag));
+            },
+            '{' => chars.by_ref().take_while(|c| *c != '}').for_each(drop),
+            ';' => chars.by_ref().take_while(|c| *c != '\n').for_each(drop),
+            // escaped lines, e.g. % comments
+            '%' => chars.by_ref().take_while(|c| *c != '\n').for_each(drop),
+            // only the main line is kept
+            '(' => variation_depth += 1,
+            ')' => variation_depth -= 1,
+            c if c.is_whitespace() => {},
+            c => {
+                let mut word = String::from(c);
+                while let Some(next) = chars.peek() {
+                    if next.is_whitespace() || "[]{}();".contains(*next) {
+                        break;
+                    }
+                    word.push(*next);
+                    chars.next();
+                }
+                if variation_depth > 0 || word.starts_with('$') {
+                    continue;
+                }
+                if ["1-0", "0-1", "1/2-1/2", "*"].contains(&word.as_str()) {
+                    tokens.push(Token::Result(word));
+                    continue;
+                }
+                // move numbers may be glued to the move: 12.Nf3 or 12...Nf6
+                let san = word.rsplit('.').next().unwrap_or_default();
+                if !san.is_empty() {
+                    tokens.push(Token::Move(String::from(san)));
+                }
+            },
+        }
+    }
+    tokens
+}
+
+fn parse_tag(tag: &str) -> Option<(String, String)> {
+    let (name, value) = tag.trim().split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((String::from(name), value.replace("\\\"", "\"").replace("\\\\", "\\")))
+}
+
+// reads every game of a PGN collection; variations, comments and NAGs are skipped
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut games = Vec::new();
+    let mut tags = Vec::new();
+    let mut sans = Vec::new();
+    let mut finish = |tags: &mut Vec<(String, String)>, sans: &mut Vec<String>, result: String| -> Result<(), PgnError> {
+        let tags = std::mem::take(tags);
+        let sans = std::mem::take(sans);
+        let variant = match tags.iter().find(|(name, _)| name == "Variant") {
+            Some((_, name)) => Variant::from_name(name).ok_or_else(|| PgnError::Variant(name.clone()))?,
+            None => Variant::Standard,
+        };
+        let start = match tags.iter().find(|(name, _)| name == "FEN") {
+            Some((_, fen)) => ChessGameState::from_variant_fen(variant, fen)?,
+            None => ChessGameState::with_variant(variant),
+        };
+        let mut game = start;
+        let mut moves = Vec::new();
+        for (ply, san) in sans.into_iter().enumerate() {
+            let Some(m) = game.parse_san(&san) else {
+                return Err(PgnError::IllegalMove { game: games.len() + 1, ply: ply + 1, san });
+            };
+            game.make_move(m);
+            moves.push(m);
+        }
+        games.push(PgnGame { tags, start, moves, result });
+        Ok(())
+    };
+    for token in tokenize(text) {
+        match token {
+            Token::Tag(tag) => {
+                // a tag after move text starts the next game, even without a termination marker
+                if !sans.is_empty() {
+                    finish(&mut tags, &mut sans, String::from("*"))?;
+                }
+                tags.push(parse_tag(&tag).ok_or(PgnError::Tag(tag))?);
+            },
+            Token::Move(san) => sans.push(san),
+            Token::Result(result) => finish(&mut tags, &mut sans, result)?,
+        }
+    }
+    if !sans.is_empty() || !tags.is_empty() {
+        finish(&mut tags, &mut sans, String::from("*"))?;
+    }
+    Ok(games)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess_game::chess_move::ChessMove;
+    use crate::chess_game::pgn::{parse_pgn, PgnError};
+    use crate::chess_game::variant::Variant;
+
+    #[test]
+    fn read_games() {
+        let pgn = r#"
+[Event "Casual"]
+[White "Anderssen"]
+[Black "Kieseritzky"]
+[Result "1-0"]
+
+1. e4 e5 2. f4 exf4 3. Bc4 Qh4+ {the king has to move} 4. Kf1 (4. g3?? fxg3) b5 $2
+5. Bxb5 1-0
+
+[Event "Second"]
+[FEN "4k3/8/8/8/8/8/8/R3K3 w Q - 0 1"]
+
+1.0-0-0 Kf7 2.Rd7+ *
+"#;
+        let games = parse_pgn(pgn).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("White"), Some("Anderssen"));
+        assert_eq!(games[0].moves.len(), 9);
+        assert_eq!(games[0].result, "1-0");
+        assert_eq!(games[1].moves[0].chess_move, ChessMove::LongCastle);
+        assert_eq!(games[1].moves.len(), 3);
+        assert_eq!(games[1].result, "*");
+
+        let illegal = parse_pgn("1. e4 e5 2. Ke3 *");
+        assert_eq!(illegal.unwrap_err(), PgnError::IllegalMove { game: 1, ply: 3, san: String::from("Ke3") });
+        assert!(matches!(parse_pgn("[Event Casual]"), Err(PgnError::Tag(_))));
+    }
+
+    #[test]
+    fn variant_games() {
+        let pgn = "[Variant \"Chess960\"]\n[FEN \"rk5r/8/8/8/8/8/8/RK5R w KQkq - 0 1\"]\n\n1. O-O-O O-O-O *\n";
+        let games = parse_pgn(pgn).unwrap();
+        assert_eq!(games[0].start.variant(), Variant::Chess960);
+        assert_eq!(games[0].final_position().get_fen(), "2kr3r/8/8/8/8/8/8/2KR3R w - - 2 2");
+        assert_eq!(games[0].to_pgn(), pgn);
+
+        let fischer = parse_pgn("[Variant \"Fischer Random\"]\n\n1. e4 *").unwrap();
+        assert_eq!(fischer[0].start.variant(), Variant::Chess960);
+        assert_eq!(parse_pgn("[Variant \"Shogi\"]\n\n*").unwrap_err(), PgnError::Variant(String::from("Shogi")));
+    }
+
+    #[test]
+    fn write_games() {
+        let pgn = "[Event \"Test \\\"quoted\\\"\"]\n[FEN \"4k3/8/8/8/8/8/8/R3K3 b Q - 0 7\"]\n\n7... Kf7 8. O-O-O Ke6 1/2-1/2\n";
+        let games = parse_pgn(pgn).unwrap();
+        assert_eq!(games[0].to_pgn(), pgn);
+        assert_eq!(games[0].final_position().get_fen(), "8/8/4k3/8/8/8/8/2KR4 w - - 3 9");
+
+        // long games are wrapped
+        let long = parse_pgn(&"1. Nf3 Nf6 2. Ng1 Ng8 ".repeat(20)).unwrap();
+        let text = long[0].to_pgn();
+        assert!(text.lines().all(|line| line.len() < 80));
+        assert_eq!(parse_pgn(&text).unwrap()[0].moves, long[0].moves);
+    }
+}
@@ -0,0 +1,414 @@
+use std::fmt::{Display, Formatter};
+use crate::chess_game::chess_move::AnnotatedMove;
+use crate::chess_game::game_clock::GameClock;
+use crate::chess_game::pgn::PgnGame;
+use crate::chess_game::variant::Variant;
+use crate::chess_game::{ChessGameState, GameResult, Player};
+
+// how a game ended, as written in the PGN Termination tag
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Termination {
+    // by the rules or an agreement between the players: mate, stalemate, resignation or a draw agreement
+    Normal,
+    TimeForfeit,
+    Adjudication,
+    Abandoned,
+}
+
+impl Termination {
+    pub fn pgn_name(&self) -> &'static str {
+        match self {
+            Termination::Normal => "normal",
+            Termination::TimeForfeit => "time forfeit",
+            Termination::Adjudication => "adjudication",
+            Termination::Abandoned => "abandoned",
+        }
+    }
+}
+
+// something besides a move that happened in a game
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum GameEvent {
+    DrawOffered(Player),
+    DrawDeclined(Player),
+    DrawAgreed,
+    Resigned(Player),
+    TimeForfeit(Player),
+    Abandoned(Player),
+    // the same position for the third time, which draws by the rules
+    ThreefoldRepetition,
+    // the result an arbiter gave, and why
+    Adjudicated(GameResult, String),
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum SessionError {
+    GameOver,
+    // there is no draw offer from the opponent to accept or decline
+    NoDrawOffer,
+}
+
+impl Display for SessionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionError::GameOver => write!(f, "the game is over"),
+            SessionError::NoDrawOffer => write!(f, "no draw has been offered"),
+        }
+    }
+}
+
+// a game between two players: the moves, and the events that can end it besides the rules of chess
+#[derive(Debug, Clone)]
+pub struct GameSession {
+    start: ChessGameState,
+    game: ChessGameState,
+    moves: Vec<AnnotatedMove>,
+    // each event with the number of moves played before it
+    events: Vec<(usize, GameEvent)>,
+    // the player whose draw offer stands until the opponent answers it or moves
+    draw_offer: Option<Player>,
+    // the result and termination of a game ended other than by the rules of chess
+    ending: Option<(GameResult, Termination)>,
+}
+
+impl GameSession {
+    pub fn new(start: ChessGameState) -> Self {
+        Self { start, game: start, moves: Vec::new(), events: Vec::new(), draw_offer: None, ending: None }
+    }
+
+    pub fn start(&self) -> &ChessGameState {
+        &self.start
+    }
+
+    pub fn game(&self) -> &ChessGameState {
+        &self.game
+    }
+
+    pub fn moves(&self) -> &[AnnotatedMove] {
+        &self.moves
+    }
+
+    pub fn events(&self) -> &[(usize, GameEvent)] {
+        &self.events
+    }
+
+    pub fn draw_offer(&self) -> Option<Player> {
+        self.draw_offer
+    }
+
+    pub fn result(&self) -> Option<GameResult> {
+        self.ending.map(|(result, _)| result).or(self.game.result())
+    }
+
+    pub fn termination(&self) -> Option<Termination> {
+        self.ending.map(|(_, termination)| termination).or(self.game.result().map(|_| Termination::Normal))
+    }
+
+    fn check_running(&self) -> Result<(), SessionError> {
+        match self.result() {
+            Some(_) => Err(SessionError::GameOver),
+            None => Ok(()),
+        }
+    }
+
+    fn end(&mut self, result: GameResult, termination: Termination, event: GameEvent) {
+        self.record(event);
+        self.draw_offer = None;
+        self.ending = Some((result, termination));
+    }
+
+    fn record(&mut self, event: GameEvent) {
+        self.events.push((self.moves.len(), event));
+    }
+
+    // plays a legal move for the active player; moving instead of answering the opponent's draw offer declines it,
+    // and a position occurring for the third time draws
+    pub fn play(&mut self, annotated_move: AnnotatedMove) -> Result<(), SessionError> {
+        self.check_running()?;
+        let player = self.game.active_player();
+        if self.draw_offer == Some(player.opponent()) {
+            self.draw_offer = None;
+        }
+        self.game.make_move(annotated_move);
+        self.moves.push(annotated_move);
+        if self.game.result().is_none() && self.repetitions() >= 3 {
+            self.end(GameResult::Draw, Termination::Normal, GameEvent::ThreefoldRepetition);
+        }
+        Ok(())
+    }
+
+    // plays a move on the clock, which starts with the first move; a move made after the flag fell forfeits instead
+    pub fn play_timed(&mut self, clock: &mut GameClock, annotated_move: AnnotatedMove) -> Result<(), SessionError> {
+        self.check_running()?;
+        if !clock.is_running() {
+            clock.start(self.game.active_player());
+        }
+        clock.press();
+        if let Some(player) = clock.flagged() {
+            clock.stop();
+            return self.flag(player);
+        }
+        self.play(annotated_move)?;
+        if self.result().is_some() {
+            clock.stop();
+        }
+        Ok(())
+    }
+
+    // how often the current position has occurred in the game
+    pub fn repetitions(&self) -> usize {
+        let hash = self.game.hash();
+        let mut game = self.start;
+        let mut count = usize::from(game.hash() == hash);
+        for m in &self.moves {
+            game.make_move(*m);
+            count += usize::from(game.hash() == hash);
+        }
+        count
+    }
+
+    // takes back the last move along with anything that happened after it, so a game that ended then goes on.
+    // a draw offer still standing is taken back too
+    pub fn undo(&mut self) -> Option<AnnotatedMove> {
+        let undone = self.moves.pop()?;
+        self.game = self.start;
+        for m in &self.moves {
+            self.game.make_move(*m);
+        }
+        if let Some(player) = self.draw_offer.take()
+            && let Some(i) = self.events.iter().rposition(|(_, event)| *event == GameEvent::DrawOffered(player)) {
+            self.events.remove(i);
+        }
+        let played = self.moves.len();
+        self.events.retain(|(ply, _)| *ply <= played);
+        self.ending = None;
+        Some(undone)
+    }
+
+    // a player offering a draw the opponent has offered as well agrees to it
+    pub fn offer_draw(&mut self, player: Player) -> Result<(), SessionError> {
+        self.check_running()?;
+        if self.draw_offer == Some(player.opponent()) {
+            return self.accept_draw(player);
+        }
+        self.draw_offer = Some(player);
+        self.record(GameEvent::DrawOffered(player));
+        Ok(())
+    }
+
+    pub fn accept_draw(&mut self, player: Player) -> Result<(), SessionError> {
+        self.check_running()?;
+        if self.draw_offer != Some(player.opponent()) {
+            return Err(SessionError::NoDrawOffer);
+        }
+        self.end(GameResult::Draw, Termination::Normal, GameEvent::DrawAgreed);
+        Ok(())
+    }
+
+    pub fn decline_draw(&mut self, player: Player) -> Result<(), SessionError> {
+        self.check_running()?;
+        if self.draw_offer != Some(player.opponent()) {
+            return Err(SessionError::NoDrawOffer);
+        }
+        self.draw_offer = None;
+        self.record(GameEvent::DrawDeclined(player));
+        Ok(())
+    }
+
+    pub fn resign(&mut self, player: Player) -> Result<(), SessionError> {
+        self.check_running()?;
+        self.end(GameResult::win_for(player.opponent()), Termination::Normal, GameEvent::Resigned(player));
+        Ok(())
+    }
+
+    // the player's time ran out: a loss, unless the opponent couldn't mate anyway
+    pub fn flag(&mut self, player: Player) -> Result<(), SessionError> {
+        self.check_running()?;
+        let mut game = self.game;
+        game.flag(player);
+        self.end(game.result().unwrap(), Termination::TimeForfeit, GameEvent::TimeForfeit(player));
+        Ok(())
+    }
+
+    pub fn abandon(&mut self, player: Player) -> Result<(), SessionError> {
+        self.check_running()?;
+        self.end(GameResult::win_for(player.opponent()), Termination::Abandoned, GameEvent::Abandoned(player));
+        Ok(())
+    }
+
+    pub fn adjudicate(&mut self, result: GameResult, reason: &str) -> Result<(), SessionError> {
+        self.check_running()?;
+        self.end(result, Termination::Adjudication, GameEvent::Adjudicated(result, String::from(reason)));
+        Ok(())
+    }
+
+    // the game for PGN export: the given tags with the Result set, followed by Termination once the game is over,
+    // and the Variant and starting position when they aren't the standard ones
+    pub fn to_pgn_game(&self, tags: &[(String, String)]) -> PgnGame {
+        let result = String::from(self.result().map_or("*", GameResult::to_pgn));
+        let mut tags: Vec<(String, String)> = tags.iter().filter(|(name, _)| name != "Termination").cloned().collect();
+        match tags.iter_mut().find(|(name, _)| name == "Result") {
+            Some((_, value)) => value.clone_from(&result),
+            None => tags.push((String::from("Result"), result.clone())),
+        }
+        if let Some(termination) = self.termination() {
+            tags.push((String::from("Termination"), String::from(termination.pgn_name())));
+        }
+        if self.start.variant() != Variant::Standard && !tags.iter().any(|(name, _)| name == "Variant") {
+            tags.push((String::from("Variant"), String::from(self.start.variant().name())));
+        }
+        if self.start.get_fen() != ChessGameState::new().get_fen() && !tags.iter().any(|(name, _)| name == "FEN") {
+            tags.push((String::from("SetUp"), String::from("1")));
+            tags.push((String::from("FEN"), self.start.get_fen()));
+        }
+        PgnGame { tags, start: self.start, moves: self.moves.clone(), result }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::chess_game::game_clock::MockClock;
+    use crate::chess_game::game_clock::{GameClock, TimeControl};
+    use crate::chess_game::pgn::parse_pgn;
+    use crate::chess_game::session::{GameEvent, GameSession, SessionError, Termination};
+    use crate::chess_game::{ChessGameState, GameResult, Player};
+
+    fn play(session: &mut GameSession, san: &str) {
+        let m = session.game().parse_san(san).unwrap();
+        session.play(m).unwrap();
+    }
+
+    #[test]
+    fn draw_offers() {
+        let mut session = GameSession::new(ChessGameState::new());
+        play(&mut session, "e4");
+        assert_eq!(session.accept_draw(Player::Black), Err(SessionError::NoDrawOffer));
+        // an offer stands while the player who made it moves, and lapses once the opponent does
+        session.offer_draw(Player::Black).unwrap();
+        play(&mut session, "e5");
+        assert_eq!(session.draw_offer(), Some(Player::Black));
+        play(&mut session, "Nf3");
+        assert_eq!(session.draw_offer(), None);
+        assert_eq!(session.accept_draw(Player::White), Err(SessionError::NoDrawOffer));
+
+        session.offer_draw(Player::Black).unwrap();
+        assert_eq!(session.accept_draw(Player::Black), Err(SessionError::NoDrawOffer));
+        session.decline_draw(Player::White).unwrap();
+        session.offer_draw(Player::White).unwrap();
+        session.accept_draw(Player::Black).unwrap();
+        assert_eq!((session.result(), session.termination()), (Some(GameResult::Draw), Some(Termination::Normal)));
+        assert_eq!(session.events(), [
+            (1, GameEvent::DrawOffered(Player::Black)),
+            (3, GameEvent::DrawOffered(Player::Black)),
+            (3, GameEvent::DrawDeclined(Player::White)),
+            (3, GameEvent::DrawOffered(Player::White)),
+            (3, GameEvent::DrawAgreed),
+        ]);
+        let m = session.game().parse_san("Nc6").unwrap();
+        assert_eq!(session.play(m), Err(SessionError::GameOver));
+
+        // offering a draw the opponent already offered agrees to it
+        let mut session = GameSession::new(ChessGameState::new());
+        session.offer_draw(Player::White).unwrap();
+        session.offer_draw(Player::Black).unwrap();
+        assert_eq!(session.result(), Some(GameResult::Draw));
+    }
+
+    #[test]
+    fn endings() {
+        let mut session = GameSession::new(ChessGameState::new());
+        assert_eq!(session.termination(), None);
+        play(&mut session, "d4");
+        session.resign(Player::Black).unwrap();
+        assert_eq!((session.result(), session.termination()), (Some(GameResult::WhiteWin), Some(Termination::Normal)));
+        assert_eq!(session.resign(Player::White), Err(SessionError::GameOver));
+
+        let mut session = GameSession::new(ChessGameState::new());
+        session.abandon(Player::White).unwrap();
+        assert_eq!((session.result(), session.termination()), (Some(GameResult::BlackWin), Some(Termination::Abandoned)));
+
+        // bare kings can't mate, so running out of time against them is a draw
+        let mut session = GameSession::new(ChessGameState::from_fen("k7/8/8/8/8/8/8/K6Q b - - 0 1").unwrap());
+        session.flag(Player::White).unwrap();
+        assert_eq!((session.result(), session.termination()), (Some(GameResult::Draw), Some(Termination::TimeForfeit)));
+
+        let mut session = GameSession::new(ChessGameState::new());
+        session.adjudicate(GameResult::BlackWin, "White left the hall").unwrap();
+        assert_eq!(session.events(), [(0, GameEvent::Adjudicated(GameResult::BlackWin, String::from("White left the hall")))]);
+
+        // taking back the last move resumes a game, without the events that happened since
+        let mut session = GameSession::new(ChessGameState::new());
+        play(&mut session, "e4");
+        session.offer_draw(Player::White).unwrap();
+        play(&mut session, "e5");
+        session.resign(Player::White).unwrap();
+        assert_eq!(session.undo().map(|m| session.game().move_to_san(m)), Some(String::from("e5")));
+        assert_eq!((session.result(), session.draw_offer(), session.moves().len()), (None, None, 1));
+        assert_eq!(session.events(), [(1, GameEvent::DrawOffered(Player::White))]);
+        session.undo();
+        assert_eq!(session.undo(), None);
+        assert_eq!(session.game(), &ChessGameState::new());
+        // an offer that still stood leaves no trace
+        for san in ["e4", "e5"] {
+            play(&mut session, san);
+        }
+        session.offer_draw(Player::White).unwrap();
+        play(&mut session, "Nf3");
+        session.undo();
+        assert_eq!((session.draw_offer(), session.events()), (None, &[][..]));
+
+        // mate is a normal ending too
+        let mut session = GameSession::new(ChessGameState::new());
+        for san in ["f3", "e5", "g4", "Qh4#"] {
+            play(&mut session, san);
+        }
+        assert_eq!((session.result(), session.termination()), (Some(GameResult::BlackWin), Some(Termination::Normal)));
+    }
+
+    #[test]
+    fn pgn_tags() {
+        let mut session = GameSession::new(ChessGameState::from_fen("k7/8/8/8/8/8/8/K6Q w - - 0 1").unwrap());
+        play(&mut session, "Qh8+");
+        let tags = [(String::from("Event"), String::from("Lesson")), (String::from("Result"), String::from("*"))];
+        assert_eq!(session.to_pgn_game(&tags).to_pgn().lines().next_back(), Some("1. Qh8+ *"));
+
+        session.adjudicate(GameResult::WhiteWin, "won position").unwrap();
+        let pgn = session.to_pgn_game(&tags).to_pgn();
+        assert!(pgn.starts_with("[Event \"Lesson\"]\n[Result \"1-0\"]\n[Termination \"adjudication\"]\n[SetUp \"1\"]\n"), "{}", pgn);
+        let games = parse_pgn(&pgn).unwrap();
+        assert_eq!((games[0].result.as_str(), games[0].tag("Termination")), ("1-0", Some("adjudication")));
+    }
+
+    #[test]
+    fn repetition() {
+        let mut session = GameSession::new(ChessGameState::new());
+        for san in ["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1"] {
+            play(&mut session, san);
+        }
+        assert_eq!(session.result(), None);
+        play(&mut session, "Ng8");
+        assert_eq!((session.result(), session.termination()), (Some(GameResult::Draw), Some(Termination::Normal)));
+        assert_eq!(session.events(), [(8, GameEvent::ThreefoldRepetition)]);
+        assert_eq!(session.to_pgn_game(&[]).tag("Termination"), Some("normal"));
+        session.undo();
+        assert_eq!((session.result(), session.events()), (None, &[][..]));
+    }
+
+    #[test]
+    fn timed_play() {
+        let time = MockClock::new();
+        let mut clock = GameClock::new(TimeControl::sudden_death(Duration::from_secs(10)), Box::new(time.clone()));
+        let mut session = GameSession::new(ChessGameState::new());
+        for san in ["Nf3", "Nf6", "Ng1", "Ng8"] {
+            let m = session.game().parse_san(san).unwrap();
+            session.play_timed(&mut clock, m).unwrap();
+        }
+        assert_eq!((session.repetitions(), session.result()), (2, None));
+        time.advance(Duration::from_secs(11));
+        let m = session.game().parse_san("Nf3").unwrap();
+        session.play_timed(&mut clock, m).unwrap();
+        assert_eq!((session.result(), session.termination()), (Some(GameResult::BlackWin), Some(Termination::TimeForfeit)));
+        assert_eq!((session.moves().len(), clock.is_running()), (4, false));
+    }
+}
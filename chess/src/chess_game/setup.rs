@@ -0,0 +1,148 @@
+use std::fmt::{Display, Formatter};
+use crate::chess_game::chess_piece::ChessPiece;
+use crate::chess_game::chess_square::SquareID;
+use crate::chess_game::variant::Variant;
+use crate::chess_game::{ChessGameState, FenError, Player};
+
+// a starting position built from a variant's own by adding and removing pieces, e.g. for odds games. Every king
+// and rook still on its original square can castle.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Setup {
+    variant: Variant,
+    pieces: [Option<ChessPiece>; 64],
+    active_player: Player,
+}
+
+impl Setup {
+    pub fn new(variant: Variant) -> Self {
+        let start = ChessGameState::with_variant(variant);
+        Self {
+            variant,
+            pieces: std::array::from_fn(|i| start.board().square_by_id(i.into()).get_piece()),
+            active_player: start.active_player(),
+        }
+    }
+
+    pub fn remove(mut self, id: SquareID) -> Self {
+        self.pieces[usize::from(id)] = None;
+        self
+    }
+
+    pub fn place(mut self, id: SquareID, piece: ChessPiece) -> Self {
+        self.pieces[usize::from(id)] = Some(piece);
+        self
+    }
+
+    pub fn to_move(mut self, player: Player) -> Self {
+        self.active_player = player;
+        self
+    }
+
+    pub fn build(&self) -> Result<ChessGameState, FenError> {
+        let rook_files = ChessGameState::parse_castling("KQkq", &self.pieces)?;
+        let pieces = std::array::from_fn(|i| self.pieces[i].map(|p| ChessGameState::fen_piece_moved(p, i.into(), rook_files)));
+        ChessGameState::from_parts(self.variant, pieces, [[0; 5]; 2], [0; 2], self.active_player, None, 0, 1)
+    }
+}
+
+// the named starting positions. White gives the odds, apart from pawn and move, where Black plays without the
+// f-pawn and White still moves first
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum StartPosition {
+    Standard,
+    KnightOdds,
+    RookOdds,
+    QueenOdds,
+    PawnAndMove,
+    Horde,
+}
+
+impl StartPosition {
+    pub const ALL: [StartPosition; 6] = [
+        StartPosition::Standard, StartPosition::KnightOdds, StartPosition::RookOdds, StartPosition::QueenOdds,
+        StartPosition::PawnAndMove, StartPosition::Horde,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StartPosition::Standard => "Standard",
+            StartPosition::KnightOdds => "Knight odds",
+            StartPosition::RookOdds => "Rook odds",
+            StartPosition::QueenOdds => "Queen odds",
+            StartPosition::PawnAndMove => "Pawn and move",
+            StartPosition::Horde => "Horde",
+        }
+    }
+
+    // ignores case, spaces and dashes like Variant::from_name
+    pub fn from_name(name: &str) -> Option<StartPosition> {
+        let name: String = name.chars().filter(|c| !matches!(c, ' ' | '-' | '_')).collect::<String>().to_lowercase();
+        StartPosition::ALL.into_iter().find(|position| position.name().replace(' ', "").to_lowercase() == name)
+    }
+
+    pub fn setup(&self) -> Setup {
+        let square = |s| SquareID::parse(s).unwrap();
+        let standard = Setup::new(Variant::Standard);
+        match self {
+            StartPosition::Standard => standard,
+            StartPosition::KnightOdds => standard.remove(square("b1")),
+            StartPosition::RookOdds => standard.remove(square("a1")),
+            StartPosition::QueenOdds => standard.remove(square("d1")),
+            StartPosition::PawnAndMove => standard.remove(square("f7")),
+            StartPosition::Horde => Setup::new(Variant::Horde),
+        }
+    }
+
+    pub fn game(&self) -> ChessGameState {
+        self.setup().build().expect("named positions are valid")
+    }
+}
+
+impl Display for StartPosition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess_game::chess_piece::{ChessPiece, PieceName};
+    use crate::chess_game::chess_square::SquareID;
+    use crate::chess_game::setup::{Setup, StartPosition};
+    use crate::chess_game::variant::Variant;
+    use crate::chess_game::{ChessGameState, FenError, Player};
+
+    #[test]
+    fn named_positions() {
+        let fens: Vec<String> = StartPosition::ALL.iter().map(|position| position.game().get_fen()).collect();
+        assert_eq!(fens, [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/R1BQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/1NBQKBNR w Kkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNB1KBNR w KQkq - 0 1",
+            "rnbqkbnr/ppppp1pp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1",
+        ]);
+        assert_eq!(StartPosition::Standard.game(), ChessGameState::new());
+        assert_eq!(StartPosition::Horde.game().variant(), Variant::Horde);
+        for position in StartPosition::ALL {
+            assert_eq!(StartPosition::from_name(position.name()), Some(position));
+        }
+        assert_eq!(StartPosition::from_name("pawn-and-move"), Some(StartPosition::PawnAndMove));
+        assert_eq!(StartPosition::from_name("bishop odds"), None);
+    }
+
+    #[test]
+    fn builder() {
+        let square = |s| SquareID::parse(s).unwrap();
+        // a rook moved off its square can't castle
+        let game = Setup::new(Variant::Standard)
+            .remove(square("h1"))
+            .place(square("h3"), ChessPiece::new(Player::White, PieceName::Rook, false))
+            .to_move(Player::Black)
+            .build()
+            .unwrap();
+        assert_eq!(game.get_fen(), "rnbqkbnr/pppppppp/8/8/8/7R/PPPPPPPP/RNBQKBN1 b Qkq - 0 1");
+        assert_eq!(Setup::new(Variant::Standard).remove(square("e1")).build(), Err(FenError::Kings));
+    }
+}
@@ -0,0 +1,462 @@
+use std::fmt::{Display, Formatter};
+use crate::chess_game::chess_board::ChessBoard;
+use crate::chess_game::chess_move::ChessMove;
+use crate::chess_game::chess_piece::PieceName;
+use crate::chess_game::chess_square::{File, Rank, SquareID};
+use crate::chess_game::{ChessGameState, GameResult, Player};
+
+// the rules a game is played by. Each variant overrides some of the hooks below, which ChessGameState calls
+// while generating moves, playing them and deciding the result; the rest fall back to the standard rules.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    // Fischer Random: one of 960 shuffled back ranks, castling by the same rules as in ChessGameState
+    Chess960,
+    // captured pieces change sides and can be dropped back on the board instead of moving
+    Crazyhouse,
+    // giving a third check wins
+    ThreeCheck,
+    // bringing the king to one of the four centre squares wins
+    KingOfTheHill,
+    // captures explode, removing the capturing piece and every piece but a pawn next to the captured one
+    Atomic,
+    // losing chess: captures are compulsory, the king is an ordinary piece, and losing every piece or being
+    // stalemated wins
+    Antichess,
+    // White's 36 pawns, without a king, against a normal army; Black wins by capturing all of them
+    Horde,
+}
+
+impl Variant {
+    pub const ALL: [Variant; 8] = [
+        Variant::Standard, Variant::Chess960, Variant::Crazyhouse, Variant::ThreeCheck, Variant::KingOfTheHill, Variant::Atomic,
+        Variant::Antichess, Variant::Horde,
+    ];
+
+    // the value of PGN Variant tags
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Standard => "Standard",
+            Variant::Chess960 => "Chess960",
+            Variant::Crazyhouse => "Crazyhouse",
+            Variant::ThreeCheck => "Three-check",
+            Variant::KingOfTheHill => "King of the Hill",
+            Variant::Atomic => "Atomic",
+            Variant::Antichess => "Antichess",
+            Variant::Horde => "Horde",
+        }
+    }
+
+    // the value of the UCI_Variant option
+    pub fn uci_name(&self) -> &'static str {
+        match self {
+            Variant::Standard => "chess",
+            Variant::Chess960 => "chess960",
+            Variant::Crazyhouse => "crazyhouse",
+            Variant::ThreeCheck => "3check",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::Atomic => "atomic",
+            Variant::Antichess => "antichess",
+            Variant::Horde => "horde",
+        }
+    }
+
+    // the name in xboard's variant command
+    pub fn xboard_name(&self) -> &'static str {
+        match self {
+            Variant::Standard => "normal",
+            Variant::Chess960 => "fischerandom",
+            Variant::Crazyhouse => "crazyhouse",
+            Variant::ThreeCheck => "3check",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::Atomic => "atomic",
+            Variant::Antichess => "giveaway",
+            Variant::Horde => "horde",
+        }
+    }
+
+    // accepts the PGN, UCI and xboard names and their common spellings, ignoring case, spaces and dashes
+    pub fn from_name(name: &str) -> Option<Variant> {
+        let name: String = name.chars().filter(|c| !matches!(c, ' ' | '-' | '_')).collect::<String>().to_lowercase();
+        match name.as_str() {
+            "standard" | "chess" | "normal" => Some(Variant::Standard),
+            "chess960" | "fischerandom" | "fischerrandom" | "frc" => Some(Variant::Chess960),
+            "crazyhouse" | "zh" => Some(Variant::Crazyhouse),
+            "threecheck" | "3check" => Some(Variant::ThreeCheck),
+            "kingofthehill" | "koth" => Some(Variant::KingOfTheHill),
+            "atomic" => Some(Variant::Atomic),
+            "antichess" | "giveaway" | "losingchess" => Some(Variant::Antichess),
+            "horde" => Some(Variant::Horde),
+            _ => None,
+        }
+    }
+
+    pub fn start_fen(&self) -> &'static str {
+        match self {
+            Variant::Standard | Variant::Chess960 | Variant::KingOfTheHill | Variant::Atomic =>
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            Variant::ThreeCheck => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 +0+0",
+            Variant::Crazyhouse => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+            // there is no castling
+            Variant::Antichess => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+            Variant::Horde => "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1",
+        }
+    }
+
+    // whether captured pieces are kept for dropping, written in FEN as [pocket] after the placement
+    pub fn has_pockets(&self) -> bool {
+        matches!(self, Variant::Crazyhouse)
+    }
+
+    // whether the checks each player gave are counted, written in FEN as +white+black after the move number
+    pub fn counts_checks(&self) -> bool {
+        matches!(self, Variant::ThreeCheck)
+    }
+
+    // whether the king has to be kept out of check and can castle; otherwise a player may have any number of kings
+    pub fn has_royal_king(&self) -> bool {
+        !matches!(self, Variant::Antichess)
+    }
+
+    // the number of kings the player must have, or None for any number
+    pub fn king_count(&self, player: Player) -> Option<usize> {
+        match (self, player) {
+            (Variant::Antichess, _) => None,
+            (Variant::Horde, Player::White) => Some(0),
+            _ => Some(1),
+        }
+    }
+
+    // whether opening books and endgame tables, which only know the standard rules, apply to the variant
+    pub fn uses_standard_rules(&self) -> bool {
+        matches!(self, Variant::Standard | Variant::Chess960)
+    }
+
+    // pseudo-legal moves besides those of the pieces on the board, e.g. drops
+    pub(crate) fn add_moves(&self, game: &ChessGameState, moves: &mut Vec<ChessMove>) {
+        if *self == Variant::Crazyhouse {
+            let pocket = game.pocket(game.active_player());
+            for name in (0..5).filter(|name| pocket[*name] > 0).map(PieceName::from) {
+                moves.extend((0..64).map(SquareID::from).filter(|id| game.can_drop(name, *id)).map(|id| ChessMove::Drop(name, id)));
+            }
+        }
+    }
+
+    // the pieces a pawn can promote to
+    pub(crate) fn promotions(&self) -> &'static [PieceName] {
+        match self {
+            Variant::Antichess => &[PieceName::Knight, PieceName::Bishop, PieceName::Rook, PieceName::Queen, PieceName::King],
+            _ => &[PieceName::Knight, PieceName::Bishop, PieceName::Rook, PieceName::Queen],
+        }
+    }
+
+    // whether a player who can capture has to
+    pub fn forces_captures(&self) -> bool {
+        matches!(self, Variant::Antichess)
+    }
+
+    // removes the pseudo-legal moves the variant forbids in the position, e.g. quiet moves when a capture is compulsory
+    pub(crate) fn restrict_moves(&self, moves: &mut Vec<ChessMove>) {
+        if self.forces_captures() && moves.iter().any(ChessMove::is_capture) {
+            moves.retain(ChessMove::is_capture);
+        }
+    }
+
+    // what a capture on the target square does besides removing the captured piece
+    pub(crate) fn capture(&self, board: &mut ChessBoard, target: SquareID) {
+        if *self == Variant::Atomic {
+            board.explode(target);
+        }
+    }
+
+    // whether the player's king would be in check on the square, judging by what the opponent attacks now;
+    // in atomic a king next to the opponent's can't be taken, since both would explode
+    pub(crate) fn king_safe_on(&self, game: &ChessGameState, player: Player, id: SquareID) -> bool {
+        let board = game.board();
+        let attacked = board.square_by_id(id).is_seen_by(player.opponent());
+        match self {
+            Variant::Atomic => !attacked || board.find_king(player.opponent()).is_some_and(|king| adjacent(king.get_id(), id)),
+            Variant::Antichess => true,
+            _ => !attacked,
+        }
+    }
+
+    pub(crate) fn in_check(&self, game: &ChessGameState, player: Player) -> bool {
+        self.has_royal_king() && game.board().find_king(player).is_some_and(|king| !self.king_safe_on(game, player, king.get_id()))
+    }
+
+    // whether the move just played in the game was legal, i.e. didn't leave the mover's king in check; in atomic it
+    // mustn't blow up the mover's king either, but blowing up the opponent's is legal even when in check
+    pub(crate) fn legal_after_move(&self, game: &ChessGameState) -> bool {
+        let mover = game.active_player().opponent();
+        match self {
+            Variant::Atomic => game.board().find_king(mover).is_some()
+                && (game.board().find_king(game.active_player()).is_none() || !self.in_check(game, mover)),
+            Variant::Antichess => true,
+            _ => !self.in_check(game, mover),
+        }
+    }
+
+    // a result by the variant's own rules in the position just reached, before checkmate and stalemate are
+    // considered
+    pub(crate) fn result(&self, game: &ChessGameState) -> Option<GameResult> {
+        let players = [Player::White, Player::Black];
+        let winner = match self {
+            Variant::ThreeCheck => players.into_iter().find(|player| game.checks_given(*player) >= 3),
+            Variant::KingOfTheHill => players.into_iter().find(|player| game.board().find_king(*player).is_some_and(|king| {
+                let id = king.get_id();
+                matches!(id.file(), File::D | File::E) && matches!(id.rank(), Rank::Four | Rank::Five)
+            })),
+            Variant::Atomic => players.into_iter().find(|player| game.board().find_king(player.opponent()).is_none()),
+            Variant::Horde => (!game.board().iter().any(|sq| sq.get_piece().is_some_and(|p| p.get_owner() == Player::White)))
+                .then_some(Player::Black),
+            _ => None,
+        };
+        winner.map(GameResult::win_for)
+    }
+
+    // the result when the player to move has no legal moves; in antichess that player has lost every piece or is
+    // stalemated, and wins
+    pub(crate) fn no_moves_result(&self, game: &ChessGameState) -> GameResult {
+        match (*self, game.in_check(), game.active_player()) {
+            (Variant::Antichess, _, player) => GameResult::win_for(player),
+            (_, true, player) => GameResult::win_for(player.opponent()),
+            (_, false, _) => GameResult::Draw,
+        }
+    }
+}
+
+fn adjacent(a: SquareID, b: SquareID) -> bool {
+    let offset = a.calc_offset(b);
+    offset.file().abs() <= 1 && offset.rank().abs() <= 1
+}
+
+impl Display for Variant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess_engine::search::{SearchConfig, SearchLimits, Searcher, MATE_SCORE};
+    use crate::chess_game::chess_move::ChessMove;
+    use crate::chess_game::chess_piece::PieceName;
+    use crate::chess_game::chess_square::SquareID;
+    use crate::chess_game::tests::perft;
+    use crate::chess_game::variant::Variant;
+    use crate::chess_game::{ChessGameState, FenError, GameResult, Player};
+
+    fn crazyhouse(fen: &str) -> ChessGameState {
+        variant_game(Variant::Crazyhouse, fen)
+    }
+
+    fn variant_game(variant: Variant, fen: &str) -> ChessGameState {
+        ChessGameState::from_variant_fen(variant, fen).unwrap()
+    }
+
+    #[test]
+    fn names() {
+        for variant in Variant::ALL {
+            assert_eq!(Variant::from_name(variant.name()), Some(variant));
+            assert_eq!(Variant::from_name(variant.uci_name()), Some(variant));
+            assert_eq!(Variant::from_name(variant.xboard_name()), Some(variant));
+        }
+        assert_eq!(Variant::from_name("Fischer Random"), Some(Variant::Chess960));
+        assert_eq!(Variant::from_name("normal"), Some(Variant::Standard));
+        assert_eq!(Variant::from_name("shogi"), None);
+    }
+
+    #[test]
+    fn crazyhouse_pockets() {
+        let start = ChessGameState::with_variant(Variant::Crazyhouse);
+        assert_eq!(start.get_fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1");
+        assert_eq!(start.get_legal_moves().len(), 20);
+        assert_eq!(ChessGameState::from_fen("4k3/8/8/8/8/8/8/4K3[Q] w - - 0 1"), Err(FenError::Placement));
+
+        let fen = "r3k3/8/8/2Q~5/8/8/8/4K3[NPPb] w - - 0 1";
+        let game = crazyhouse(fen);
+        assert_eq!(game.get_fen(), fen);
+        assert_eq!(game.pocket(Player::White), [2, 1, 0, 0, 0]);
+        assert_eq!(game.pocket(Player::Black), [0, 0, 1, 0, 0]);
+        assert_ne!(game.hash(), crazyhouse("r3k3/8/8/2Q~5/8/8/8/4K3[NPb] w - - 0 1").hash());
+
+        // captures go to the capturer's pocket, promoted pieces as pawns
+        let mut game = crazyhouse("1r2k3/P7/8/8/8/8/8/4K3[] w - - 0 1");
+        game.make_move(game.parse_san("a8=Q").unwrap());
+        assert_eq!(game.get_fen(), "Q~r2k3/8/8/8/8/8/8/4K3[] b - - 0 1");
+        game.make_move(game.parse_san("Rxa8").unwrap());
+        assert_eq!(game.get_fen(), "r3k3/8/8/8/8/8/8/4K3[p] w - - 0 2");
+        // only black has a pawn to drop
+        assert!(game.parse_uci_move("P@e4").is_none());
+        game.make_move(game.parse_san("Kd1").unwrap());
+        let drop = game.parse_san("@e4").unwrap();
+        assert_eq!(drop.chess_move, ChessMove::Drop(PieceName::Pawn, SquareID::parse("e4").unwrap()));
+        game.make_move(drop);
+        assert_eq!(game.get_fen(), "r3k3/8/8/8/4p3/8/8/3K4[] w - - 2 3");
+    }
+
+    #[test]
+    fn crazyhouse_drops() {
+        // pawns can't be dropped on the first or last rank
+        let pawn = crazyhouse("4k3/8/8/8/8/8/8/4K3[P] w - - 0 1");
+        assert_eq!(pawn.get_legal_moves().len(), 48 + 5);
+        // a pawn dropped on its starting rank may advance two squares
+        let mut game = pawn;
+        game.make_move(game.parse_uci_move("P@d2").unwrap());
+        game.make_move(game.parse_uci_move("e8f8").unwrap());
+        assert!(game.parse_uci_move("d2d4").is_some());
+
+        // a drop can block a check that would otherwise be mate
+        let blocked = crazyhouse("R5k1/5ppp/8/8/8/8/8/6K1[n] b - - 0 1");
+        assert_eq!(blocked.result(), None);
+        let blocks: Vec<String> = blocked.get_legal_moves().iter().map(|m| blocked.move_to_uci(m.chess_move)).collect();
+        assert_eq!(blocks, ["N@b8", "N@c8", "N@d8", "N@e8", "N@f8"]);
+        assert_eq!(ChessGameState::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").unwrap().result(), Some(GameResult::WhiteWin));
+
+        // the engine mates by dropping the rook on the back rank
+        let mate = crazyhouse("6k1/5ppp/8/8/8/8/8/6K1[R] w - - 0 1");
+        let mut searcher = Searcher::new(SearchConfig::default());
+        let result = searcher.search(&mate, SearchLimits { depth: Some(2), ..SearchLimits::default() });
+        assert!(matches!(result.best_move, Some(ChessMove::Drop(PieceName::Rook, _))));
+        assert_eq!(result.score, MATE_SCORE - 1);
+    }
+
+    #[test]
+    fn three_check() {
+        let mut game = ChessGameState::with_variant(Variant::ThreeCheck);
+        assert_eq!(game.get_fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 +0+0");
+        for san in ["e4", "f6", "Qh5+"] {
+            game.make_move(game.parse_san(san).unwrap());
+        }
+        assert_eq!(game.checks_given(Player::White), 1);
+        assert_eq!(game.get_fen(), "rnbqkbnr/ppppp1pp/5p2/7Q/4P3/8/PPPP1PPP/RNB1KBNR b KQkq - 1 2 +1+0");
+        assert_ne!(game.hash(), variant_game(Variant::ThreeCheck, "rnbqkbnr/ppppp1pp/5p2/7Q/4P3/8/PPPP1PPP/RNB1KBNR b KQkq - 1 2 +0+0").hash());
+        assert_eq!(ChessGameState::from_variant_fen(Variant::ThreeCheck, "4k3/8/8/8/8/8/8/R3K3 w - - 0 1 +4+0"), Err(FenError::Checks));
+        assert_eq!(ChessGameState::from_variant_fen(Variant::ThreeCheck, "4k3/8/8/8/8/8/8/R3K3 w - - 0 1 +1"), Err(FenError::Checks));
+
+        // the third check wins, even though it isn't mate
+        let mut game = variant_game(Variant::ThreeCheck, "4k3/8/8/8/8/8/8/R3K3 w - - 0 1 +2+0");
+        game.make_move(game.parse_san("Ra8+").unwrap());
+        assert_eq!(game.get_fen(), "R3k3/8/8/8/8/8/8/4K3 b - - 1 1 +3+0");
+        assert_eq!(game.result(), Some(GameResult::WhiteWin));
+
+        // one check left for each side
+        let kiwipete = variant_game(Variant::ThreeCheck, "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1 +2+2");
+        assert_eq!((1..=3).map(|depth| perft(&kiwipete, depth)).collect::<Vec<_>>(), [48, 2039, 97848]);
+    }
+
+    #[test]
+    fn king_of_the_hill() {
+        let mut game = variant_game(Variant::KingOfTheHill, "4k3/8/8/8/8/4K3/8/8 w - - 0 1");
+        assert_eq!(game.result(), None);
+        game.make_move(game.parse_san("Ke4").unwrap());
+        assert_eq!(game.result(), Some(GameResult::WhiteWin));
+        assert_eq!(variant_game(Variant::KingOfTheHill, "8/8/8/3k4/8/8/8/4K3 w - - 0 1").result(), Some(GameResult::BlackWin));
+
+        // no king reaches the centre in the first four plies
+        let start = ChessGameState::with_variant(Variant::KingOfTheHill);
+        assert_eq!(perft(&start, 4), 197281);
+    }
+
+    #[test]
+    fn atomic() {
+        // the capture removes the capturing pawn and the pieces next to the captured one, but not the pawn
+        let mut game = variant_game(Variant::Atomic, "4k3/8/8/2nrp3/3p4/2P5/8/4K3 w - - 0 1");
+        game.make_move(game.parse_san("cxd4").unwrap());
+        assert_eq!(game.get_fen(), "4k3/8/8/4p3/8/8/8/4K3 b - - 0 1");
+
+        // kings can't capture
+        let game = variant_game(Variant::Atomic, "4k3/8/8/8/8/8/3p4/4K3 w - - 0 1");
+        assert!(game.parse_san("Kxd2").is_none());
+        assert_eq!(game.get_legal_moves().len(), 4);
+
+        // a king next to the other one can't be in check, and can step onto attacked squares next to it
+        let game = variant_game(Variant::Atomic, "8/8/8/8/8/4k3/r3K3/8 w - - 0 1");
+        assert!(!game.in_check());
+        assert!(game.parse_san("Kf2").is_some());
+        assert!(game.parse_san("Kd2").is_some());
+
+        // blowing up the opponent's king wins, even out of check
+        let mut game = variant_game(Variant::Atomic, "4k3/3n4/8/8/8/8/4q3/3RK3 w - - 0 1");
+        assert!(game.in_check());
+        game.make_move(game.parse_san("Rxd7").unwrap());
+        assert_eq!(game.get_fen(), "8/8/8/8/8/8/4q3/4K3 b - - 0 1");
+        assert_eq!(game.result(), Some(GameResult::WhiteWin));
+
+        for (fen, nodes) in [
+            ("rn2kb1r/1pp1p2p/p2q1pp1/3P4/2P3b1/4PN2/PP3PPP/R2QKB1R b KQkq - 0 1", [40, 1238, 45237]),
+            ("rn1qkb1r/p5pp/2p5/3p4/N3P3/5P2/PPP4P/R1BQK3 w Qkq - 0 1", [28, 833, 23353]),
+            ("8/8/8/8/8/8/2k5/rR4KR w KQ - 0 1", [18, 180, 4364]),
+        ] {
+            let game = variant_game(Variant::Atomic, fen);
+            assert_eq!((1..=3).map(|depth| perft(&game, depth)).collect::<Vec<_>>(), nodes, "{}", fen);
+        }
+        assert_eq!(perft(&ChessGameState::with_variant(Variant::Atomic), 4), 197326);
+    }
+
+    #[test]
+    fn antichess() {
+        let start = ChessGameState::with_variant(Variant::Antichess);
+        assert_eq!(start.get_fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1");
+        assert_eq!((1..=4).map(|depth| perft(&start, depth)).collect::<Vec<_>>(), [20, 400, 8067, 153299]);
+        let pawns = variant_game(Variant::Antichess, "8/1p6/8/8/8/8/P7/8 w - - 0 1");
+        assert_eq!((1..=6).map(|depth| perft(&pawns, depth)).collect::<Vec<_>>(), [2, 4, 4, 3, 1, 0]);
+
+        // captures are compulsory, and the king can be taken, or left en prise
+        let game = variant_game(Variant::Antichess, "8/8/8/3k4/8/8/3Q4/K7 b - - 0 1");
+        assert!(!game.in_check());
+        assert_eq!(game.get_legal_moves().len(), 8);
+        let game = variant_game(Variant::Antichess, "8/8/8/3k4/8/8/8/K2Q4 w - - 0 1");
+        let moves: Vec<String> = game.get_legal_moves().iter().map(|m| game.move_to_san(*m)).collect();
+        assert_eq!(moves, ["Qxd5"]);
+        assert!(game.parse_san("Qd2").is_none());
+        assert!(!game.is_pseudo_legal(ChessMove::Move(SquareID::parse("d1").unwrap(), SquareID::parse("d2").unwrap())));
+
+        // pawns can promote to a king, and players don't need exactly one
+        let mut game = variant_game(Variant::Antichess, "8/P7/8/8/8/8/8/k1K5 w - - 0 1");
+        let promotion = game.parse_uci_move("a7a8k").unwrap();
+        assert_eq!(game.move_to_san(promotion), "a8=K");
+        game.make_move(promotion);
+        assert_eq!(game.get_fen(), "K7/8/8/8/8/8/8/k1K5 b - - 0 1");
+
+        // losing the last piece wins, and so does being stalemated
+        let mut game = variant_game(Variant::Antichess, "8/8/8/8/8/8/1p6/2R5 b - - 0 1");
+        game.make_move(game.parse_san("bxc1=Q").unwrap());
+        assert_eq!(game.result(), Some(GameResult::WhiteWin));
+        assert_eq!(variant_game(Variant::Antichess, "8/8/8/8/8/p7/P7/8 w - - 0 1").result(), Some(GameResult::WhiteWin));
+
+        // the engine has to capture, even when that gives the opponent the win
+        let game = variant_game(Variant::Antichess, "8/8/8/3k4/8/8/8/K2Q4 w - - 0 1");
+        let mut searcher = Searcher::new(SearchConfig::default());
+        let result = searcher.search(&game, SearchLimits { depth: Some(2), ..SearchLimits::default() });
+        assert_eq!(result.best_move, Some(ChessMove::Capture(SquareID::parse("d1").unwrap(), SquareID::parse("d5").unwrap())));
+        assert_eq!(result.score, -MATE_SCORE + 1);
+    }
+
+    #[test]
+    fn horde() {
+        let start = ChessGameState::with_variant(Variant::Horde);
+        assert_eq!((1..=4).map(|depth| perft(&start, depth)).collect::<Vec<_>>(), [8, 128, 1274, 23310]);
+        for (fen, nodes) in [
+            ("4k3/pp4q1/3P2p1/8/P3PP2/PPP2r2/PPP5/PPPP4 b - - 0 1", [30, 241, 6633]),
+            ("k7/5p2/4p2P/3p2P1/2p2P2/1p2P2P/p2P2P1/2P2P2 w - - 0 1", [13, 172, 2205]),
+        ] {
+            let game = variant_game(Variant::Horde, fen);
+            assert_eq!((1..=3).map(|depth| perft(&game, depth)).collect::<Vec<_>>(), nodes, "{}", fen);
+        }
+        assert_eq!(ChessGameState::from_variant_fen(Variant::Horde, "4k3/8/8/8/8/8/P7/4K3 w - - 0 1"), Err(FenError::Kings));
+
+        // pawns on the first rank can advance two squares, without leaving an en passant square
+        let mut game = variant_game(Variant::Horde, "4k3/8/8/8/8/8/8/P7 w - - 0 1");
+        game.make_move(game.parse_uci_move("a1a3").unwrap());
+        assert_eq!(game.get_fen(), "4k3/8/8/8/8/P7/8/8 b - - 0 1");
+
+        // capturing the last white pawn wins
+        let mut game = variant_game(Variant::Horde, "4k3/8/8/8/8/8/4P3/3q4 b - - 0 1");
+        assert_eq!(game.result(), None);
+        game.make_move(game.parse_san("Qxe2").unwrap());
+        assert_eq!(game.result(), Some(GameResult::BlackWin));
+    }
+}
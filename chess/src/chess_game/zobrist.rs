@@ -0,0 +1,136 @@
+use crate::chess_game::chess_piece::{ChessPiece, PieceName};
+use crate::chess_game::chess_square::SquareID;
+use crate::chess_game::Player;
+
+// Random keys for Zobrist hashing of game states.
+// They are generated at compile time from a fixed seed, so hashes are stable between runs.
+pub struct ZobristKeys {
+    pieces: [[[u64; 64]; 6]; 2],
+    black_to_move: u64,
+    castling: [u64; 4],
+    ep_file: [u64; 8],
+    // crazyhouse pockets: a key for each count of each piece but the king, up to 16
+    pocket: [[[u64; 17]; 5]; 2],
+    // three-check: a key for each number of checks given
+    checks: [[u64; 4]; 2],
+}
+
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (state, z ^ (z >> 31))
+}
+
+const fn generate() -> ZobristKeys {
+    let mut state = 0x1234_5678_9ABC_DEF0;
+    let mut keys = ZobristKeys {
+        pieces: [[[0; 64]; 6]; 2],
+        black_to_move: 0,
+        castling: [0; 4],
+        ep_file: [0; 8],
+        pocket: [[[0; 17]; 5]; 2],
+        checks: [[0; 4]; 2],
+    };
+    let mut player = 0;
+    while player < 2 {
+        let mut name = 0;
+        while name < 6 {
+            let mut sq = 0;
+            while sq < 64 {
+                let (s, key) = splitmix64(state);
+                state = s;
+                keys.pieces[player][name][sq] = key;
+                sq += 1;
+            }
+            name += 1;
+        }
+        player += 1;
+    }
+    let (s, key) = splitmix64(state);
+    state = s;
+    keys.black_to_move = key;
+    let mut i = 0;
+    while i < 4 {
+        let (s, key) = splitmix64(state);
+        state = s;
+        keys.castling[i] = key;
+        i += 1;
+    }
+    let mut i = 0;
+    while i < 8 {
+        let (s, key) = splitmix64(state);
+        state = s;
+        keys.ep_file[i] = key;
+        i += 1;
+    }
+    let mut player = 0;
+    while player < 2 {
+        let mut name = 0;
+        while name < 5 {
+            // no pieces in the pocket hashes to nothing, like an empty square
+            let mut count = 1;
+            while count < 17 {
+                let (s, key) = splitmix64(state);
+                state = s;
+                keys.pocket[player][name][count] = key;
+                count += 1;
+            }
+            name += 1;
+        }
+        player += 1;
+    }
+    let mut player = 0;
+    while player < 2 {
+        let mut count = 1;
+        while count < 4 {
+            let (s, key) = splitmix64(state);
+            state = s;
+            keys.checks[player][count] = key;
+            count += 1;
+        }
+        player += 1;
+    }
+    keys
+}
+
+// a single copy of the tables, rather than one inlined wherever a const would be used
+pub static KEYS: ZobristKeys = generate();
+
+impl ZobristKeys {
+    pub fn piece(&self, piece: ChessPiece, id: SquareID) -> u64 {
+        let player: usize = piece.get_owner().into();
+        let name: usize = piece.get_name().into();
+        let sq: usize = id.into();
+        self.pieces[player][name][sq]
+    }
+
+    pub fn side(&self, player: Player) -> u64 {
+        match player {
+            Player::White => 0,
+            Player::Black => self.black_to_move,
+        }
+    }
+
+    // index follows the FEN order: K, Q, k, q
+    pub fn castling(&self, index: usize) -> u64 {
+        self.castling[index]
+    }
+
+    pub fn ep(&self, id: SquareID) -> u64 {
+        let file: usize = id.file().into();
+        self.ep_file[file]
+    }
+
+    pub fn pocket(&self, player: Player, name: PieceName, count: u8) -> u64 {
+        let player: usize = player.into();
+        let name: usize = name.into();
+        self.pocket[player][name][usize::from(count).min(16)]
+    }
+
+    pub fn checks(&self, player: Player, count: u8) -> u64 {
+        let player: usize = player.into();
+        self.checks[player][usize::from(count).min(3)]
+    }
+}
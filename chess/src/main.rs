@@ -1,5 +1,113 @@
 pub mod chess_game;
+pub mod chess_engine;
+pub mod protocol;
+
+use std::io::{stdin, stdout, BufRead};
+use chess_engine::datagen::{generate, write_binary, write_epd, DatagenConfig};
+use chess_engine::evaluation::DEFAULT_PARAMS;
+use chess_engine::search::SearchLimits;
+use chess_engine::tuning::{parse_positions, rust_constants, Tuner};
+use protocol::server::Server;
+use protocol::tui::Tui;
+use protocol::uci::Uci;
+use protocol::xboard::Xboard;
+
+// a subcommand, given the arguments after its name
+type Command = fn(&[String]) -> Result<(), String>;
+
+// chess tune <positions> <output> [epochs]: writes Rust constants if the output ends in .rs, a config otherwise
+fn tune(args: &[String]) -> Result<(), String> {
+    let [positions, output, rest @ ..] = args else {
+        return Err(String::from("usage: chess tune <positions> <output> [epochs]"));
+    };
+    let epochs = match rest.first() {
+        Some(epochs) => epochs.parse().map_err(|_| format!("invalid number of epochs: {}", epochs))?,
+        None => 1000,
+    };
+    let text = std::fs::read_to_string(positions).map_err(|err| err.to_string())?;
+    let positions = parse_positions(&text).map_err(|err| err.to_string())?;
+    let mut tuner = Tuner::new(&positions, &DEFAULT_PARAMS);
+    let k = tuner.optimal_k();
+    println!("{} positions, K = {:.4}, error {:.6}", positions.len(), k, tuner.error(k));
+    let mut done = 0;
+    while done < epochs {
+        let batch = (epochs - done).min(100);
+        println!("error {:.6} after {} epochs", tuner.tune(k, batch), done + batch);
+        done += batch;
+    }
+    let params = tuner.params();
+    let contents = if output.ends_with(".rs") { rust_constants(&params) } else { params.to_config() };
+    std::fs::write(output, contents).map_err(|err| err.to_string())
+}
+
+// chess datagen <output> <games> [depth] [seed]: writes EPD if the output ends in .epd, binary records otherwise
+fn datagen(args: &[String]) -> Result<(), String> {
+    let [output, games, rest @ ..] = args else {
+        return Err(String::from("usage: chess datagen <output> <games> [depth] [seed]"));
+    };
+    let number = |arg: Option<&String>, default: u64| -> Result<u64, String> {
+        arg.map_or(Ok(default), |arg| arg.parse().map_err(|_| format!("invalid number: {}", arg)))
+    };
+    let config = DatagenConfig {
+        games: number(Some(games), 0)? as usize,
+        limits: SearchLimits { depth: Some(number(rest.first(), 6)? as i32), ..SearchLimits::default() },
+        seed: number(rest.get(1), 0)?,
+        threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        ..DatagenConfig::default()
+    };
+    let records = generate(&config);
+    println!("{} positions from {} games", records.len(), config.games);
+    let result = if output.ends_with(".epd") {
+        std::fs::write(output, write_epd(&records))
+    } else {
+        std::fs::write(output, write_binary(&records))
+    };
+    result.map_err(|err| err.to_string())
+}
+
+// chess play: a game in the terminal, against the engine or another person
+fn play(_args: &[String]) -> Result<(), String> {
+    Tui::new(stdout()).run(stdin().lock());
+    Ok(())
+}
+
+// chess serve [address]: a game server for browser clients, on localhost unless told otherwise
+fn serve(args: &[String]) -> Result<(), String> {
+    let address = args.first().map_or("127.0.0.1:8080", String::as_str);
+    let server = Server::bind(address).map_err(|err| format!("cannot listen on {}: {}", address, err))?;
+    println!("listening on http://{}", server.local_addr().map_err(|err| err.to_string())?);
+    server.run();
+    Ok(())
+}
 
 fn main() {
-    println!("Hello, world!");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command: Option<Command> = match args.first().map(String::as_str) {
+        Some("tune") => Some(tune),
+        Some("datagen") => Some(datagen),
+        Some("play") => Some(play),
+        Some("serve") => Some(serve),
+        _ => None,
+    };
+    if let Some(command) = command {
+        if let Err(err) = command(&args[1..]) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let mut input = stdin().lock();
+    let mut first_line = String::new();
+    if input.read_line(&mut first_line).is_err() {
+        return;
+    }
+    // GUIs announce the protocol they speak with their first command
+    if first_line.trim() == "xboard" {
+        Xboard::new(stdout()).run(input);
+    } else {
+        let mut uci = Uci::new(stdout());
+        if uci.handle_command(&first_line) {
+            uci.run(input);
+        }
+    }
 }
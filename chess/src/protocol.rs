@@ -0,0 +1,52 @@
+use std::io::Write;
+use std::sync::Mutex;
+
+pub mod json;
+pub mod server;
+pub mod tui;
+pub mod uci;
+pub mod websocket;
+pub mod xboard;
+
+// writes a line and flushes it; there is nothing sensible to do if the GUI went away
+pub fn write_line<W: Write>(out: &mut W, line: &str) {
+    let _ = writeln!(out, "{}", line);
+    let _ = out.flush();
+}
+
+// writes a line to an output shared with the search threads
+pub fn send<W: Write>(out: &Mutex<W>, line: &str) {
+    if let Ok(mut out) = out.lock() {
+        write_line(&mut *out, line);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    // an output that the test can still read after handing it to the engine
+    #[derive(Clone, Default)]
+    pub struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        pub fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+
+        pub fn lines(&self) -> Vec<String> {
+            self.text().lines().map(String::from).collect()
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use std::fmt::{Display, Formatter};
+
+// a JSON value; objects keep their keys in order
+#[derive(Debug, PartialEq, Clone)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum JsonError {
+    UnexpectedEnd,
+    // an unexpected character at this byte offset
+    Unexpected(usize),
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonError::UnexpectedEnd => write!(f, "unexpected end of JSON"),
+            JsonError::Unexpected(offset) => write!(f, "unexpected character in JSON at offset {}", offset),
+        }
+    }
+}
+
+// nesting beyond this is rejected rather than risking the stack
+const MAX_DEPTH: usize = 64;
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(name, value)| (String::from(name), value)).collect())
+    }
+
+    pub fn parse(text: &str) -> Result<Json, JsonError> {
+        let mut parser = Parser { text: text.as_bytes(), pos: 0 };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        match parser.pos < parser.text.len() {
+            true => Err(JsonError::Unexpected(parser.pos)),
+            false => Ok(value),
+        }
+    }
+
+    // the value of a field of an object
+    pub fn get(&self, name: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(n, _)| n == name).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 && *n < u64::MAX as f64 => Some(*n as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+fn write_string(f: &mut Formatter<'_>, s: &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+// the compact serialization, with integers written without a fraction
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if !n.is_finite() => write!(f, "null"),
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            },
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, name)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Json::Bool(b)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(n: i64) -> Self {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<u64> for Json {
+    fn from(n: u64) -> Self {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(String::from(s))
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Self {
+        Json::String(s)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(items: Vec<T>) -> Self {
+        Json::Array(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.text.len() && matches!(self.text[self.pos], b' ' | b'\t' | b'\n' | b'\r') {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Result<u8, JsonError> {
+        self.text.get(self.pos).copied().ok_or(JsonError::UnexpectedEnd)
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), JsonError> {
+        match self.peek()? {
+            b if b == byte => {
+                self.pos += 1;
+                Ok(())
+            },
+            _ => Err(JsonError::Unexpected(self.pos)),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, JsonError> {
+        for byte in word.bytes() {
+            self.expect(byte)?;
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, JsonError> {
+        self.skip_whitespace();
+        if depth > MAX_DEPTH {
+            return Err(JsonError::Unexpected(self.pos));
+        }
+        match self.peek()? {
+            b'n' => self.literal("null", Json::Null),
+            b't' => self.literal("true", Json::Bool(true)),
+            b'f' => self.literal("false", Json::Bool(false)),
+            b'"' => self.string().map(Json::String),
+            b'[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.peek()? == b']' {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    self.skip_whitespace();
+                    match self.peek()? {
+                        b',' => self.pos += 1,
+                        b']' => {
+                            self.pos += 1;
+                            return Ok(Json::Array(items));
+                        },
+                        _ => return Err(JsonError::Unexpected(self.pos)),
+                    }
+                }
+            },
+            b'{' => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.peek()? == b'}' {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let name = self.string()?;
+                    self.skip_whitespace();
+                    self.expect(b':')?;
+                    fields.push((name, self.value(depth + 1)?));
+                    self.skip_whitespace();
+                    match self.peek()? {
+                        b',' => self.pos += 1,
+                        b'}' => {
+                            self.pos += 1;
+                            return Ok(Json::Object(fields));
+                        },
+                        _ => return Err(JsonError::Unexpected(self.pos)),
+                    }
+                }
+            },
+            b'-' | b'0'..=b'9' => self.number(),
+            _ => Err(JsonError::Unexpected(self.pos)),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.pos;
+        while self.pos < self.text.len() && matches!(self.text[self.pos], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.text[start..self.pos]).map_err(|_| JsonError::Unexpected(start))?;
+        text.parse().map(Json::Number).map_err(|_| JsonError::Unexpected(start))
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self.text.get(self.pos..self.pos + 4).ok_or(JsonError::UnexpectedEnd)?;
+        let digits = std::str::from_utf8(digits).map_err(|_| JsonError::Unexpected(self.pos))?;
+        let value = u32::from_str_radix(digits, 16).map_err(|_| JsonError::Unexpected(self.pos))?;
+        self.pos += 4;
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = self.peek()?;
+            self.pos += 1;
+            match byte {
+                b'"' => return String::from_utf8(bytes).map_err(|_| JsonError::Unexpected(self.pos)),
+                b'\\' => {
+                    let escape = self.peek()?;
+                    self.pos += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            // characters outside the basic plane come as a surrogate pair
+                            if (0xd800..0xdc00).contains(&code) {
+                                self.expect(b'\\')?;
+                                self.expect(b'u')?;
+                                let low = self.hex4()?;
+                                code = 0x10000 + ((code - 0xd800) << 10) + low.wrapping_sub(0xdc00);
+                            }
+                            char::from_u32(code).ok_or(JsonError::Unexpected(self.pos))?
+                        },
+                        _ => return Err(JsonError::Unexpected(self.pos - 1)),
+                    };
+                    let mut buffer = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                },
+                byte if byte < 0x20 => return Err(JsonError::Unexpected(self.pos - 1)),
+                byte => bytes.push(byte),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::json::{Json, JsonError};
+
+    #[test]
+    fn round_trips() {
+        let text = r#"{"id":3,"fen":"8/8 w","moves":["e2e4",null],"score":-0.5,"over":false,"nested":{"a":[]}}"#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(json.to_string(), text);
+        assert_eq!(json.get("id").and_then(Json::as_u64), Some(3));
+        assert_eq!(json.get("moves").and_then(Json::as_array).map(|moves| moves.len()), Some(2));
+        assert_eq!(json.get("missing"), None);
+
+        let json = Json::parse(" { \"s\" : \"a\\\"b\\n\\u00e9\\ud83d\\ude00\" } ").unwrap();
+        assert_eq!(json.get("s").and_then(Json::as_str), Some("a\"b\né😀"));
+        assert_eq!(json.to_string(), "{\"s\":\"a\\\"b\\né😀\"}");
+        assert_eq!(Json::from(vec![Some(1u64), None]).to_string(), "[1,null]");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Json::parse(""), Err(JsonError::UnexpectedEnd));
+        assert_eq!(Json::parse("[1,]"), Err(JsonError::Unexpected(3)));
+        assert_eq!(Json::parse("{\"a\" 1}"), Err(JsonError::Unexpected(5)));
+        assert_eq!(Json::parse("tru"), Err(JsonError::UnexpectedEnd));
+        assert_eq!(Json::parse("1 2"), Err(JsonError::Unexpected(2)));
+        assert!(Json::parse(&"[".repeat(100)).is_err());
+    }
+}
@@ -0,0 +1,934 @@
+// A local HTTP server for browser clients. Bodies and responses are JSON; errors are {"error": message} with a
+// 4xx status. Every response allows any origin, so a frontend served elsewhere can use it.
+//
+//   GET    /games                 {"games": [Game]}
+//   POST   /games                 NewGame -> 201 Game
+//   GET    /games/{id}            Game
+//   DELETE /games/{id}            {"id": id, "deleted": true}
+//   GET    /games/{id}/moves      {"moves": [Move]}, the legal moves
+//   POST   /games/{id}/moves      {"move": "e4" | "e2e4"} -> Game
+//   POST   /games/{id}/undo       Game
+//   POST   /games/{id}/resign     {"player"?: Side} -> Game
+//   POST   /games/{id}/draw       {"player"?: Side, "action"?: "offer" | "accept" | "decline"} -> Game
+//   POST   /games/{id}/engine     {"side": "white" | "black" | "both" | "none"} -> Game
+//   POST   /games/{id}/analysis   {"depth"?: plies, "movetime"?: ms} -> Analysis
+//   GET    /games/{id}/ws         a WebSocket of Events for the game
+//
+// The player of an action defaults to the side to move. The engine plays its sides in the background, so its
+// moves arrive as Events or on the next request, and a move posted for its side is refused with 409. Every search stops after its movetime, 1 s unless the body asks for
+// another, up to 60 s.
+//
+//   Side      "white" | "black"
+//   NewGame   {"variant"?: name, "fen"?: FEN, "time_control"?: PGN time control such as "300+2",
+//              "engine"?: "white" | "black" | "both" | "none", "depth"?: plies, "movetime"?: ms}
+//   Move      {"uci": "e2e4", "san": "e4"}
+//   Game      {"id", "variant", "start_fen", "fen", "active_player": Side, "check": bool, "moves": [Move],
+//              "legal_moves": [uci], "engine": [Side], "draw_offer": Side | null,
+//              "clock": {"white": ms, "black": ms, "running": bool, "time_control"} | null,
+//              "result": "1-0" | "0-1" | "1/2-1/2" | null, "termination": PGN Termination | null}
+//   Analysis  {"depth", "score": centipawns for white, "mate": moves for white | null, "nodes", "best_move": uci | null,
+//              "pv": [uci]}
+//   Event     {"type": "state", "game": Game}                       on connecting, and after undo, draw offers, ...
+//             {"type": "move", "move": Move, "player": Side, "game": Game}
+//             {"type": "result", "result", "termination", "game": Game}
+//             {"type": "clock", "white": ms, "black": ms}            every second while the clock runs
+//             {"type": "analysis", ...Analysis}                     each iteration of an engine search
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+use crate::chess_engine::search::{is_mate_score, SearchConfig, SearchControl, SearchLimits, SearchResult, Searcher, MATE_SCORE};
+use crate::chess_game::game_clock::SystemClock;
+use crate::chess_game::chess_move::AnnotatedMove;
+use crate::chess_game::game_clock::{GameClock, TimeControl};
+use crate::chess_game::session::{GameSession, SessionError};
+use crate::chess_game::variant::Variant;
+use crate::chess_game::{ChessGameState, GameResult, Player};
+use crate::protocol::json::Json;
+use crate::protocol::websocket::{accept_key, write_frame, MessageReader, CLOSE, PING, PONG, TEXT};
+
+// requests with larger bodies are refused
+const MAX_BODY: usize = 1 << 16;
+// and so are longer request lines and headers
+const MAX_LINE: usize = 8 << 10;
+const MAX_HEAD: usize = 32 << 10;
+const MAX_HEADERS: usize = 64;
+// how long a client may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// the engine's time per move when a body doesn't say, and the most a body may ask for
+const DEFAULT_MOVETIME: Duration = Duration::from_secs(1);
+const MAX_MOVETIME: Duration = Duration::from_secs(60);
+// how often running clocks are checked for a fallen flag
+const CLOCK_POLL: Duration = Duration::from_millis(100);
+const CLOCK_EVENTS_EVERY: u32 = 10;
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn side_name(player: Player) -> &'static str {
+    match player {
+        Player::White => "white",
+        Player::Black => "black",
+    }
+}
+
+fn parse_side(name: &str) -> Option<Player> {
+    match name {
+        "white" => Some(Player::White),
+        "black" => Some(Player::Black),
+        _ => None,
+    }
+}
+
+fn engine_sides(name: &str) -> Option<[bool; 2]> {
+    match name {
+        "white" => Some([true, false]),
+        "black" => Some([false, true]),
+        "both" => Some([true, true]),
+        "none" => Some([false, false]),
+        _ => None,
+    }
+}
+
+fn move_json(game: &ChessGameState, m: AnnotatedMove) -> Json {
+    Json::object(vec![("uci", Json::from(game.move_to_uci(m.chess_move))), ("san", Json::from(game.move_to_san(m)))])
+}
+
+// a search result with the score from white's point of view
+fn analysis_json(game: &ChessGameState, result: &SearchResult) -> Vec<(&'static str, Json)> {
+    let score = match game.active_player() {
+        Player::White => result.score,
+        Player::Black => -result.score,
+    };
+    let mate = Some(score).filter(|score| is_mate_score(*score)).map(|score| score.signum() * (MATE_SCORE - score.abs() + 1) / 2);
+    vec![
+        ("depth", Json::from(result.depth as i64)),
+        ("score", Json::from(score as i64)),
+        ("mate", Json::from(mate.map(i64::from))),
+        ("nodes", Json::from(result.nodes)),
+        ("best_move", Json::from(result.best_move.map(|m| game.move_to_uci(m)))),
+        ("pv", Json::from(result.pv.iter().map(|m| game.move_to_uci(*m)).collect::<Vec<String>>())),
+    ]
+}
+
+// the queue of a WebSocket's frames, as opcode and payload; a thread of its own writes them, so a slow client can't
+// hold up the game
+type Frames = Sender<(u8, Vec<u8>)>;
+type Subscribers = Arc<Mutex<Vec<Frames>>>;
+
+// queues an event for every WebSocket of a game, dropping those that went away
+fn broadcast(subscribers: &Mutex<Vec<Frames>>, event: &Json) {
+    let text = event.to_string();
+    lock(subscribers).retain(|frames| frames.send((TEXT, text.clone().into_bytes())).is_ok());
+}
+
+// writes the frames queued for a WebSocket until it is closed or the client stops reading
+fn write_frames(mut stream: TcpStream, frames: Receiver<(u8, Vec<u8>)>) {
+    for (opcode, payload) in frames {
+        if write_frame(&mut stream, opcode, &payload).is_err() || opcode == CLOSE {
+            break;
+        }
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+struct ServerGame {
+    id: u64,
+    session: GameSession,
+    clock: Option<GameClock>,
+    // the sides the engine plays, indexed by player
+    engine_sides: [bool; 2],
+    limits: SearchLimits,
+    // taken by the thread searching for the engine's move
+    searcher: Option<Searcher>,
+    control: Arc<SearchControl>,
+    subscribers: Subscribers,
+}
+
+type GameRef = Arc<Mutex<ServerGame>>;
+
+impl ServerGame {
+    fn to_json(&self) -> Json {
+        let game = self.session.game();
+        let mut position = *self.session.start();
+        let mut moves = Vec::new();
+        for m in self.session.moves() {
+            moves.push(move_json(&position, *m));
+            position.make_move(*m);
+        }
+        let legal_moves: Vec<String> = match self.session.result() {
+            Some(_) => Vec::new(),
+            None => game.get_legal_moves().iter().map(|m| game.move_to_uci(m.chess_move)).collect(),
+        };
+        let engine: Vec<&str> = [Player::White, Player::Black].into_iter()
+            .filter(|player| self.engine_sides[usize::from(*player)])
+            .map(side_name)
+            .collect();
+        let clock = self.clock.as_ref().map_or(Json::Null, |clock| Json::object(vec![
+            ("white", Json::from(clock.remaining(Player::White).as_millis() as u64)),
+            ("black", Json::from(clock.remaining(Player::Black).as_millis() as u64)),
+            ("running", Json::from(clock.is_running())),
+            ("time_control", Json::from(clock.control().to_pgn())),
+        ]));
+        Json::object(vec![
+            ("id", Json::from(self.id)),
+            ("variant", Json::from(game.variant().name())),
+            ("start_fen", Json::from(self.session.start().get_fen())),
+            ("fen", Json::from(game.get_fen())),
+            ("active_player", Json::from(side_name(game.active_player()))),
+            ("check", Json::from(game.in_check())),
+            ("moves", Json::Array(moves)),
+            ("legal_moves", Json::from(legal_moves)),
+            ("engine", Json::from(engine)),
+            ("draw_offer", Json::from(self.session.draw_offer().map(side_name))),
+            ("clock", clock),
+            ("result", Json::from(self.session.result().map(GameResult::to_pgn))),
+            ("termination", Json::from(self.session.termination().map(|t| t.pgn_name()))),
+        ])
+    }
+
+    fn state_event(&self) -> Json {
+        match self.session.result() {
+            Some(result) => Json::object(vec![
+                ("type", Json::from("result")),
+                ("result", Json::from(result.to_pgn())),
+                ("termination", Json::from(self.session.termination().map(|t| t.pgn_name()))),
+                ("game", self.to_json()),
+            ]),
+            None => Json::object(vec![("type", Json::from("state")), ("game", self.to_json())]),
+        }
+    }
+
+    fn send_state(&self) {
+        broadcast(&self.subscribers, &self.state_event());
+    }
+
+    fn engine_to_move(&self) -> bool {
+        self.session.result().is_none() && self.engine_sides[usize::from(self.session.game().active_player())]
+    }
+
+    // plays a move on the board and the clock, ending the game if the flag fell or the position repeated three times
+    fn play(&mut self, m: AnnotatedMove) {
+        let before = *self.session.game();
+        let played = match &mut self.clock {
+            Some(clock) => self.session.play_timed(clock, m),
+            None => self.session.play(m),
+        };
+        if played.is_err() {
+            return;
+        }
+        if self.session.moves().last() == Some(&m) {
+            broadcast(&self.subscribers, &Json::object(vec![
+                ("type", Json::from("move")),
+                ("move", move_json(&before, m)),
+                ("player", Json::from(side_name(before.active_player()))),
+                ("game", self.to_json()),
+            ]));
+        }
+        if self.session.result().is_some() {
+            self.send_state();
+        }
+    }
+
+    // ends the game if a player's time ran out while thinking
+    fn check_flag(&mut self) {
+        if self.session.result().is_some() {
+            return;
+        }
+        if let Some(clock) = &mut self.clock && let Some(player) = clock.flagged() {
+            clock.stop();
+            let _ = self.session.flag(player);
+            self.control.stop();
+            self.send_state();
+        }
+    }
+
+    // the hashes of the positions before the current one
+    fn history(&self) -> Vec<u64> {
+        let mut game = *self.session.start();
+        let mut hashes = Vec::new();
+        for m in self.session.moves() {
+            hashes.push(game.hash());
+            game.make_move(*m);
+        }
+        hashes
+    }
+}
+
+// lets the engine play while it is to move, searching without holding the game
+fn play_engine_moves(game: &GameRef) {
+    loop {
+        let (position, history, limits, mut searcher, subscribers, control) = {
+            let mut g = lock(game);
+            if !g.engine_to_move() {
+                return;
+            }
+            // another thread is already searching, and will carry on after its move
+            let Some(searcher) = g.searcher.take() else {
+                return;
+            };
+            let player = g.session.game().active_player();
+            let mut limits = g.limits;
+            if let Some(clock) = &g.clock {
+                limits.game_time = Some(clock.game_time(player));
+            }
+            (*g.session.game(), g.history(), limits, searcher, g.subscribers.clone(), g.control.clone())
+        };
+        let ply = history.len();
+        control.reset();
+        searcher.set_game_history(history);
+        searcher.set_info_callback(Some(Box::new(move |result| {
+            let mut event = vec![("type", Json::from("analysis"))];
+            event.extend(analysis_json(&position, result));
+            broadcast(&subscribers, &Json::object(event));
+        })));
+        let result = searcher.search(&position, limits);
+        searcher.set_info_callback(None);
+
+        let mut g = lock(game);
+        g.searcher = Some(searcher);
+        g.check_flag();
+        // the game may have changed while searching
+        if g.engine_to_move() && g.session.moves().len() == ply && *g.session.game() == position
+            && let Some(m) = result.best_move.and_then(|m| position.annotate_move(m)) {
+            g.play(m);
+        }
+    }
+}
+
+fn spawn_engine(game: &GameRef) {
+    let game = game.clone();
+    std::thread::spawn(move || play_engine_moves(&game));
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    // the path split at the slashes, without the query string
+    fn segments(&self) -> Vec<&str> {
+        let path = self.path.split('?').next().unwrap_or_default();
+        path.split('/').filter(|segment| !segment.is_empty()).collect()
+    }
+
+    // the JSON body, where an empty one counts as an empty object
+    fn json(&self) -> Result<Json, Response> {
+        let text = std::str::from_utf8(&self.body).map_err(|_| Response::error(400, "the body is not UTF-8"))?;
+        if text.trim().is_empty() {
+            return Ok(Json::Object(Vec::new()));
+        }
+        match Json::parse(text) {
+            Ok(json @ Json::Object(_)) => Ok(json),
+            Ok(_) => Err(Response::error(400, "the body must be a JSON object")),
+            Err(err) => Err(Response::error(400, &err.to_string())),
+        }
+    }
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+// a line of the request line and headers, or None at the end of the input
+fn read_head_line<R: BufRead>(input: &mut R, head_size: &mut usize) -> std::io::Result<Option<String>> {
+    let mut line = Vec::new();
+    input.by_ref().take(MAX_LINE as u64).read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if !line.ends_with(b"\n") && line.len() == MAX_LINE {
+        return Err(invalid("header line too long"));
+    }
+    *head_size += line.len();
+    if *head_size > MAX_HEAD {
+        return Err(invalid("headers too large"));
+    }
+    String::from_utf8(line).map(Some).map_err(|_| invalid("headers are not UTF-8"))
+}
+
+fn read_request<R: BufRead>(input: &mut R) -> std::io::Result<Request> {
+    let mut head_size = 0;
+    let line = read_head_line(input, &mut head_size)?.unwrap_or_default();
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(invalid("bad request line"));
+    };
+    let (method, path) = (String::from(method), String::from(path));
+    let mut headers = Vec::new();
+    loop {
+        let Some(line) = read_head_line(input, &mut head_size)? else {
+            return Err(invalid("unexpected end of headers"));
+        };
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(invalid("too many headers"));
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((String::from(name.trim()), String::from(value.trim())));
+        }
+    }
+    let mut request = Request { method, path, headers, body: Vec::new() };
+    let length: usize = request.header("Content-Length").map_or(Ok(0), str::parse).map_err(|_| invalid("bad length"))?;
+    if length > MAX_BODY {
+        return Err(invalid("body too large"));
+    }
+    request.body = vec![0; length];
+    input.read_exact(&mut request.body)?;
+    Ok(request)
+}
+
+#[derive(Debug)]
+struct Response {
+    status: u16,
+    body: Option<Json>,
+}
+
+impl Response {
+    fn json(status: u16, body: Json) -> Self {
+        Self { status, body: Some(body) }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, Json::object(vec![("error", Json::from(message))]))
+    }
+
+    fn write<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            201 => "Created",
+            204 => "No Content",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            _ => "Internal Server Error",
+        };
+        let body = self.body.as_ref().map_or(String::new(), Json::to_string);
+        write!(
+            out,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\n\
+             Access-Control-Allow-Methods: GET, POST, DELETE, OPTIONS\r\nAccess-Control-Allow-Headers: Content-Type\r\n\
+             Connection: close\r\n\r\n{}",
+            self.status, reason, body.len(), body
+        )?;
+        out.flush()
+    }
+}
+
+// the games being played, shared by the connections
+#[derive(Default)]
+struct Games {
+    games: Mutex<BTreeMap<u64, GameRef>>,
+    next_id: AtomicU64,
+}
+
+impl Games {
+    fn get(&self, id: &str) -> Result<GameRef, Response> {
+        let game = id.parse().ok().and_then(|id: u64| lock(&self.games).get(&id).cloned());
+        game.ok_or_else(|| Response::error(404, &format!("no game {}", id)))
+    }
+
+    fn all(&self) -> Vec<GameRef> {
+        lock(&self.games).values().cloned().collect()
+    }
+
+    fn create(&self, body: &Json) -> Result<Json, Response> {
+        let text = |name: &str| body.get(name).and_then(Json::as_str);
+        let variant = match text("variant") {
+            Some(name) => Variant::from_name(name).ok_or_else(|| Response::error(400, &format!("unknown variant: {}", name)))?,
+            None => Variant::Standard,
+        };
+        let start = match text("fen") {
+            Some(fen) => ChessGameState::from_variant_fen(variant, fen).map_err(|err| Response::error(400, &format!("invalid FEN: {}", err)))?,
+            None => ChessGameState::with_variant(variant),
+        };
+        let clock = match text("time_control") {
+            Some(control) => {
+                let control = TimeControl::parse(control).ok_or_else(|| Response::error(400, &format!("invalid time control: {}", control)))?;
+                Some(GameClock::new(control, Box::new(SystemClock::new())))
+            },
+            None => None,
+        };
+        let engine = text("engine").unwrap_or("none");
+        let engine_sides = engine_sides(engine).ok_or_else(|| Response::error(400, &format!("invalid engine side: {}", engine)))?;
+        let limits = search_limits(body)?;
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let searcher = Searcher::new(SearchConfig::default());
+        let game = ServerGame {
+            id,
+            session: GameSession::new(start),
+            clock,
+            engine_sides,
+            limits,
+            control: searcher.control(),
+            searcher: Some(searcher),
+            subscribers: Subscribers::default(),
+        };
+        let json = game.to_json();
+        let game = Arc::new(Mutex::new(game));
+        lock(&self.games).insert(id, game.clone());
+        spawn_engine(&game);
+        Ok(json)
+    }
+
+    fn delete(&self, id: &str) -> Result<Json, Response> {
+        let game = self.get(id)?;
+        let mut g = lock(&game);
+        lock(&self.games).remove(&g.id);
+        g.engine_sides = [false, false];
+        g.control.stop();
+        for frames in lock(&g.subscribers).drain(..) {
+            let _ = frames.send((CLOSE, Vec::new()));
+        }
+        Ok(Json::object(vec![("id", Json::from(g.id)), ("deleted", Json::from(true))]))
+    }
+
+    // the checks for fallen flags and the clock events, until the server stops
+    fn watch_clocks(&self) {
+        let mut polls = 0u32;
+        loop {
+            std::thread::sleep(CLOCK_POLL);
+            polls = polls.wrapping_add(1);
+            for game in self.all() {
+                let mut g = lock(&game);
+                g.check_flag();
+                if let Some(clock) = &g.clock && clock.is_running() && polls.is_multiple_of(CLOCK_EVENTS_EVERY) {
+                    broadcast(&g.subscribers, &Json::object(vec![
+                        ("type", Json::from("clock")),
+                        ("white", Json::from(clock.remaining(Player::White).as_millis() as u64)),
+                        ("black", Json::from(clock.remaining(Player::Black).as_millis() as u64)),
+                    ]));
+                }
+            }
+        }
+    }
+}
+
+// the engine's limits from the "depth" and "movetime" of a body; every search has a time limit, so a deep one can't
+// tie up a thread
+fn search_limits(body: &Json) -> Result<SearchLimits, Response> {
+    let number = |name: &str| match body.get(name) {
+        Some(value) => value.as_u64().map(Some).ok_or_else(|| Response::error(400, &format!("invalid {}", name))),
+        None => Ok(None),
+    };
+    let depth = number("depth")?.map(|depth| depth.min(64) as i32);
+    let movetime = number("movetime")?.map_or(DEFAULT_MOVETIME, Duration::from_millis).min(MAX_MOVETIME);
+    Ok(SearchLimits { depth, movetime: Some(movetime), ..SearchLimits::default() })
+}
+
+// the player an action is for, the side to move unless the body names one
+fn player(body: &Json, game: &ServerGame) -> Result<Player, Response> {
+    match body.get("player").and_then(Json::as_str) {
+        Some(name) => parse_side(name).ok_or_else(|| Response::error(400, &format!("invalid player: {}", name))),
+        None => Ok(game.session.game().active_player()),
+    }
+}
+
+fn game_action(game: &GameRef, action: &str, body: &Json) -> Result<Json, Response> {
+    let mut g = lock(game);
+    g.check_flag();
+    let conflict = |err: SessionError| Response::error(409, &err.to_string());
+    match action {
+        "moves" => {
+            let Some(text) = body.get("move").and_then(Json::as_str) else {
+                return Err(Response::error(400, "missing move"));
+            };
+            if g.session.result().is_some() {
+                return Err(Response::error(409, "the game is over"));
+            }
+            if g.engine_to_move() {
+                return Err(Response::error(409, "the engine is to move"));
+            }
+            let position = *g.session.game();
+            let m = position.parse_san(text).or_else(|| position.parse_uci_move(text));
+            match m {
+                Some(m) => g.play(m),
+                None => return Err(Response::error(400, &format!("illegal move: {}", text))),
+            }
+        },
+        "undo" => {
+            if g.session.undo().is_none() {
+                return Err(Response::error(409, "no moves to take back"));
+            }
+            g.control.stop();
+            let player = g.session.game().active_player();
+            if let Some(clock) = &mut g.clock && clock.is_running() {
+                clock.start(player);
+            }
+            g.send_state();
+        },
+        "resign" => {
+            let player = player(body, &g)?;
+            g.session.resign(player).map_err(conflict)?;
+            g.send_state();
+        },
+        "draw" => {
+            let player = player(body, &g)?;
+            let result = match body.get("action").and_then(Json::as_str).unwrap_or("offer") {
+                "offer" => g.session.offer_draw(player),
+                "accept" => g.session.accept_draw(player),
+                "decline" => g.session.decline_draw(player),
+                other => return Err(Response::error(400, &format!("invalid draw action: {}", other))),
+            };
+            result.map_err(conflict)?;
+            if g.session.result().is_some() && let Some(clock) = &mut g.clock {
+                clock.stop();
+            }
+            g.send_state();
+        },
+        "engine" => {
+            let side = body.get("side").and_then(Json::as_str).unwrap_or_default();
+            g.engine_sides = engine_sides(side).ok_or_else(|| Response::error(400, &format!("invalid engine side: {}", side)))?;
+            g.send_state();
+        },
+        _ => return Err(Response::error(404, &format!("no action {}", action))),
+    }
+    let json = g.to_json();
+    drop(g);
+    spawn_engine(game);
+    Ok(json)
+}
+
+fn analyse(game: &GameRef, body: &Json) -> Result<Json, Response> {
+    let limits = search_limits(body)?;
+    let (position, history, subscribers) = {
+        let g = lock(game);
+        (*g.session.game(), g.history(), g.subscribers.clone())
+    };
+    if position.result().is_some() {
+        return Err(Response::error(409, "the game is over"));
+    }
+    let mut searcher = Searcher::new(SearchConfig::default());
+    searcher.set_game_history(history);
+    searcher.set_info_callback(Some(Box::new(move |result| {
+        let mut event = vec![("type", Json::from("analysis"))];
+        event.extend(analysis_json(&position, result));
+        broadcast(&subscribers, &Json::object(event));
+    })));
+    let result = searcher.search(&position, limits);
+    Ok(Json::object(analysis_json(&position, &result)))
+}
+
+fn route(games: &Games, request: &Request) -> Result<Response, Response> {
+    let segments = request.segments();
+    let method = request.method.as_str();
+    if method == "OPTIONS" {
+        return Ok(Response { status: 204, body: None });
+    }
+    let not_allowed = || Response::error(405, &format!("{} is not allowed on {}", method, request.path));
+    match (method, segments.as_slice()) {
+        ("GET", ["games"]) => {
+            let list = games.all().iter().map(|game| lock(game).to_json()).collect::<Vec<Json>>();
+            Ok(Response::json(200, Json::object(vec![("games", Json::Array(list))])))
+        },
+        ("POST", ["games"]) => Ok(Response::json(201, games.create(&request.json()?)?)),
+        (_, ["games"]) => Err(not_allowed()),
+        ("GET", ["games", id]) => {
+            let game = games.get(id)?;
+            let json = lock(&game).to_json();
+            Ok(Response::json(200, json))
+        },
+        ("DELETE", ["games", id]) => Ok(Response::json(200, games.delete(id)?)),
+        (_, ["games", _]) => Err(not_allowed()),
+        ("GET", ["games", id, "moves"]) => {
+            let game = games.get(id)?;
+            let g = lock(&game);
+            let position = g.session.game();
+            let moves = match g.session.result() {
+                Some(_) => Vec::new(),
+                None => position.get_legal_moves().iter().map(|m| move_json(position, *m)).collect(),
+            };
+            Ok(Response::json(200, Json::object(vec![("moves", Json::Array(moves))])))
+        },
+        ("POST", ["games", id, "analysis"]) => Ok(Response::json(200, analyse(&games.get(id)?, &request.json()?)?)),
+        ("POST", ["games", id, action]) => Ok(Response::json(200, game_action(&games.get(id)?, action, &request.json()?)?)),
+        (_, ["games", _, _]) => Err(not_allowed()),
+        _ => Err(Response::error(404, &format!("no such resource: {}", request.path))),
+    }
+}
+
+// upgrades the connection to a WebSocket sending the game's events, answering pings until the client closes it
+fn websocket(games: &Games, request: &Request, stream: TcpStream, mut input: BufReader<TcpStream>, id: &str) -> std::io::Result<()> {
+    let game = match games.get(id) {
+        Ok(game) => game,
+        Err(response) => return response.write(&mut &stream),
+    };
+    let Some(key) = request.header("Sec-WebSocket-Key") else {
+        return Response::error(400, "missing Sec-WebSocket-Key").write(&mut &stream);
+    };
+    write!(
+        &mut &stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    )?;
+    stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+    // the client may stay quiet for as long as it watches the game
+    stream.set_read_timeout(None)?;
+    let (frames, queue) = mpsc::channel();
+    let writer = stream.try_clone()?;
+    std::thread::spawn(move || write_frames(writer, queue));
+    {
+        let g = lock(&game);
+        let _ = frames.send((TEXT, g.state_event().to_string().into_bytes()));
+        lock(&g.subscribers).push(frames.clone());
+    }
+    let mut reader = MessageReader::new();
+    loop {
+        let (opcode, payload) = reader.read(&mut input)?;
+        match opcode {
+            PING => {
+                let _ = frames.send((PONG, payload));
+            },
+            CLOSE => {
+                // the writer answers and shuts the connection; the next event to the subscriber fails and drops it
+                let _ = frames.send((CLOSE, payload));
+                return Ok(());
+            },
+            _ => {},
+        }
+    }
+}
+
+fn handle_connection(games: &Games, stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut input = BufReader::new(stream.try_clone()?);
+    let request = match read_request(&mut input) {
+        Ok(request) => request,
+        Err(err) => return Response::error(400, &err.to_string()).write(&mut &stream),
+    };
+    let upgrade = request.header("Upgrade").is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+    if let ("GET", ["games", id, "ws"]) = (request.method.as_str(), request.segments().as_slice()) && upgrade {
+        return websocket(games, &request, stream, input, id);
+    }
+    let response = route(games, &request).unwrap_or_else(|err| err);
+    response.write(&mut &stream)
+}
+
+// a game server for browser clients, keeping its games in memory
+pub struct Server {
+    listener: TcpListener,
+    games: Arc<Games>,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(address: A) -> std::io::Result<Self> {
+        Ok(Self { listener: TcpListener::bind(address)?, games: Arc::new(Games::default()) })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // serves each connection on its own thread
+    pub fn run(self) {
+        let games = self.games.clone();
+        std::thread::spawn(move || games.watch_clocks());
+        for stream in self.listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let games = self.games.clone();
+            std::thread::spawn(move || handle_connection(&games, stream));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::time::{Duration, Instant};
+    use crate::protocol::json::Json;
+    use crate::protocol::server::{read_request, search_limits, Server, DEFAULT_MOVETIME, MAX_HEAD, MAX_LINE, MAX_MOVETIME};
+    use crate::protocol::websocket::{read_frame, CLOSE, PONG, TEXT};
+
+    fn start() -> SocketAddr {
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap();
+        std::thread::spawn(move || server.run());
+        address
+    }
+
+    fn request(address: SocketAddr, method: &str, path: &str, body: &str) -> (u16, Json) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}", method, path, body.len(), body).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = &response[response.find("\r\n\r\n").unwrap() + 4..];
+        (status, if body.is_empty() { Json::Null } else { Json::parse(body).unwrap() })
+    }
+
+    fn text<'a>(json: &'a Json, name: &str) -> &'a str {
+        json.get(name).and_then(Json::as_str).unwrap_or_default()
+    }
+
+    // polls a game until the condition holds
+    fn wait_for(address: SocketAddr, path: &str, condition: impl Fn(&Json) -> bool) -> Json {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let (_, game) = request(address, "GET", path, "");
+            if condition(&game) || Instant::now() > deadline {
+                return game;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn rest_games() {
+        let address = start();
+        let (status, game) = request(address, "POST", "/games", "");
+        assert_eq!(status, 201);
+        assert_eq!(game.get("id").and_then(Json::as_u64), Some(1));
+        assert_eq!(game.get("legal_moves").and_then(Json::as_array).map(|moves| moves.len()), Some(20));
+        let (_, other) = request(address, "POST", "/games", r#"{"variant": "horde", "engine": "none"}"#);
+        assert_eq!(text(&other, "variant"), "Horde");
+
+        let (status, game) = request(address, "POST", "/games/1/moves", r#"{"move": "e4"}"#);
+        assert_eq!(status, 200);
+        assert_eq!(text(&game, "fen"), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        assert_eq!(game.get("moves").unwrap().to_string(), r#"[{"uci":"e2e4","san":"e4"}]"#);
+        let (status, game) = request(address, "POST", "/games/1/moves", r#"{"move": "c7c5"}"#);
+        assert_eq!((status, text(&game, "active_player")), (200, "white"));
+        let (status, error) = request(address, "POST", "/games/1/moves", r#"{"move": "Ke3"}"#);
+        assert_eq!((status, text(&error, "error")), (400, "illegal move: Ke3"));
+        let (status, _) = request(address, "POST", "/games/1/moves", "{\"move\": ");
+        assert_eq!(status, 400);
+        let (_, moves) = request(address, "GET", "/games/1/moves", "");
+        assert_eq!(moves.get("moves").and_then(Json::as_array).map(|moves| moves.len()), Some(30));
+        let (_, list) = request(address, "GET", "/games", "");
+        assert_eq!(list.get("games").and_then(Json::as_array).map(|games| games.len()), Some(2));
+
+        let (_, game) = request(address, "POST", "/games/1/undo", "");
+        assert_eq!(text(&game, "active_player"), "black");
+        request(address, "POST", "/games/1/draw", "");
+        let (_, game) = request(address, "POST", "/games/1/draw", r#"{"player": "white", "action": "accept"}"#);
+        assert_eq!((text(&game, "result"), text(&game, "termination")), ("1/2-1/2", "normal"));
+        let (status, _) = request(address, "POST", "/games/1/resign", "");
+        assert_eq!(status, 409);
+
+        let (status, _) = request(address, "DELETE", "/games/1", "");
+        assert_eq!(status, 200);
+        let (status, error) = request(address, "GET", "/games/1", "");
+        assert_eq!((status, text(&error, "error")), (404, "no game 1"));
+        assert_eq!(request(address, "PUT", "/games/2", "").0, 405);
+        assert_eq!(request(address, "OPTIONS", "/games", "").0, 204);
+        let (status, error) = request(address, "POST", "/games", r#"{"variant": "shogi"}"#);
+        assert_eq!((status, text(&error, "error")), (400, "unknown variant: shogi"));
+    }
+
+    #[test]
+    fn request_head() {
+        let read = |text: String| read_request(&mut text.as_bytes()).map(|request| request.path).map_err(|err| err.to_string());
+        assert_eq!(read(String::from("GET /games HTTP/1.1\r\nHost: localhost\r\n\r\n")), Ok(String::from("/games")));
+        let long_line = format!("GET /games HTTP/1.1\r\nX-Long: {}\r\n\r\n", "a".repeat(MAX_LINE));
+        assert_eq!(read(long_line), Err(String::from("header line too long")));
+        let header = format!("X-Header: {}\r\n", "a".repeat(MAX_LINE / 2));
+        let many_lines = format!("GET /games HTTP/1.1\r\n{}\r\n", header.repeat(MAX_HEAD / header.len() + 1));
+        assert_eq!(read(many_lines), Err(String::from("headers too large")));
+        assert_eq!(read(String::from("GET /games HTTP/1.1\r\nHost: localhost\r\n")), Err(String::from("unexpected end of headers")));
+    }
+
+    #[test]
+    fn limits() {
+        let limits = search_limits(&Json::parse(r#"{"depth": 30}"#).unwrap()).unwrap();
+        assert_eq!((limits.depth, limits.movetime), (Some(30), Some(DEFAULT_MOVETIME)));
+        let limits = search_limits(&Json::parse(r#"{"movetime": 100000000}"#).unwrap()).unwrap();
+        assert_eq!((limits.depth, limits.movetime), (None, Some(MAX_MOVETIME)));
+        assert!(search_limits(&Json::parse(r#"{"depth": -1}"#).unwrap()).is_err());
+    }
+
+    #[test]
+    fn engine_and_analysis() {
+        let address = start();
+        request(address, "POST", "/games", r#"{"fen": "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "depth": 3}"#);
+        let (_, analysis) = request(address, "POST", "/games/1/analysis", r#"{"depth": 3}"#);
+        assert_eq!((text(&analysis, "best_move"), analysis.get("mate").and_then(Json::as_u64)), ("a1a8", Some(1)));
+        request(address, "POST", "/games/1/engine", r#"{"side": "white"}"#);
+        let game = wait_for(address, "/games/1", |game| !matches!(game.get("result"), Some(Json::Null)));
+        assert_eq!((text(&game, "result"), text(&game, "fen")), ("1-0", "R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1"));
+
+        // the engine answers a move against it
+        request(address, "POST", "/games", r#"{"engine": "black", "depth": 2}"#);
+        request(address, "POST", "/games/2/moves", r#"{"move": "d4"}"#);
+        let game = wait_for(address, "/games/2", |game| text(game, "active_player") == "white");
+        assert_eq!(game.get("moves").and_then(Json::as_array).map(|moves| moves.len()), Some(2));
+
+        // nobody else moves for the engine while it thinks
+        request(address, "POST", "/games", r#"{"engine": "white", "movetime": 2000}"#);
+        let (status, error) = request(address, "POST", "/games/3/moves", r#"{"move": "e4"}"#);
+        assert_eq!((status, text(&error, "error")), (409, "the engine is to move"));
+        request(address, "POST", "/games/3/engine", r#"{"side": "none"}"#);
+    }
+
+    #[test]
+    fn websocket_events() {
+        let address = start();
+        request(address, "POST", "/games", r#"{"engine": "black", "depth": 2, "time_control": "1"}"#);
+        let mut ws = TcpStream::connect(address).unwrap();
+        ws.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        write!(ws, "GET /games/1/ws HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+            Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n").unwrap();
+        let mut handshake = Vec::new();
+        while !handshake.ends_with(b"\r\n\r\n") {
+            let mut byte = [0];
+            ws.read_exact(&mut byte).unwrap();
+            handshake.push(byte[0]);
+        }
+        let handshake = String::from_utf8(handshake).unwrap();
+        assert!(handshake.starts_with("HTTP/1.1 101"));
+        assert!(handshake.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+        let mut events = Vec::new();
+        let next = |ws: &mut TcpStream| {
+            let frame = read_frame(ws).unwrap();
+            assert_eq!(frame.opcode, TEXT);
+            Json::parse(std::str::from_utf8(&frame.payload).unwrap()).unwrap()
+        };
+        let state = next(&mut ws);
+        assert_eq!(text(&state, "type"), "state");
+        // a message in two masked fragments, which is ignored, and a ping
+        ws.write_all(&[0x01, 0x81, 0, 0, 0, 0, b'a', 0x80, 0x81, 0, 0, 0, 0, b'b', 0x89, 0x81, 0, 0, 0, 0, b'p']).unwrap();
+        let pong = read_frame(&mut ws).unwrap();
+        assert_eq!((pong.opcode, pong.payload), (PONG, b"p".to_vec()));
+        request(address, "POST", "/games/1/moves", r#"{"move": "e4"}"#);
+        // the moves, the engine's thoughts, and black running out of time one second in
+        loop {
+            let event = next(&mut ws);
+            let kind = String::from(text(&event, "type"));
+            events.push(event);
+            if kind == "result" {
+                break;
+            }
+        }
+        let kinds: Vec<&str> = events.iter().map(|event| text(event, "type")).collect();
+        assert_eq!(kinds[0], "move");
+        assert!(kinds.contains(&"analysis"));
+        let moves: Vec<&Json> = events.iter().filter(|event| text(event, "type") == "move").collect();
+        assert_eq!(moves.iter().map(|m| text(m, "player")).collect::<Vec<&str>>(), ["white", "black"]);
+        let result = events.last().unwrap();
+        assert_eq!((text(result, "result"), text(result, "termination")), ("0-1", "time forfeit"));
+
+        let (_, deleted) = request(address, "DELETE", "/games/1", "");
+        assert_eq!(deleted.get("deleted").and_then(Json::as_bool), Some(true));
+        assert_eq!(read_frame(&mut ws).unwrap().opcode, CLOSE);
+    }
+}
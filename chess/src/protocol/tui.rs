@@ -0,0 +1,533 @@
+use std::io::{BufRead, Write};
+use std::time::Duration;
+use crate::chess_engine::search::{is_mate_score, SearchConfig, SearchLimits, Searcher, MATE_SCORE};
+use crate::chess_game::game_clock::{Clock, SystemClock};
+use crate::chess_game::chess_board::Highlight;
+use crate::chess_game::chess_move::AnnotatedMove;
+use crate::chess_game::game_clock::{GameClock, TimeControl};
+use crate::chess_game::pgn::parse_pgn;
+use crate::chess_game::session::GameSession;
+use crate::chess_game::variant::Variant;
+use crate::chess_game::{ChessGameState, GameResult, Player};
+use crate::protocol::uci::ENGINE_NAME;
+use crate::protocol::write_line;
+
+const HELP: &str = "\
+moves are given in SAN (Nf3, O-O, e8=Q) or UCI (g1f3, e1g1, e7e8q)
+  new [variant]        start a new game
+  fen [FEN]            show the position, or set it up
+  pgn                  show the game
+  load <file>          load the first game of a PGN file
+  save <file>          save the game as PGN
+  undo                 take back a move, or your last move against the engine
+  engine <side>        let the engine play white, black, both or none
+  go                   let the engine play the side to move
+  depth <plies>        limit the engine's search depth
+  movetime <ms>        limit the engine's time per move
+  clock <control>      play with a PGN time control such as 300+2, or none
+  eval                 show the engine's evaluation of the position
+  flip                 turn the board around
+  draw                 offer a draw, or accept the one offered
+  resign               resign the game
+  quit                 leave";
+
+// centipawns from white's point of view, as pawns or moves to mate
+pub fn format_eval(score: i32) -> String {
+    if is_mate_score(score) {
+        let moves = (MATE_SCORE - score.abs() + 1) / 2;
+        format!("#{}", score.signum() * moves)
+    } else {
+        format!("{:+.2}", score as f64 / 100.0)
+    }
+}
+
+fn format_duration(time: Duration) -> String {
+    let seconds = time.as_secs();
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else {
+        format!("{}:{:02}.{}", seconds / 60, seconds % 60, time.subsec_millis() / 100)
+    }
+}
+
+fn result_text(result: GameResult) -> String {
+    let outcome = match result {
+        GameResult::WhiteWin => "white wins",
+        GameResult::BlackWin => "black wins",
+        GameResult::Draw => "draw",
+    };
+    format!("{}, {}", result.to_pgn(), outcome)
+}
+
+// creates the time source of each new game clock
+pub type ClockFactory = Box<dyn Fn() -> Box<dyn Clock>>;
+
+// an interactive game in the terminal between people, the engine, or both
+pub struct Tui<W: Write> {
+    out: W,
+    session: GameSession,
+    clock: Option<GameClock>,
+    new_clock: ClockFactory,
+    // the sides the engine plays, indexed by player
+    engine_sides: [bool; 2],
+    searcher: Searcher,
+    depth: Option<i32>,
+    movetime: Option<Duration>,
+    // the engine's last evaluation, from white's point of view
+    eval: Option<i32>,
+    flipped: bool,
+}
+
+impl<W: Write> Tui<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            session: GameSession::new(ChessGameState::new()),
+            clock: None,
+            new_clock: Box::new(|| Box::new(SystemClock::new())),
+            engine_sides: [false, true],
+            searcher: Searcher::new(SearchConfig::default()),
+            depth: None,
+            movetime: Some(Duration::from_secs(1)),
+            eval: None,
+            flipped: false,
+        }
+    }
+
+    pub fn set_clock_factory(&mut self, factory: ClockFactory) {
+        self.new_clock = factory;
+    }
+
+    pub fn session(&self) -> &GameSession {
+        &self.session
+    }
+
+    pub fn run<R: BufRead>(&mut self, input: R) {
+        self.show();
+        self.prompt();
+        for line in input.lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if !self.handle_command(&line) {
+                return;
+            }
+            self.prompt();
+        }
+    }
+
+    // returns false once the player wants to leave
+    pub fn handle_command(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some(command) = tokens.first() else {
+            return true;
+        };
+        self.check_flag();
+        let arg = tokens.get(1).copied();
+        match *command {
+            "quit" | "exit" => return false,
+            "help" => self.send(HELP),
+            "new" => match arg.map_or(Some(Variant::Standard), Variant::from_name) {
+                Some(variant) => self.set_game(ChessGameState::with_variant(variant), &[]),
+                None => self.send(&format!("unknown variant: {}", tokens[1..].join(" "))),
+            },
+            "fen" if tokens.len() == 1 => self.send(&self.session.game().get_fen()),
+            "fen" => match ChessGameState::from_variant_fen(self.session.start().variant(), &tokens[1..].join(" ")) {
+                Ok(game) => self.set_game(game, &[]),
+                Err(err) => self.send(&format!("invalid FEN: {}", err)),
+            },
+            "pgn" => self.send(self.pgn().trim_end()),
+            "load" => match arg {
+                Some(path) => self.load(path),
+                None => self.send("usage: load <file>"),
+            },
+            "save" => match arg {
+                Some(path) => match std::fs::write(path, self.pgn()) {
+                    Ok(()) => self.send(&format!("saved to {}", path)),
+                    Err(err) => self.send(&format!("could not save {}: {}", path, err)),
+                },
+                None => self.send("usage: save <file>"),
+            },
+            "undo" => self.undo(),
+            "engine" => match arg {
+                Some("white") => self.engine_sides = [true, false],
+                Some("black") => self.engine_sides = [false, true],
+                Some("both") => self.engine_sides = [true, true],
+                Some("none") => self.engine_sides = [false, false],
+                _ => self.send("usage: engine white|black|both|none"),
+            },
+            "go" => {
+                let player = self.session.game().active_player();
+                self.engine_sides[usize::from(player)] = true;
+            },
+            "depth" => match arg.and_then(|depth| depth.parse().ok()) {
+                Some(depth) => {
+                    self.depth = Some(depth);
+                    self.movetime = None;
+                },
+                None => self.send("usage: depth <plies>"),
+            },
+            "movetime" => match arg.and_then(|ms| ms.parse().ok()) {
+                Some(ms) => {
+                    self.movetime = Some(Duration::from_millis(ms));
+                    self.depth = None;
+                },
+                None => self.send("usage: movetime <ms>"),
+            },
+            "clock" => match arg {
+                Some("none") => self.clock = None,
+                Some(control) => match TimeControl::parse(control) {
+                    Some(control) => self.clock = Some(GameClock::new(control, (self.new_clock)())),
+                    None => self.send(&format!("invalid time control: {}", control)),
+                },
+                None => self.send("usage: clock <control>|none"),
+            },
+            "eval" => {
+                self.search();
+                self.show();
+            },
+            "flip" => {
+                self.flipped = !self.flipped;
+                self.show();
+            },
+            "draw" => {
+                // offering a draw the opponent offered accepts it
+                let player = self.session.game().active_player();
+                match self.session.offer_draw(player) {
+                    Ok(()) => self.show(),
+                    Err(err) => self.send(&format!("cannot offer a draw: {}", err)),
+                }
+            },
+            "resign" => {
+                let player = self.session.game().active_player();
+                match self.session.resign(player) {
+                    Ok(()) => self.show(),
+                    Err(err) => self.send(&format!("cannot resign: {}", err)),
+                }
+            },
+            _ => self.user_move(line.trim()),
+        }
+        // the engine answers whatever changed
+        self.engine_moves();
+        true
+    }
+
+    fn send(&mut self, text: &str) {
+        write_line(&mut self.out, text);
+    }
+
+    fn prompt(&mut self) {
+        let _ = write!(self.out, "> ");
+        let _ = self.out.flush();
+    }
+
+    fn set_game(&mut self, game: ChessGameState, moves: &[AnnotatedMove]) {
+        self.session = GameSession::new(game);
+        for m in moves {
+            let _ = self.session.play(*m);
+        }
+        if let Some(clock) = &self.clock {
+            self.clock = Some(GameClock::new(clock.control().clone(), (self.new_clock)()));
+        }
+        self.searcher.new_game();
+        self.eval = None;
+        self.show();
+    }
+
+    fn load(&mut self, path: &str) {
+        let games = std::fs::read_to_string(path).map_err(|err| err.to_string())
+            .and_then(|text| parse_pgn(&text).map_err(|err| err.to_string()));
+        match games {
+            Ok(games) => match games.first() {
+                Some(game) => self.set_game(game.start, &game.moves),
+                None => self.send(&format!("no games in {}", path)),
+            },
+            Err(err) => self.send(&format!("could not load {}: {}", path, err)),
+        }
+    }
+
+    fn pgn(&self) -> String {
+        let player = |side: usize| String::from(if self.engine_sides[side] { ENGINE_NAME } else { "Human" });
+        let mut tags = vec![(String::from("White"), player(0)), (String::from("Black"), player(1))];
+        if let Some(clock) = &self.clock {
+            tags.push((String::from("TimeControl"), clock.control().to_pgn()));
+        }
+        self.session.to_pgn_game(&tags).to_pgn()
+    }
+
+    // the positions of the game so far, the current one last
+    fn positions(&self) -> Vec<ChessGameState> {
+        let mut game = *self.session.start();
+        let mut positions = vec![game];
+        for m in self.session.moves() {
+            game.make_move(*m);
+            positions.push(game);
+        }
+        positions
+    }
+
+    fn user_move(&mut self, text: &str) {
+        let game = self.session.game();
+        let Some(m) = game.parse_san(text).or_else(|| game.parse_uci_move(text)) else {
+            self.send(&format!("illegal move or unknown command: {} (try help)", text));
+            return;
+        };
+        if self.session.result().is_some() {
+            self.send("the game is over");
+            return;
+        }
+        self.play(m);
+        self.show();
+    }
+
+    // plays a move on the board and the clock, if there is one
+    fn play(&mut self, m: AnnotatedMove) {
+        let _ = match &mut self.clock {
+            Some(clock) => self.session.play_timed(clock, m),
+            None => self.session.play(m),
+        };
+    }
+
+    fn check_flag(&mut self) {
+        if self.session.result().is_some() {
+            return;
+        }
+        if let Some(clock) = &mut self.clock && let Some(player) = clock.flagged() {
+            clock.stop();
+            let _ = self.session.flag(player);
+            self.show();
+        }
+    }
+
+    // takes back one move, or two when that would leave the engine to move
+    fn undo(&mut self) {
+        if self.session.undo().is_none() {
+            self.send("no moves to take back");
+            return;
+        }
+        let player = self.session.game().active_player();
+        if self.engine_sides[usize::from(player)] && !self.engine_sides[usize::from(player.opponent())] {
+            self.session.undo();
+        }
+        if let Some(clock) = &mut self.clock && clock.is_running() {
+            clock.start(self.session.game().active_player());
+        }
+        self.eval = None;
+        self.show();
+    }
+
+    fn limits(&self) -> SearchLimits {
+        let player = self.session.game().active_player();
+        match &self.clock {
+            Some(clock) => SearchLimits { depth: self.depth, game_time: Some(clock.game_time(player)), ..SearchLimits::default() },
+            None => SearchLimits { depth: self.depth, movetime: self.movetime, ..SearchLimits::default() },
+        }
+    }
+
+    // searches the current position, remembering the evaluation, and returns the best move
+    fn search(&mut self) -> Option<AnnotatedMove> {
+        let game = *self.session.game();
+        if game.result().is_some() {
+            return None;
+        }
+        let mut positions = self.positions();
+        positions.pop();
+        self.searcher.set_game_history(positions.iter().map(|g| g.hash()).collect());
+        let result = self.searcher.search(&game, self.limits());
+        self.eval = Some(match game.active_player() {
+            Player::White => result.score,
+            Player::Black => -result.score,
+        });
+        result.best_move.and_then(|m| game.annotate_move(m))
+    }
+
+    // lets the engine play while it is to move
+    fn engine_moves(&mut self) {
+        while self.session.result().is_none() && self.engine_sides[usize::from(self.session.game().active_player())] {
+            let Some(m) = self.search() else {
+                break;
+            };
+            let san = self.session.game().move_to_san(m);
+            self.play(m);
+            self.send(&format!("{} plays {}", ENGINE_NAME, san));
+            self.show();
+        }
+    }
+
+    fn show(&mut self) {
+        let text = self.render();
+        self.send(&text);
+    }
+
+    // the board with the last move and any check highlighted, followed by the moves, clocks, evaluation and status
+    pub fn render(&self) -> String {
+        let positions = self.positions();
+        let game = self.session.game();
+        let mut highlights = Vec::new();
+        if let (Some(m), [.., before, _]) = (self.session.moves().last(), positions.as_slice()) {
+            let (from, to) = before.move_squares(m.chess_move);
+            highlights.push((from, Highlight::LastMove));
+            highlights.push((to, Highlight::LastMove));
+        }
+        if game.in_check() && let Some(king) = game.board().find_king(game.active_player()) {
+            highlights.push((king.get_id(), Highlight::Check));
+        }
+        let mut text = game.board().render(&highlights, self.flipped);
+
+        let mut words = Vec::new();
+        for (m, before) in self.session.moves().iter().zip(&positions) {
+            if before.active_player() == Player::White {
+                words.push(format!("{}.", before.turn()));
+            } else if words.is_empty() {
+                words.push(format!("{}...", before.turn()));
+            }
+            words.push(before.move_to_san(*m));
+        }
+        if !words.is_empty() {
+            text += &format!("\n{}", words.join(" "));
+        }
+        if let Some(clock) = &self.clock {
+            let white = format_duration(clock.remaining(Player::White));
+            let black = format_duration(clock.remaining(Player::Black));
+            text += &format!("\nwhite {}  black {}", white, black);
+        }
+        if let Some(eval) = self.eval {
+            text += &format!("\neval {}", format_eval(eval));
+        }
+        let status = match self.session.result() {
+            Some(result) => format!("{} ({})", result_text(result), self.session.termination().unwrap().pgn_name()),
+            None => {
+                let player = game.active_player();
+                let side = if player == Player::White { "white" } else { "black" };
+                let check = if game.in_check() { ", in check" } else { "" };
+                match self.session.draw_offer() {
+                    Some(offer) if offer != player => format!("{} to move{}, a draw is offered", side, check),
+                    _ => format!("{} to move{}", side, check),
+                }
+            },
+        };
+        text += &format!("\n{}", status);
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::chess_engine::search::MATE_SCORE;
+    use crate::chess_game::game_clock::{Clock, MockClock};
+    use crate::chess_game::session::Termination;
+    use crate::chess_game::{ChessGameState, GameResult};
+    use crate::protocol::tests::SharedBuffer;
+    use crate::protocol::tui::{format_eval, Tui};
+
+    #[test]
+    fn evals() {
+        assert_eq!(format_eval(35), "+0.35");
+        assert_eq!(format_eval(-120), "-1.20");
+        assert_eq!(format_eval(MATE_SCORE - 5), "#3");
+        assert_eq!(format_eval(-MATE_SCORE + 2), "#-1");
+    }
+
+    #[test]
+    fn human_games() {
+        let out = SharedBuffer::default();
+        let mut tui = Tui::new(out.clone());
+        tui.handle_command("engine none");
+        for line in ["e4", "e7e5", "Nf3", "Nc6", "Bb5"] {
+            assert!(tui.handle_command(line));
+        }
+        assert!(out.text().contains("1. e4 e5 2. Nf3 Nc6 3. Bb5\nblack to move"));
+        tui.handle_command("Ke3");
+        assert!(out.text().ends_with("illegal move or unknown command: Ke3 (try help)\n"));
+        tui.handle_command("undo");
+        tui.handle_command("fen");
+        assert!(out.text().ends_with("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3\n"));
+        tui.handle_command("draw");
+        tui.handle_command("Bb5");
+        assert!(out.text().ends_with("black to move, a draw is offered\n"));
+        tui.handle_command("draw");
+        assert!(out.text().ends_with("1/2-1/2, draw (normal)\n"));
+        tui.handle_command("pgn");
+        assert!(out.text().contains("[Result \"1/2-1/2\"]"));
+        assert!(out.text().ends_with("1. e4 e5 2. Nf3 Nc6 3. Bb5 1/2-1/2\n"));
+        assert!(!tui.handle_command("quit"));
+    }
+
+    #[test]
+    fn repetition() {
+        let mut tui = Tui::new(SharedBuffer::default());
+        tui.handle_command("engine none");
+        for line in ["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1"] {
+            tui.handle_command(line);
+        }
+        assert_eq!(tui.session().result(), None);
+        tui.handle_command("Ng8");
+        assert_eq!(tui.session().result(), Some(GameResult::Draw));
+        assert_eq!(tui.session().termination(), Some(Termination::Normal));
+    }
+
+    #[test]
+    fn engine_games() {
+        let out = SharedBuffer::default();
+        let mut tui = Tui::new(out.clone());
+        tui.handle_command("depth 2");
+        tui.handle_command("fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        tui.handle_command("engine white");
+        assert!(out.text().contains("ChessAI plays Ra8#"));
+        assert!(out.text().ends_with("eval #1\n1-0, white wins (normal)\n"));
+        assert!(out.text().contains("\x1b[48;5;167m \x1b[30m♚"));
+
+        // undo takes back the engine's move and lets it play again, unless it plays both sides
+        tui.handle_command("engine none");
+        tui.handle_command("undo");
+        assert_eq!(tui.session().moves().len(), 0);
+        tui.handle_command("engine both");
+        assert_eq!(tui.session().result(), Some(GameResult::WhiteWin));
+        tui.handle_command("engine black");
+        tui.handle_command("new");
+        assert_eq!(tui.session().game(), &ChessGameState::new());
+    }
+
+    #[test]
+    fn clocks() {
+        let out = SharedBuffer::default();
+        let mut tui = Tui::new(out.clone());
+        let time = MockClock::new();
+        let factory_time = time.clone();
+        tui.set_clock_factory(Box::new(move || Box::new(factory_time.clone()) as Box<dyn Clock>));
+        tui.handle_command("engine none");
+        tui.handle_command("clock 60+1");
+        tui.handle_command("new");
+        tui.handle_command("e4");
+        time.advance(Duration::from_millis(2500));
+        tui.handle_command("e5");
+        assert!(out.text().contains("white 1:01.0  black 0:58.5"));
+        time.advance(Duration::from_secs(61));
+        tui.handle_command("fen");
+        assert_eq!(tui.session().result(), Some(GameResult::BlackWin));
+        assert_eq!(tui.session().termination(), Some(Termination::TimeForfeit));
+        tui.handle_command("pgn");
+        assert!(out.text().contains("[TimeControl \"60+1\"]"));
+    }
+
+    #[test]
+    fn pgn_files() {
+        let path = std::env::temp_dir().join(format!("tui-{}.pgn", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut tui = Tui::new(SharedBuffer::default());
+        tui.handle_command("engine none");
+        tui.handle_command("new kingofthehill");
+        tui.handle_command("e4");
+        tui.handle_command(&format!("save {}", path));
+        let out = SharedBuffer::default();
+        let mut loaded = Tui::new(out.clone());
+        loaded.handle_command("engine none");
+        loaded.handle_command(&format!("load {}", path));
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.session().game(), tui.session().game());
+        assert!(out.text().contains("1. e4\nblack to move"));
+        loaded.handle_command("load /nonexistent.pgn");
+        assert!(out.text().contains("could not load /nonexistent.pgn"));
+    }
+}
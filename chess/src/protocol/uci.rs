@@ -0,0 +1,676 @@
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use crate::chess_engine::search::{is_mate_score, SearchConfig, SearchControl, SearchLimits, SearchResult, Searcher, MATE_SCORE};
+use game::rng::Rng;
+use crate::chess_engine::book::PolyglotBook;
+use crate::chess_engine::evaluation::EvalParams;
+use crate::chess_engine::nnue::Network;
+use crate::chess_engine::syzygy::Tablebase;
+use crate::chess_engine::time_manager::GameTime;
+use crate::chess_game::chess_move::ChessMove;
+use crate::chess_game::variant::Variant;
+use crate::chess_game::{ChessGameState, Player};
+use crate::protocol::send;
+
+pub const ENGINE_NAME: &str = "ChessAI";
+pub const ENGINE_AUTHOR: &str = "Denver Woodward";
+pub const MAX_THREADS: usize = 256;
+// more than the number of legal moves in any position
+pub const MAX_MULTIPV: usize = 256;
+
+// the parameters of a "go" command
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct GoParams {
+    pub depth: Option<i32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<u64>,
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<u64>,
+    pub infinite: bool,
+    pub ponder: bool,
+}
+
+impl GoParams {
+    pub fn parse(tokens: &[&str]) -> GoParams {
+        let mut params = GoParams::default();
+        let mut iter = tokens.iter();
+        while let Some(token) = iter.next() {
+            let mut value = || iter.next().and_then(|v| v.parse::<u64>().ok());
+            match *token {
+                "depth" => params.depth = value().map(|d| d as i32),
+                "nodes" => params.nodes = value(),
+                "movetime" => params.movetime = value(),
+                "wtime" => params.wtime = value(),
+                "btime" => params.btime = value(),
+                "winc" => params.winc = value(),
+                "binc" => params.binc = value(),
+                "movestogo" => params.movestogo = value(),
+                "infinite" => params.infinite = true,
+                "ponder" => params.ponder = true,
+                _ => {},
+            }
+        }
+        params
+    }
+
+    pub fn limits(&self, player: Player) -> SearchLimits {
+        let (time, inc) = match player {
+            Player::White => (self.wtime, self.winc),
+            Player::Black => (self.btime, self.binc),
+        };
+        let game_time = time.map(|time| GameTime {
+            remaining: Duration::from_millis(time),
+            increment: Duration::from_millis(inc.unwrap_or(0)),
+            moves_to_go: self.movestogo.map(|moves| moves.min(u32::MAX as u64) as u32),
+        });
+        SearchLimits {
+            depth: self.depth,
+            nodes: self.nodes,
+            movetime: self.movetime.map(Duration::from_millis),
+            game_time,
+            infinite: self.infinite,
+        }
+    }
+}
+
+pub fn format_score(score: i32) -> String {
+    if is_mate_score(score) {
+        // UCI counts mates in moves rather than plies
+        let plies = MATE_SCORE - score.abs();
+        let moves = (plies + 1) / 2;
+        if score > 0 {
+            format!("mate {}", moves)
+        } else {
+            format!("mate -{}", moves)
+        }
+    } else {
+        format!("cp {}", score)
+    }
+}
+
+// converts a line of moves to UCI notation, playing them on a copy of the game
+pub fn format_pv(game: &ChessGameState, pv: &[ChessMove], chess960: bool) -> String {
+    let mut game = *game;
+    let mut moves = Vec::new();
+    for m in pv {
+        moves.push(if chess960 { game.move_to_uci_960(*m) } else { game.move_to_uci(*m) });
+        match game.make_pseudo_legal(*m) {
+            Some(next) => game = next,
+            None => break,
+        }
+    }
+    moves.join(" ")
+}
+
+pub fn format_info(game: &ChessGameState, result: &SearchResult, chess960: bool) -> String {
+    let millis = result.time.as_millis() as u64;
+    let nps = result.nodes * 1000 / millis.max(1);
+    format!(
+        "info depth {} multipv {} score {} nodes {} nps {} time {} pv {}",
+        result.depth, result.multipv, format_score(result.score), result.nodes, nps, millis, format_pv(game, &result.pv, chess960)
+    )
+}
+
+// a UCI engine session: reads commands, searches on a separate thread and writes responses to the output
+pub struct Uci<W: Write + Send + 'static> {
+    out: Arc<Mutex<W>>,
+    game: ChessGameState,
+    // hashes of the positions before the current one
+    history: Vec<u64>,
+    config: SearchConfig,
+    // the number of lines to analyse
+    multipv: usize,
+    own_book: bool,
+    book_file: String,
+    book: Option<PolyglotBook>,
+    // castling is sent and received as the king taking its own rook
+    chess960: bool,
+    variant: Variant,
+    rng: Rng,
+    searcher: Option<Searcher>,
+    control: Arc<SearchControl>,
+    search_thread: Option<JoinHandle<Searcher>>,
+}
+
+impl<W: Write + Send + 'static> Uci<W> {
+    pub fn new(out: W) -> Self {
+        let config = SearchConfig::default();
+        let searcher = Searcher::new(config);
+        Self {
+            out: Arc::new(Mutex::new(out)),
+            game: ChessGameState::new(),
+            history: Vec::new(),
+            config,
+            multipv: 1,
+            own_book: false,
+            book_file: String::new(),
+            book: None,
+            chess960: false,
+            variant: Variant::Standard,
+            rng: Rng::from_time(),
+            control: searcher.control(),
+            searcher: Some(searcher),
+            search_thread: None,
+        }
+    }
+
+    pub fn run<R: BufRead>(&mut self, input: R) {
+        for line in input.lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if !self.handle_command(&line) {
+                return;
+            }
+        }
+        self.stop_search();
+    }
+
+    // returns false once the engine should quit
+    pub fn handle_command(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some(command) = tokens.first() else {
+            return true;
+        };
+        match *command {
+            "uci" => self.uci(),
+            "isready" => self.send("readyok"),
+            "ucinewgame" => {
+                self.stop_search();
+                self.game = ChessGameState::new();
+                self.history.clear();
+                if let Some(searcher) = self.searcher.as_mut() {
+                    searcher.new_game();
+                }
+            },
+            "position" => {
+                self.stop_search();
+                self.position(&tokens[1..]);
+            },
+            "go" => {
+                self.stop_search();
+                self.go(GoParams::parse(&tokens[1..]));
+            },
+            "stop" => self.stop_search(),
+            "ponderhit" => self.control.set_pondering(false),
+            "setoption" => {
+                self.stop_search();
+                self.set_option(&tokens[1..]);
+            },
+            "quit" => {
+                self.stop_search();
+                return false;
+            },
+            _ => {},
+        }
+        true
+    }
+
+    fn send(&self, line: &str) {
+        send(&self.out, line);
+    }
+
+    fn uci(&self) {
+        self.send(&format!("id name {}", ENGINE_NAME));
+        self.send(&format!("id author {}", ENGINE_AUTHOR));
+        self.send(&format!("option name Hash type spin default {} min 1 max 4096", self.config.hash_size_mb));
+        self.send(&format!("option name MultiPV type spin default 1 min 1 max {}", MAX_MULTIPV));
+        self.send(&format!("option name Threads type spin default {} min 1 max {}", self.config.threads, MAX_THREADS));
+        self.send("option name Clear Hash type button");
+        self.send(&format!("option name Move Overhead type spin default {} min 0 max 5000", self.config.move_overhead.as_millis()));
+        self.send("option name Ponder type check default false");
+        self.send("option name OwnBook type check default false");
+        self.send("option name BookFile type string default <empty>");
+        self.send("option name SyzygyPath type string default <empty>");
+        self.send("option name EvalFile type string default <empty>");
+        self.send("option name EvalParamsFile type string default <empty>");
+        self.send("option name UCI_Chess960 type check default false");
+        let variants: Vec<String> = Variant::ALL.iter().map(|v| format!("var {}", v.uci_name())).collect();
+        self.send(&format!("option name UCI_Variant type combo default chess {}", variants.join(" ")));
+        for (name, value) in self.check_options() {
+            self.send(&format!("option name {} type check default {}", name, value));
+        }
+        self.send("uciok");
+    }
+
+    // the search techniques that can be switched off, e.g. to measure them in engine matches
+    fn check_options(&self) -> [(&'static str, bool); 7] {
+        [
+            ("MoveOrdering", self.config.move_ordering),
+            ("NullMovePruning", self.config.null_move_pruning),
+            ("LateMoveReductions", self.config.late_move_reductions),
+            ("FutilityPruning", self.config.futility_pruning),
+            ("ReverseFutilityPruning", self.config.reverse_futility_pruning),
+            ("CheckExtensions", self.config.check_extensions),
+            ("AspirationWindows", self.config.aspiration_windows),
+        ]
+    }
+
+    fn set_option(&mut self, tokens: &[&str]) {
+        // option names may contain spaces: "setoption name Clear Hash"
+        let value_index = tokens.iter().position(|t| *t == "value");
+        let name_end = value_index.unwrap_or(tokens.len());
+        if tokens.first() != Some(&"name") {
+            return;
+        }
+        let name = tokens[1..name_end].join(" ").to_lowercase();
+        let value = value_index.map(|i| tokens[i + 1..].join(" ")).unwrap_or_default();
+        let flag = value == "true";
+        match name.as_str() {
+            "hash" => {
+                if let Ok(size) = value.parse::<usize>() {
+                    self.config.hash_size_mb = size.clamp(1, 4096);
+                }
+            },
+            "ownbook" => self.own_book = flag,
+            "uci_chess960" => self.chess960 = flag,
+            "uci_variant" => match Variant::from_name(&value) {
+                Some(variant) => self.variant = variant,
+                None => self.send(&format!("info string unsupported variant {}", value)),
+            },
+            "bookfile" => {
+                self.book_file = value;
+                self.load_book();
+            },
+            "syzygypath" => {
+                let tablebase = match value.as_str() {
+                    "" | "<empty>" => None,
+                    paths => match Tablebase::open(paths) {
+                        Ok(tablebase) => {
+                            self.send(&format!("info string found {} tablebases with up to {} pieces", tablebase.table_count(), tablebase.max_pieces()));
+                            Some(Arc::new(tablebase))
+                        },
+                        Err(err) => {
+                            self.send(&format!("info string could not read tablebases: {}", err));
+                            None
+                        },
+                    },
+                };
+                if let Some(searcher) = self.searcher.as_mut() {
+                    searcher.set_tablebase(tablebase);
+                }
+            },
+            "evalfile" => {
+                let network = match value.as_str() {
+                    "" | "<empty>" => None,
+                    path => match Network::load(std::path::Path::new(path)) {
+                        Ok(network) => {
+                            self.send(&format!("info string using the network in {}", path));
+                            Some(Arc::new(network))
+                        },
+                        Err(err) => {
+                            self.send(&format!("info string could not read network: {}", err));
+                            None
+                        },
+                    },
+                };
+                if let Some(searcher) = self.searcher.as_mut() {
+                    searcher.set_network(network);
+                }
+            },
+            // weights for the handcrafted evaluation written by the tuner
+            "evalparamsfile" => {
+                let params = match value.as_str() {
+                    "" | "<empty>" => None,
+                    path => {
+                        let params = std::fs::read_to_string(path).map_err(|err| err.to_string())
+                            .and_then(|text| EvalParams::from_config(&text).map_err(|err| err.to_string()));
+                        match params {
+                            Ok(params) => {
+                                self.send(&format!("info string using the evaluation weights in {}", path));
+                                Some(Arc::new(params))
+                            },
+                            Err(err) => {
+                                self.send(&format!("info string could not read evaluation weights: {}", err));
+                                None
+                            },
+                        }
+                    },
+                };
+                if let Some(searcher) = self.searcher.as_mut() {
+                    searcher.set_params(params);
+                }
+            },
+            "multipv" => {
+                if let Ok(lines) = value.parse::<usize>() {
+                    self.multipv = lines.clamp(1, MAX_MULTIPV);
+                }
+            },
+            "threads" => {
+                if let Ok(threads) = value.parse::<usize>() {
+                    self.config.threads = threads.clamp(1, MAX_THREADS);
+                }
+            },
+            "move overhead" => {
+                if let Ok(millis) = value.parse::<u64>() {
+                    self.config.move_overhead = Duration::from_millis(millis.min(5000));
+                }
+            },
+            "clear hash" => {
+                if let Some(searcher) = self.searcher.as_mut() {
+                    searcher.clear_hash();
+                }
+            },
+            "moveordering" => self.config.move_ordering = flag,
+            "nullmovepruning" => self.config.null_move_pruning = flag,
+            "latemovereductions" => self.config.late_move_reductions = flag,
+            "futilitypruning" => self.config.futility_pruning = flag,
+            "reversefutilitypruning" => self.config.reverse_futility_pruning = flag,
+            "checkextensions" => self.config.check_extensions = flag,
+            "aspirationwindows" => self.config.aspiration_windows = flag,
+            _ => {},
+        }
+        if let Some(searcher) = self.searcher.as_mut() {
+            searcher.set_config(self.config);
+        }
+    }
+
+    fn position(&mut self, tokens: &[&str]) {
+        let moves_index = tokens.iter().position(|t| *t == "moves").unwrap_or(tokens.len());
+        let game = match tokens.first() {
+            Some(&"startpos") => Some(ChessGameState::with_variant(self.variant)),
+            Some(&"fen") => ChessGameState::from_variant_fen(self.variant, &tokens[1..moves_index].join(" ")).ok(),
+            _ => None,
+        };
+        let Some(mut game) = game else {
+            self.send("info string invalid position");
+            return;
+        };
+        let mut history = Vec::new();
+        for uci_move in tokens.iter().skip(moves_index + 1) {
+            let parsed = if self.king_takes_rook() { game.parse_uci_move_960(uci_move) } else { game.parse_uci_move(uci_move) };
+            match parsed {
+                Some(m) => {
+                    history.push(game.hash());
+                    game.make_move(m);
+                },
+                None => {
+                    self.send(&format!("info string illegal move {}", uci_move));
+                    break;
+                },
+            }
+        }
+        self.game = game;
+        self.history = history;
+    }
+
+    fn load_book(&mut self) {
+        self.book = None;
+        if self.book_file.is_empty() || self.book_file == "<empty>" {
+            return;
+        }
+        match PolyglotBook::open(&self.book_file) {
+            Ok(book) => self.book = Some(book),
+            Err(err) => self.send(&format!("info string could not load book: {}", err)),
+        }
+    }
+
+    fn go(&mut self, params: GoParams) {
+        if self.own_book && !params.infinite && !params.ponder
+            && let Some(book_move) = self.book.as_ref().and_then(|book| book.weighted_move(&self.game, &mut self.rng)) {
+            self.send(&format!("bestmove {}", format_pv(&self.game, &[book_move], self.king_takes_rook())));
+            return;
+        }
+        let Some(mut searcher) = self.searcher.take() else {
+            return;
+        };
+        let game = self.game;
+        let limits = params.limits(game.active_player());
+        let multipv = self.multipv;
+        let chess960 = self.king_takes_rook();
+        self.control.reset();
+        self.control.set_pondering(params.ponder);
+        searcher.set_game_history(self.history.clone());
+        let out = self.out.clone();
+        searcher.set_info_callback(Some(Box::new(move |result| send(&out, &format_info(&game, result, chess960)))));
+
+        let out = self.out.clone();
+        let control = self.control.clone();
+        self.search_thread = Some(std::thread::spawn(move || {
+            let result = searcher.search_lines(&game, limits, multipv).remove(0);
+            // bestmove may only be sent after "stop" when analysing, or after "ponderhit" when pondering
+            while (limits.infinite || control.is_pondering()) && !control.is_stopped() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            let best_move = match result.best_move {
+                Some(m) => format_pv(&game, &[m], chess960),
+                None => String::from("0000"),
+            };
+            let ponder_move = result.best_move.and_then(|m| game.make_pseudo_legal(m))
+                .zip(result.pv.get(1))
+                .map(|(next, m)| format_pv(&next, &[*m], chess960));
+            match ponder_move {
+                Some(ponder_move) => send(&out, &format!("bestmove {} ponder {}", best_move, ponder_move)),
+                None => send(&out, &format!("bestmove {}", best_move)),
+            }
+            searcher.set_info_callback(None);
+            searcher
+        }));
+    }
+
+    // the castling notation of UCI_Chess960, which Chess960 games use even without the option
+    fn king_takes_rook(&self) -> bool {
+        self.chess960 || self.variant == Variant::Chess960
+    }
+
+    // stops a running search and waits for it to report its best move
+    fn stop_search(&mut self) {
+        if let Some(handle) = self.search_thread.take() {
+            self.control.stop();
+            if let Ok(searcher) = handle.join() {
+                self.searcher = Some(searcher);
+            } else {
+                // the search thread panicked; start over with a fresh searcher
+                let searcher = Searcher::new(self.config);
+                self.control = searcher.control();
+                self.searcher = Some(searcher);
+            }
+        }
+    }
+
+    // waits for the current search to finish on its own
+    pub fn wait(&mut self) {
+        if let Some(handle) = self.search_thread.take()
+            && let Ok(searcher) = handle.join() {
+            self.searcher = Some(searcher);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::chess_engine::book::BookBuilder;
+    use crate::chess_engine::evaluation::DEFAULT_PARAMS;
+    use crate::chess_engine::nnue::tests::small_network;
+    use crate::chess_engine::search::MATE_SCORE;
+    use crate::chess_game::variant::Variant;
+    use crate::protocol::tests::SharedBuffer;
+    use crate::chess_game::Player;
+    use crate::protocol::uci::{format_score, GoParams, Uci};
+
+    #[test]
+    fn parse_go() {
+        let params = GoParams::parse(&["wtime", "60000", "btime", "30000", "winc", "1000", "binc", "500", "movestogo", "20"]);
+        assert_eq!(params.wtime, Some(60_000));
+        assert_eq!(params.binc, Some(500));
+        assert_eq!(params.movestogo, Some(20));
+        let limits = params.limits(Player::Black);
+        assert!(limits.movetime.is_none());
+        let game_time = limits.game_time.unwrap();
+        assert_eq!(game_time.remaining, Duration::from_millis(30_000));
+        assert_eq!(game_time.increment, Duration::from_millis(500));
+        assert_eq!(game_time.moves_to_go, Some(20));
+
+        let params = GoParams::parse(&["depth", "7", "infinite"]);
+        assert_eq!(params.depth, Some(7));
+        assert!(params.infinite);
+        assert!(params.limits(Player::White).game_time.is_none());
+    }
+
+    #[test]
+    fn scores() {
+        assert_eq!(format_score(35), "cp 35");
+        assert_eq!(format_score(MATE_SCORE - 1), "mate 1");
+        assert_eq!(format_score(MATE_SCORE - 3), "mate 2");
+        assert_eq!(format_score(-MATE_SCORE + 2), "mate -1");
+    }
+
+    #[test]
+    fn handshake() {
+        let out = SharedBuffer::default();
+        let mut uci = Uci::new(out.clone());
+        uci.run("uci\nisready\nquit\n".as_bytes());
+        let lines = out.lines();
+        assert_eq!(lines[0], "id name ChessAI");
+        assert!(lines.contains(&String::from("option name Hash type spin default 16 min 1 max 4096")));
+        assert!(lines.contains(&String::from("uciok")));
+        assert_eq!(lines.last().unwrap(), "readyok");
+    }
+
+    #[test]
+    fn search_position() {
+        let out = SharedBuffer::default();
+        let mut uci = Uci::new(out.clone());
+        uci.handle_command("setoption name Hash value 4");
+        uci.handle_command("position fen 6k1/5ppp/8/8/8/8/8/R5K1 b - - 0 1 moves g8f8 g1f1 f8g8");
+        uci.handle_command("go depth 3");
+        uci.wait();
+        let lines = out.lines();
+        assert!(lines.iter().any(|l| l.starts_with("info depth 1 multipv 1 score")));
+        assert!(lines.iter().any(|l| l.contains("score mate 1") && l.ends_with("pv a1a8")));
+        assert_eq!(lines.last().unwrap(), "bestmove a1a8");
+    }
+
+    #[test]
+    fn chess960_castling() {
+        let out = SharedBuffer::default();
+        let mut uci = Uci::new(out.clone());
+        uci.handle_command("position fen rk5r/8/8/8/8/8/8/RK5R w KQkq - 0 1 moves b1a1");
+        assert_eq!(out.lines().last().unwrap(), "info string illegal move b1a1");
+
+        uci.handle_command("setoption name UCI_Chess960 value true");
+        uci.handle_command("position fen rk5r/8/8/8/8/8/8/RK5R w KQkq - 0 1 moves b1a1 b8a8");
+        assert_eq!(uci.game.get_fen(), "2kr3r/8/8/8/8/8/8/2KR3R w - - 2 2");
+
+        uci.handle_command("setoption name UCI_Chess960 value false");
+        uci.handle_command("setoption name UCI_Variant value chess960");
+        uci.handle_command("position startpos moves g1f3 g8f6 e2e3 e7e6 f1e2 f8e7 e1h1");
+        assert_eq!(uci.game.variant(), Variant::Chess960);
+        assert_eq!(uci.game.get_fen(), "rnbqk2r/ppppbppp/4pn2/8/8/4PN2/PPPPBPPP/RNBQ1RK1 b kq - 3 4");
+        uci.handle_command("setoption name UCI_Variant value shogi");
+        assert_eq!(out.lines().last().unwrap(), "info string unsupported variant shogi");
+    }
+
+    #[test]
+    fn stop_infinite() {
+        let out = SharedBuffer::default();
+        let mut uci = Uci::new(out.clone());
+        uci.handle_command("position startpos moves e2e4 e7e5");
+        uci.handle_command("go infinite");
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!out.lines().iter().any(|l| l.starts_with("bestmove")));
+        uci.handle_command("stop");
+        let lines = out.lines();
+        assert!(lines.last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn ponderhit() {
+        let out = SharedBuffer::default();
+        let mut uci = Uci::new(out.clone());
+        uci.handle_command("position startpos");
+        uci.handle_command("go ponder wtime 100 btime 100");
+        std::thread::sleep(Duration::from_millis(200));
+        // the clock doesn't run while pondering
+        assert!(!out.lines().iter().any(|l| l.starts_with("bestmove")));
+        uci.handle_command("ponderhit");
+        uci.wait();
+        assert!(out.lines().last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn multipv() {
+        let out = SharedBuffer::default();
+        let mut uci = Uci::new(out.clone());
+        uci.handle_command("setoption name MultiPV value 3");
+        uci.handle_command("position startpos");
+        uci.handle_command("go depth 2");
+        uci.wait();
+        let lines = out.lines();
+        for k in 1..=3 {
+            assert!(lines.iter().any(|l| l.starts_with(&format!("info depth 2 multipv {} score", k))));
+        }
+        assert!(!lines.iter().any(|l| l.contains("multipv 4")));
+        assert!(lines.last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn own_book() {
+        let mut builder = BookBuilder::new(4, 1);
+        builder.add_pgn("1. e4 e5 2. Nf3 Nc6 *").unwrap();
+        let path = std::env::temp_dir().join(format!("chess_uci_book_{}.bin", std::process::id()));
+        builder.build().save(&path).unwrap();
+
+        let out = SharedBuffer::default();
+        let mut uci = Uci::new(out.clone());
+        uci.handle_command(&format!("setoption name BookFile value {}", path.display()));
+        uci.handle_command("setoption name OwnBook value true");
+        uci.handle_command("position startpos moves e2e4");
+        uci.handle_command("go wtime 1000 btime 1000");
+        uci.wait();
+        assert_eq!(out.lines().last().unwrap(), "bestmove e7e5");
+
+        // out of book
+        uci.handle_command("position startpos moves d2d4");
+        uci.handle_command("go depth 1");
+        uci.wait();
+        assert!(out.lines().iter().any(|l| l.starts_with("info depth 1")));
+
+        uci.handle_command("setoption name BookFile value /nonexistent/book.bin");
+        assert!(out.lines().last().unwrap().starts_with("info string could not load book"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn eval_file() {
+        let path = std::env::temp_dir().join(format!("chess_uci_nnue_{}.bin", std::process::id()));
+        small_network(6).save(&path).unwrap();
+        let out = SharedBuffer::default();
+        let mut uci = Uci::new(out.clone());
+        uci.handle_command(&format!("setoption name EvalFile value {}", path.display()));
+        assert!(out.lines().last().unwrap().starts_with("info string using the network"));
+        uci.handle_command("position startpos");
+        uci.handle_command("go depth 2");
+        uci.wait();
+        assert!(out.lines().last().unwrap().starts_with("bestmove "));
+
+        uci.handle_command("setoption name EvalFile value /nonexistent/net.bin");
+        assert!(out.lines().last().unwrap().starts_with("info string could not read network"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn eval_params_file() {
+        let path = std::env::temp_dir().join(format!("chess_uci_params_{}.txt", std::process::id()));
+        std::fs::write(&path, DEFAULT_PARAMS.to_config()).unwrap();
+        let out = SharedBuffer::default();
+        let mut uci = Uci::new(out.clone());
+        uci.handle_command(&format!("setoption name EvalParamsFile value {}", path.display()));
+        assert!(out.lines().last().unwrap().starts_with("info string using the evaluation weights"));
+        uci.handle_command("position startpos");
+        uci.handle_command("go depth 2");
+        uci.wait();
+        assert!(out.lines().last().unwrap().starts_with("bestmove "));
+
+        std::fs::write(&path, "piece_values 1 2").unwrap();
+        uci.handle_command(&format!("setoption name EvalParamsFile value {}", path.display()));
+        assert_eq!(out.lines().last().unwrap(), "info string could not read evaluation weights: invalid line 1");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,237 @@
+use std::io::{Read, Write};
+
+// the frame opcodes of RFC 6455
+pub const CONTINUATION: u8 = 0x0;
+pub const TEXT: u8 = 0x1;
+pub const BINARY: u8 = 0x2;
+pub const CLOSE: u8 = 0x8;
+pub const PING: u8 = 0x9;
+pub const PONG: u8 = 0xa;
+
+// frames and messages from clients larger than this are refused
+const MAX_PAYLOAD: u64 = 1 << 20;
+
+// a frame as it was sent; a message can be split over frames, the last of which has fin set
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Frame {
+    pub fin: bool,
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([chunk[4 * i], chunk[4 * i + 1], chunk[4 * i + 2], chunk[4 * i + 3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..20 => ((b & c) | (!b & d), 0x5a827999),
+                20..40 => (b ^ c ^ d, 0x6ed9eba1),
+                40..60 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (h, x) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(x);
+        }
+    }
+    let mut digest = [0; 20];
+    for (i, word) in h.iter().enumerate() {
+        digest[4 * i..4 * i + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+pub fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+// the Sec-WebSocket-Accept answer to a client's Sec-WebSocket-Key
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}258EAFA5-E914-47DA-95CA-C5AB0DC85B11", key.trim()).as_bytes()))
+}
+
+// writes a single unmasked frame, as servers send them
+pub fn write_frame<W: Write>(out: &mut W, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        },
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        },
+    }
+    frame.extend_from_slice(payload);
+    out.write_all(&frame)?;
+    out.flush()
+}
+
+// reads a frame, unmasking its payload
+pub fn read_frame<R: Read>(input: &mut R) -> std::io::Result<Frame> {
+    let mut header = [0; 2];
+    input.read_exact(&mut header)?;
+    let fin = header[0] & 0x80 != 0;
+    let opcode = header[0] & 0x0f;
+    let masked = header[1] & 0x80 != 0;
+    let len = match header[1] & 0x7f {
+        126 => {
+            let mut len = [0; 2];
+            input.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as u64
+        },
+        127 => {
+            let mut len = [0; 8];
+            input.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        },
+        len => len as u64,
+    };
+    if len > MAX_PAYLOAD {
+        return Err(invalid("frame too large"));
+    }
+    let mut mask = [0; 4];
+    if masked {
+        input.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0; len as usize];
+    input.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok(Frame { fin, opcode, payload })
+}
+
+// reads whole messages, joining the frames of fragmented ones; control frames may come between the fragments and are
+// returned as they arrive
+#[derive(Debug, Default)]
+pub struct MessageReader {
+    // the opcode and payload of a fragmented message so far
+    partial: Option<(u8, Vec<u8>)>,
+}
+
+impl MessageReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read<R: Read>(&mut self, input: &mut R) -> std::io::Result<(u8, Vec<u8>)> {
+        loop {
+            let frame = read_frame(input)?;
+            // control opcodes have the high bit set, and are never fragmented
+            if frame.opcode & 0x8 != 0 {
+                if !frame.fin {
+                    return Err(invalid("fragmented control frame"));
+                }
+                return Ok((frame.opcode, frame.payload));
+            }
+            let (opcode, payload) = match (self.partial.take(), frame.opcode) {
+                (None, CONTINUATION) => return Err(invalid("continuation frame without a message")),
+                (None, opcode) => (opcode, frame.payload),
+                (Some((opcode, mut payload)), CONTINUATION) => {
+                    payload.extend_from_slice(&frame.payload);
+                    (opcode, payload)
+                },
+                (Some(_), _) => return Err(invalid("new message before the last one ended")),
+            };
+            if payload.len() as u64 > MAX_PAYLOAD {
+                return Err(invalid("message too large"));
+            }
+            if frame.fin {
+                return Ok((opcode, payload));
+            }
+            self.partial = Some((opcode, payload));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::websocket::{accept_key, base64, read_frame, sha1, write_frame, MessageReader, PING, TEXT};
+
+    #[test]
+    fn handshake() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(sha1(b"abc")[..4], [0xa9, 0x99, 0x3e, 0x36]);
+        // the example of RFC 6455
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn frames() {
+        let mut out = Vec::new();
+        write_frame(&mut out, TEXT, b"hello").unwrap();
+        assert_eq!(out, b"\x81\x05hello");
+        let frame = read_frame(&mut out.as_slice()).unwrap();
+        assert_eq!((frame.fin, frame.opcode, frame.payload), (true, TEXT, b"hello".to_vec()));
+
+        let long = vec![b'x'; 300];
+        let mut out = Vec::new();
+        write_frame(&mut out, TEXT, &long).unwrap();
+        assert_eq!(out[..4], [0x81, 126, 1, 44]);
+        assert_eq!(read_frame(&mut out.as_slice()).unwrap().payload, long);
+
+        // clients mask their frames
+        let masked = [0x89, 0x83, 1, 2, 3, 4, b'a' ^ 1, b'b' ^ 2, b'c' ^ 3];
+        assert_eq!(MessageReader::new().read(&mut masked.as_slice()).unwrap(), (PING, b"abc".to_vec()));
+        assert!(read_frame(&mut [0x81, 0x05, b'h'].as_slice()).is_err());
+    }
+
+    #[test]
+    fn fragmented_messages() {
+        // "hel" and "lo" in a text frame and a continuation, with a ping between them
+        let frames = [&[0x01, 0x03, b'h', b'e', b'l'][..], &[0x89, 0x01, b'x'], &[0x80, 0x02, b'l', b'o'], &[0x81, 0x01, b'!']].concat();
+        let mut input = frames.as_slice();
+        let mut reader = MessageReader::new();
+        assert!(!read_frame(&mut frames.as_slice()).unwrap().fin);
+        assert_eq!(reader.read(&mut input).unwrap(), (PING, b"x".to_vec()));
+        assert_eq!(reader.read(&mut input).unwrap(), (TEXT, b"hello".to_vec()));
+        assert_eq!(reader.read(&mut input).unwrap(), (TEXT, b"!".to_vec()));
+
+        assert!(MessageReader::new().read(&mut [0x80, 0x01, b'x'].as_slice()).is_err());
+        assert!(MessageReader::new().read(&mut [0x01, 0x01, b'x', 0x81, 0x01, b'y'].as_slice()).is_err());
+        assert!(MessageReader::new().read(&mut [0x09, 0x00].as_slice()).is_err());
+    }
+}
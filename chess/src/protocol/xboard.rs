@@ -0,0 +1,556 @@
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use crate::chess_engine::search::{is_mate_score, SearchConfig, SearchControl, SearchResult, Searcher, MATE_SCORE};
+use crate::chess_game::chess_move::ChessMove;
+use crate::chess_game::variant::Variant;
+use crate::chess_game::{ChessGameState, GameResult, Player};
+use crate::protocol::send;
+use crate::protocol::uci::{format_pv, GoParams, ENGINE_NAME, MAX_THREADS};
+
+// the time control set by "level", "st" and "sd"
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct TimeControl {
+    // moves per session, 0 for the whole game
+    pub moves_per_session: u64,
+    pub base_ms: u64,
+    pub increment_ms: u64,
+    // a fixed time per move from "st"
+    pub move_time_ms: Option<u64>,
+    pub max_depth: Option<i32>,
+}
+
+impl TimeControl {
+    // parses the arguments of "level MPS BASE INC", where BASE is minutes or minutes:seconds
+    pub fn set_level(&mut self, tokens: &[&str]) -> Option<()> {
+        let [mps, base, inc] = tokens else {
+            return None;
+        };
+        let mut base_parts = base.split(':');
+        let minutes: u64 = base_parts.next()?.parse().ok()?;
+        let seconds: u64 = match base_parts.next() {
+            Some(seconds) => seconds.parse().ok()?,
+            None => 0,
+        };
+        let increment: f64 = inc.parse().ok()?;
+        self.moves_per_session = mps.parse().ok()?;
+        self.base_ms = (minutes * 60 + seconds) * 1000;
+        self.increment_ms = (increment * 1000.0) as u64;
+        self.move_time_ms = None;
+        Some(())
+    }
+}
+
+// xboard reports mates as 100000 + the number of moves
+pub fn xboard_score(score: i32) -> i32 {
+    if is_mate_score(score) {
+        let moves = (MATE_SCORE - score.abs() + 1) / 2;
+        score.signum() * (100_000 + moves)
+    } else {
+        score
+    }
+}
+
+pub fn format_thinking(game: &ChessGameState, result: &SearchResult) -> String {
+    let centis = result.time.as_millis() / 10;
+    format!("{} {} {} {} {}", result.depth, xboard_score(result.score), centis, result.nodes, format_pv(game, &result.pv, false))
+}
+
+// castling in Chess960 is written O-O and O-O-O, as the king's move alone can be ambiguous
+pub fn format_move(game: &ChessGameState, chess_move: ChessMove) -> String {
+    match chess_move {
+        ChessMove::ShortCastle if game.variant() == Variant::Chess960 => String::from("O-O"),
+        ChessMove::LongCastle if game.variant() == Variant::Chess960 => String::from("O-O-O"),
+        _ => game.move_to_uci(chess_move),
+    }
+}
+
+pub fn result_string(result: GameResult) -> String {
+    let comment = match result {
+        GameResult::WhiteWin => "White mates",
+        GameResult::BlackWin => "Black mates",
+        GameResult::Draw => "Draw",
+    };
+    format!("{} {{{}}}", result.to_pgn(), comment)
+}
+
+// the game shared with the search thread, which plays its move here as soon as it is found
+struct Board {
+    game: ChessGameState,
+    // the positions before the current one, for "undo" and repetitions
+    history: Vec<ChessGameState>,
+    // set while the engine thinks; pings are answered once it has moved
+    thinking: bool,
+    pongs: Vec<String>,
+}
+
+impl Board {
+    fn new(game: ChessGameState) -> Self {
+        Self { game, history: Vec::new(), thinking: false, pongs: Vec::new() }
+    }
+
+    fn play<W: Write>(&mut self, out: &Mutex<W>, chess_move: ChessMove) -> bool {
+        let Some(annotated) = self.game.annotate_move(chess_move) else {
+            return false;
+        };
+        self.history.push(self.game);
+        self.game.make_move(annotated);
+        if let Some(result) = self.game.result() {
+            send(out, &result_string(result));
+        }
+        true
+    }
+
+    fn stop_thinking<W: Write>(&mut self, out: &Mutex<W>) {
+        self.thinking = false;
+        for pong in self.pongs.drain(..) {
+            send(out, &pong);
+        }
+    }
+}
+
+struct SearchThread {
+    handle: JoinHandle<Searcher>,
+    // set when the move found should not be played
+    abort: Arc<AtomicBool>,
+}
+
+// an engine session speaking the Chess Engine Communication Protocol
+pub struct Xboard<W: Write + Send + 'static> {
+    out: Arc<Mutex<W>>,
+    board: Arc<Mutex<Board>>,
+    variant: Variant,
+    // the side the engine plays, or None in force mode
+    engine_side: Option<Player>,
+    time_control: TimeControl,
+    engine_time_ms: Option<u64>,
+    opponent_time_ms: Option<u64>,
+    post: bool,
+    config: SearchConfig,
+    searcher: Option<Searcher>,
+    control: Arc<SearchControl>,
+    search_thread: Option<SearchThread>,
+}
+
+impl<W: Write + Send + 'static> Xboard<W> {
+    pub fn new(out: W) -> Self {
+        let config = SearchConfig::default();
+        let searcher = Searcher::new(config);
+        Self {
+            out: Arc::new(Mutex::new(out)),
+            board: Arc::new(Mutex::new(Board::new(ChessGameState::new()))),
+            variant: Variant::Standard,
+            engine_side: Some(Player::Black),
+            time_control: TimeControl::default(),
+            engine_time_ms: None,
+            opponent_time_ms: None,
+            post: false,
+            config,
+            control: searcher.control(),
+            searcher: Some(searcher),
+            search_thread: None,
+        }
+    }
+
+    pub fn run<R: BufRead>(&mut self, input: R) {
+        for line in input.lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if !self.handle_command(&line) {
+                return;
+            }
+        }
+        self.wait();
+    }
+
+    // returns false once the engine should quit
+    pub fn handle_command(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some(command) = tokens.first() else {
+            return true;
+        };
+        match *command {
+            // these don't touch the game, so they can be handled while thinking
+            "xboard" | "accepted" | "rejected" | "random" | "hard" | "easy" | "computer" | "draw" | "name" | "rating" | "ics"
+            | "hint" | "bk" => {},
+            "protover" => self.send(&format!(
+                "feature myname=\"{}\" variants=\"{}\" setboard=1 usermove=1 ping=1 playother=1 smp=1 colors=0 analyze=0 sigint=0 sigterm=0 done=1",
+                ENGINE_NAME,
+                Variant::ALL.map(|v| v.xboard_name()).join(",")
+            )),
+            "ping" => {
+                let pong = format!("pong {}", tokens.get(1).unwrap_or(&""));
+                let mut board = self.board();
+                match board.thinking {
+                    true => board.pongs.push(pong),
+                    false => send(&self.out, &pong),
+                }
+            },
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "time" => self.engine_time_ms = tokens.get(1).and_then(|t| t.parse::<u64>().ok()).map(|t| t * 10),
+            "otim" => self.opponent_time_ms = tokens.get(1).and_then(|t| t.parse::<u64>().ok()).map(|t| t * 10),
+            "?" => {
+                self.control.stop();
+                self.wait();
+            },
+            // the time control and threads apply to the next search
+            "level" => {
+                if self.time_control.set_level(&tokens[1..]).is_none() {
+                    self.send(&format!("Error (bad arguments): {}", tokens.join(" ")));
+                }
+            },
+            "st" => match tokens.get(1).and_then(|t| t.parse::<f64>().ok()) {
+                Some(seconds) => self.time_control.move_time_ms = Some((seconds * 1000.0) as u64),
+                None => self.send(&format!("Error (bad arguments): {}", tokens.join(" "))),
+            },
+            "sd" => match tokens.get(1).and_then(|t| t.parse::<i32>().ok()) {
+                Some(depth) => self.time_control.max_depth = Some(depth),
+                None => self.send(&format!("Error (bad arguments): {}", tokens.join(" "))),
+            },
+            "cores" => match tokens.get(1).and_then(|t| t.parse::<usize>().ok()) {
+                Some(cores) => {
+                    self.config.threads = cores.clamp(1, MAX_THREADS);
+                    if let Some(searcher) = self.searcher.as_mut() {
+                        searcher.set_config(self.config);
+                    }
+                },
+                None => self.send(&format!("Error (bad arguments): {}", tokens.join(" "))),
+            },
+            "quit" => {
+                self.abort_search();
+                return false;
+            },
+            // these change the game the engine may be thinking about
+            "new" | "variant" | "setboard" | "force" | "result" | "go" | "playother" | "usermove" | "undo" | "remove" => {
+                self.abort_search();
+                self.game_command(&tokens);
+            },
+            command => self.send(&format!("Error (unknown command): {}", command)),
+        }
+        true
+    }
+
+    fn game_command(&mut self, tokens: &[&str]) {
+        match tokens[0] {
+            "new" => {
+                self.variant = Variant::Standard;
+                self.set_game(ChessGameState::new());
+                self.engine_side = Some(Player::Black);
+                self.time_control.max_depth = None;
+                self.engine_time_ms = None;
+                self.opponent_time_ms = None;
+                if let Some(searcher) = self.searcher.as_mut() {
+                    searcher.new_game();
+                }
+            },
+            // sent after "new" for games of other variants
+            "variant" => match tokens.get(1).and_then(|name| Variant::from_name(name)) {
+                Some(variant) => {
+                    self.variant = variant;
+                    self.set_game(ChessGameState::with_variant(variant));
+                },
+                None => self.send(&format!("Error (unsupported variant): {}", tokens[1..].join(" "))),
+            },
+            "setboard" => match ChessGameState::from_variant_fen(self.variant, &tokens[1..].join(" ")) {
+                Ok(game) => self.set_game(game),
+                Err(err) => self.send(&format!("tellusererror Illegal position: {}", err)),
+            },
+            "force" | "result" => self.engine_side = None,
+            "go" => {
+                let active = self.board().game.active_player();
+                self.engine_side = Some(active);
+                self.think();
+            },
+            "playother" => {
+                let active = self.board().game.active_player();
+                self.engine_side = Some(active.opponent());
+            },
+            "usermove" => match tokens.get(1) {
+                Some(user_move) => self.user_move(user_move),
+                None => self.send("Error (missing move): usermove"),
+            },
+            "undo" => self.take_back(1),
+            "remove" => self.take_back(2),
+            _ => unreachable!(),
+        }
+    }
+
+    fn send(&self, line: &str) {
+        send(&self.out, line);
+    }
+
+    fn board(&self) -> MutexGuard<'_, Board> {
+        self.board.lock().unwrap()
+    }
+
+    fn set_game(&mut self, game: ChessGameState) {
+        *self.board() = Board::new(game);
+    }
+
+    fn user_move(&mut self, user_move: &str) {
+        let mut board = self.board();
+        if board.game.result().is_some() {
+            send(&self.out, &format!("Illegal move (game is over): {}", user_move));
+            return;
+        }
+        let parsed = match user_move {
+            "O-O" | "O-O-O" => board.game.parse_san(user_move),
+            _ => board.game.parse_uci_move(user_move),
+        };
+        match parsed {
+            Some(m) => {
+                board.play(&self.out, m.chess_move);
+                let engine_to_move = self.engine_side == Some(board.game.active_player());
+                drop(board);
+                if engine_to_move {
+                    self.think();
+                }
+            },
+            None => send(&self.out, &format!("Illegal move: {}", user_move)),
+        }
+    }
+
+    fn take_back(&mut self, moves: usize) {
+        let mut board = self.board();
+        for _ in 0..moves {
+            if let Some(game) = board.history.pop() {
+                board.game = game;
+            }
+        }
+    }
+
+    fn go_params(&self, board: &Board) -> GoParams {
+        let tc = &self.time_control;
+        let moves_to_go = if tc.moves_per_session > 0 {
+            // moves made by the engine's side since the start of the current session
+            let played = board.history.len() as u64 / 2;
+            Some(tc.moves_per_session - played % tc.moves_per_session)
+        } else {
+            None
+        };
+        // until the first "time" and "otim" both clocks show the base time of the level
+        let base = Some(tc.base_ms).filter(|base| *base > 0 && tc.move_time_ms.is_none());
+        let (engine_time, opponent_time) = (self.engine_time_ms.or(base), self.opponent_time_ms.or(base));
+        let (wtime, btime) = match board.game.active_player() {
+            Player::White => (engine_time, opponent_time),
+            Player::Black => (opponent_time, engine_time),
+        };
+        let inc = Some(tc.increment_ms).filter(|inc| *inc > 0);
+        GoParams {
+            depth: tc.max_depth,
+            movetime: tc.move_time_ms,
+            wtime,
+            btime,
+            winc: inc,
+            binc: inc,
+            movestogo: moves_to_go,
+            ..GoParams::default()
+        }
+    }
+
+    fn think(&mut self) {
+        let shared = self.board.clone();
+        let mut board = shared.lock().unwrap();
+        if board.game.result().is_some() {
+            return;
+        }
+        let Some(mut searcher) = self.searcher.take() else {
+            return;
+        };
+        let game = board.game;
+        let limits = self.go_params(&board).limits(game.active_player());
+        self.control.reset();
+        searcher.set_game_history(board.history.iter().map(|g| g.hash()).collect());
+        board.thinking = true;
+        drop(board);
+        let out = self.out.clone();
+        if self.post {
+            searcher.set_info_callback(Some(Box::new(move |result| send(&out, &format_thinking(&game, result)))));
+        } else {
+            searcher.set_info_callback(None);
+        }
+
+        let out = self.out.clone();
+        let abort = Arc::new(AtomicBool::new(false));
+        let thread_abort = abort.clone();
+        let handle = std::thread::spawn(move || {
+            let result = searcher.search(&game, limits);
+            let mut board = shared.lock().unwrap();
+            if let Some(m) = result.best_move.filter(|_| !thread_abort.load(Ordering::SeqCst)) {
+                send(&out, &format!("move {}", format_move(&game, m)));
+                board.play(&out, m);
+            }
+            board.stop_thinking(&out);
+            searcher
+        });
+        self.search_thread = Some(SearchThread { handle, abort });
+    }
+
+    // stops thinking without playing the move, unless it was already sent
+    fn abort_search(&mut self) {
+        if let Some(thread) = &self.search_thread {
+            thread.abort.store(true, Ordering::SeqCst);
+            self.control.stop();
+        }
+        self.wait();
+    }
+
+    // waits for the engine to finish thinking, which plays the move it found
+    pub fn wait(&mut self) {
+        let Some(thread) = self.search_thread.take() else {
+            return;
+        };
+        match thread.handle.join() {
+            Ok(mut searcher) => {
+                // "cores" may have come in while it was thinking
+                searcher.set_config(self.config);
+                self.searcher = Some(searcher);
+            },
+            Err(_) => {
+                let searcher = Searcher::new(self.config);
+                self.control = searcher.control();
+                self.searcher = Some(searcher);
+                self.board().stop_thinking(&self.out);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use crate::chess_engine::search::MATE_SCORE;
+    use crate::chess_game::variant::Variant;
+    use crate::protocol::tests::SharedBuffer;
+    use crate::protocol::xboard::{xboard_score, TimeControl, Xboard};
+
+    #[test]
+    fn level() {
+        let mut tc = TimeControl::default();
+        assert!(tc.set_level(&["40", "5", "0"]).is_some());
+        assert_eq!((tc.moves_per_session, tc.base_ms, tc.increment_ms), (40, 300_000, 0));
+        assert!(tc.set_level(&["0", "2:30", "1.5"]).is_some());
+        assert_eq!((tc.moves_per_session, tc.base_ms, tc.increment_ms), (0, 150_000, 1500));
+        assert!(tc.set_level(&["0", "x", "1"]).is_none());
+    }
+
+    #[test]
+    fn mate_scores() {
+        assert_eq!(xboard_score(-40), -40);
+        assert_eq!(xboard_score(MATE_SCORE - 1), 100_001);
+        assert_eq!(xboard_score(-MATE_SCORE + 4), -100_002);
+    }
+
+    #[test]
+    fn plays_a_game() {
+        let out = SharedBuffer::default();
+        let mut xboard = Xboard::new(out.clone());
+        for command in ["xboard", "protover 2", "new", "sd 2", "post", "usermove e2e4"] {
+            xboard.handle_command(command);
+        }
+        xboard.wait();
+        let lines = out.lines();
+        assert!(lines[0].starts_with("feature myname=\"ChessAI\""));
+        assert!(lines.iter().any(|l| l.starts_with("2 ")));
+        assert!(lines.last().unwrap().starts_with("move "));
+
+        xboard.handle_command("usermove e2e5");
+        assert_eq!(out.lines().last().unwrap(), "Illegal move: e2e5");
+
+        // take back both moves and let the engine play white
+        xboard.handle_command("remove");
+        xboard.handle_command("force");
+        xboard.handle_command("usermove e2e4");
+        xboard.handle_command("undo");
+        xboard.handle_command("go");
+        xboard.wait();
+        let lines = out.lines();
+        assert!(lines.last().unwrap().starts_with("move "));
+        assert_eq!(lines.iter().filter(|l| l.starts_with("move ")).count(), 2);
+    }
+
+    #[test]
+    fn fischer_random() {
+        let out = SharedBuffer::default();
+        let mut xboard = Xboard::new(out.clone());
+        for command in ["xboard", "protover 2", "new", "variant fischerandom", "force"] {
+            xboard.handle_command(command);
+        }
+        let variants: Vec<&str> = Variant::ALL.iter().map(|v| v.xboard_name()).collect();
+        assert!(out.lines()[0].contains(&format!("variants=\"{}\"", variants.join(","))));
+        xboard.handle_command("setboard rk5r/8/8/8/8/8/8/RK5R w KQkq - 0 1");
+        xboard.handle_command("usermove O-O-O");
+        assert_eq!(xboard.board().game.get_fen(), "rk5r/8/8/8/8/8/8/2KR3R b kq - 1 1");
+        assert_eq!(xboard.board().game.variant(), Variant::Chess960);
+
+        xboard.handle_command("variant shogi");
+        assert_eq!(out.lines().last().unwrap(), "Error (unsupported variant): shogi");
+    }
+
+    #[test]
+    fn announces_mate() {
+        let out = SharedBuffer::default();
+        let mut xboard = Xboard::new(out.clone());
+        xboard.handle_command("force");
+        xboard.handle_command("setboard 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        xboard.handle_command("st 1");
+        xboard.handle_command("sd 3");
+        xboard.handle_command("ping 7");
+        xboard.handle_command("go");
+        xboard.wait();
+        let lines = out.lines();
+        assert_eq!(lines[0], "pong 7");
+        assert_eq!(lines[1], "move a1a8");
+        assert_eq!(lines[2], "1-0 {White mates}");
+    }
+
+    #[test]
+    fn base_time() {
+        let mut xboard = Xboard::new(SharedBuffer::default());
+        xboard.handle_command("level 40 5 0");
+        let params = xboard.go_params(&xboard.board());
+        assert_eq!((params.wtime, params.btime, params.movestogo), (Some(300_000), Some(300_000), Some(40)));
+        // the engine is asked to think with the side to move
+        xboard.handle_command("time 1000");
+        assert_eq!(xboard.go_params(&xboard.board()).wtime, Some(10_000));
+    }
+
+    #[test]
+    fn pong_after_move() {
+        let out = SharedBuffer::default();
+        let mut xboard = Xboard::new(out.clone());
+        xboard.handle_command("force");
+        xboard.handle_command("sd 4");
+        xboard.handle_command("go");
+        xboard.handle_command("ping 3");
+        // the move is played as soon as it is found, without waiting for another command
+        let start = Instant::now();
+        while !out.lines().iter().any(|l| l == "pong 3") {
+            assert!(start.elapsed() < Duration::from_secs(60));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let lines = out.lines();
+        assert!(lines[0].starts_with("move "));
+        assert_eq!(lines[1], "pong 3");
+        assert_eq!(xboard.board().history.len(), 1);
+        xboard.wait();
+    }
+
+    #[test]
+    fn thinks_through_other_commands() {
+        let out = SharedBuffer::default();
+        let mut xboard = Xboard::new(out.clone());
+        xboard.handle_command("force");
+        xboard.handle_command("st 1");
+        xboard.handle_command("go");
+        for command in ["computer", "name Someone", "rating 2100 1900", "ics -", "draw", "hint", "bk", "level 40 5 0", "sd 8"] {
+            xboard.handle_command(command);
+        }
+        xboard.wait();
+        assert!(out.lines()[0].starts_with("move "));
+        assert_eq!(xboard.board().history.len(), 1);
+    }
+}
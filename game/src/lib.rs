@@ -1,3 +1,25 @@
+pub mod rng;
+pub mod mcts;
+
+// the outcome of a finished game
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Outcome<P> {
+    Win(P),
+    Draw,
+}
+
+// a two player game with perfect information, as seen by the generic search algorithms
+pub trait Game: Clone {
+    type Move: Copy + PartialEq;
+    type Player: Copy + PartialEq;
+
+    fn current_player(&self) -> Self::Player;
+    fn legal_moves(&self) -> Vec<Self::Move>;
+    fn play(&mut self, m: Self::Move);
+    // None while the game is still going
+    fn outcome(&self) -> Option<Outcome<Self::Player>>;
+}
+
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
 }
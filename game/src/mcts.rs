@@ -0,0 +1,355 @@
+use std::time::{Duration, Instant};
+use crate::rng::Rng;
+use crate::{Game, Outcome};
+
+// how much thinking a search may do
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Budget {
+    Iterations(u32),
+    Time(Duration),
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct MctsConfig {
+    // the weight of exploration against exploitation in the UCT formula
+    pub exploration: f64,
+    pub budget: Budget,
+    // rollouts that run longer than this count as draws
+    pub max_rollout_plies: usize,
+    pub seed: u64,
+}
+
+impl Default for MctsConfig {
+    fn default() -> Self {
+        Self {
+            exploration: std::f64::consts::SQRT_2,
+            budget: Budget::Iterations(10_000),
+            max_rollout_plies: 200,
+            seed: 0,
+        }
+    }
+}
+
+// picks the moves of the simulated games
+pub trait RolloutPolicy<G: Game> {
+    // moves is never empty
+    fn choose(&mut self, game: &G, moves: &[G::Move], rng: &mut Rng) -> G::Move;
+}
+
+pub struct RandomRollout;
+
+impl<G: Game> RolloutPolicy<G> for RandomRollout {
+    fn choose(&mut self, _game: &G, moves: &[G::Move], rng: &mut Rng) -> G::Move {
+        moves[rng.below(moves.len())]
+    }
+}
+
+struct Node<G: Game> {
+    // the move leading here, None at the root
+    last_move: Option<G::Move>,
+    // the player who made that move, whose point of view the reward is in
+    mover: Option<G::Player>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<G::Move>,
+    visits: u32,
+    reward: f64,
+}
+
+impl<G: Game> Node<G> {
+    fn new(game: &G, last_move: Option<G::Move>, mover: Option<G::Player>, parent: Option<usize>) -> Self {
+        let untried = if game.outcome().is_some() { Vec::new() } else { game.legal_moves() };
+        Self { last_move, mover, parent, children: Vec::new(), untried, visits: 0, reward: 0.0 }
+    }
+}
+
+// statistics for one of the root moves after a search
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct MoveStats<M> {
+    pub chess_move: M,
+    pub visits: u32,
+    // the average reward for the player making the move, from 0 (loss) to 1 (win)
+    pub value: f64,
+}
+
+// Monte Carlo tree search with UCT selection; the tree is kept between moves
+pub struct Mcts<G: Game, P: RolloutPolicy<G>> {
+    config: MctsConfig,
+    policy: P,
+    rng: Rng,
+    root_state: Option<G>,
+    nodes: Vec<Node<G>>,
+}
+
+impl<G: Game + PartialEq, P: RolloutPolicy<G>> Mcts<G, P> {
+    pub fn new(config: MctsConfig, policy: P) -> Self {
+        Self { config, rng: Rng::new(config.seed), policy, root_state: None, nodes: Vec::new() }
+    }
+
+    pub fn config(&self) -> &MctsConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: MctsConfig) {
+        self.config = config;
+    }
+
+    // the number of simulations below the root, including those kept from earlier searches
+    pub fn root_visits(&self) -> u32 {
+        self.nodes.first().map_or(0, |root| root.visits)
+    }
+
+    // runs the search within the budget and returns the most visited move
+    pub fn search(&mut self, game: &G) -> Option<G::Move> {
+        if self.root_state.as_ref() != Some(game) {
+            self.root_state = Some(game.clone());
+            self.nodes = vec![Node::new(game, None, None, None)];
+        }
+        let start = Instant::now();
+        let mut iterations = 0;
+        loop {
+            let finished = match self.config.budget {
+                Budget::Iterations(n) => iterations >= n,
+                Budget::Time(t) => start.elapsed() >= t,
+            };
+            if finished {
+                break;
+            }
+            self.iterate(game);
+            iterations += 1;
+        }
+        self.best_move()
+    }
+
+    pub fn best_move(&self) -> Option<G::Move> {
+        self.root_stats().into_iter().max_by_key(|stats| stats.visits).map(|stats| stats.chess_move)
+    }
+
+    pub fn root_stats(&self) -> Vec<MoveStats<G::Move>> {
+        let Some(root) = self.nodes.first() else {
+            return Vec::new();
+        };
+        root.children.iter().map(|&i| {
+            let child = &self.nodes[i];
+            MoveStats {
+                chess_move: child.last_move.expect("children have a move"),
+                visits: child.visits,
+                value: child.reward / child.visits.max(1) as f64,
+            }
+        }).collect()
+    }
+
+    // moves the root to the position after a move, keeping the statistics of that subtree
+    pub fn advance(&mut self, m: G::Move) {
+        let Some(mut state) = self.root_state.take() else {
+            return;
+        };
+        state.play(m);
+        let child = self.nodes.first()
+            .and_then(|root| root.children.iter().copied().find(|&i| self.nodes[i].last_move == Some(m)));
+        self.nodes = match child {
+            Some(child) => self.extract_subtree(child),
+            None => vec![Node::new(&state, None, None, None)],
+        };
+        self.root_state = Some(state);
+    }
+
+    fn extract_subtree(&mut self, new_root: usize) -> Vec<Node<G>> {
+        let mut old_nodes: Vec<Option<Node<G>>> = std::mem::take(&mut self.nodes).into_iter().map(Some).collect();
+        let mut nodes = Vec::new();
+        // (old index, new parent index)
+        let mut queue = std::collections::VecDeque::from([(new_root, None)]);
+        while let Some((old, parent)) = queue.pop_front() {
+            let mut node = old_nodes[old].take().expect("a tree visits every node once");
+            let index = nodes.len();
+            node.parent = parent;
+            queue.extend(node.children.iter().map(|&c| (c, Some(index))));
+            node.children.clear();
+            if let Some(parent) = parent {
+                let parent_node: &mut Node<G> = &mut nodes[parent];
+                parent_node.children.push(index);
+            }
+            nodes.push(node);
+        }
+        nodes[0].last_move = None;
+        nodes[0].mover = None;
+        nodes
+    }
+
+    fn uct_child(&self, index: usize) -> usize {
+        let node = &self.nodes[index];
+        let log_visits = (node.visits.max(1) as f64).ln();
+        let score = |i: usize| {
+            let child = &self.nodes[i];
+            let visits = child.visits.max(1) as f64;
+            child.reward / visits + self.config.exploration * (log_visits / visits).sqrt()
+        };
+        *node.children.iter().max_by(|&&a, &&b| score(a).total_cmp(&score(b))).expect("a fully expanded node has children")
+    }
+
+    fn iterate(&mut self, root_state: &G) {
+        let mut game = root_state.clone();
+        let mut index = 0;
+
+        // selection
+        while self.nodes[index].untried.is_empty() && !self.nodes[index].children.is_empty() {
+            index = self.uct_child(index);
+            game.play(self.nodes[index].last_move.expect("children have a move"));
+        }
+
+        // expansion
+        if !self.nodes[index].untried.is_empty() {
+            let untried = &mut self.nodes[index].untried;
+            let m = untried.swap_remove(self.rng.below(untried.len()));
+            let mover = game.current_player();
+            game.play(m);
+            let child = Node::new(&game, Some(m), Some(mover), Some(index));
+            self.nodes.push(child);
+            let child_index = self.nodes.len() - 1;
+            self.nodes[index].children.push(child_index);
+            index = child_index;
+        }
+
+        // simulation
+        let outcome = self.rollout(game);
+
+        // backpropagation
+        let mut current = Some(index);
+        while let Some(i) = current {
+            let node = &mut self.nodes[i];
+            node.visits += 1;
+            node.reward += match (outcome, node.mover) {
+                (Some(Outcome::Win(winner)), Some(mover)) if winner == mover => 1.0,
+                (Some(Outcome::Win(_)), Some(_)) => 0.0,
+                _ => 0.5,
+            };
+            current = node.parent;
+        }
+    }
+
+    fn rollout(&mut self, mut game: G) -> Option<Outcome<G::Player>> {
+        for _ in 0..self.config.max_rollout_plies {
+            if let Some(outcome) = game.outcome() {
+                return Some(outcome);
+            }
+            let moves = game.legal_moves();
+            if moves.is_empty() {
+                return Some(Outcome::Draw);
+            }
+            let m = self.policy.choose(&game, &moves, &mut self.rng);
+            game.play(m);
+        }
+        game.outcome()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mcts::{Budget, Mcts, MctsConfig, RandomRollout};
+    use crate::{Game, Outcome};
+
+    // noughts and crosses, with the squares numbered 0 to 8
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct TicTacToe {
+        board: [Option<bool>; 9],
+        crosses_to_move: bool,
+    }
+
+    impl TicTacToe {
+        fn from_moves(moves: &[usize]) -> Self {
+            let mut game = TicTacToe { board: [None; 9], crosses_to_move: true };
+            moves.iter().for_each(|m| game.play(*m));
+            game
+        }
+    }
+
+    impl Game for TicTacToe {
+        type Move = usize;
+        type Player = bool;
+
+        fn current_player(&self) -> bool {
+            self.crosses_to_move
+        }
+
+        fn legal_moves(&self) -> Vec<usize> {
+            (0..9).filter(|i| self.board[*i].is_none()).collect()
+        }
+
+        fn play(&mut self, m: usize) {
+            self.board[m] = Some(self.crosses_to_move);
+            self.crosses_to_move = !self.crosses_to_move;
+        }
+
+        fn outcome(&self) -> Option<Outcome<bool>> {
+            const LINES: [[usize; 3]; 8] = [[0, 1, 2], [3, 4, 5], [6, 7, 8], [0, 3, 6], [1, 4, 7], [2, 5, 8], [0, 4, 8], [2, 4, 6]];
+            for [a, b, c] in LINES {
+                if let Some(player) = self.board[a] && self.board[b] == Some(player) && self.board[c] == Some(player) {
+                    return Some(Outcome::Win(player));
+                }
+            }
+            if self.board.iter().all(|s| s.is_some()) {
+                Some(Outcome::Draw)
+            } else {
+                None
+            }
+        }
+    }
+
+    fn mcts(iterations: u32) -> Mcts<TicTacToe, RandomRollout> {
+        let config = MctsConfig { budget: Budget::Iterations(iterations), seed: 1, ..Default::default() };
+        Mcts::new(config, RandomRollout)
+    }
+
+    #[test]
+    fn wins_and_blocks() {
+        // X: 0, 1 and O: 3, 4, so X wins at 2
+        let game = TicTacToe::from_moves(&[0, 3, 1, 4]);
+        assert_eq!(mcts(2000).search(&game), Some(2));
+
+        // X threatens 0, 1, 2; O must block
+        let game = TicTacToe::from_moves(&[0, 4, 1]);
+        assert_eq!(mcts(2000).search(&game), Some(2));
+    }
+
+    #[test]
+    fn reproducible() {
+        let game = TicTacToe::from_moves(&[4]);
+        let mut a = mcts(500);
+        let mut b = mcts(500);
+        assert_eq!(a.search(&game), b.search(&game));
+        assert_eq!(a.root_stats(), b.root_stats());
+    }
+
+    #[test]
+    fn tree_reuse() {
+        let mut game = TicTacToe::from_moves(&[]);
+        let mut mcts = mcts(3000);
+        let first = mcts.search(&game).unwrap();
+        let reply = mcts.root_stats().len();
+        assert_eq!(reply, 9);
+
+        game.play(first);
+        mcts.advance(first);
+        // the subtree below the chosen move survives
+        let kept = mcts.root_visits();
+        assert!(kept > 100);
+        let second = mcts.search(&game).unwrap();
+        game.play(second);
+        mcts.advance(second);
+        assert!(mcts.root_visits() > 0);
+        assert_eq!(mcts.root_stats().iter().map(|s| s.visits).sum::<u32>() + 1, mcts.root_visits());
+
+        // an unrelated position starts a new tree
+        let other = TicTacToe::from_moves(&[8]);
+        mcts.search(&other);
+        assert_eq!(mcts.root_visits(), 3000);
+    }
+
+    #[test]
+    fn time_budget() {
+        let config = MctsConfig { budget: Budget::Time(std::time::Duration::from_millis(20)), ..Default::default() };
+        let mut mcts = Mcts::new(config, RandomRollout);
+        assert!(mcts.search(&TicTacToe::from_moves(&[])).is_some());
+        assert!(mcts.root_visits() > 0);
+    }
+}